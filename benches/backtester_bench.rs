@@ -0,0 +1,126 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use src::{
+    backtester::{
+        backtester_core::Backtest,
+        no_rebalance_strategy::NoRebalanceStrategy,
+        test_helpers::{
+            create_laddered_test_liquidity_array, create_test_liquidity_array,
+            create_test_swap_transactions, test_wallet, InMemoryTransactionRepo,
+        },
+    },
+    utils::core_math::{
+        set_tick_price_cache_size, tick_to_sqrt_price_u256, tick_to_sqrt_price_u256_uncached,
+        DEFAULT_TICK_PRICE_CACHE_SIZE, U256,
+    },
+};
+
+const TOKEN_A: &str = "TokenA";
+const TOKEN_B: &str = "TokenB";
+
+// Sell then buy back what came out, so the array ends up roughly where it started and can be reused across iterations.
+fn bench_simulate_swap(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulate_swap");
+
+    let mut wide_arr = create_test_liquidity_array(5);
+    group.bench_function("round_trip_no_tick_crossings", |b| {
+        b.iter(|| {
+            let amount_out = wide_arr
+                .simulate_swap(black_box(U256::from(1_000_000)), true)
+                .unwrap();
+            wide_arr.simulate_swap(amount_out, false).unwrap()
+        })
+    });
+
+    // ~100 initialized ticks crossed each way.
+    let mut laddered_arr = create_laddered_test_liquidity_array(5, 10, 200);
+    group.bench_function("round_trip_tick_crossings", |b| {
+        b.iter(|| {
+            let amount_out = laddered_arr
+                .simulate_swap(black_box(U256::from(50_000_000)), true)
+                .unwrap();
+            laddered_arr.simulate_swap(amount_out, false).unwrap()
+        })
+    });
+
+    group.finish();
+}
+
+// The same couple hundred ticks over and over, like the cached initialized ticks and position bounds during a replay.
+fn bench_tick_to_sqrt_price(c: &mut Criterion) {
+    let mut group = c.benchmark_group("tick_to_sqrt_price");
+    let ticks: Vec<i32> = (-1_000..1_000).step_by(10).collect();
+
+    group.bench_function("cached", |b| {
+        b.iter(|| {
+            for tick in &ticks {
+                black_box(tick_to_sqrt_price_u256(black_box(*tick)));
+            }
+        })
+    });
+    group.bench_function("uncached", |b| {
+        b.iter(|| {
+            for tick in &ticks {
+                black_box(tick_to_sqrt_price_u256_uncached(black_box(*tick)));
+            }
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_sync_forward(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let tx_count = 10_000;
+    let repo = InMemoryTransactionRepo {
+        transactions: create_test_swap_transactions(tx_count, 1_000_000, TOKEN_A, TOKEN_B),
+    };
+
+    for (name, cache_size) in [
+        ("sync_forward_10k_swaps", DEFAULT_TICK_PRICE_CACHE_SIZE),
+        ("sync_forward_10k_swaps_uncached_ticks", 0),
+    ] {
+        set_tick_price_cache_size(cache_size);
+        bench_sync_forward_run(c, &runtime, &repo, tx_count, name);
+    }
+    set_tick_price_cache_size(DEFAULT_TICK_PRICE_CACHE_SIZE);
+}
+
+fn bench_sync_forward_run(
+    c: &mut Criterion,
+    runtime: &tokio::runtime::Runtime,
+    repo: &InMemoryTransactionRepo,
+    tx_count: i64,
+    name: &str,
+) {
+    c.bench_function(name, |b| {
+        b.iter_batched(
+            || {
+                let amount = U256::from(1_000_000_000_u64);
+                let wallet = test_wallet(amount, amount);
+
+                Backtest::new(
+                    amount,
+                    amount,
+                    create_laddered_test_liquidity_array(5, 10, 200),
+                    wallet,
+                    Box::new(NoRebalanceStrategy::new(-300, 300)),
+                )
+            },
+            |mut backtest| {
+                runtime
+                    .block_on(backtest.sync_forward(repo, tx_count + 1, 1, "test_pool", 1000))
+                    .unwrap();
+                backtest
+            },
+            BatchSize::SmallInput,
+        )
+    });
+}
+
+criterion_group!(
+    benches,
+    bench_simulate_swap,
+    bench_tick_to_sqrt_price,
+    bench_sync_forward
+);
+criterion_main!(benches);
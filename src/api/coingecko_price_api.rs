@@ -0,0 +1,98 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use dotenv::dotenv;
+use serde_json::Value;
+use std::env;
+
+use super::price_api::{last_price_at_or_before, PriceApi, PricePoint, PriceSource, PriceToken};
+
+// Uses the same CoinGecko credentials as PoolApi. Looks tokens up by Solana mint, so it also covers tokens without a CEX pair.
+pub struct CoinGeckoPriceApi {
+    client: reqwest::Client,
+    coingecko_api_key: String,
+    coingecko_api_url: String,
+    coingecko_header: String,
+}
+
+impl CoinGeckoPriceApi {
+    pub fn new() -> Result<Self> {
+        dotenv().ok();
+        let coingecko_api_key =
+            env::var("COINGECKO_API_KEY").context("COINGECKO_API_KEY must be set")?;
+        let coingecko_api_url =
+            env::var("COINGECKO_API_URL").context("COINGECKO_API_URL must be set")?;
+        let coingecko_header =
+            env::var("COINGECKO_HEADER").context("COINGECKO_HEADER must be set")?;
+
+        Ok(Self {
+            client: reqwest::Client::new(),
+            coingecko_api_key,
+            coingecko_api_url,
+            coingecko_header,
+        })
+    }
+
+    // Response is {"prices": [[unix millis, price], ...]}.
+    fn parse_prices(&self, response: Value) -> Result<Vec<(i64, f64)>> {
+        let prices = response["prices"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Invalid CoinGecko market chart response"))?;
+
+        prices
+            .iter()
+            .map(|point| {
+                let time = point[0]
+                    .as_i64()
+                    .or_else(|| point[0].as_f64().map(|t| t as i64))
+                    .ok_or_else(|| anyhow!("Invalid CoinGecko time"))?;
+                let price = point[1]
+                    .as_f64()
+                    .ok_or_else(|| anyhow!("Invalid CoinGecko price"))?;
+
+                Ok((time / 1000, price))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PriceApi for CoinGeckoPriceApi {
+    fn source(&self) -> PriceSource {
+        PriceSource::CoinGecko
+    }
+
+    async fn get_price(&self, token: &PriceToken, timestamp: DateTime<Utc>) -> Result<PricePoint> {
+        let url = format!(
+            "{}coins/solana/contract/{}/market_chart/range",
+            self.coingecko_api_url, token.address
+        );
+
+        // Ranges under a day come back in ~5 minute steps, an hour back is enough to always have a point.
+        let params = [
+            ("vs_currency", "usd".to_string()),
+            (
+                "from",
+                (timestamp - Duration::hours(1)).timestamp().to_string(),
+            ),
+            ("to", timestamp.timestamp().to_string()),
+        ];
+
+        let response = self
+            .client
+            .get(&url)
+            .header(&self.coingecko_header, &self.coingecko_api_key)
+            .query(&params)
+            .send()
+            .await
+            .context("Failed to send request to CoinGecko")?;
+
+        if !response.status().is_success() {
+            return Err(anyhow!("CoinGecko API error: {}", response.status()));
+        }
+
+        let response = response.json::<Value>().await?;
+
+        last_price_at_or_before(&self.parse_prices(response)?, timestamp)
+    }
+}
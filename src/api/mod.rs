@@ -1,5 +1,7 @@
+pub mod coingecko_price_api;
 pub mod pool_api;
-pub mod transactions_api;
 pub mod positions_api;
 pub mod price_api;
+pub mod pyth_price_api;
 pub mod token_metadata_api;
+pub mod transactions_api;
@@ -1,10 +1,13 @@
 use anyhow::{Context, Result};
+use base64::{engine::general_purpose, Engine as _};
 use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::env;
 
-use crate::models::token_metadata::TokenMetadata;
+use crate::models::{pool_model::PoolPrice, token_metadata::TokenMetadata};
+use crate::services::transactions_sync_amm_service::AMMPlatforms;
+use crate::utils::decode::decode_pool_price;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ApiResponse {
@@ -70,6 +73,63 @@ impl PoolApi {
         Ok(api_response.result)
     }
 
+    // Program that owns the account, for pools that is the AMM program.
+    pub async fn fetch_account_owner(&self, address: &str) -> Result<String> {
+        let result = self.fetch_pool_data(address).await?;
+
+        result["value"]["owner"]
+            .as_str()
+            .map(String::from)
+            .ok_or_else(|| anyhow::anyhow!("Account not found: {}", address))
+    }
+
+    // Current sqrt price and tick stored in the pool account (Orca or Raydium, told apart by the owner program).
+    pub async fn fetch_pool_price(&self, pool_address: &str) -> Result<PoolPrice> {
+        let result = self.fetch_pool_data(pool_address).await?;
+
+        let owner = result["value"]["owner"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("Account not found: {}", pool_address))?;
+        let data = result["value"]["data"][0]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("No account data for pool: {}", pool_address))?;
+        let decoded = general_purpose::STANDARD
+            .decode(data)
+            .context("Failed to decode base64 data")?;
+
+        decode_pool_price(AMMPlatforms::from_program_id(owner)?, &decoded)
+            .context("Failed to decode the pool price")
+    }
+
+    // Raw token amount (decimals included) held by an SPL token account, e.g. a pool vault.
+    pub async fn fetch_token_account_balance(&self, token_account: &str) -> Result<u128> {
+        let url = format!("{}/v2/{}", self.alchemy_api_url, self.alchemy_api_key);
+
+        let response = self
+            .client
+            .post(&url)
+            .header("accept", "application/json")
+            .header("content-type", "application/json")
+            .json(&serde_json::json!({
+                "id": 1,
+                "jsonrpc": "2.0",
+                "method": "getTokenAccountBalance",
+                "params": [token_account]
+            }))
+            .send()
+            .await?;
+
+        let api_response: ApiResponse = response.json().await?;
+
+        api_response.result["value"]["amount"]
+            .as_str()
+            .ok_or_else(|| {
+                anyhow::anyhow!("No balance found for token account: {}", token_account)
+            })?
+            .parse::<u128>()
+            .context("Failed to parse token account balance")
+    }
+
     pub async fn fetch_token_metadata(&self, token_address: &str) -> Result<TokenMetadata> {
         let url = format!(
             "{}coins/solana/contract/{}",
@@ -1,19 +1,137 @@
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
+use serde::Serialize;
 use serde_json::Value;
+use std::{fmt, str::FromStr};
 
 use crate::models::kline_model::KlineModel;
 
+use super::{coingecko_price_api::CoinGeckoPriceApi, pyth_price_api::PythPriceApi};
+
 const BINANCE_API_URL: &str = "https://fapi.binance.com/fapi/v1/klines";
 
-pub struct PriceApi {
+// Providers disagree on the price of the same token at the same time, which moves USD PnL. Picked with PRICE_SOURCE.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum PriceSource {
+    Binance,
+    Pyth,
+    CoinGecko,
+}
+
+impl FromStr for PriceSource {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_uppercase().as_str() {
+            "BINANCE" => Ok(PriceSource::Binance),
+            "PYTH" => Ok(PriceSource::Pyth),
+            "COINGECKO" => Ok(PriceSource::CoinGecko),
+            _ => Err(anyhow!("Unknown price source: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PriceSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PriceSource::Binance => write!(f, "BINANCE"),
+            PriceSource::Pyth => write!(f, "PYTH"),
+            PriceSource::CoinGecko => write!(f, "COINGECKO"),
+        }
+    }
+}
+
+// Side of the pool that is a USD stablecoin (STABLE_TOKEN). Its price is taken as the peg and the other side is priced off the pool,
+// so only one token goes through the PriceApi.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum StableToken {
+    A,
+    B,
+}
+
+impl FromStr for StableToken {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_uppercase().as_str() {
+            "A" => Ok(StableToken::A),
+            "B" => Ok(StableToken::B),
+            _ => Err(anyhow!("Invalid stable token: {} (expected A or B)", s)),
+        }
+    }
+}
+
+impl fmt::Display for StableToken {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StableToken::A => write!(f, "token A"),
+            StableToken::B => write!(f, "token B"),
+        }
+    }
+}
+
+// Binance and Pyth look tokens up by symbol, CoinGecko by mint address, so both are passed along.
+#[derive(Debug, Clone)]
+pub struct PriceToken {
+    pub symbol: String,
+    pub address: String,
+}
+
+// A USD price and the time it is actually for. Providers answer with the nearest point they have, which for illiquid
+// tokens can be well off the time asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PricePoint {
+    pub price: f64,
+    pub timestamp: DateTime<Utc>,
+}
+
+// USD price of a token at a point in time, same interface for every provider.
+#[async_trait]
+pub trait PriceApi: Send + Sync {
+    fn source(&self) -> PriceSource;
+
+    async fn get_price(&self, token: &PriceToken, timestamp: DateTime<Utc>) -> Result<PricePoint>;
+}
+
+pub fn create_price_api(source: PriceSource) -> Result<Box<dyn PriceApi>> {
+    match source {
+        PriceSource::Binance => Ok(Box::new(BinancePriceApi::new()?)),
+        PriceSource::Pyth => Ok(Box::new(PythPriceApi::new()?)),
+        PriceSource::CoinGecko => Ok(Box::new(CoinGeckoPriceApi::new()?)),
+    }
+}
+
+// Last (unix seconds, price) point at or before timestamp. Providers return a window of points around the requested time.
+pub fn last_price_at_or_before(
+    points: &[(i64, f64)],
+    timestamp: DateTime<Utc>,
+) -> Result<PricePoint> {
+    let (time, price) = points
+        .iter()
+        .filter(|(time, _)| *time <= timestamp.timestamp())
+        .max_by_key(|(time, _)| *time)
+        .ok_or_else(|| anyhow!("No price data found for the given timestamp"))?;
+
+    Ok(PricePoint {
+        price: *price,
+        timestamp: DateTime::from_timestamp(*time, 0)
+            .ok_or_else(|| anyhow!("Invalid price timestamp: {}", time))?,
+    })
+}
+
+pub struct BinancePriceApi {
     client: reqwest::Client,
+    api_url: String,
 }
 
-impl PriceApi {
+impl BinancePriceApi {
     pub fn new() -> Result<Self> {
         Ok(Self {
             client: reqwest::Client::new(),
+            // Overridable (e.g. through a profile) for a proxy or a mirror of the binance klines endpoint.
+            api_url: std::env::var("PRICE_API_URL").unwrap_or_else(|_| BINANCE_API_URL.to_string()),
         })
     }
 
@@ -44,7 +162,7 @@ impl PriceApi {
 
         let response = self
             .client
-            .get(BINANCE_API_URL)
+            .get(&self.api_url)
             .query(&params)
             .send()
             .await?
@@ -120,11 +238,12 @@ impl PriceApi {
         Ok(kline_models)
     }
 
+    // Close of the 1m candle ending at timestamp, at the candle's close time.
     pub async fn get_historical_price(
         &self,
         symbol: &str,
         timestamp: DateTime<Utc>,
-    ) -> Result<f64> {
+    ) -> Result<PricePoint> {
         let klines = self
             .get_kline_data(
                 symbol,
@@ -139,6 +258,60 @@ impl PriceApi {
             return Err(anyhow!("No kline data found for the given timestamp"));
         }
 
-        Ok(klines[0].close)
+        Ok(PricePoint {
+            price: klines[0].close,
+            timestamp: DateTime::from_timestamp_millis(klines[0].close_time)
+                .ok_or_else(|| anyhow!("Invalid kline close time: {}", klines[0].close_time))?,
+        })
+    }
+}
+
+#[async_trait]
+impl PriceApi for BinancePriceApi {
+    fn source(&self) -> PriceSource {
+        PriceSource::Binance
+    }
+
+    // USDT pairs stand in for USD.
+    async fn get_price(&self, token: &PriceToken, timestamp: DateTime<Utc>) -> Result<PricePoint> {
+        self.get_historical_price(&format!("{}USDT", token.symbol), timestamp)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_price_source_from_str() {
+        assert_eq!("pyth".parse::<PriceSource>().unwrap(), PriceSource::Pyth);
+        assert_eq!(
+            "CoinGecko".parse::<PriceSource>().unwrap(),
+            PriceSource::CoinGecko
+        );
+        assert_eq!(PriceSource::Binance.to_string(), "BINANCE");
+        assert!("kraken".parse::<PriceSource>().is_err());
+    }
+
+    #[test]
+    fn test_last_price_at_or_before() {
+        let points = vec![(100, 1.0), (160, 2.0), (220, 3.0)];
+
+        let at = |secs| Utc.timestamp_opt(secs, 0).unwrap();
+
+        assert_eq!(
+            last_price_at_or_before(&points, at(200)).unwrap(),
+            PricePoint {
+                price: 2.0,
+                timestamp: at(160)
+            }
+        );
+        assert_eq!(
+            last_price_at_or_before(&points, at(220)).unwrap().price,
+            3.0
+        );
+        assert!(last_price_at_or_before(&points, at(50)).is_err());
     }
 }
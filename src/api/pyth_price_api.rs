@@ -0,0 +1,88 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::Value;
+
+use super::price_api::{last_price_at_or_before, PriceApi, PricePoint, PriceSource, PriceToken};
+
+// Pyth benchmarks (historical oracle prices) through its TradingView compatible candles endpoint.
+const PYTH_API_URL: &str = "https://benchmarks.pyth.network/v1/shims/tradingview/history";
+
+pub struct PythPriceApi {
+    client: reqwest::Client,
+    api_url: String,
+}
+
+impl PythPriceApi {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            api_url: std::env::var("PYTH_API_URL").unwrap_or_else(|_| PYTH_API_URL.to_string()),
+        })
+    }
+
+    // Response is column based: {"s": "ok", "t": [unix secs], "c": [closes], ...}.
+    fn parse_closes(&self, response: Value) -> Result<Vec<(i64, f64)>> {
+        if response["s"].as_str() != Some("ok") {
+            return Err(anyhow!(
+                "Pyth returned no data: {}",
+                response["errmsg"].as_str().unwrap_or("unknown error")
+            ));
+        }
+
+        let times = response["t"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Invalid Pyth times"))?;
+        let closes = response["c"]
+            .as_array()
+            .ok_or_else(|| anyhow!("Invalid Pyth closes"))?;
+
+        times
+            .iter()
+            .zip(closes.iter())
+            .map(|(time, close)| {
+                Ok((
+                    time.as_i64().ok_or_else(|| anyhow!("Invalid Pyth time"))?,
+                    close
+                        .as_f64()
+                        .ok_or_else(|| anyhow!("Invalid Pyth close"))?,
+                ))
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PriceApi for PythPriceApi {
+    fn source(&self) -> PriceSource {
+        PriceSource::Pyth
+    }
+
+    async fn get_price(&self, token: &PriceToken, timestamp: DateTime<Utc>) -> Result<PricePoint> {
+        // 1 minute candles over the few minutes before timestamp, oracle updates can be sparse for smaller feeds.
+        let params = [
+            (
+                "symbol",
+                format!("Crypto.{}/USD", token.symbol.to_uppercase()),
+            ),
+            ("resolution", "1".to_string()),
+            (
+                "from",
+                (timestamp - Duration::minutes(5)).timestamp().to_string(),
+            ),
+            ("to", timestamp.timestamp().to_string()),
+        ];
+
+        let response = self
+            .client
+            .get(&self.api_url)
+            .query(&params)
+            .send()
+            .await
+            .context("Failed to send request to Pyth")?
+            .json::<Value>()
+            .await?;
+
+        last_price_at_or_before(&self.parse_closes(response)?, timestamp)
+    }
+}
@@ -1,9 +1,16 @@
 use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use serde_json::Value;
 use std::collections::HashMap;
 
 const JUPITER_API_URL: &str = "https://token.jup.ag/strict";
 
+// Symbols of token mints, a trait so the PnL calculation can run without the Jupiter token list.
+#[async_trait]
+pub trait TokenMetadataApiTrait: Send + Sync {
+    async fn get_token_symbols_for_addresses(&self, addresses: &[String]) -> Result<Vec<String>>;
+}
+
 pub struct TokenMetadataApi {
     client: reqwest::Client,
 }
@@ -40,11 +47,11 @@ impl TokenMetadataApi {
 
         Ok(symbol_map)
     }
+}
 
-    pub async fn get_token_symbols_for_addresses(
-        &self,
-        addresses: &[String],
-    ) -> Result<Vec<String>> {
+#[async_trait]
+impl TokenMetadataApiTrait for TokenMetadataApi {
+    async fn get_token_symbols_for_addresses(&self, addresses: &[String]) -> Result<Vec<String>> {
         let symbols = self.get_token_symbols().await?;
 
         addresses
@@ -54,11 +54,16 @@ impl TransactionApi {
         let alchemy_api_key = env::var("ALCHEMY_API_KEY").context("ALCHEMY_API_KEY must be set")?;
         let alchemy_api_url = env::var("ALCHEMY_API_URL").context("ALCHEMY_API_URL must be set")?;
 
-        Ok(Self {
+        Ok(Self::with_endpoint(alchemy_api_url, alchemy_api_key))
+    }
+
+    // Same as new() without reading the env, e.g. for tests that never reach the rpc.
+    pub fn with_endpoint(alchemy_api_url: String, alchemy_api_key: String) -> Self {
+        Self {
             client: reqwest::Client::new(),
             alchemy_api_key,
             alchemy_api_url,
-        })
+        }
     }
 
     pub async fn fetch_transaction_signatures(
@@ -147,7 +152,7 @@ impl TransactionApi {
         let response_text = response.text().await?;
         let api_responses: Vec<TransactionApiResponse> = serde_json::from_str(&response_text)
             .context("Failed to parse API response")
-            .map_err(|e| ApiError::Other(e))?;
+            .map_err(ApiError::Other)?;
 
         Ok(api_responses.into_iter().map(|r| r.result).collect())
     }
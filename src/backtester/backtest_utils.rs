@@ -1,15 +1,21 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use std::str::FromStr;
 
 use crate::{
     models::{
-        pool_model::PoolModel, positions_model::LivePositionModel,
+        pool_model::{PoolModel, PoolPrice},
+        positions_model::LivePositionModel,
         transactions_model::TransactionModelFromDB,
     },
     repositories::transactions_repo::{OrderDirection, TransactionRepoTrait},
+    services::transactions_sync_amm_service::AMMPlatforms,
     utils::{
         core_math::{
-            calculate_liquidity_a, calculate_liquidity_b, calculate_token_a_from_liquidity,
-            calculate_token_b_from_liquidity, price_to_tick, tick_to_sqrt_price_u256, U256,
+            calculate_amounts, calculate_liquidity, calculate_liquidity_a, calculate_liquidity_b,
+            calculate_token_a_from_liquidity, calculate_token_b_from_liquidity, price_to_tick,
+            sqrt_price_to_price, tick_to_sqrt_price_u256, within_tolerance, MAX_TICK, MIN_TICK,
+            Q64, U256,
         },
         error::SyncError,
     },
@@ -17,47 +23,221 @@ use crate::{
 
 use super::liquidity_array::LiquidityArray;
 
+// Decides how many ticks the LiquidityArray allocates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TickBounds {
+    // Explicit range, useful for exotic pairs or to cap memory.
+    Fixed { min_tick: i32, max_tick: i32 },
+    // Tick extremes of the live positions and current tick, widened by padding on both sides to leave room for price moves.
+    Derived { padding: i32 },
+}
+
+impl TickBounds {
+    pub fn resolve(&self, positions: &[LivePositionModel], current_tick: i32) -> (i32, i32) {
+        match *self {
+            TickBounds::Fixed { min_tick, max_tick } => (min_tick, max_tick),
+            TickBounds::Derived { padding } => {
+                let (lowest_tick, highest_tick) = positions.iter().fold(
+                    (current_tick, current_tick),
+                    |(lowest, highest), position| {
+                        (
+                            lowest.min(position.tick_lower),
+                            highest.max(position.tick_upper),
+                        )
+                    },
+                );
+
+                (
+                    lowest_tick.saturating_sub(padding).max(MIN_TICK),
+                    highest_tick.saturating_add(padding).min(MAX_TICK),
+                )
+            }
+        }
+    }
+}
+
+// How the starting price is averaged over the most recent swaps (START_PRICE_METHOD).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StartPriceMethod {
+    // Middle of the swaps' implied ticks, a single outlier can't move it.
+    #[default]
+    Median,
+    // Mean of the implied prices weighted by each swap's token B amount.
+    VolumeWeighted,
+}
+
+impl FromStr for StartPriceMethod {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_uppercase().as_str() {
+            "MEDIAN" => Ok(StartPriceMethod::Median),
+            "VOLUME_WEIGHTED" => Ok(StartPriceMethod::VolumeWeighted),
+            _ => Err(anyhow!(
+                "Unknown start price method: {} (expected MEDIAN or VOLUME_WEIGHTED)",
+                s
+            )),
+        }
+    }
+}
+
+// Which swaps the starting price is reconstructed from when there's no live pool price.
+#[derive(Debug, Clone, Copy)]
+pub enum StartPrice<'a> {
+    // The implied price of the latest swap alone.
+    LatestSwap,
+    // The N most recent swaps (START_PRICE_SWAPS), the latest one among them.
+    Averaged {
+        swaps: &'a [TransactionModelFromDB],
+        method: StartPriceMethod,
+    },
+}
+
+impl<'a> StartPrice<'a> {
+    pub fn over(swaps: &'a [TransactionModelFromDB], method: StartPriceMethod) -> Self {
+        if swaps.len() > 1 {
+            StartPrice::Averaged { swaps, method }
+        } else {
+            StartPrice::LatestSwap
+        }
+    }
+
+    pub fn tick(
+        &self,
+        latest_transaction: &TransactionModelFromDB,
+        token_a_address: &str,
+    ) -> Result<i32> {
+        let (swaps, method) = match *self {
+            StartPrice::LatestSwap => {
+                let swap_data = latest_transaction
+                    .data
+                    .to_swap_data()
+                    .map_err(SyncError::DatabaseError)?;
+                let is_sell = swap_data.token_in == token_a_address;
+
+                return Ok(price_to_tick(swap_data.implied_price(is_sell)));
+            }
+            StartPrice::Averaged { swaps, method } => (swaps, method),
+        };
+
+        // (implied price, token B amount) of every swap that moved both tokens.
+        let mut prices = Vec::with_capacity(swaps.len());
+        for swap in swaps {
+            let swap_data = swap.data.to_swap_data().map_err(SyncError::DatabaseError)?;
+            if swap_data.amount_in == 0 || swap_data.amount_out == 0 {
+                continue;
+            }
+
+            let is_sell = swap_data.token_in == token_a_address;
+            let amount_b = if is_sell {
+                swap_data.amount_out
+            } else {
+                swap_data.amount_in
+            };
+            prices.push((swap_data.implied_price(is_sell), amount_b as f64));
+        }
+
+        if prices.is_empty() {
+            return Err(anyhow!(
+                "None of the {} swaps for the start price moved both tokens",
+                swaps.len()
+            ));
+        }
+
+        match method {
+            StartPriceMethod::Median => {
+                let mut ticks: Vec<i32> = prices
+                    .iter()
+                    .map(|(price, _)| price_to_tick(*price))
+                    .collect();
+                ticks.sort_unstable();
+
+                let middle = ticks.len() / 2;
+                if ticks.len().is_multiple_of(2) {
+                    Ok((ticks[middle - 1] + ticks[middle]).div_euclid(2))
+                } else {
+                    Ok(ticks[middle])
+                }
+            }
+            StartPriceMethod::VolumeWeighted => {
+                let (weighted_sum, volume) = prices
+                    .iter()
+                    .fold((0.0, 0.0), |(sum, volume), (price, amount_b)| {
+                        (sum + price * amount_b, volume + amount_b)
+                    });
+
+                Ok(price_to_tick(weighted_sum / volume))
+            }
+        }
+    }
+}
+
+// Beyond this many ticks between the live pool price and the latest swap's price, the synced swaps likely lag the chain.
+const LIVE_PRICE_DRIFT_WARNING_TICKS: i32 = 100;
+
+// live_price (read from the pool account) is the current price when given. Otherwise it's reconstructed from the latest
+// swap's amounts, which is only an approximation (the swap's average price, not where it left the pool). Averaging over
+// the most recent swaps (start_price) keeps one outlier swap from setting the start.
+#[allow(clippy::too_many_arguments)]
 pub fn create_full_liquidity_range(
     tick_spacing: i16,
     positions: Vec<LivePositionModel>,
     pool_model: PoolModel,
     latest_transaction: TransactionModelFromDB,
+    start_price: StartPrice<'_>,
+    live_price: Option<PoolPrice>,
     fee_rate: i16,
+    tick_bounds: TickBounds,
 ) -> Result<LiquidityArray> {
-    let min_tick = -500_000;
-    let max_tick = 500_000;
-
-    let mut liquidity_array =
-        LiquidityArray::new(min_tick, max_tick, tick_spacing as i32, fee_rate);
-
     // set price to correctly calculate active liquidity inside update_liquidity
     let swap_data = latest_transaction
         .data
         .to_swap_data()
-        .map_err(|e| SyncError::DatabaseError(e.to_string()))?
+        .map_err(SyncError::DatabaseError)?
         .clone();
 
     let is_sell = swap_data.token_in == pool_model.token_a_address;
 
-    // Set essential info before simulation.
-    if is_sell {
-        let tick = price_to_tick(swap_data.amount_out as f64 / swap_data.amount_in as f64);
+    let swap_tick = start_price.tick(&latest_transaction, &pool_model.token_a_address)?;
 
-        liquidity_array.current_tick = tick;
-        liquidity_array.current_sqrt_price = tick_to_sqrt_price_u256(tick);
-    } else {
-        let tick = price_to_tick(swap_data.amount_in as f64 / swap_data.amount_out as f64);
+    let (current_tick, current_sqrt_price) = match live_price {
+        Some(live_price) => {
+            if (live_price.tick - swap_tick).abs() > LIVE_PRICE_DRIFT_WARNING_TICKS {
+                println!(
+                    "WARNING: The pool account is at tick {} but the latest synced swap {} is at {}. The synced transactions may lag the chain, run `cargo run sync`.",
+                    live_price.tick, latest_transaction.signature, swap_tick
+                );
+            }
 
-        liquidity_array.current_tick = tick;
-        liquidity_array.current_sqrt_price = tick_to_sqrt_price_u256(tick);
+            (live_price.tick, U256::from(live_price.sqrt_price))
+        }
+        None => (swap_tick, tick_to_sqrt_price_u256(swap_tick)),
     };
 
+    let (min_tick, max_tick) = tick_bounds.resolve(&positions, current_tick);
+
+    if current_tick < min_tick || current_tick > max_tick {
+        return Err(anyhow::anyhow!(
+            "Current tick {} is outside of the configured tick range [{}, {}]",
+            current_tick,
+            min_tick,
+            max_tick
+        ));
+    }
+
+    let mut liquidity_array =
+        LiquidityArray::new(min_tick, max_tick, tick_spacing as i32, fee_rate);
+
+    // Set essential info before simulation.
+    liquidity_array.current_tick = current_tick;
+    liquidity_array.current_sqrt_price = current_sqrt_price;
+
     for position in positions {
         // default true since we are adding all positions.
         liquidity_array.update_liquidity(
             position.tick_lower,
             position.tick_upper,
-            position.liquidity as i128,
+            position.liquidity,
             true,
         );
     }
@@ -72,11 +252,222 @@ pub fn create_full_liquidity_range(
     Ok(liquidity_array)
 }
 
+// Pre-flight check before replaying swaps on a reconstructed range. A swap on zero active liquidity or with caches that
+// don't bracket the price fails deep in the swap math, this names the likely cause instead.
+pub fn check_liquidity_health(liquidity_arr: &LiquidityArray) -> Result<()> {
+    let current_tick = liquidity_arr.current_tick;
+
+    if liquidity_arr.active_liquidity.is_zero() {
+        return Err(anyhow::anyhow!(
+            "No active liquidity at tick {}. Likely causes: the pool's positions are missing (run `cargo run sync`), \
+             the positions around the price were filtered out or their ticks weren't filled (`cargo run doctor`).",
+            current_tick
+        ));
+    }
+
+    match (
+        liquidity_arr.cached_lower_initialized_tick,
+        liquidity_arr.cached_upper_initialized_tick,
+    ) {
+        (Some(lower), Some(upper)) if lower <= current_tick && current_tick <= upper => Ok(()),
+        (Some(lower), Some(upper)) => Err(anyhow::anyhow!(
+            "Cached initialized ticks [{}, {}] don't bracket the current tick {}. The ticks were likely mis-mapped, \
+             check the positions' tick_lower/tick_upper and the pool's tick_spacing.",
+            lower,
+            upper,
+            current_tick
+        )),
+        _ => Err(anyhow::anyhow!(
+            "No initialized tick cached around the current tick {}. Likely causes: missing positions or unfilled ticks \
+             (`cargo run doctor`).",
+            current_tick
+        )),
+    }
+}
+
+// The replay scales swap amounts with the pool row's decimals, while the synced amounts were converted with the decimals
+// of the sync. Any difference skews volumes and USD figures by orders of magnitude, so it's an error.
+pub fn check_decimals_match(pool_decimals: (i16, i16), sync_decimals: (i16, i16)) -> Result<()> {
+    if pool_decimals == sync_decimals {
+        return Ok(());
+    }
+
+    Err(anyhow::anyhow!(
+        "Token decimals of the pool row (A {}, B {}) don't match the decimals the transactions were synced with (A {}, B {}). \
+         Fix the pool row or re-sync the pool's transactions.",
+        pool_decimals.0,
+        pool_decimals.1,
+        sync_decimals.0,
+        sync_decimals.1
+    ))
+}
+
+// Cheapest guard against a broken reconstruction: the starting price it lands on (token B per token A) should be close
+// to the market price at that time. Both prices are in the error so the gap can be judged.
+pub fn check_start_price_drift(
+    reconstructed_price: f64,
+    external_price: f64,
+    max_drift_pct: f64,
+) -> Result<()> {
+    let drift_pct = (reconstructed_price / external_price - 1.0).abs() * 100.0;
+    if drift_pct <= max_drift_pct {
+        return Ok(());
+    }
+
+    Err(anyhow!(
+        "The reconstructed starting price {} is {:.2}% off the price source's {} (max {}%). The reconstruction is likely \
+         broken, check the synced positions (`cargo run doctor`) or raise MAX_START_PRICE_DRIFT_PCT.",
+        reconstructed_price,
+        drift_pct,
+        external_price,
+        max_drift_pct
+    ))
+}
+
+// A tick spacing that doesn't belong to the fee rate (a bad sync of the pool row) silently spreads the liquidity over the
+// wrong ticks. Governance can change a pool's fee rate though, so this only warns. None when the pair is a known tier or
+// the platform has no table.
+pub fn fee_tier_warning(
+    platform: AMMPlatforms,
+    tick_spacing: i16,
+    fee_rate: i16,
+) -> Option<String> {
+    let fee_tiers = platform.known_fee_tiers()?;
+    if fee_tiers.contains(&(tick_spacing, fee_rate)) {
+        return None;
+    }
+
+    let tier_fee_rate = fee_tiers
+        .iter()
+        .find(|(spacing, _)| *spacing == tick_spacing)
+        .map(|(_, fee_rate)| *fee_rate);
+
+    Some(match tier_fee_rate {
+        Some(tier_fee_rate) => format!(
+            "The pool row's fee rate {} isn't the {:?} tier of tick spacing {} ({}). Check the pool row (re-sync it) or \
+             set FEE_RATE_HISTORY if the fee was changed.",
+            fee_rate, platform, tick_spacing, tier_fee_rate
+        ),
+        None => format!(
+            "The pool row's tick spacing {} (fee rate {}) isn't a known {:?} fee tier, the pool row may be corrupted. \
+             Re-sync it before trusting the reconstruction.",
+            tick_spacing, fee_rate, platform
+        ),
+    })
+}
+
+pub const DEFAULT_CHECKPOINT_TICK_TOLERANCE: i32 = 2;
+pub const DEFAULT_CHECKPOINT_LIQUIDITY_TOLERANCE_BPS: u32 = 50;
+
+// An on-chain snapshot of the pool at a known time (RECONSTRUCTION_CHECKPOINT). Once a replay passes that time, the
+// reconstructed tick and active liquidity should match it within the tolerances, otherwise the reconstruction drifted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReconstructionCheckpoint {
+    pub timestamp: DateTime<Utc>,
+    pub expected_tick: i32,
+    pub expected_liquidity: u128,
+    pub tick_tolerance: i32,
+    pub liquidity_tolerance_bps: u32,
+    // Error out on a mismatch instead of warning (FAIL_ON_CHECKPOINT_MISMATCH).
+    pub strict: bool,
+}
+
+// "<timestamp>,<tick>,<liquidity>", the timestamp as RFC 3339 or unix seconds. Default tolerances, warns on a mismatch.
+impl FromStr for ReconstructionCheckpoint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let parts: Vec<&str> = s.split(',').map(str::trim).collect();
+        let [timestamp, tick, liquidity] = parts.as_slice() else {
+            return Err(anyhow!(
+                "Invalid checkpoint '{}', expected <timestamp>,<tick>,<liquidity>",
+                s
+            ));
+        };
+
+        let timestamp = match timestamp.parse::<i64>() {
+            Ok(seconds) => DateTime::from_timestamp(seconds, 0)
+                .ok_or_else(|| anyhow!("Checkpoint timestamp {} is out of range", seconds))?,
+            Err(_) => DateTime::parse_from_rfc3339(timestamp)
+                .context(format!("Invalid checkpoint timestamp: {}", timestamp))?
+                .with_timezone(&Utc),
+        };
+
+        Ok(Self {
+            timestamp,
+            expected_tick: tick
+                .parse()
+                .context(format!("Invalid checkpoint tick: {}", tick))?,
+            expected_liquidity: liquidity
+                .parse()
+                .context(format!("Invalid checkpoint liquidity: {}", liquidity))?,
+            tick_tolerance: DEFAULT_CHECKPOINT_TICK_TOLERANCE,
+            liquidity_tolerance_bps: DEFAULT_CHECKPOINT_LIQUIDITY_TOLERANCE_BPS,
+            strict: false,
+        })
+    }
+}
+
+impl ReconstructionCheckpoint {
+    // The pool state at the checkpoint time: every transaction up to it applied, none after it. own_liquidity is the
+    // backtest's own in-range liquidity, which was never in the real pool.
+    pub fn verify(
+        &self,
+        liquidity_array: &LiquidityArray,
+        own_liquidity: u128,
+        replay: &str,
+    ) -> Result<(), SyncError> {
+        let tick_divergence = (liquidity_array.current_tick - self.expected_tick).abs();
+        let liquidity = liquidity_array
+            .active_liquidity
+            .saturating_sub(U256::from(own_liquidity));
+
+        if tick_divergence <= self.tick_tolerance
+            && within_tolerance(
+                liquidity,
+                U256::from(self.expected_liquidity),
+                self.liquidity_tolerance_bps,
+            )
+        {
+            println!(
+                "Checkpoint at {} matched in the {}: tick {} (expected {}), liquidity {} (expected {}).",
+                self.timestamp, replay, liquidity_array.current_tick, self.expected_tick, liquidity, self.expected_liquidity
+            );
+            return Ok(());
+        }
+
+        let message = format!(
+            "{} reached tick {} with liquidity {} at {}, the checkpoint expects tick {} (tolerance {}) and liquidity {} (tolerance {} bps)",
+            replay,
+            liquidity_array.current_tick,
+            liquidity,
+            self.timestamp,
+            self.expected_tick,
+            self.tick_tolerance,
+            self.expected_liquidity,
+            self.liquidity_tolerance_bps
+        );
+
+        if self.strict {
+            return Err(SyncError::CheckpointMismatch(message));
+        }
+
+        eprintln!("WARNING: Reconstruction diverged. The {}.", message);
+        Ok(())
+    }
+}
+
+// Rewinds the transactions older than latest_transaction, back to start_time or through the whole synced history when None.
+// Swaps below min_swap_amount_in are skipped like in sync_forward, so the replay starts from a state it can return to.
+#[allow(clippy::too_many_arguments)]
 pub async fn sync_backwards<T: TransactionRepoTrait>(
     transaction_repo: &T,
     mut liquidity_array: LiquidityArray,
     pool_model: PoolModel,
     latest_transaction: TransactionModelFromDB,
+    start_time: Option<DateTime<Utc>>,
+    min_swap_amount_in: u64,
+    checkpoint: Option<&ReconstructionCheckpoint>,
     batch_size: i64,
 ) -> Result<(LiquidityArray, TransactionModelFromDB), SyncError> {
     // Initialize the cursor with the latest tx_id
@@ -85,7 +476,22 @@ pub async fn sync_backwards<T: TransactionRepoTrait>(
     // Initialize highest_tx_id with the latest transaction ID. The latest txs are the first ones being inserted, so its a low nmr. Then we ascend to the past.
     let mut highest_tx = latest_transaction;
 
-    loop {
+    // Oldest transaction of the window (highest tx_id at or after start_time), nothing past it needs rewinding.
+    let oldest_tx_id = match start_time {
+        Some(start_time) => match transaction_repo
+            .tx_id_at_or_after(&pool_model.address, start_time)
+            .await
+            .map_err(SyncError::DatabaseError)?
+        {
+            Some(tx_id) => Some(tx_id),
+            None => return Ok((liquidity_array, highest_tx)),
+        },
+        None => None,
+    };
+
+    let mut checkpoint_checked = false;
+
+    'rewind: loop {
         let transactions = transaction_repo
             .fetch_transactions(
                 &pool_model.address,
@@ -94,7 +500,7 @@ pub async fn sync_backwards<T: TransactionRepoTrait>(
                 OrderDirection::Ascending,
             )
             .await
-            .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+            .map_err(SyncError::DatabaseError)?;
 
         if transactions.is_empty() {
             break;
@@ -102,12 +508,24 @@ pub async fn sync_backwards<T: TransactionRepoTrait>(
 
         // Process transactions in order (oldest to newest)
         for transaction in transactions.iter() {
+            if oldest_tx_id.is_some_and(|oldest_tx_id| transaction.tx_id > oldest_tx_id) {
+                break 'rewind;
+            }
+
+            // Everything after the checkpoint is rewound and nothing before it yet.
+            if let Some(checkpoint) = checkpoint.filter(|_| !checkpoint_checked) {
+                if transaction.block_time_utc <= checkpoint.timestamp {
+                    checkpoint.verify(&liquidity_array, 0, "backwards sync")?;
+                    checkpoint_checked = true;
+                }
+            }
+
             match transaction.transaction_type.as_str() {
                 "IncreaseLiquidity" | "DecreaseLiquidity" => {
                     let liquidity_data = transaction
                         .data
                         .to_liquidity_data()
-                        .map_err(|e| SyncError::ParseError(e.to_string()))?;
+                        .map_err(SyncError::ParseError)?;
 
                     // Reverse the operation for backwards sync
                     let is_increase = transaction.transaction_type.as_str() != "IncreaseLiquidity";
@@ -115,7 +533,7 @@ pub async fn sync_backwards<T: TransactionRepoTrait>(
                     let (tick_lower, tick_upper, liquidity_amount) = match (
                         liquidity_data.tick_lower,
                         liquidity_data.tick_upper,
-                        liquidity_data.liquidity_amount.parse::<i128>(),
+                        liquidity_data.liquidity_amount.parse::<u128>(),
                     ) {
                         (Some(lower), Some(upper), Ok(amount)) => (lower, upper, amount),
                         _ => {
@@ -138,13 +556,19 @@ pub async fn sync_backwards<T: TransactionRepoTrait>(
                     let swap_data = transaction
                         .data
                         .to_swap_data()
-                        .map_err(|e| SyncError::ParseError(e.to_string()))?;
+                        .map_err(SyncError::ParseError)?;
 
-                    let is_sell = swap_data.token_in == pool_model.token_a_address;
+                    // Dust is left out, but still counts towards highest_tx below.
+                    if swap_data.amount_in >= min_swap_amount_in {
+                        let is_sell = swap_data.token_in == pool_model.token_a_address;
 
-                    // Flip the is_sell for backwards sync and always pass in amount_out since we reversing each tx.
-                    // For instance we have SOL -> POPCAT (aka sell) with amount_in being SOL. So now we are pasing POPCAT -> SOL and flip sell to buy. Both need reversion!
-                    liquidity_array.simulate_swap(U256::from(swap_data.amount_out), !is_sell)?;
+                        liquidity_array.apply_fee_rate_at(transaction.block_time);
+
+                        // Flip the is_sell for backwards sync and always pass in amount_out since we reversing each tx.
+                        // For instance we have SOL -> POPCAT (aka sell) with amount_in being SOL. So now we are pasing POPCAT -> SOL and flip sell to buy. Both need reversion!
+                        liquidity_array
+                            .simulate_swap(U256::from(swap_data.amount_out), !is_sell)?;
+                    }
                 }
                 _ => {}
             }
@@ -155,14 +579,21 @@ pub async fn sync_backwards<T: TransactionRepoTrait>(
             }
         }
 
-        // Update cursor for the next iteration
-        cursor = transactions.last().map(|t| t.tx_id + 1);
+        // Update cursor for the next iteration. The cursor is exclusive, so it's the last tx_id itself.
+        cursor = transactions.last().map(|t| t.tx_id);
 
         if transactions.len() < batch_size as usize {
             break;
         }
     }
 
+    if let Some(checkpoint) = checkpoint.filter(|_| !checkpoint_checked) {
+        println!(
+            "Checkpoint at {} is older than the rewound transactions, the backwards sync didn't reach it.",
+            checkpoint.timestamp
+        );
+    }
+
     Ok((liquidity_array, highest_tx))
 }
 
@@ -236,40 +667,89 @@ pub fn calculate_amount_b_needed_for_liquidity(
     }
 }
 
+// Liquidity a full range position would get from the given capital, after splitting it evenly by value like a rebalance would.
+pub fn calculate_full_range_liquidity(amount_a: U256, amount_b: U256, sqrt_price: U256) -> U256 {
+    // No need to use decimals since when using raw token amounts as below it sorts itself out.
+    let price = sqrt_price_to_price(sqrt_price);
+
+    let total_amount_a = amount_a.as_u128() as f64 + amount_b.as_u128() as f64 / price;
+    let half_amount_a = total_amount_a / 2.0;
+
+    calculate_liquidity(
+        U256::from(half_amount_a as u128),
+        U256::from((half_amount_a * price) as u128),
+        sqrt_price,
+        tick_to_sqrt_price_u256(MIN_TICK),
+        tick_to_sqrt_price_u256(MAX_TICK),
+    )
+}
+
+// Total token A/B locked across the given positions at a given price. Used to sanity check the reconstruction against on-chain vaults.
+pub fn calculate_total_position_amounts(
+    positions: &[LivePositionModel],
+    current_sqrt_price: U256,
+) -> (U256, U256) {
+    positions.iter().fold(
+        (U256::zero(), U256::zero()),
+        |(total_a, total_b), position| {
+            let (amount_a, amount_b) = calculate_amounts(
+                U256::from(position.liquidity),
+                current_sqrt_price,
+                tick_to_sqrt_price_u256(position.tick_lower),
+                tick_to_sqrt_price_u256(position.tick_upper),
+            );
+
+            (total_a + amount_a, total_b + amount_b)
+        },
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::{
+        backtester::test_helpers::{
+            create_test_liquidity_array, create_test_swap_transactions, InMemoryTransactionRepo,
+            TestLiquidityArrayBuilder,
+        },
         models::transactions_model::{SwapData, TransactionData, TransactionModelFromDB},
         utils::core_math::{calculate_liquidity, tick_to_sqrt_price_u256},
     };
     use anyhow::Result;
     use async_trait::async_trait;
-    use chrono::Utc;
+    use chrono::{TimeZone, Utc};
 
-    struct MockTransactionRepo {
-        transactions: Vec<TransactionModelFromDB>,
-    }
+    #[test]
+    fn test_reconstruction_checkpoint_parsing() {
+        let checkpoint: ReconstructionCheckpoint =
+            "2024-08-01T12:00:00Z, -20000, 123456789".parse().unwrap();
+        assert_eq!(
+            checkpoint.timestamp,
+            Utc.with_ymd_and_hms(2024, 8, 1, 12, 0, 0).unwrap()
+        );
+        assert_eq!(checkpoint.expected_tick, -20_000);
+        assert_eq!(checkpoint.expected_liquidity, 123_456_789);
+        assert_eq!(checkpoint.tick_tolerance, DEFAULT_CHECKPOINT_TICK_TOLERANCE);
+        assert!(!checkpoint.strict);
 
-    #[async_trait]
-    impl TransactionRepoTrait for MockTransactionRepo {
-        async fn fetch_transactions(
-            &self,
-            _pool_address: &str,
-            _cursor: Option<i64>,
-            _batch_size: i64,
-            _order: OrderDirection,
-        ) -> Result<Vec<TransactionModelFromDB>> {
-            Ok(self.transactions.clone())
-        }
+        let unix: ReconstructionCheckpoint = "1722513600,-20000,123456789".parse().unwrap();
+        assert_eq!(unix, checkpoint);
+
+        assert!("2024-08-01T12:00:00Z,-20000"
+            .parse::<ReconstructionCheckpoint>()
+            .is_err());
+        assert!("yesterday,-20000,1"
+            .parse::<ReconstructionCheckpoint>()
+            .is_err());
     }
 
     #[tokio::test]
     async fn test_sync_backwards() {
-        let mock_repo_1 = MockTransactionRepo {
+        // The repo cursor is exclusive, everything rewound sits past the latest transaction (tx 1).
+        let mock_repo_1 = InMemoryTransactionRepo {
             transactions: vec![TransactionModelFromDB {
-                tx_id: 1,
-                signature: "sig1".to_string(),
+                tx_id: 2,
+                signature: "sig2".to_string(),
                 pool_address: "pool1".to_string(),
                 block_time: 1000,
                 block_time_utc: Utc::now(),
@@ -283,6 +763,7 @@ mod tests {
                     // amount_out: 718.793826,
                     amount_in: 5301077056,
                     amount_out: 718793826,
+                    source_program: None,
                 }),
             }],
         };
@@ -317,18 +798,6 @@ mod tests {
             tick_to_sqrt_price_u256(upper_tick),
         );
 
-        let mut initial_liquidity_array = LiquidityArray::new(-30000, 30000, 2, 300);
-        // necessary so update liquidity sets liq as active liquidity
-        initial_liquidity_array.current_tick = starting_tick;
-        initial_liquidity_array.current_sqrt_price = starting_sqrt_price_u256;
-
-        initial_liquidity_array.update_liquidity(
-            lower_tick,
-            upper_tick,
-            liquidity_1.as_u128() as i128,
-            true,
-        );
-
         // U need two liquidity positions since we only "cross" initialized ticks, so a wide position is very hard to cross.
         let liquidity_2 = calculate_liquidity(
             U256::from(2 * 10_u128.pow(9)),
@@ -338,18 +807,15 @@ mod tests {
             tick_to_sqrt_price_u256(starting_tick + 10),
         );
 
-        initial_liquidity_array.update_liquidity(
-            starting_tick - 10,
-            starting_tick + 10,
-            liquidity_2.as_u128() as i128,
-            true,
-        );
-
-        let (upper_tick_data, lower_tick_data) = initial_liquidity_array
-            .get_upper_and_lower_ticks(starting_tick, true)
-            .unwrap();
-        initial_liquidity_array.cached_lower_initialized_tick = Some(lower_tick_data.tick);
-        initial_liquidity_array.cached_upper_initialized_tick = Some(upper_tick_data.tick);
+        let initial_liquidity_array = TestLiquidityArrayBuilder::new(-30000, 30000, 2, 300)
+            .price_at_tick(starting_tick)
+            .position(lower_tick, upper_tick, liquidity_1.as_u128())
+            .position(
+                starting_tick - 10,
+                starting_tick + 10,
+                liquidity_2.as_u128(),
+            )
+            .build();
 
         let result_1 = sync_backwards(
             &mock_repo_1,
@@ -372,8 +838,12 @@ mod tests {
                     // amount_out: 718.793826,
                     amount_in: 5301077056,
                     amount_out: 718793826,
+                    source_program: None,
                 }),
             },
+            None,
+            0,
+            None,
             10,
         )
         .await;
@@ -393,7 +863,7 @@ mod tests {
             "The SELL reversed transaction (ie buy) should have increased the sqrtPrice."
         );
 
-        let mock_repo_2 = MockTransactionRepo {
+        let mock_repo_2 = InMemoryTransactionRepo {
             transactions: vec![TransactionModelFromDB {
                 tx_id: 3,
                 signature: "sig3".to_string(),
@@ -411,6 +881,7 @@ mod tests {
                     // amount_out: 4.0,
                     amount_in: 4 * 135904 * 10_u64.pow(6) / 1000, // the 1000 to normalize the price to 135.904
                     amount_out: 4 * 10_u64.pow(9),
+                    source_program: None,
                 }),
             }],
         };
@@ -435,9 +906,13 @@ mod tests {
                     // amount_in: 1.0 * 135.904,
                     // amount_out: 1.0,
                     amount_in: 135904 * 10_u64.pow(6) / 1000, // the 1000 to normalize the price to 135.904
-                    amount_out: 1 * 10_u64.pow(9),
+                    amount_out: 10_u64.pow(9),
+                    source_program: None,
                 }),
             },
+            None,
+            0,
+            None,
             10,
         )
         .await;
@@ -454,20 +929,339 @@ mod tests {
         );
 
         // SHOULD END UP QUITE CLOSE TO EACH OTHER. OBV BECAUSE OF PRICE DIFF IT DOESNT.
-        let sqrt_price_diff =
-            if final_liquidity_array_2.current_sqrt_price > starting_sqrt_price_u256 {
-                final_liquidity_array_2.current_sqrt_price - starting_sqrt_price_u256
-            } else {
-                starting_sqrt_price_u256 - final_liquidity_array_2.current_sqrt_price
-            };
+        assert!(
+            within_tolerance(
+                final_liquidity_array_2.current_sqrt_price,
+                starting_sqrt_price_u256,
+                10
+            ),
+            "Final sqrt_price should be within 0.1% of starting sqrt_price. Actual: {}, starting: {}",
+            final_liquidity_array_2.current_sqrt_price,
+            starting_sqrt_price_u256
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_backwards_stops_at_start_time() {
+        // tx_ids descend with time like synced data, tx 1 is the present swap the rewind starts from.
+        let mut transactions = create_test_swap_transactions(6, 1_000_000, "TokenA", "TokenB");
+        // Newest first, transactions[i] is tx i + 1.
+        transactions.reverse();
+        for transaction in transactions.iter_mut() {
+            transaction.block_time = 1_700_000_000 - transaction.tx_id * 60;
+            transaction.block_time_utc =
+                DateTime::from_timestamp(transaction.block_time, 0).unwrap();
+        }
+        let latest_transaction = transactions[0].clone();
+        let start_time = transactions[3].block_time_utc;
+
+        let pool_model = PoolModel {
+            address: "test_pool".to_string(),
+            name: "TokenA/TokenB".to_string(),
+            token_a_name: "TokenA".to_string(),
+            token_b_name: "TokenB".to_string(),
+            token_a_address: "TokenA".to_string(),
+            token_b_address: "TokenB".to_string(),
+            token_a_vault: "TokenAVault".to_string(),
+            token_b_vault: "TokenBVault".to_string(),
+            token_a_decimals: 6,
+            token_b_decimals: 6,
+            tick_spacing: 10,
+            fee_rate: 500,
+            last_updated_at: Utc::now(),
+        };
+
+        let rewind = |start_time: Option<DateTime<Utc>>,
+                      transactions: Vec<TransactionModelFromDB>| {
+            let pool_model = pool_model.clone();
+            let latest_transaction = latest_transaction.clone();
+            async move {
+                sync_backwards(
+                    &InMemoryTransactionRepo { transactions },
+                    create_test_liquidity_array(0),
+                    pool_model,
+                    latest_transaction,
+                    start_time,
+                    0,
+                    None,
+                    2,
+                )
+                .await
+                .unwrap()
+            }
+        };
+
+        let (_, full_start_tx) = rewind(None, transactions.clone()).await;
+        assert_eq!(full_start_tx.tx_id, 6);
+
+        // Stops on the oldest transaction at or after start_time, same as if nothing older was synced.
+        let (bounded_arr, bounded_start_tx) = rewind(Some(start_time), transactions.clone()).await;
+        let (window_arr, _) = rewind(None, transactions[..4].to_vec()).await;
+        assert_eq!(bounded_start_tx.tx_id, 4);
+        assert_eq!(
+            bounded_arr.current_sqrt_price,
+            window_arr.current_sqrt_price
+        );
+        assert_eq!(bounded_arr.active_liquidity, window_arr.active_liquidity);
+
+        // Nothing synced after start_time, nothing to rewind.
+        let (_, untouched_start_tx) = rewind(
+            Some(latest_transaction.block_time_utc + chrono::Duration::hours(1)),
+            transactions,
+        )
+        .await;
+        assert_eq!(untouched_start_tx.tx_id, 1);
+    }
+
+    #[test]
+    fn test_live_pool_price_overrides_the_swap_reconstruction() {
+        let pool_model = PoolModel {
+            address: "pool1".to_string(),
+            name: "TokenA/TokenB".to_string(),
+            token_a_name: "TokenA".to_string(),
+            token_b_name: "TokenB".to_string(),
+            token_a_address: "TokenAAddress".to_string(),
+            token_b_address: "TokenBAddress".to_string(),
+            token_a_vault: "TokenAVault".to_string(),
+            token_b_vault: "TokenBVault".to_string(),
+            token_a_decimals: 9,
+            token_b_decimals: 6,
+            tick_spacing: 1,
+            fee_rate: 300,
+            last_updated_at: Utc::now(),
+        };
+        // Same swap as in test_sync_backwards, its average price reconstructs to tick -19982.
+        let latest_swap = TransactionModelFromDB {
+            tx_id: 1,
+            signature: "sig1".to_string(),
+            pool_address: "pool1".to_string(),
+            block_time: 1000,
+            block_time_utc: Utc::now(),
+            transaction_type: "Swap".to_string(),
+            ready_for_backtesting: true,
+            data: TransactionData::Swap(SwapData {
+                token_in: "TokenAAddress".to_string(),
+                token_out: "TokenBAddress".to_string(),
+                amount_in: 5301077056,
+                amount_out: 718793826,
+                source_program: None,
+            }),
+        };
+        let positions = vec![LivePositionModel {
+            address: "position1".to_string(),
+            liquidity: 1_000_000_000,
+            tick_lower: -20_000,
+            tick_upper: -17_000,
+            created_at: Utc::now(),
+        }];
+        // The pool left a few ticks past the swap's average price.
+        let live_price = PoolPrice {
+            sqrt_price: tick_to_sqrt_price_u256(-19_985).as_u128() + 1_000,
+            tick: -19_985,
+        };
 
-        let percentage_diff =
-            (sqrt_price_diff.as_u128() as f64 / starting_sqrt_price_u256.as_u128() as f64) * 100.0;
+        let build = |live_price| {
+            create_full_liquidity_range(
+                1,
+                positions.clone(),
+                pool_model.clone(),
+                latest_swap.clone(),
+                StartPrice::LatestSwap,
+                live_price,
+                300,
+                TickBounds::Derived { padding: 1_000 },
+            )
+            .unwrap()
+        };
+
+        let reconstructed = build(None);
+        let live = build(Some(live_price));
+
+        assert_eq!(reconstructed.current_tick, -19_982);
+        assert_eq!(live.current_tick, live_price.tick);
+        assert_eq!(live.current_sqrt_price, U256::from(live_price.sqrt_price));
+        assert!((live.current_tick - reconstructed.current_tick).abs() <= 5);
+        assert_eq!(live.active_liquidity, reconstructed.active_liquidity);
+    }
+
+    #[test]
+    fn test_median_start_price_ignores_an_outlier_swap() {
+        let swap = |tx_id: i64, amount_in: u64, amount_out: u64| TransactionModelFromDB {
+            tx_id,
+            signature: format!("sig{}", tx_id),
+            pool_address: "pool1".to_string(),
+            block_time: 1000 - tx_id,
+            block_time_utc: Utc::now(),
+            transaction_type: "Swap".to_string(),
+            ready_for_backtesting: true,
+            data: TransactionData::Swap(SwapData {
+                token_in: "TokenAAddress".to_string(),
+                token_out: "TokenBAddress".to_string(),
+                amount_in,
+                amount_out,
+                source_program: None,
+            }),
+        };
+
+        // The latest swap filled 30% below the rest (a thin moment or a bad route), the others are around tick -19982.
+        let swaps = vec![
+            swap(1, 5_301_077_056, 503_155_678),
+            swap(2, 5_301_077_056, 718_793_826),
+            swap(3, 5_301_077_056, 718_900_000),
+            swap(4, 5_301_077_056, 718_700_000),
+            swap(5, 5_301_077_056, 718_800_000),
+        ];
+
+        let latest_only = StartPrice::LatestSwap
+            .tick(&swaps[0], "TokenAAddress")
+            .unwrap();
+        let median = StartPrice::over(&swaps, StartPriceMethod::Median)
+            .tick(&swaps[0], "TokenAAddress")
+            .unwrap();
+        let volume_weighted = StartPrice::over(&swaps, StartPriceMethod::VolumeWeighted)
+            .tick(&swaps[0], "TokenAAddress")
+            .unwrap();
+
+        assert!((latest_only + 19_982).abs() > 3_000);
+        assert!((median + 19_982).abs() <= 2);
+        // The outlier still pulls the mean, by its share of the volume.
+        assert!((volume_weighted + 19_982).abs() < (latest_only + 19_982).abs() / 2);
+
+        // A single swap is the latest swap alone.
+        assert!(matches!(
+            StartPrice::over(&swaps[..1], StartPriceMethod::Median),
+            StartPrice::LatestSwap
+        ));
+        assert_eq!(
+            StartPriceMethod::from_str("volume_weighted").unwrap(),
+            StartPriceMethod::VolumeWeighted
+        );
+        assert!(StartPriceMethod::from_str("mean").is_err());
+    }
 
+    #[test]
+    fn test_fee_tier_warning() {
+        assert_eq!(fee_tier_warning(AMMPlatforms::Orca, 64, 3000), None);
+        assert_eq!(fee_tier_warning(AMMPlatforms::Orca, 8, 500), None);
+
+        // 0.30% on the 0.05% spacing, and a spacing Orca doesn't have.
+        let mismatched = fee_tier_warning(AMMPlatforms::Orca, 8, 3000).unwrap();
         assert!(
-            percentage_diff < 0.1,
-            "Final sqrt_price should be within 0.1% of starting sqrt_price. Actual difference: {}%",
-            percentage_diff
+            mismatched.contains("tick spacing 8 (500)"),
+            "{}",
+            mismatched
+        );
+        assert!(fee_tier_warning(AMMPlatforms::Orca, 10, 3000)
+            .unwrap()
+            .contains("isn't a known Orca fee tier"));
+
+        // No table, nothing to check against.
+        assert_eq!(fee_tier_warning(AMMPlatforms::Raydium, 10, 3000), None);
+    }
+
+    #[test]
+    fn test_tick_bounds_resolve() {
+        let positions = vec![
+            LivePositionModel {
+                address: "pos1".to_string(),
+                liquidity: 1_000,
+                tick_lower: -25_000,
+                tick_upper: -18_000,
+                created_at: Utc::now(),
+            },
+            LivePositionModel {
+                address: "pos2".to_string(),
+                liquidity: 1_000,
+                tick_lower: -20_000,
+                tick_upper: -15_000,
+                created_at: Utc::now(),
+            },
+        ];
+
+        let derived = TickBounds::Derived { padding: 1_000 }.resolve(&positions, -19_000);
+        assert_eq!(derived, (-26_000, -14_000));
+
+        // Padding never pushes the range past the whirlpool limits.
+        let capped = TickBounds::Derived { padding: 500_000 }.resolve(&positions, -19_000);
+        assert_eq!(capped, (MIN_TICK, MAX_TICK));
+
+        let fixed = TickBounds::Fixed {
+            min_tick: -30_000,
+            max_tick: 30_000,
+        }
+        .resolve(&positions, -19_000);
+        assert_eq!(fixed, (-30_000, 30_000));
+    }
+
+    #[test]
+    fn test_check_liquidity_health() {
+        let healthy = TestLiquidityArrayBuilder::new(-1000, 1000, 10, 500)
+            .price_at_tick(5)
+            .position(-100, 100, 1_000_000)
+            .build();
+        assert!(check_liquidity_health(&healthy).is_ok());
+
+        // The only position sits above the price, nothing is active at the start tick.
+        let no_liquidity = TestLiquidityArrayBuilder::new(-1000, 1000, 10, 500)
+            .price_at_tick(5)
+            .position(100, 200, 1_000_000)
+            .cached_ticks(-1000, 100)
+            .build();
+        let error = check_liquidity_health(&no_liquidity).unwrap_err();
+        assert!(error.to_string().contains("No active liquidity"));
+
+        let mut stale_caches = healthy.clone();
+        stale_caches.cached_upper_initialized_tick = Some(-10);
+        let error = check_liquidity_health(&stale_caches).unwrap_err();
+        assert!(error.to_string().contains("don't bracket"));
+
+        let mut no_caches = healthy;
+        no_caches.cached_lower_initialized_tick = None;
+        assert!(check_liquidity_health(&no_caches).is_err());
+    }
+
+    #[test]
+    fn test_check_decimals_match() {
+        assert!(check_decimals_match((9, 6), (9, 6)).is_ok());
+
+        // Pool row updated to 8 decimals for token A after the sync.
+        let error = check_decimals_match((8, 6), (9, 6)).unwrap_err();
+        assert!(error.to_string().contains("(A 8, B 6)"));
+        assert!(check_decimals_match((9, 6), (6, 9)).is_err());
+    }
+
+    #[test]
+    fn test_check_start_price_drift() {
+        assert!(check_start_price_drift(151.0, 150.0, 5.0).is_ok());
+        assert!(check_start_price_drift(145.0, 150.0, 5.0).is_ok());
+
+        // Reconstruction landed on the inverted price.
+        let error = check_start_price_drift(1.0 / 150.0, 150.0, 5.0).unwrap_err();
+        assert!(error.to_string().contains("off the price source's 150"));
+        assert!(check_start_price_drift(160.0, 150.0, 5.0).is_err());
+    }
+
+    #[test]
+    fn test_calculate_full_range_liquidity_uses_capital_evenly() {
+        let sqrt_price = tick_to_sqrt_price_u256(-19_969);
+        let price = sqrt_price_to_price(sqrt_price);
+
+        // All capital in token A, the full range position should still get ~half of it in each token.
+        let amount_a = U256::from(10_u128.pow(12));
+        let liquidity = calculate_full_range_liquidity(amount_a, U256::zero(), sqrt_price);
+
+        let (used_a, used_b) = calculate_amounts(
+            liquidity,
+            sqrt_price,
+            tick_to_sqrt_price_u256(MIN_TICK),
+            tick_to_sqrt_price_u256(MAX_TICK),
         );
+
+        let used_value_in_a = used_a.as_u128() as f64 + used_b.as_u128() as f64 / price;
+        let relative_error =
+            (used_value_in_a - amount_a.as_u128() as f64).abs() / amount_a.as_u128() as f64;
+
+        assert!(used_a <= amount_a);
+        assert!(relative_error < 0.01, "relative error: {}", relative_error);
     }
 }
@@ -1,27 +1,49 @@
-use anyhow::Result;
+use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    time::Duration,
+};
 
 use crate::{
     backtester::backtest_utils::{
         calculate_amount_a_needed_for_liquidity, calculate_amount_b_needed_for_liquidity,
-        calculate_rebalance_ratio,
+        calculate_rebalance_ratio, ReconstructionCheckpoint,
     },
     models::transactions_model::{SwapData, TransactionModelFromDB},
     repositories::transactions_repo::{OrderDirection, TransactionRepoTrait},
     utils::{
-        core_math::{calculate_amounts, calculate_liquidity, tick_to_sqrt_price_u256, Q64, U256},
-        data_logger::DataLogger,
-        error::{BacktestError, SyncError},
+        core_math::{
+            amounts_for_target_liquidity, calculate_amounts, calculate_liquidity, price_to_tick,
+            sqrt_price_to_price, tick_to_sqrt_price_u256, U256,
+        },
+        data_logger::{DataLogger, PositionRecord, TimelinePosition},
+        error::{BacktestError, LiquidityArrayError, SyncError},
     },
 };
 
 use super::liquidity_array::{LiquidityArray, OwnersPosition};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct StartInfo {
     pub token_a_amount: U256,
     pub token_b_amount: U256,
+    // Pool state when the backtest started, used for comparisons against hypothetical positions.
+    pub block_time: i64,
+    pub sqrt_price: U256,
+    pub fee_growth_global_a: U256,
+    pub fee_growth_global_b: U256,
+    // Value lost on the pre-swap of the positions opened by initialize_strategy, in raw units of the token received.
+    // Measured against the mid price before the swap, so it covers both price impact and SLIPPAGE_FOR_SWAP.
+    pub entry_cost_a: u128,
+    pub entry_cost_b: u128,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct SwappingData {
     pub current_swap_nmr: u128,
     pub current_token_a_volume: u128,
@@ -29,9 +51,152 @@ pub struct SwappingData {
     pub swap_nmr_in_position: u128,
     pub token_a_volume_in_position: u128,
     pub token_b_volume_in_position: u128,
+    // Fees split by when they were collected. During run = strategy driven closes (rebalances), at end = on_window_end closes.
+    pub fees_a_collected_during_run: u128,
+    pub fees_b_collected_during_run: u128,
+    pub fees_a_collected_at_end: u128,
+    pub fees_b_collected_at_end: u128,
+    // The during run fees as if each collection was converted to a 50/50 basket at the pool price of the moment (raw units).
+    pub fee_basket_a: f64,
+    pub fee_basket_b: f64,
+    // Fees pending in the positions closed at the end while count_fees_at_end is off. Left out of the wallet.
+    pub fees_a_uncollected_at_end: u128,
+    pub fees_b_uncollected_at_end: u128,
+    // Positions opened during the run (not by initialize_strategy).
+    pub rebalance_count: u64,
+    // Same as entry_cost in StartInfo, for the swaps of positions opened during the run (rebalances).
+    pub rebalance_cost_a: u128,
+    pub rebalance_cost_b: u128,
+    // Part of the rebalance cost above that comes from the sandwich penalty, raw units of the token received.
+    pub sandwich_loss_a: u128,
+    pub sandwich_loss_b: u128,
+    // Wallet plus open positions valued in raw token B at the pool price, tracked after every transaction.
+    // Uncollected fees are left out.
+    pub peak_value_b: f64,
+    pub max_drawdown_pct: f64,
+    // Share of the active liquidity held by the in-range positions, weighted by the block time it was held for.
+    // Only time spent in range counts, so a bad range doesn't drag the share down.
+    pub liquidity_share_weighted_sum: f64,
+    pub liquidity_share_seconds: i64,
+    // Wallet (idle) and total value in raw token B at the pool price, weighted by the block time between timeline samples.
+    // Only tracked while sampling is on.
+    pub idle_value_b_weighted_sum: f64,
+    pub total_value_b_weighted_sum: f64,
+    pub idle_value_seconds: i64,
+    // Swaps left out of the replay by the source program filter.
+    pub swaps_filtered_by_source: u128,
+    // Swaps below min_swap_amount_in, left out of the replay.
+    pub dust_swaps_skipped: u128,
+}
+
+impl SwappingData {
+    pub fn average_liquidity_share(&self) -> f64 {
+        if self.liquidity_share_seconds == 0 {
+            return 0.0;
+        }
+
+        self.liquidity_share_weighted_sum / self.liquidity_share_seconds as f64
+    }
+
+    // (idle, total) time weighted value in raw token B, None without samples.
+    pub fn average_idle_and_total_value_b(&self) -> Option<(f64, f64)> {
+        if self.idle_value_seconds == 0 {
+            return None;
+        }
+
+        let seconds = self.idle_value_seconds as f64;
+        Some((
+            self.idle_value_b_weighted_sum / seconds,
+            self.total_value_b_weighted_sum / seconds,
+        ))
+    }
+}
+
+// Picks the swaps sync_forward replays by the program that sent them (SwapData::source_program).
+// Filtered swaps are dropped entirely: no volume, no fees and no price move. Liquidity transactions always go through.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceProgramFilter {
+    // Swaps without a known source program are dropped too.
+    Only(Vec<String>),
+    Exclude(Vec<String>),
+}
+
+impl SourceProgramFilter {
+    pub fn allows(&self, source_program: Option<&str>) -> bool {
+        let listed = |programs: &[String]| {
+            source_program.is_some_and(|source| programs.iter().any(|program| program == source))
+        };
+
+        match self {
+            SourceProgramFilter::Only(programs) => listed(programs),
+            SourceProgramFilter::Exclude(programs) => !listed(programs),
+        }
+    }
+}
+
+// Pessimistic execution of rebalance swaps. Swaps selling more than min_swap_share of the pool's virtual reserve of the
+// token sold (from active_liquidity at the current price) are assumed to get sandwiched and lose penalty_bps of their
+// output on top of SLIPPAGE_FOR_SWAP. Entry swaps are left alone.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SandwichPenalty {
+    pub penalty_bps: u32,
+    pub min_swap_share: f64,
+}
+
+impl SandwichPenalty {
+    pub fn applies(&self, amount_in: U256, reserve_in: f64) -> bool {
+        reserve_in > 0.0 && amount_in.as_u128() as f64 > reserve_in * self.min_swap_share
+    }
+
+    pub fn penalty(&self, amount_out: U256) -> U256 {
+        amount_out * U256::from(self.penalty_bps.min(10_000)) / U256::from(10_000)
+    }
+}
+
+// Tracks how far the simulated tick drifts from the price implied by each real swap (amount_out/amount_in).
+// Growing divergence means the reconstructed liquidity doesn't match what was really in the pool.
+#[derive(Debug, Clone, Default)]
+pub struct ReplayVerification {
+    pub tick_tolerance: i32,
+    pub swaps_checked: u64,
+    pub swaps_diverged: u64,
+    pub max_divergence: i32,
+    pub total_divergence: i64,
+}
+
+impl ReplayVerification {
+    pub fn new(tick_tolerance: i32) -> Self {
+        Self {
+            tick_tolerance,
+            ..Default::default()
+        }
+    }
+
+    pub fn average_divergence(&self) -> f64 {
+        if self.swaps_checked == 0 {
+            return 0.0;
+        }
+
+        self.total_divergence as f64 / self.swaps_checked as f64
+    }
+}
+
+// How much of the tokens earmarked for a position (after the pre-swap) actually went into it.
+// calculate_liquidity is bound by one side, so the other one is partly held back in the wallet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionFunding {
+    pub position_id: String,
+    pub pct_a_deployed: f64,
+    pub pct_b_deployed: f64,
+}
+
+impl PositionFunding {
+    pub fn least_deployed_pct(&self) -> f64 {
+        self.pct_a_deployed.min(self.pct_b_deployed)
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Wallet {
     pub token_a_addr: String,
     pub token_b_addr: String,
@@ -45,7 +210,9 @@ pub struct Wallet {
     pub amount_b_fees_collected: U256,
 }
 
-// The types of actions you can take as a user. For now, creating/closing uses your full wallet amounts. Later support can be added for custom amounts, also updating liquidity (increase/decrease) and so on.
+// The types of actions you can take as a user. CreatePosition uses your full wallet amounts, CreatePositionWithShare only a share of it (0.0 - 1.0) so several positions can be open at once.
+// CreatePositionWithTargetLiquidity deploys just enough for the given liquidity (capped by what the wallet holds), the rest stays in the wallet.
+// Later support can be added for updating liquidity (increase/decrease) and so on.
 pub enum Action {
     ClosePosition {
         position_id: String,
@@ -55,6 +222,18 @@ pub enum Action {
         lower_tick: i32,
         upper_tick: i32,
     },
+    CreatePositionWithShare {
+        position_id: String,
+        lower_tick: i32,
+        upper_tick: i32,
+        wallet_share: f64,
+    },
+    CreatePositionWithTargetLiquidity {
+        position_id: String,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity: u128,
+    },
 }
 
 pub struct Backtest {
@@ -64,22 +243,129 @@ pub struct Backtest {
     pub start_info: StartInfo,
     pub data_logger: DataLogger,
     pub data: SwappingData,
+    // Set while the on_window_end actions are executed, so closes can be tagged as end of window.
+    pub is_finalizing: bool,
+    // Whether the fees of the positions closed at the end count as collected. Off treats them as uncaptured (realized only PnL).
+    pub count_fees_at_end: bool,
+    // Set while the initialize_strategy actions are executed, so the entry swap cost can be told apart from rebalances.
+    pub is_initializing: bool,
+    // Opt-in, None skips the check entirely.
+    pub replay_verification: Option<ReplayVerification>,
+    pub position_fundings: Vec<PositionFunding>,
+    // Effective entry price of every open position (raw B per A), logged again next to the exit price when it closes.
+    pub position_entry_prices: HashMap<String, f64>,
+    // Seconds of block time between samples taken during sync_forward. None disables sampling.
+    pub sample_interval_seconds: Option<i64>,
+    next_sample_time: Option<i64>,
+    // Block time and liquidity share after the last replayed transaction, the share holds until the next one.
+    last_liquidity_share: Option<(i64, f64)>,
+    // Block time, idle and total value in raw token B at the last timeline sample, held until the next one.
+    last_idle_value: Option<(i64, f64, f64)>,
+    // Safety valve for runaway strategies. Once the actions returned by update would go past it, sync_forward stops and finalizes.
+    pub max_actions: Option<usize>,
+    // Actions returned by update and executed so far (initialize/finalize aren't counted).
+    pub strategy_actions_executed: usize,
+    pub max_actions_hit: bool,
+    // Pause after every replayed swap, to watch a run (or a suspicious segment of it) step by step. None runs at full speed.
+    pub throttle: Option<Duration>,
+    // None replays every swap.
+    pub source_program_filter: Option<SourceProgramFilter>,
+    // Swaps paying in less than this don't move the price or count as volume. 0 replays all of them.
+    pub min_swap_amount_in: u64,
+    // Checked once the replay passes its timestamp, None skips it.
+    pub reconstruction_checkpoint: Option<ReconstructionCheckpoint>,
+    // None executes every rebalance swap at the modeled slippage only.
+    pub sandwich_penalty: Option<SandwichPenalty>,
+    // Where sync_forward saves the state right before the window end closes the positions, see save_state.
+    pub save_state_to: Option<String>,
+    // Loaded with load_state, the positions are already open so sync_forward doesn't initialize the strategy.
+    is_resumed: bool,
+}
+
+// Everything a backtest needs to carry on over a later window with the same positions and cumulative counters
+// (walk-forward testing). The strategy comes from the config again, the replay settings too.
+#[derive(Serialize, Deserialize)]
+pub struct BacktestState {
+    pub wallet: Wallet,
+    pub start_info: StartInfo,
+    pub data: SwappingData,
+    pub liquidity_arr: LiquidityArray,
+    pub position_fundings: Vec<PositionFunding>,
+    pub position_entry_prices: HashMap<String, f64>,
+    pub strategy_actions_executed: usize,
+    pub last_liquidity_share: Option<(i64, f64)>,
+    pub last_idle_value: Option<(i64, f64, f64)>,
+    // The data logger's position records.
+    pub position_records: Vec<PositionRecord>,
+    // Where the replay stopped, resume_forward continues right after it.
+    pub replay_position: ReplayPosition,
+}
+
+// The block of the last transaction a replay applied and the transactions of that block it got to. A block can be split
+// between the saved run and the resumed one, tx_ids alone don't tell which side a transaction is on once the pool was
+// synced again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayPosition {
+    pub block_time: i64,
+    pub tx_ids: Vec<i64>,
+}
+
+impl ReplayPosition {
+    fn advance(&mut self, transaction: &TransactionModelFromDB) {
+        if transaction.block_time != self.block_time {
+            self.block_time = transaction.block_time;
+            self.tx_ids.clear();
+        }
+
+        self.tx_ids.push(transaction.tx_id);
+    }
+
+    // Whether the replay already got to transaction. Everything before the block was.
+    pub fn applied(&self, transaction: &TransactionModelFromDB) -> bool {
+        transaction.block_time < self.block_time
+            || (transaction.block_time == self.block_time
+                && self.tx_ids.contains(&transaction.tx_id))
+    }
+}
+
+// Where a loaded backtest picks up the replay.
+pub struct ResumePoint {
+    // First transaction of the original window, the report measures from there.
+    pub window_start_tx: TransactionModelFromDB,
+    // Passed to resume_forward, the replay goes on with the transactions after it.
+    pub replay_position: ReplayPosition,
 }
 
 pub trait Strategy {
     fn initialize_strategy(&self) -> Vec<Action>;
 
+    // liquidity_array is the pool right after the transaction, recent_fee_growth_per_second on it tells how much the
+    // pool has been earning lately.
     fn update(
         &mut self,
         liquidity_array: &LiquidityArray,
         transaction: TransactionModelFromDB,
     ) -> Vec<Action>;
 
-    fn finalize_strategy(&self) -> Vec<Action>;
+    // Runs once the window ends, only meant to close whatever is still open. Those closes are logged as forced, so they
+    // can be told apart from the ones the strategy decided on in update.
+    fn on_window_end(&self, liquidity_array: &LiquidityArray) -> Vec<Action>;
+
+    // Bookkeeping after the run, positions are already closed by then.
+    fn finalize_strategy(&mut self) {}
+
+    // Runs instead of initialize_strategy when a saved backtest is loaded, its positions are already in liquidity_array.
+    // Strategies that track their open range pick it back up from there.
+    fn resume_strategy(&mut self, _liquidity_array: &LiquidityArray) {}
 
     fn get_ticks(&self) -> (i32, i32);
 }
 
+fn block_time_utc(block_time: i64) -> Result<DateTime<Utc>, SyncError> {
+    DateTime::from_timestamp(block_time, 0)
+        .ok_or_else(|| SyncError::CalculationError(format!("Invalid block time {}", block_time)))
+}
+
 // both divided by 10^6
 const SLIPPAGE_FOR_SWAP: i32 = 10000;
 
@@ -95,6 +381,12 @@ impl Backtest {
             start_info: StartInfo {
                 token_a_amount: amount_a_start,
                 token_b_amount: amount_b_start,
+                block_time: liquidity_arr.current_block_time,
+                sqrt_price: liquidity_arr.current_sqrt_price,
+                fee_growth_global_a: liquidity_arr.fee_growth_global_a,
+                fee_growth_global_b: liquidity_arr.fee_growth_global_b,
+                entry_cost_a: 0,
+                entry_cost_b: 0,
             },
             liquidity_arr,
             wallet: wallet_state,
@@ -107,10 +399,175 @@ impl Backtest {
                 swap_nmr_in_position: 0,
                 token_a_volume_in_position: 0,
                 token_b_volume_in_position: 0,
+                fees_a_collected_during_run: 0,
+                fees_b_collected_during_run: 0,
+                fees_a_collected_at_end: 0,
+                fees_b_collected_at_end: 0,
+                fee_basket_a: 0.0,
+                fee_basket_b: 0.0,
+                fees_a_uncollected_at_end: 0,
+                fees_b_uncollected_at_end: 0,
+                rebalance_count: 0,
+                rebalance_cost_a: 0,
+                rebalance_cost_b: 0,
+                sandwich_loss_a: 0,
+                sandwich_loss_b: 0,
+                peak_value_b: 0.0,
+                max_drawdown_pct: 0.0,
+                liquidity_share_weighted_sum: 0.0,
+                liquidity_share_seconds: 0,
+                idle_value_b_weighted_sum: 0.0,
+                total_value_b_weighted_sum: 0.0,
+                idle_value_seconds: 0,
+                swaps_filtered_by_source: 0,
+                dust_swaps_skipped: 0,
             },
+            is_finalizing: false,
+            count_fees_at_end: true,
+            is_initializing: false,
+            replay_verification: None,
+            position_fundings: Vec::new(),
+            position_entry_prices: HashMap::new(),
+            sample_interval_seconds: None,
+            next_sample_time: None,
+            last_liquidity_share: None,
+            last_idle_value: None,
+            max_actions: None,
+            strategy_actions_executed: 0,
+            max_actions_hit: false,
+            throttle: None,
+            source_program_filter: None,
+            min_swap_amount_in: 0,
+            reconstruction_checkpoint: None,
+            sandwich_penalty: None,
+            save_state_to: None,
+            is_resumed: false,
         }
     }
 
+    // JSON, so it can be inspected. Meant to be taken between transactions, with the positions still open. replay_position
+    // is where the replay got to with this state.
+    pub fn save_state(&self, path: &str, replay_position: &ReplayPosition) -> Result<()> {
+        let state = BacktestState {
+            wallet: self.wallet.clone(),
+            start_info: self.start_info.clone(),
+            data: self.data.clone(),
+            liquidity_arr: self.liquidity_arr.clone(),
+            position_fundings: self.position_fundings.clone(),
+            position_entry_prices: self.position_entry_prices.clone(),
+            strategy_actions_executed: self.strategy_actions_executed,
+            last_liquidity_share: self.last_liquidity_share,
+            last_idle_value: self.last_idle_value,
+            position_records: self.data_logger.position_records().to_vec(),
+            replay_position: replay_position.clone(),
+        };
+
+        let file = File::create(path).context(format!("Failed to create state file {}", path))?;
+        serde_json::to_writer(BufWriter::new(file), &state)
+            .context(format!("Failed to write state file {}", path))?;
+
+        Ok(())
+    }
+
+    // Rebuilds a backtest saved with save_state. The strategy is built fresh and picks its open ranges back up from the
+    // positions (resume_strategy). tx_repo resolves the original window start.
+    pub async fn load_state<T: TransactionRepoTrait>(
+        path: &str,
+        mut strategy: Box<dyn Strategy>,
+        tx_repo: &T,
+        pool_address: &str,
+    ) -> Result<(Self, ResumePoint)> {
+        let file = File::open(path).context(format!("Failed to open state file {}", path))?;
+        let state: BacktestState = serde_json::from_reader(BufReader::new(file))
+            .context(format!("Failed to parse state file {}", path))?;
+
+        let window_start = DateTime::from_timestamp(state.start_info.block_time, 0)
+            .ok_or_else(|| anyhow!("Invalid window start in the state file"))?;
+        let window_start_tx = tx_repo
+            .fetch_transactions_in_time_range(
+                pool_address,
+                window_start,
+                window_start + chrono::Duration::seconds(1),
+                OrderDirection::Ascending,
+            )
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("The saved window start ({}) isn't synced", window_start))?;
+
+        strategy.resume_strategy(&state.liquidity_arr);
+
+        let mut backtest = Backtest::new(
+            state.start_info.token_a_amount,
+            state.start_info.token_b_amount,
+            state.liquidity_arr,
+            state.wallet,
+            strategy,
+        );
+        backtest.start_info = state.start_info;
+        backtest.data = state.data;
+        backtest.position_fundings = state.position_fundings;
+        backtest.position_entry_prices = state.position_entry_prices;
+        backtest.strategy_actions_executed = state.strategy_actions_executed;
+        backtest.last_liquidity_share = state.last_liquidity_share;
+        backtest.last_idle_value = state.last_idle_value;
+        backtest.data_logger = DataLogger::resumed(state.position_records);
+        backtest.is_resumed = true;
+
+        Ok((
+            backtest,
+            ResumePoint {
+                window_start_tx,
+                replay_position: state.replay_position,
+            },
+        ))
+    }
+
+    // Positions sorted by how much of their least used token was deployed, worst first.
+    pub fn worst_funded_positions(&self, limit: usize) -> Vec<&PositionFunding> {
+        let mut fundings: Vec<&PositionFunding> = self.position_fundings.iter().collect();
+        fundings.sort_by(|a, b| a.least_deployed_pct().total_cmp(&b.least_deployed_pct()));
+        fundings.truncate(limit);
+
+        fundings
+    }
+
+    // Opens the strategy's initial positions at the current price. Called by sync_forward, public to inspect the starting
+    // positions without a replay.
+    pub fn initialize(&mut self) -> Result<(), BacktestError> {
+        let actions = self.strategy.initialize_strategy();
+
+        self.is_initializing = true;
+        let init_result = self.execute_actions(actions);
+        self.is_initializing = false;
+
+        init_result?;
+
+        self.track_drawdown();
+
+        Ok(())
+    }
+
+    // What an open position would hold if the price moved to tick, straight from the range math without replaying swaps.
+    pub fn position_value_at_tick(
+        &self,
+        position_id: &str,
+        tick: i32,
+    ) -> Result<(U256, U256), BacktestError> {
+        let position = self
+            .liquidity_arr
+            .positions
+            .get(position_id)
+            .ok_or_else(|| LiquidityArrayError::PositionNotFound(position_id.to_string()))?;
+
+        Ok(calculate_amounts(
+            U256::from(position.liquidity),
+            tick_to_sqrt_price_u256(tick),
+            tick_to_sqrt_price_u256(position.lower_tick),
+            tick_to_sqrt_price_u256(position.upper_tick),
+        ))
+    }
+
     pub async fn sync_forward<T: TransactionRepoTrait>(
         &mut self,
         transaction_repo: &T,
@@ -121,86 +578,44 @@ impl Backtest {
     ) -> Result<(), SyncError> {
         // Initialize the cursor with the start_tx_id
         let mut cursor = Some(start_tx_id);
+        // Where a saved state picks the replay back up, nothing replayed yet is the start itself.
+        let mut replay_position = ReplayPosition {
+            block_time: self.liquidity_arr.current_block_time,
+            tx_ids: vec![start_tx_id],
+        };
 
-        // Init strategy
-        let actions = self.strategy.initialize_strategy();
-
-        self.execute_actions(actions)
-            .map_err(|e| SyncError::Other(e.to_string()))?;
+        let mut checkpoint_pending = self.begin_replay()?;
 
-        while cursor.is_some() && cursor.unwrap() >= end_tx_id {
+        'sync: while cursor.is_some() && cursor.unwrap() >= end_tx_id {
             let transactions = transaction_repo
                 .fetch_transactions(pool_address, cursor, batch_size, OrderDirection::Descending)
                 .await
-                .map_err(|e| SyncError::DatabaseError(e.to_string()))?;
+                .map_err(SyncError::DatabaseError)?;
 
             if transactions.is_empty() {
                 break;
             }
 
-            // Process transactions in reverse order (newest to oldest)
-            for transaction in transactions.iter().rev() {
-                match transaction.transaction_type.as_str() {
-                    "IncreaseLiquidity" | "DecreaseLiquidity" => {
-                        let liquidity_data = transaction
-                            .data
-                            .to_liquidity_data()
-                            .map_err(|e| SyncError::ParseError(e.to_string()))?;
-
-                        let is_increase =
-                            transaction.transaction_type.as_str() == "IncreaseLiquidity";
-
-                        let (tick_lower, tick_upper, liquidity_amount) = match (
-                            liquidity_data.tick_lower,
-                            liquidity_data.tick_upper,
-                            liquidity_data.liquidity_amount.parse::<i128>(),
-                        ) {
-                            (Some(lower), Some(upper), Ok(amount)) => (lower, upper, amount),
-                            _ => {
-                                // eprintln!(
-                                //     "Liquidity transaction missing tick data, skipping: {}",
-                                //     transaction.signature
-                                // );
-                                continue;
-                            }
-                        };
-
-                        self.liquidity_arr.update_liquidity(
-                            tick_lower,
-                            tick_upper,
-                            liquidity_amount,
-                            is_increase,
-                        );
-                    }
-                    "Swap" => {
-                        let swap_data = transaction
-                            .data
-                            .to_swap_data()
-                            .map_err(|e| SyncError::ParseError(e.to_string()))?;
-
-                        let is_sell = swap_data.token_in == self.wallet.token_a_addr;
-
-                        self.save_data(transaction, swap_data, is_sell);
-
-                        self.liquidity_arr
-                            .simulate_swap(U256::from(swap_data.amount_in), is_sell)?;
-                    }
-                    _ => {}
+            // tx_ids descend with time, so the descending batch is already oldest to newest. A batch can reach past
+            // end_tx_id, nothing after it is part of the window (with start_tx_id == end_tx_id that's everything).
+            for transaction in transactions
+                .iter()
+                .filter(|transaction| transaction.tx_id >= end_tx_id)
+            {
+                replay_position.advance(transaction);
+
+                if !self
+                    .apply_transaction(transaction, &mut checkpoint_pending)
+                    .await?
+                {
+                    break 'sync;
                 }
-
-                // Process strategy actions
-                let actions = self
-                    .strategy
-                    .update(&self.liquidity_arr, transaction.clone());
-
-                self.execute_actions(actions)
-                    .map_err(|e| SyncError::Other(e.to_string()))?;
             }
 
-            // Update cursor for the next iteration
+            // Update cursor for the next iteration. The cursor is exclusive, so it's the last tx_id itself.
             cursor = transactions.last().and_then(|t| {
                 if t.tx_id > end_tx_id {
-                    Some(t.tx_id - 1)
+                    Some(t.tx_id)
                 } else {
                     None
                 }
@@ -211,14 +626,259 @@ impl Backtest {
             }
         }
 
-        let actions = self.strategy.finalize_strategy();
+        self.finish_replay(checkpoint_pending, &replay_position)
+    }
+
+    // Carries a loaded backtest on from where the saved run stopped, through the block of end_time. A sync after the save
+    // appends the new transactions with higher tx_ids than the saved ones (BIGSERIAL), so the tx_id cursor of sync_forward
+    // can't reach them. This goes by block time instead, in replay order, batch_seconds of transactions at a time.
+    pub async fn resume_forward<T: TransactionRepoTrait>(
+        &mut self,
+        transaction_repo: &T,
+        from: &ReplayPosition,
+        end_time: i64,
+        pool_address: &str,
+        batch_seconds: i64,
+    ) -> Result<(), SyncError> {
+        let mut replay_position = from.clone();
+        let mut checkpoint_pending = self.begin_replay()?;
+        let mut batch_start = from.block_time;
+
+        'sync: while batch_start <= end_time {
+            let batch_end = (batch_start + batch_seconds).min(end_time + 1);
+            let transactions = transaction_repo
+                .fetch_transactions_in_time_range(
+                    pool_address,
+                    block_time_utc(batch_start)?,
+                    block_time_utc(batch_end)?,
+                    OrderDirection::Ascending,
+                )
+                .await
+                .map_err(SyncError::DatabaseError)?;
+
+            // The saved block can hold transactions the saved run hadn't reached yet, only the applied ones are skipped.
+            for transaction in transactions
+                .iter()
+                .filter(|transaction| !from.applied(transaction))
+            {
+                replay_position.advance(transaction);
+
+                if !self
+                    .apply_transaction(transaction, &mut checkpoint_pending)
+                    .await?
+                {
+                    break 'sync;
+                }
+            }
+
+            batch_start = batch_end;
+        }
+
+        self.finish_replay(checkpoint_pending, &replay_position)
+    }
+
+    // Initializes the strategy unless the backtest was loaded with open positions. Returns whether the reconstruction
+    // checkpoint is still ahead of the replay.
+    fn begin_replay(&mut self) -> Result<bool, SyncError> {
+        // A checkpoint from before the window can't be reached, the backwards sync checks those.
+        let window_start = self.liquidity_arr.current_block_time;
+        let checkpoint_pending = self
+            .reconstruction_checkpoint
+            .as_ref()
+            .is_some_and(|checkpoint| checkpoint.timestamp.timestamp() >= window_start);
+
+        if !self.is_resumed {
+            self.initialize()?;
+        }
+
+        Ok(checkpoint_pending)
+    }
+
+    // Replays one transaction and lets the strategy act on it. false once max_actions stops the replay.
+    async fn apply_transaction(
+        &mut self,
+        transaction: &TransactionModelFromDB,
+        checkpoint_pending: &mut bool,
+    ) -> Result<bool, SyncError> {
+        // Everything up to the checkpoint is applied and nothing after it yet.
+        if *checkpoint_pending
+            && self
+                .reconstruction_checkpoint
+                .as_ref()
+                .is_some_and(|checkpoint| transaction.block_time > checkpoint.timestamp.timestamp())
+        {
+            self.verify_checkpoint()?;
+            *checkpoint_pending = false;
+        }
+
+        match transaction.transaction_type.as_str() {
+            "IncreaseLiquidity" | "DecreaseLiquidity" => {
+                let liquidity_data = transaction
+                    .data
+                    .to_liquidity_data()
+                    .map_err(SyncError::ParseError)?;
+
+                let is_increase = transaction.transaction_type.as_str() == "IncreaseLiquidity";
+
+                let (tick_lower, tick_upper, liquidity_amount) = match (
+                    liquidity_data.tick_lower,
+                    liquidity_data.tick_upper,
+                    liquidity_data.liquidity_amount.parse::<u128>(),
+                ) {
+                    (Some(lower), Some(upper), Ok(amount)) => (lower, upper, amount),
+                    _ => {
+                        // eprintln!(
+                        //     "Liquidity transaction missing tick data, skipping: {}",
+                        //     transaction.signature
+                        // );
+                        return Ok(true);
+                    }
+                };
+
+                self.liquidity_arr.update_liquidity(
+                    tick_lower,
+                    tick_upper,
+                    liquidity_amount,
+                    is_increase,
+                );
+            }
+            "Swap" => {
+                let swap_data = transaction
+                    .data
+                    .to_swap_data()
+                    .map_err(SyncError::ParseError)?;
+
+                if let Some(filter) = &self.source_program_filter {
+                    if !filter.allows(swap_data.source_program.as_deref()) {
+                        self.data.swaps_filtered_by_source += 1;
+                        return Ok(true);
+                    }
+                }
+
+                if swap_data.amount_in < self.min_swap_amount_in {
+                    self.data.dust_swaps_skipped += 1;
+                    return Ok(true);
+                }
+
+                let is_sell = swap_data.token_in == self.wallet.token_a_addr;
+
+                self.save_data(transaction, swap_data, is_sell);
+
+                self.liquidity_arr.apply_fee_rate_at(transaction.block_time);
+                self.liquidity_arr
+                    .simulate_swap(U256::from(swap_data.amount_in), is_sell)?;
+
+                self.verify_replay_tick(transaction, swap_data, is_sell);
+
+                if let Some(throttle) = self.throttle {
+                    tokio::time::sleep(throttle).await;
+                }
+            }
+            _ => {}
+        }
+
+        // Process strategy actions
+        let actions = self
+            .strategy
+            .update(&self.liquidity_arr, transaction.clone());
+
+        if let Some(max_actions) = self.max_actions {
+            if self.strategy_actions_executed + actions.len() > max_actions {
+                println!(
+                    "WARNING: Strategy hit max_actions ({}) at tx {} ({}). Stopping the replay and finalizing.",
+                    max_actions, transaction.tx_id, transaction.block_time_utc
+                );
+                self.max_actions_hit = true;
+                return Ok(false);
+            }
+        }
+
+        self.strategy_actions_executed += actions.len();
+        self.execute_actions(actions)?;
+
+        self.track_drawdown();
+        self.track_liquidity_share(transaction.block_time);
+        self.sample_if_due(transaction.block_time);
+
+        Ok(true)
+    }
+
+    // Saves the state when asked to, then closes what's still open and finalizes the strategy.
+    fn finish_replay(
+        &mut self,
+        checkpoint_pending: bool,
+        replay_position: &ReplayPosition,
+    ) -> Result<(), SyncError> {
+        // The checkpoint is after the last replayed transaction, the final state is the pool at that time.
+        if checkpoint_pending && !self.max_actions_hit {
+            self.verify_checkpoint()?;
+        }
+
+        if let Some(path) = &self.save_state_to {
+            self.save_state(path, replay_position)
+                .map_err(SyncError::SaveState)?;
+        }
+
+        let actions = self.strategy.on_window_end(&self.liquidity_arr);
 
-        self.execute_actions(actions)
-            .map_err(|e| SyncError::Other(e.to_string()))?;
+        self.is_finalizing = true;
+        let result = self.execute_actions(actions);
+        self.is_finalizing = false;
+
+        result?;
+
+        self.strategy.finalize_strategy();
 
         Ok(())
     }
 
+    fn verify_checkpoint(&self) -> Result<(), SyncError> {
+        match &self.reconstruction_checkpoint {
+            Some(checkpoint) => checkpoint.verify(
+                &self.liquidity_arr,
+                self.in_range_liquidity(),
+                "forward replay",
+            ),
+            None => Ok(()),
+        }
+    }
+
+    // The swap's own execution price is averaged over the ticks it walked through, so small gaps are expected.
+    fn verify_replay_tick(
+        &mut self,
+        transaction: &TransactionModelFromDB,
+        swap_data: &SwapData,
+        is_sell: bool,
+    ) {
+        let Some(verification) = self.replay_verification.as_mut() else {
+            return;
+        };
+
+        if swap_data.amount_in == 0 || swap_data.amount_out == 0 {
+            return;
+        }
+
+        let implied_price = swap_data.implied_price(is_sell);
+
+        let divergence = (price_to_tick(implied_price) - self.liquidity_arr.current_tick).abs();
+
+        verification.swaps_checked += 1;
+        verification.total_divergence += divergence as i64;
+        verification.max_divergence = verification.max_divergence.max(divergence);
+
+        if divergence > verification.tick_tolerance {
+            verification.swaps_diverged += 1;
+
+            eprintln!(
+                "WARNING: simulated tick {} diverged {} ticks from swap {} (tolerance {})",
+                self.liquidity_arr.current_tick,
+                divergence,
+                transaction.signature,
+                verification.tick_tolerance
+            );
+        }
+    }
+
     // saving data for later analysis
     fn save_data(
         &mut self,
@@ -256,6 +916,144 @@ impl Backtest {
         }
     }
 
+    // Wallet plus the principal of every open position, in raw token B at the current pool price.
+    pub fn current_value_b(&self) -> f64 {
+        let price = self.liquidity_arr.current_price(0, 0);
+
+        let (positions_a, positions_b) = self.liquidity_arr.positions.values().fold(
+            (U256::zero(), U256::zero()),
+            |(total_a, total_b), position| {
+                let (amount_a, amount_b) = calculate_amounts(
+                    U256::from(position.liquidity),
+                    self.liquidity_arr.current_sqrt_price,
+                    tick_to_sqrt_price_u256(position.lower_tick),
+                    tick_to_sqrt_price_u256(position.upper_tick),
+                );
+
+                (total_a + amount_a, total_b + amount_b)
+            },
+        );
+
+        (self.wallet.amount_token_a + positions_a).as_u128() as f64 * price
+            + (self.wallet.amount_token_b + positions_b).as_u128() as f64
+    }
+
+    // Just the wallet (remainders, undeployed capital, collected fees), in raw token B at the current pool price.
+    pub fn idle_value_b(&self) -> f64 {
+        self.wallet.amount_token_a.as_u128() as f64 * self.liquidity_arr.current_price(0, 0)
+            + self.wallet.amount_token_b.as_u128() as f64
+    }
+
+    fn track_drawdown(&mut self) {
+        let value = self.current_value_b();
+
+        if value > self.data.peak_value_b {
+            self.data.peak_value_b = value;
+        } else if self.data.peak_value_b > 0.0 {
+            let drawdown_pct = (self.data.peak_value_b - value) / self.data.peak_value_b * 100.0;
+            self.data.max_drawdown_pct = self.data.max_drawdown_pct.max(drawdown_pct);
+        }
+    }
+
+    // The strategy's own part of the active liquidity.
+    fn in_range_liquidity(&self) -> u128 {
+        let current_tick = self.liquidity_arr.current_tick;
+
+        self.liquidity_arr
+            .positions
+            .values()
+            .filter(|position| {
+                position.lower_tick <= current_tick && current_tick < position.upper_tick
+            })
+            .map(|position| position.liquidity)
+            .sum()
+    }
+
+    // Liquidity of the positions covering the current tick over the pool's active liquidity (which includes them).
+    pub fn liquidity_share(&self) -> f64 {
+        let in_range_liquidity = self.in_range_liquidity();
+
+        if in_range_liquidity == 0 || self.liquidity_arr.active_liquidity.is_zero() {
+            return 0.0;
+        }
+
+        (in_range_liquidity as f64 / self.liquidity_arr.active_liquidity.as_u128() as f64).min(1.0)
+    }
+
+    fn track_liquidity_share(&mut self, block_time: i64) {
+        if let Some((last_block_time, last_share)) = self.last_liquidity_share {
+            let elapsed = block_time - last_block_time;
+
+            if last_share > 0.0 && elapsed > 0 {
+                self.data.liquidity_share_weighted_sum += last_share * elapsed as f64;
+                self.data.liquidity_share_seconds += elapsed;
+            }
+        }
+
+        self.last_liquidity_share = Some((block_time, self.liquidity_share()));
+    }
+
+    fn track_idle_value(&mut self, block_time: i64) {
+        if let Some((last_block_time, last_idle, last_total)) = self.last_idle_value {
+            let elapsed = block_time - last_block_time;
+
+            if elapsed > 0 {
+                self.data.idle_value_b_weighted_sum += last_idle * elapsed as f64;
+                self.data.total_value_b_weighted_sum += last_total * elapsed as f64;
+                self.data.idle_value_seconds += elapsed;
+            }
+        }
+
+        self.last_idle_value = Some((block_time, self.idle_value_b(), self.current_value_b()));
+    }
+
+    // Sampling hook, runs after every replayed transaction and records the state once per sample_interval_seconds of block time.
+    fn sample_if_due(&mut self, block_time: i64) {
+        let interval = match self.sample_interval_seconds {
+            Some(interval) if interval > 0 => interval,
+            _ => return,
+        };
+
+        if self.next_sample_time.is_some_and(|next| block_time < next) {
+            return;
+        }
+
+        let mut positions: Vec<TimelinePosition> = self
+            .liquidity_arr
+            .positions
+            .iter()
+            .map(|(position_id, position)| TimelinePosition {
+                position_id: position_id.clone(),
+                lower_tick: position.lower_tick,
+                upper_tick: position.upper_tick,
+            })
+            .collect();
+        positions.sort_by(|a, b| a.position_id.cmp(&b.position_id));
+
+        self.track_idle_value(block_time);
+        self.data_logger.log_timeline_sample(
+            block_time,
+            self.data.current_swap_nmr,
+            self.liquidity_arr.current_tick,
+            self.liquidity_share(),
+            positions,
+        );
+
+        self.next_sample_time = Some(block_time + interval);
+    }
+
+    // Half of the fees' value in each token at the current pool price, see FeeHandling::Basket.
+    fn add_fees_to_basket(&mut self, fees_a: U256, fees_b: U256) {
+        let price = self.liquidity_arr.current_price(0, 0);
+        if price <= 0.0 {
+            return;
+        }
+
+        let value_b = fees_a.as_u128() as f64 * price + fees_b.as_u128() as f64;
+        self.data.fee_basket_a += value_b / 2.0 / price;
+        self.data.fee_basket_b += value_b / 2.0;
+    }
+
     fn execute_actions(&mut self, actions: Vec<Action>) -> Result<(), BacktestError> {
         for action in actions {
             match action {
@@ -263,14 +1061,30 @@ impl Backtest {
                     println!("Closing position and collecting fees");
 
                     // collect fees and remove position
-                    let (fees_a, fees_b) = self.liquidity_arr.collect_fees(&position_id)?;
+                    let (mut fees_a, mut fees_b) = self.liquidity_arr.collect_fees(&position_id)?;
                     let position = self.liquidity_arr.remove_owners_position(&position_id)?;
 
                     println!("Fees in token_a: {}, fees in token_b: {}", fees_a, fees_b);
 
+                    if self.is_finalizing && !self.count_fees_at_end {
+                        self.data.fees_a_uncollected_at_end += fees_a.as_u128();
+                        self.data.fees_b_uncollected_at_end += fees_b.as_u128();
+                        fees_a = U256::zero();
+                        fees_b = U256::zero();
+                    }
+
                     self.wallet.amount_a_fees_collected += fees_a;
                     self.wallet.amount_b_fees_collected += fees_b;
 
+                    if self.is_finalizing {
+                        self.data.fees_a_collected_at_end += fees_a.as_u128();
+                        self.data.fees_b_collected_at_end += fees_b.as_u128();
+                    } else {
+                        self.data.fees_a_collected_during_run += fees_a.as_u128();
+                        self.data.fees_b_collected_during_run += fees_b.as_u128();
+                        self.add_fees_to_basket(fees_a, fees_b);
+                    }
+
                     let (amount_a, amount_b) = calculate_amounts(
                         U256::from(position.liquidity),
                         self.liquidity_arr.current_sqrt_price,
@@ -281,6 +1095,14 @@ impl Backtest {
                     self.wallet.amount_token_a += amount_a + fees_a;
                     self.wallet.amount_token_b += amount_b + fees_b;
 
+                    // Closing doesn't swap, the tokens come out at the spot price. The next position's rebalance swap is
+                    // logged as its entry price.
+                    let exit_price = self.liquidity_arr.current_price(0, 0);
+                    let entry_price = self
+                        .position_entry_prices
+                        .remove(&position_id)
+                        .unwrap_or(exit_price);
+
                     self.data_logger.log_close_position(
                         position_id,
                         position.lower_tick,
@@ -299,6 +1121,9 @@ impl Backtest {
                         self.data.swap_nmr_in_position,
                         self.data.token_a_volume_in_position,
                         self.data.token_b_volume_in_position,
+                        entry_price,
+                        exit_price,
+                        self.is_finalizing,
                     );
                 }
                 Action::CreatePosition {
@@ -309,152 +1134,365 @@ impl Backtest {
                     let amount_a = self.wallet.amount_token_a;
                     let amount_b = self.wallet.amount_token_b;
 
-                    let upper_sqrt_price = tick_to_sqrt_price_u256(upper_tick);
-                    let lower_sqrt_price = tick_to_sqrt_price_u256(lower_tick);
-                    let curr_sqrt_price = self.liquidity_arr.current_sqrt_price;
-
-                    let rebalance_ratio = calculate_rebalance_ratio(
-                        curr_sqrt_price,
-                        upper_sqrt_price,
-                        lower_sqrt_price,
-                    );
-
-                    // No need to use decimals since when using raw token amounts as below it sorts itself out.
-                    let current_price =
-                        (curr_sqrt_price.as_u128() as f64 / Q64.as_u128() as f64).powf(2.0);
-
-                    let total_amount_a =
-                        amount_a.as_u128() as f64 + amount_b.as_u128() as f64 / current_price;
-                    let current_ratio = amount_a.as_u128() as f64 / total_amount_a;
-
-                    let mut latest_amount_a_in_wallet = amount_a;
-                    let mut latest_amount_b_in_wallet = amount_b;
-
-                    // In case the amounts are very close, dont swap.
-                    let no_swap_tolerance = (current_ratio - rebalance_ratio).abs() < 0.05;
-
-                    // If price is closer to upper limit, we mainly provide liquidity in B. Therefore we need to sell more token A if its below current ratio.
-                    if current_ratio > rebalance_ratio && !no_swap_tolerance {
-                        let amount_a_needed_for_liquidity = calculate_amount_a_needed_for_liquidity(
-                            rebalance_ratio,
-                            total_amount_a,
-                            current_price,
-                            lower_sqrt_price,
-                            curr_sqrt_price,
-                            upper_sqrt_price,
-                        );
-
-                        // sell whats unnecessary for liquidity
-                        let amount_a_to_sell = if amount_a_needed_for_liquidity >= amount_a {
-                            amount_a
-                        } else {
-                            amount_a - amount_a_needed_for_liquidity
-                        };
-
-                        let amount_out =
-                            self.liquidity_arr.simulate_swap(amount_a_to_sell, true)?;
-
-                        let amount_out_after_slippage = (amount_out
-                            * U256::from(1_000_000 - SLIPPAGE_FOR_SWAP))
-                            / U256::from(1_000_000);
-
-                        latest_amount_a_in_wallet -= amount_a_to_sell;
-                        latest_amount_b_in_wallet += amount_out_after_slippage;
-                    } else if !no_swap_tolerance {
-                        let amount_b_needed_for_liq = calculate_amount_b_needed_for_liquidity(
-                            rebalance_ratio,
-                            total_amount_a,
-                            current_price,
-                            lower_sqrt_price,
-                            curr_sqrt_price,
-                            upper_sqrt_price,
-                        );
-
-                        let amount_b_to_sell = if amount_b_needed_for_liq >= amount_b {
-                            amount_b
-                        } else {
-                            amount_b - amount_b_needed_for_liq
-                        };
-
-                        let amount_out =
-                            self.liquidity_arr.simulate_swap(amount_b_to_sell, false)?;
-
-                        let amount_out_after_slippage = (amount_out
-                            * U256::from(1_000_000 - SLIPPAGE_FOR_SWAP))
-                            / U256::from(1_000_000);
-
-                        latest_amount_a_in_wallet += amount_out_after_slippage;
-                        latest_amount_b_in_wallet -= amount_b_to_sell;
-                    }
-
-                    let newest_liquidity = calculate_liquidity(
-                        latest_amount_a_in_wallet,
-                        latest_amount_b_in_wallet,
-                        curr_sqrt_price,
-                        lower_sqrt_price,
-                        upper_sqrt_price,
-                    );
-
-                    let (amount_a_provided_to_pool, amount_b_provided_to_pool) = calculate_amounts(
-                        newest_liquidity,
-                        curr_sqrt_price,
-                        lower_sqrt_price,
-                        upper_sqrt_price,
-                    );
-
-                    self.wallet.amount_token_a =
-                        latest_amount_a_in_wallet - amount_a_provided_to_pool;
-                    self.wallet.amount_token_b =
-                        latest_amount_b_in_wallet - amount_b_provided_to_pool;
-
-                    self.liquidity_arr.add_owners_position(
-                        OwnersPosition {
-                            owner: String::from(""),
-                            lower_tick,
-                            upper_tick,
-                            liquidity: newest_liquidity.as_u128() as i128,
-                            fee_growth_inside_a_last: U256::zero(),
-                            fee_growth_inside_b_last: U256::zero(),
-                        },
-                        position_id.clone(),
-                    );
+                    self.create_position(
+                        position_id,
+                        lower_tick,
+                        upper_tick,
+                        amount_a,
+                        amount_b,
+                        None,
+                    )?;
+                }
+                Action::CreatePositionWithShare {
+                    position_id,
+                    lower_tick,
+                    upper_tick,
+                    wallet_share,
+                } => {
+                    let wallet_share = wallet_share.clamp(0.0, 1.0);
 
-                    println!(
-                        "Created position with liquidity {}, amount_a LPed: {}, amount_b LPed: {}, lower tick: {}, upper tick: {}",
-                        newest_liquidity, amount_a_provided_to_pool, amount_b_provided_to_pool,lower_tick,upper_tick
+                    let amount_a = U256::from(
+                        (self.wallet.amount_token_a.as_u128() as f64 * wallet_share) as u128,
                     );
-                    println!(
-                        "Left in wallet - {} token A , {} token B",
-                        self.wallet.amount_token_a, self.wallet.amount_token_b
+                    let amount_b = U256::from(
+                        (self.wallet.amount_token_b.as_u128() as f64 * wallet_share) as u128,
                     );
 
-                    self.data_logger.log_create_position(
+                    self.create_position(
                         position_id,
                         lower_tick,
                         upper_tick,
-                        self.liquidity_arr.current_tick,
-                        self.wallet.amount_token_a.as_u128(),
-                        self.wallet.amount_token_b.as_u128(),
-                        amount_a_provided_to_pool.as_u128(),
-                        amount_b_provided_to_pool.as_u128(),
-                        newest_liquidity.as_u128(),
-                        self.liquidity_arr.current_block_time as u128,
-                        self.data.current_swap_nmr,
-                        self.data.current_token_a_volume,
-                        self.data.current_token_b_volume,
-                        self.liquidity_arr.active_liquidity.as_u128(),
-                    );
+                        amount_a,
+                        amount_b,
+                        None,
+                    )?;
                 }
-            }
+                Action::CreatePositionWithTargetLiquidity {
+                    position_id,
+                    lower_tick,
+                    upper_tick,
+                    liquidity,
+                } => {
+                    let (amount_a, amount_b) =
+                        self.amounts_for_target_liquidity(lower_tick, upper_tick, liquidity);
+
+                    self.create_position(
+                        position_id,
+                        lower_tick,
+                        upper_tick,
+                        amount_a,
+                        amount_b,
+                        Some(U256::from(liquidity)),
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Wallet amounts to earmark for a position of the given liquidity. A token the wallet is short of is made up
+    // for with the other one (by value, plus a margin for the rebalance swap), create_position swaps it over.
+    fn amounts_for_target_liquidity(
+        &self,
+        lower_tick: i32,
+        upper_tick: i32,
+        liquidity: u128,
+    ) -> (U256, U256) {
+        // Covers SLIPPAGE_FOR_SWAP and some price impact, whatever isn't needed goes back to the wallet.
+        const SWAP_MARGIN: f64 = 1.03;
+
+        let (needed_a, needed_b) = amounts_for_target_liquidity(
+            U256::from(liquidity),
+            self.liquidity_arr.current_sqrt_price,
+            tick_to_sqrt_price_u256(lower_tick),
+            tick_to_sqrt_price_u256(upper_tick),
+        );
+        let price = self.liquidity_arr.current_price(0, 0);
+
+        let amount_a = needed_a.min(self.wallet.amount_token_a);
+        let amount_b = needed_b.min(self.wallet.amount_token_b);
+
+        let short_a = (needed_a - amount_a).as_u128() as f64;
+        let short_b = (needed_b - amount_b).as_u128() as f64;
+
+        let extra_a = U256::from((short_b / price * SWAP_MARGIN) as u128)
+            .min(self.wallet.amount_token_a - amount_a);
+        let extra_b = U256::from((short_a * price * SWAP_MARGIN) as u128)
+            .min(self.wallet.amount_token_b - amount_b);
+
+        (amount_a + extra_a, amount_b + extra_b)
+    }
+
+    // What a rebalance swap still pays out once the sandwich penalty is taken off, the penalty is tracked as sandwich loss.
+    fn apply_sandwich_penalty(&mut self, amount_in: U256, amount_out: U256, a_to_b: bool) -> U256 {
+        let Some(sandwich_penalty) = self.sandwich_penalty else {
+            return amount_out;
+        };
+        if self.is_initializing {
+            return amount_out;
+        }
+
+        // Virtual reserves of the active liquidity, x = L / sqrt(P) and y = L * sqrt(P).
+        let sqrt_price = sqrt_price_to_price(self.liquidity_arr.current_sqrt_price).sqrt();
+        let active_liquidity = self.liquidity_arr.active_liquidity.as_u128() as f64;
+        let reserve_in = if a_to_b {
+            active_liquidity / sqrt_price
+        } else {
+            active_liquidity * sqrt_price
+        };
+
+        if !sandwich_penalty.applies(amount_in, reserve_in) {
+            return amount_out;
+        }
+
+        let penalty = sandwich_penalty.penalty(amount_out);
+        if a_to_b {
+            self.data.sandwich_loss_b += penalty.as_u128();
+        } else {
+            self.data.sandwich_loss_a += penalty.as_u128();
+        }
+
+        amount_out - penalty
+    }
+
+    // Provides liquidity with the given token amounts, swapping between them first to match the range ratio.
+    // Amounts come out of the wallet, leftovers that couldn't be LPed go back into it. max_liquidity caps the
+    // liquidity provided, the tokens it leaves unused are leftovers too.
+    fn create_position(
+        &mut self,
+        position_id: String,
+        lower_tick: i32,
+        upper_tick: i32,
+        amount_a: U256,
+        amount_b: U256,
+        max_liquidity: Option<U256>,
+    ) -> Result<(), BacktestError> {
+        let upper_sqrt_price = tick_to_sqrt_price_u256(upper_tick);
+        let lower_sqrt_price = tick_to_sqrt_price_u256(lower_tick);
+        let curr_sqrt_price = self.liquidity_arr.current_sqrt_price;
+
+        let rebalance_ratio =
+            calculate_rebalance_ratio(curr_sqrt_price, upper_sqrt_price, lower_sqrt_price);
+
+        // No need to use decimals since when using raw token amounts as below it sorts itself out.
+        let current_price = sqrt_price_to_price(curr_sqrt_price);
+        // Spot unless the rebalance swap below runs, then that swap's VWAP (total out / total in after slippage).
+        let mut entry_price = current_price;
+
+        let total_amount_a = amount_a.as_u128() as f64 + amount_b.as_u128() as f64 / current_price;
+        let current_ratio = amount_a.as_u128() as f64 / total_amount_a;
+
+        let mut latest_amount_a_in_wallet = amount_a;
+        let mut latest_amount_b_in_wallet = amount_b;
+        let (mut swap_cost_a, mut swap_cost_b) = (0, 0);
+
+        // In case the amounts are very close, dont swap.
+        let no_swap_tolerance = (current_ratio - rebalance_ratio).abs() < 0.05;
+
+        // If price is closer to upper limit, we mainly provide liquidity in B. Therefore we need to sell more token A if its below current ratio.
+        if current_ratio > rebalance_ratio && !no_swap_tolerance {
+            let amount_a_needed_for_liquidity = calculate_amount_a_needed_for_liquidity(
+                rebalance_ratio,
+                total_amount_a,
+                current_price,
+                lower_sqrt_price,
+                curr_sqrt_price,
+                upper_sqrt_price,
+            );
+
+            // sell whats unnecessary for liquidity
+            let amount_a_to_sell = if amount_a_needed_for_liquidity >= amount_a {
+                amount_a
+            } else {
+                amount_a - amount_a_needed_for_liquidity
+            };
+
+            let amount_out = self.liquidity_arr.simulate_swap(amount_a_to_sell, true)?;
+
+            let amount_out_after_slippage =
+                (amount_out * U256::from(1_000_000 - SLIPPAGE_FOR_SWAP)) / U256::from(1_000_000);
+            let amount_out_after_slippage =
+                self.apply_sandwich_penalty(amount_a_to_sell, amount_out_after_slippage, true);
+
+            latest_amount_a_in_wallet -= amount_a_to_sell;
+            latest_amount_b_in_wallet += amount_out_after_slippage;
+
+            if !amount_a_to_sell.is_zero() {
+                entry_price =
+                    amount_out_after_slippage.as_u128() as f64 / amount_a_to_sell.as_u128() as f64;
+            }
+
+            let expected_amount_b = amount_a_to_sell.as_u128() as f64 * current_price;
+            swap_cost_b =
+                (expected_amount_b - amount_out_after_slippage.as_u128() as f64).max(0.0) as u128;
+            if self.is_initializing {
+                self.start_info.entry_cost_b += swap_cost_b;
+            } else {
+                self.data.rebalance_cost_b += swap_cost_b;
+            }
+        } else if !no_swap_tolerance {
+            let amount_b_needed_for_liq = calculate_amount_b_needed_for_liquidity(
+                rebalance_ratio,
+                total_amount_a,
+                current_price,
+                lower_sqrt_price,
+                curr_sqrt_price,
+                upper_sqrt_price,
+            );
+
+            let amount_b_to_sell = if amount_b_needed_for_liq >= amount_b {
+                amount_b
+            } else {
+                amount_b - amount_b_needed_for_liq
+            };
+
+            let amount_out = self.liquidity_arr.simulate_swap(amount_b_to_sell, false)?;
+
+            let amount_out_after_slippage =
+                (amount_out * U256::from(1_000_000 - SLIPPAGE_FOR_SWAP)) / U256::from(1_000_000);
+            let amount_out_after_slippage =
+                self.apply_sandwich_penalty(amount_b_to_sell, amount_out_after_slippage, false);
+
+            latest_amount_a_in_wallet += amount_out_after_slippage;
+            latest_amount_b_in_wallet -= amount_b_to_sell;
+
+            if !amount_out_after_slippage.is_zero() {
+                entry_price =
+                    amount_b_to_sell.as_u128() as f64 / amount_out_after_slippage.as_u128() as f64;
+            }
+
+            let expected_amount_a = amount_b_to_sell.as_u128() as f64 / current_price;
+            swap_cost_a =
+                (expected_amount_a - amount_out_after_slippage.as_u128() as f64).max(0.0) as u128;
+            if self.is_initializing {
+                self.start_info.entry_cost_a += swap_cost_a;
+            } else {
+                self.data.rebalance_cost_a += swap_cost_a;
+            }
+        }
+
+        let mut newest_liquidity = calculate_liquidity(
+            latest_amount_a_in_wallet,
+            latest_amount_b_in_wallet,
+            curr_sqrt_price,
+            lower_sqrt_price,
+            upper_sqrt_price,
+        );
+        if let Some(max_liquidity) = max_liquidity {
+            newest_liquidity = newest_liquidity.min(max_liquidity);
+        }
+
+        let (amount_a_provided_to_pool, amount_b_provided_to_pool) = calculate_amounts(
+            newest_liquidity,
+            curr_sqrt_price,
+            lower_sqrt_price,
+            upper_sqrt_price,
+        );
+
+        let funding = PositionFunding {
+            position_id: position_id.clone(),
+            pct_a_deployed: deployed_pct(amount_a_provided_to_pool, latest_amount_a_in_wallet),
+            pct_b_deployed: deployed_pct(amount_b_provided_to_pool, latest_amount_b_in_wallet),
+        };
+
+        // Whatever wasn't earmarked for this position stays in the wallet on top of the leftovers.
+        self.wallet.amount_token_a = (self.wallet.amount_token_a - amount_a)
+            + (latest_amount_a_in_wallet - amount_a_provided_to_pool);
+        self.wallet.amount_token_b = (self.wallet.amount_token_b - amount_b)
+            + (latest_amount_b_in_wallet - amount_b_provided_to_pool);
+
+        self.liquidity_arr.add_owners_position(
+            OwnersPosition {
+                owner: String::from(""),
+                lower_tick,
+                upper_tick,
+                liquidity: newest_liquidity.as_u128(),
+                fee_growth_inside_a_last: U256::zero(),
+                fee_growth_inside_b_last: U256::zero(),
+            },
+            position_id.clone(),
+        )?;
+
+        println!(
+            "Created position with liquidity {}, amount_a LPed: {}, amount_b LPed: {}, lower tick: {}, upper tick: {}",
+            newest_liquidity, amount_a_provided_to_pool, amount_b_provided_to_pool,lower_tick,upper_tick
+        );
+        println!(
+            "Left in wallet - {} token A , {} token B",
+            self.wallet.amount_token_a, self.wallet.amount_token_b
+        );
+        println!(
+            "Deployed {:.1}% of token A and {:.1}% of token B",
+            funding.pct_a_deployed, funding.pct_b_deployed
+        );
+
+        self.position_entry_prices
+            .insert(position_id.clone(), entry_price);
+
+        self.data_logger.log_create_position(
+            position_id.clone(),
+            lower_tick,
+            upper_tick,
+            self.liquidity_arr.current_tick,
+            self.wallet.amount_token_a.as_u128(),
+            self.wallet.amount_token_b.as_u128(),
+            amount_a_provided_to_pool.as_u128(),
+            amount_b_provided_to_pool.as_u128(),
+            newest_liquidity.as_u128(),
+            self.liquidity_arr.current_block_time as u128,
+            self.data.current_swap_nmr,
+            self.data.current_token_a_volume,
+            self.data.current_token_b_volume,
+            self.liquidity_arr.active_liquidity.as_u128(),
+            funding.pct_a_deployed,
+            funding.pct_b_deployed,
+            entry_price,
+        );
+        if !self.is_initializing {
+            self.data.rebalance_count += 1;
+            self.data_logger.log_rebalance(
+                position_id,
+                self.liquidity_arr.current_block_time as u128,
+                self.data.current_swap_nmr,
+                (swap_cost_a, swap_cost_b),
+                self.data.rebalance_count,
+                (self.data.rebalance_cost_a, self.data.rebalance_cost_b),
+            );
         }
 
+        self.position_fundings.push(funding);
+
         Ok(())
     }
 }
 
+// Nothing available means nothing was held back either.
+fn deployed_pct(deployed: U256, available: U256) -> f64 {
+    if available.is_zero() {
+        return 100.0;
+    }
+
+    deployed.as_u128() as f64 / available.as_u128() as f64 * 100.0
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::api::price_api::StableToken;
+    use crate::backtester::{
+        jit_band_strategy::JitBandStrategy,
+        ladder_strategy::LadderStrategy,
+        liquidity_array::FeeRateChange,
+        no_rebalance_strategy::NoRebalanceStrategy,
+        simple_rebalance_strategy::SimpleRebalanceStrategy,
+        test_helpers::{
+            create_test_liquidity_array, create_test_swap_transactions,
+            reset_initialized_tick_caches, test_wallet, InMemoryTransactionRepo,
+            TestLiquidityArrayBuilder,
+        },
+    };
+    use crate::utils::profit_calcs::{pegged_prices_usd, pool_price};
     use std::sync::Arc;
     use tokio::sync::Mutex;
 
@@ -473,7 +1511,7 @@ mod tests {
             vec![]
         }
 
-        fn finalize_strategy(&self) -> Vec<Action> {
+        fn on_window_end(&self, _liquidity_array: &LiquidityArray) -> Vec<Action> {
             vec![]
         }
 
@@ -482,35 +1520,6 @@ mod tests {
         }
     }
 
-    struct MockTransactionRepo {
-        transactions: Arc<Mutex<Vec<TransactionModelFromDB>>>,
-    }
-
-    #[async_trait::async_trait]
-    impl TransactionRepoTrait for MockTransactionRepo {
-        async fn fetch_transactions(
-            &self,
-            _pool_address: &str,
-            _cursor: Option<i64>,
-            _batch_size: i64,
-            _order: OrderDirection,
-        ) -> Result<Vec<TransactionModelFromDB>, anyhow::Error> {
-            Ok(self.transactions.lock().await.clone())
-        }
-    }
-
-    fn create_test_liquidity_array(current_tick: i32) -> LiquidityArray {
-        let mut liquidity_arr = LiquidityArray::new(-500_000, 500_000, 10, 500);
-
-        liquidity_arr.current_tick = current_tick;
-        liquidity_arr.current_sqrt_price = tick_to_sqrt_price_u256(current_tick);
-
-        liquidity_arr.update_liquidity(-100_000, 100_000, 1_000_000_000, true);
-        liquidity_arr.update_liquidity(-200_000, 200_000, 1_000_000_000, true);
-
-        liquidity_arr
-    }
-
     #[tokio::test]
     async fn test_backtest_initialization() {
         let liquidity_arr = create_test_liquidity_array(0);
@@ -518,16 +1527,7 @@ mod tests {
         let starting_token_a_amount = U256::from(1000_u128 * 10_u128.pow(6));
         let starting_token_b_amount = U256::from(1000_u128 * 10_u128.pow(6));
 
-        let wallet = Wallet {
-            token_a_addr: "TokenA".to_string(),
-            token_b_addr: "TokenB".to_string(),
-            amount_token_a: starting_token_a_amount,
-            amount_token_b: starting_token_b_amount,
-            token_a_decimals: 6,
-            token_b_decimals: 6,
-            amount_a_fees_collected: U256::zero(),
-            amount_b_fees_collected: U256::zero(),
-        };
+        let wallet = test_wallet(starting_token_a_amount, starting_token_b_amount);
         let strategy = Box::new(MockStrategy);
 
         let backtest = Backtest::new(
@@ -557,16 +1557,7 @@ mod tests {
         let lower_tick = current_tick - 100;
         let upper_tick = current_tick + 100;
 
-        let wallet = Wallet {
-            token_a_addr: "TokenA".to_string(),
-            token_b_addr: "TokenB".to_string(),
-            amount_token_a: starting_amount_a,
-            amount_token_b: starting_amount_b,
-            token_a_decimals: 6,
-            token_b_decimals: 6,
-            amount_a_fees_collected: U256::zero(),
-            amount_b_fees_collected: U256::zero(),
-        };
+        let wallet = test_wallet(starting_amount_a, starting_amount_b);
         let strategy = Box::new(MockStrategy);
 
         let mut backtest = Backtest::new(
@@ -585,14 +1576,8 @@ mod tests {
             }])
             .unwrap();
 
-        // manually set cached ticks
-        let (upper_tick_data, lower_tick_data) = backtest
-            .liquidity_arr
-            .get_upper_and_lower_ticks(current_tick, true)
-            .unwrap();
-
-        backtest.liquidity_arr.cached_lower_initialized_tick = Some(lower_tick_data.tick);
-        backtest.liquidity_arr.cached_upper_initialized_tick = Some(upper_tick_data.tick);
+        // The new position's ticks sit between the price and the cached ones.
+        reset_initialized_tick_caches(&mut backtest.liquidity_arr);
 
         let _ = backtest
             .liquidity_arr
@@ -632,16 +1617,7 @@ mod tests {
         let lower_tick = current_tick - 100;
         let upper_tick = current_tick + 100;
 
-        let wallet = Wallet {
-            token_a_addr: "TokenA".to_string(),
-            token_b_addr: "TokenB".to_string(),
-            amount_token_a: starting_amount_a,
-            amount_token_b: starting_amount_b,
-            token_a_decimals: 6,
-            token_b_decimals: 6,
-            amount_a_fees_collected: U256::zero(),
-            amount_b_fees_collected: U256::zero(),
-        };
+        let wallet = test_wallet(starting_amount_a, starting_amount_b);
         let strategy = Box::new(MockStrategy);
 
         let mut backtest = Backtest::new(
@@ -660,14 +1636,8 @@ mod tests {
             }])
             .unwrap();
 
-        // manually set cached ticks
-        let (upper_tick_data, lower_tick_data) = backtest
-            .liquidity_arr
-            .get_upper_and_lower_ticks(current_tick, true)
-            .unwrap();
-
-        backtest.liquidity_arr.cached_lower_initialized_tick = Some(lower_tick_data.tick);
-        backtest.liquidity_arr.cached_upper_initialized_tick = Some(upper_tick_data.tick);
+        // The new position's ticks sit between the price and the cached ones.
+        reset_initialized_tick_caches(&mut backtest.liquidity_arr);
 
         let _ = backtest
             .liquidity_arr
@@ -708,16 +1678,7 @@ mod tests {
         let lower_tick = current_tick - 100;
         let upper_tick = current_tick + 100;
 
-        let wallet = Wallet {
-            token_a_addr: "TokenA".to_string(),
-            token_b_addr: "TokenB".to_string(),
-            amount_token_a: starting_amount_a,
-            amount_token_b: starting_amount_b,
-            token_a_decimals: 6,
-            token_b_decimals: 6,
-            amount_a_fees_collected: U256::zero(),
-            amount_b_fees_collected: U256::zero(),
-        };
+        let wallet = test_wallet(starting_amount_a, starting_amount_b);
         let strategy = Box::new(MockStrategy);
 
         let mut backtest = Backtest::new(
@@ -736,14 +1697,8 @@ mod tests {
             }])
             .unwrap();
 
-        // manually set cached ticks
-        let (upper_tick_data, lower_tick_data) = backtest
-            .liquidity_arr
-            .get_upper_and_lower_ticks(current_tick, true)
-            .unwrap();
-
-        backtest.liquidity_arr.cached_lower_initialized_tick = Some(lower_tick_data.tick);
-        backtest.liquidity_arr.cached_upper_initialized_tick = Some(upper_tick_data.tick);
+        // The new position's ticks sit between the price and the cached ones.
+        reset_initialized_tick_caches(&mut backtest.liquidity_arr);
 
         let _ = backtest
             .liquidity_arr
@@ -774,4 +1729,1354 @@ mod tests {
             "All liquidity in token A, above starting"
         );
     }
+
+    #[test]
+    fn test_replay_verification_flags_divergent_swaps() {
+        let amount = U256::from(100 * 10_i32.pow(6));
+
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+        backtest.replay_verification = Some(ReplayVerification::new(50));
+
+        let transaction = TransactionModelFromDB {
+            tx_id: 1,
+            signature: "sig1".to_string(),
+            pool_address: "pool1".to_string(),
+            block_time: 1000,
+            block_time_utc: chrono::Utc::now(),
+            transaction_type: "Swap".to_string(),
+            ready_for_backtesting: true,
+            data: crate::models::transactions_model::TransactionData::Swap(SwapData {
+                token_in: "TokenA".to_string(),
+                token_out: "TokenB".to_string(),
+                amount_in: 1_000_000,
+                amount_out: 1_000_000,
+                source_program: None,
+            }),
+        };
+
+        // Price 1.0 sits right at tick 0.
+        let swap_data = transaction.data.to_swap_data().unwrap().clone();
+        backtest.verify_replay_tick(&transaction, &swap_data, true);
+
+        // Price ~1.02 implies tick ~198, way past the tolerance.
+        let swap_data = SwapData {
+            amount_out: 1_020_000,
+            ..swap_data
+        };
+        backtest.verify_replay_tick(&transaction, &swap_data, true);
+
+        let verification = backtest.replay_verification.unwrap();
+        assert_eq!(verification.swaps_checked, 2);
+        assert_eq!(verification.swaps_diverged, 1);
+        assert!(verification.max_divergence > 150);
+    }
+
+    #[tokio::test]
+    async fn test_ladder_strategy_splits_wallet_across_rungs() {
+        let starting_amount_a = U256::from(100 * 10_i32.pow(6));
+        let starting_amount_b = U256::from(100 * 10_i32.pow(6));
+
+        let current_tick = 11;
+
+        let wallet = test_wallet(starting_amount_a, starting_amount_b);
+        let ladder = LadderStrategy::new(current_tick - 200, current_tick + 200, 4, 10).unwrap();
+        let actions = ladder.initialize_strategy();
+
+        let mut backtest = Backtest::new(
+            starting_amount_a,
+            starting_amount_b,
+            create_test_liquidity_array(current_tick),
+            wallet,
+            Box::new(ladder),
+        );
+
+        // rungs swap to rebalance before LPing, create_test_liquidity_array already set the cached ticks for that.
+        backtest.execute_actions(actions).unwrap();
+
+        assert_eq!(backtest.liquidity_arr.positions.len(), 4);
+
+        let rung_liquidity: Vec<u128> = (0..4)
+            .map(|rung| backtest.liquidity_arr.positions[&format!("ladder_{}", rung)].liquidity)
+            .collect();
+        assert!(rung_liquidity.iter().all(|liquidity| *liquidity > 0));
+
+        // Most of the wallet should be deployed, only swap/rounding leftovers remain.
+        assert!(backtest.wallet.amount_token_a < starting_amount_a / 10);
+        assert!(backtest.wallet.amount_token_b < starting_amount_b / 10);
+    }
+
+    #[test]
+    fn test_ladder_rungs_align_to_tick_spacing() {
+        // -195 and 212 widen to -200 and 220, 42 spacings over 4 rungs.
+        let ladder = LadderStrategy::new(-195, 212, 4, 10).unwrap();
+        assert_eq!(ladder.get_ticks(), (-200, 220));
+        assert_eq!(
+            ladder.rung_ticks(),
+            vec![(-200, -100), (-100, 10), (10, 110), (110, 220)]
+        );
+
+        // Each rung needs at least one tick spacing.
+        let narrow = LadderStrategy::new(0, 20, 2, 10).unwrap();
+        assert_eq!(narrow.rung_ticks(), vec![(0, 10), (10, 20)]);
+        assert!(LadderStrategy::new(0, 20, 3, 10).is_err());
+
+        assert!(LadderStrategy::new(0, 100, 0, 10).is_err());
+        assert!(LadderStrategy::new(100, 0, 2, 10).is_err());
+        assert!(LadderStrategy::new(0, 100, 2, 0).is_err());
+    }
+
+    #[test]
+    fn test_jit_band_strategy_enters_and_leaves_band() {
+        let mut strategy = JitBandStrategy::new(-100, 100, 50, 10).unwrap();
+        let mut liquidity_array = create_test_liquidity_array(500);
+        let swap = create_test_swap_transactions(1, 1_000, "TokenA", "TokenB").remove(0);
+
+        let mut update_at = |strategy: &mut JitBandStrategy, tick: i32| {
+            liquidity_array.current_tick = tick;
+            strategy.update(&liquidity_array, swap.clone())
+        };
+
+        // Outside the band with nothing open, stays in the wallet.
+        assert!(strategy.initialize_strategy().is_empty());
+        assert!(update_at(&mut strategy, 500).is_empty());
+
+        // Entering opens a position around the tick widened to the tick spacing, and only once.
+        assert!(matches!(
+            update_at(&mut strategy, 13).as_slice(),
+            [Action::CreatePosition {
+                lower_tick: -40,
+                upper_tick: 70,
+                ..
+            }]
+        ));
+        assert!(update_at(&mut strategy, 20).is_empty());
+        assert_eq!(strategy.get_ticks(), (-40, 70));
+
+        // Leaving closes it, and only once.
+        assert!(matches!(
+            update_at(&mut strategy, 150).as_slice(),
+            [Action::ClosePosition { .. }]
+        ));
+        assert!(update_at(&mut strategy, 160).is_empty());
+
+        assert!(JitBandStrategy::new(100, -100, 50, 10).is_err());
+        assert!(JitBandStrategy::new(-100, 100, 0, 10).is_err());
+        assert!(JitBandStrategy::new(-100, 100, 50, 0).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_jit_band_idle_window_keeps_wallet() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(10, 1_000, "TokenA", "TokenB"),
+        };
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        // The price sits around tick 0 and never reaches the band.
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(JitBandStrategy::new(1_000, 2_000, 50, 10).unwrap()),
+        );
+
+        backtest
+            .sync_forward(&repo, 11, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        // Nothing was ever deployed, the equity is just the untouched wallet.
+        assert_eq!(backtest.data.current_swap_nmr, 10);
+        assert_eq!(backtest.strategy_actions_executed, 0);
+        assert_eq!(backtest.data.swap_nmr_in_position, 0);
+        assert!(backtest.liquidity_arr.positions.is_empty());
+        assert_eq!(backtest.wallet.amount_token_a, amount);
+        assert_eq!(backtest.wallet.amount_token_b, amount);
+    }
+
+    #[tokio::test]
+    async fn test_create_position_records_funding() {
+        let amount = U256::from(1000_u128 * 10_u128.pow(6));
+
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+
+        backtest
+            .execute_actions(vec![
+                Action::CreatePosition {
+                    position_id: "centered".to_string(),
+                    lower_tick: -100,
+                    upper_tick: 100,
+                },
+                Action::CreatePosition {
+                    position_id: "skewed".to_string(),
+                    lower_tick: -20,
+                    upper_tick: 180,
+                },
+            ])
+            .unwrap();
+
+        assert_eq!(backtest.position_fundings.len(), 2);
+
+        for funding in &backtest.position_fundings {
+            // The binding token is fully deployed, the other one at most fully.
+            let most_deployed = funding.pct_a_deployed.max(funding.pct_b_deployed);
+            assert!(most_deployed > 99.0 && most_deployed <= 100.0);
+            assert!(funding.least_deployed_pct() <= most_deployed);
+        }
+
+        let worst = backtest.worst_funded_positions(1);
+        assert_eq!(worst.len(), 1);
+        assert!(backtest
+            .position_fundings
+            .iter()
+            .all(|f| f.least_deployed_pct() >= worst[0].least_deployed_pct()));
+    }
+
+    #[tokio::test]
+    async fn test_entry_price_is_rebalance_swap_vwap() {
+        let amount = U256::from(10_u128.pow(7));
+
+        let wallet = test_wallet(amount, U256::zero());
+
+        let mut backtest = Backtest::new(
+            amount,
+            U256::zero(),
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+        let spot_price = backtest.liquidity_arr.current_price(0, 0);
+
+        // All token A, so about half of it is sold into B before LPing.
+        backtest
+            .execute_actions(vec![Action::CreatePosition {
+                position_id: "sold_a".to_string(),
+                lower_tick: -100,
+                upper_tick: 100,
+            }])
+            .unwrap();
+
+        // Selling A fills below spot, at least by the slippage.
+        let entry_price = backtest.position_entry_prices["sold_a"];
+        assert!(entry_price < spot_price);
+        assert!(entry_price > spot_price * 0.98);
+
+        backtest
+            .execute_actions(vec![Action::ClosePosition {
+                position_id: "sold_a".to_string(),
+            }])
+            .unwrap();
+
+        assert!(backtest.position_entry_prices.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_position_with_target_liquidity() {
+        let amount = U256::from(100 * 10_u128.pow(6));
+        let target_liquidity = 100_000_000_u128;
+
+        for amount_token_b in [amount, U256::zero()] {
+            let wallet = test_wallet(amount, amount_token_b);
+
+            let mut backtest = Backtest::new(
+                amount,
+                amount_token_b,
+                create_test_liquidity_array(0),
+                wallet,
+                Box::new(MockStrategy),
+            );
+
+            backtest
+                .execute_actions(vec![Action::CreatePositionWithTargetLiquidity {
+                    position_id: "depth".to_string(),
+                    lower_tick: -100,
+                    upper_tick: 100,
+                    liquidity: target_liquidity,
+                }])
+                .unwrap();
+
+            // Exactly the target, from both tokens or from token A alone (swapping part of it), the rest stays put.
+            assert_eq!(
+                backtest.liquidity_arr.positions["depth"].liquidity,
+                target_liquidity
+            );
+            assert!(backtest.wallet.amount_token_a > amount * 9 / 10);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_entry_cost_only_recorded_while_initializing() {
+        let amount = U256::from(1000_u128 * 10_u128.pow(6));
+
+        let new_backtest = || {
+            // All token A, so opening a centered position has to sell about half of it.
+            let wallet = test_wallet(amount, U256::zero());
+
+            Backtest::new(
+                amount,
+                U256::zero(),
+                create_test_liquidity_array(0),
+                wallet,
+                Box::new(MockStrategy),
+            )
+        };
+        let create_action = || {
+            vec![Action::CreatePosition {
+                position_id: "centered".to_string(),
+                lower_tick: -100,
+                upper_tick: 100,
+            }]
+        };
+
+        let mut rebalance_backtest = new_backtest();
+        rebalance_backtest.execute_actions(create_action()).unwrap();
+
+        assert_eq!(rebalance_backtest.start_info.entry_cost_a, 0);
+        assert_eq!(rebalance_backtest.start_info.entry_cost_b, 0);
+
+        let mut entry_backtest = new_backtest();
+        entry_backtest.is_initializing = true;
+        entry_backtest.execute_actions(create_action()).unwrap();
+
+        // Roughly half the wallet is sold, SLIPPAGE_FOR_SWAP alone costs 1% of that and the thin test pool adds price impact.
+        let entry_cost_b = entry_backtest.start_info.entry_cost_b;
+        assert_eq!(entry_backtest.start_info.entry_cost_a, 0);
+        assert!(entry_cost_b > amount.as_u128() / 2 / 100 * 9 / 10);
+        assert!(entry_cost_b < amount.as_u128() / 2);
+
+        // The same swap outside of initialization is a rebalance cost instead.
+        assert_eq!(rebalance_backtest.data.rebalance_cost_b, entry_cost_b);
+        assert_eq!(entry_backtest.data.rebalance_cost_b, 0);
+
+        // And counted as a rebalance.
+        assert_eq!(rebalance_backtest.data.rebalance_count, 1);
+        assert_eq!(entry_backtest.data.rebalance_count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sandwich_penalty_only_hits_large_rebalance_swaps() {
+        let amount = U256::from(1000_u128 * 10_u128.pow(6));
+
+        let new_backtest = |sandwich_penalty: Option<SandwichPenalty>, is_initializing: bool| {
+            // All token A, opening a centered position sells about half of it.
+            let wallet = test_wallet(amount, U256::zero());
+
+            let mut backtest = Backtest::new(
+                amount,
+                U256::zero(),
+                create_test_liquidity_array(0),
+                wallet,
+                Box::new(MockStrategy),
+            );
+            backtest.sandwich_penalty = sandwich_penalty;
+            backtest.is_initializing = is_initializing;
+            backtest
+                .execute_actions(vec![Action::CreatePosition {
+                    position_id: "centered".to_string(),
+                    lower_tick: -100,
+                    upper_tick: 100,
+                }])
+                .unwrap();
+
+            backtest
+        };
+
+        let penalty = SandwichPenalty {
+            penalty_bps: 50,
+            min_swap_share: 0.0,
+        };
+        let plain = new_backtest(None, false);
+        let sandwiched = new_backtest(Some(penalty), false);
+        let below_threshold = new_backtest(
+            Some(SandwichPenalty {
+                min_swap_share: 1_000.0,
+                ..penalty
+            }),
+            false,
+        );
+
+        assert_eq!(plain.data.sandwich_loss_b, 0);
+        assert_eq!(below_threshold.data.sandwich_loss_b, 0);
+        assert_eq!(
+            below_threshold.data.rebalance_cost_b,
+            plain.data.rebalance_cost_b
+        );
+
+        // 0.5% of the swap output on top of the regular cost, which it is part of.
+        let loss = sandwiched.data.sandwich_loss_b;
+        assert!(loss > 0);
+        assert_eq!(sandwiched.data.sandwich_loss_a, 0);
+        let cost_difference = sandwiched.data.rebalance_cost_b - plain.data.rebalance_cost_b;
+        assert!(cost_difference.abs_diff(loss) <= 1);
+        assert!(sandwiched.wallet.amount_token_b <= plain.wallet.amount_token_b);
+
+        // Entry swaps are never sandwiched.
+        let entry = new_backtest(Some(penalty), true);
+        assert_eq!(entry.data.sandwich_loss_b, 0);
+        assert_eq!(entry.start_info.entry_cost_b, plain.data.rebalance_cost_b);
+    }
+
+    #[tokio::test]
+    async fn test_max_drawdown_tracks_value_drop() {
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+
+        backtest
+            .execute_actions(vec![Action::CreatePosition {
+                position_id: "centered".to_string(),
+                lower_tick: -100,
+                upper_tick: 100,
+            }])
+            .unwrap();
+        reset_initialized_tick_caches(&mut backtest.liquidity_arr);
+
+        backtest.track_drawdown();
+        let peak = backtest.data.peak_value_b;
+        assert_eq!(backtest.data.max_drawdown_pct, 0.0);
+
+        // Selling token A pushes its price down, so the position and the wallet are worth less token B.
+        backtest
+            .liquidity_arr
+            .simulate_swap(U256::from(5_000_000), true)
+            .unwrap();
+        backtest.track_drawdown();
+
+        let value = backtest.current_value_b();
+        assert!(value < peak);
+        assert_eq!(backtest.data.peak_value_b, peak);
+        assert!((backtest.data.max_drawdown_pct - (peak - value) / peak * 100.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_liquidity_share_only_weights_time_in_range() {
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+        let pool_liquidity = backtest.liquidity_arr.active_liquidity.as_u128();
+        assert_eq!(backtest.liquidity_share(), 0.0);
+
+        backtest
+            .execute_actions(vec![Action::CreatePosition {
+                position_id: "centered".to_string(),
+                lower_tick: -100,
+                upper_tick: 100,
+            }])
+            .unwrap();
+
+        let my_liquidity = backtest.liquidity_arr.positions["centered"].liquidity;
+        let share = backtest.liquidity_share();
+        assert!(
+            (share - my_liquidity as f64 / (my_liquidity + pool_liquidity) as f64).abs() < 1e-12
+        );
+
+        backtest.track_liquidity_share(1_000);
+        backtest.track_liquidity_share(1_010);
+
+        backtest
+            .execute_actions(vec![Action::ClosePosition {
+                position_id: "centered".to_string(),
+            }])
+            .unwrap();
+        backtest.track_liquidity_share(1_020);
+        // Out of range (closed) for the next 30 seconds, which doesn't count.
+        backtest.track_liquidity_share(1_050);
+
+        assert_eq!(backtest.data.liquidity_share_seconds, 20);
+        assert!((backtest.data.average_liquidity_share() - share).abs() < 1e-12);
+    }
+
+    #[tokio::test]
+    async fn test_idle_value_is_weighted_between_samples() {
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+        assert_eq!(backtest.data.average_idle_and_total_value_b(), None);
+
+        // Everything idle for 10 seconds, then half of it deployed for 30.
+        let all_idle = backtest.idle_value_b();
+        backtest.track_idle_value(1_000);
+        backtest
+            .execute_actions(vec![Action::CreatePositionWithShare {
+                position_id: "half".to_string(),
+                lower_tick: -100,
+                upper_tick: 100,
+                wallet_share: 0.5,
+            }])
+            .unwrap();
+        let half_idle = backtest.idle_value_b();
+        let total = backtest.current_value_b();
+        backtest.track_idle_value(1_010);
+        backtest.track_idle_value(1_040);
+
+        assert_eq!(backtest.data.idle_value_seconds, 40);
+        let (avg_idle, avg_total) = backtest.data.average_idle_and_total_value_b().unwrap();
+        let expected_idle = (all_idle * 10.0 + half_idle * 30.0) / 40.0;
+        assert!((avg_idle - expected_idle).abs() / expected_idle < 1e-9);
+        assert!(half_idle < all_idle * 0.6);
+        assert!((avg_total - total).abs() / total < 1e-3);
+    }
+
+    #[test]
+    fn test_position_value_at_tick() {
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(NoRebalanceStrategy::new(-100, 100)),
+        );
+        backtest.initialize().unwrap();
+
+        let position_id = backtest
+            .liquidity_arr
+            .positions
+            .keys()
+            .next()
+            .unwrap()
+            .clone();
+        let position = backtest.liquidity_arr.positions[&position_id].clone();
+
+        // At the current tick it holds what was deposited.
+        let (amount_a, amount_b) = backtest.position_value_at_tick(&position_id, 0).unwrap();
+        assert_eq!(
+            (amount_a, amount_b),
+            calculate_amounts(
+                U256::from(position.liquidity),
+                backtest.liquidity_arr.current_sqrt_price,
+                tick_to_sqrt_price_u256(-100),
+                tick_to_sqrt_price_u256(100),
+            )
+        );
+
+        // Below the range it is all token A, above all token B.
+        let (below_a, below_b) = backtest.position_value_at_tick(&position_id, -500).unwrap();
+        assert!(below_a > amount_a && below_b.is_zero());
+        let (above_a, above_b) = backtest.position_value_at_tick(&position_id, 500).unwrap();
+        assert!(above_a.is_zero() && above_b > amount_b);
+
+        assert!(matches!(
+            backtest.position_value_at_tick("missing", 0),
+            Err(BacktestError::LiquidityArray(
+                LiquidityArrayError::PositionNotFound(_)
+            ))
+        ));
+    }
+
+    #[test]
+    fn test_sol_usdc_decimals_end_to_end() {
+        // SOL (9 decimals) / USDC (6 decimals) at ~149.5 USDC per SOL. Expectations are computed in f64 straight from the
+        // Uniswap v3 formulas on raw units, independent of the fixed point math, then converted to human units.
+        let (sol, usdc) = (1e9, 1e6);
+        let current_tick = -19_005;
+        let (lower_tick, upper_tick) = (-19_500, -18_500);
+        let pool_liquidity = 10_000_000_000_000_u128;
+        let my_liquidity = 70_000_000_000_u128;
+        let fee_rate = 3000;
+
+        let liquidity_arr = TestLiquidityArrayBuilder::new(-30_000, 0, 10, fee_rate)
+            .price_at_tick(current_tick)
+            .position(-25_000, -13_000, pool_liquidity)
+            .build();
+
+        let raw_sqrt_price = |tick: i32| 1.0001_f64.powi(tick).sqrt();
+        let human_price = |raw_sqrt: f64| raw_sqrt * raw_sqrt * sol / usdc;
+        let (sqrt_p, sqrt_pa, sqrt_pb) = (
+            raw_sqrt_price(current_tick),
+            raw_sqrt_price(lower_tick),
+            raw_sqrt_price(upper_tick),
+        );
+
+        assert!((liquidity_arr.current_price(9, 6) / human_price(sqrt_p) - 1.0).abs() < 1e-9);
+
+        let start_sol = U256::from(10 * 10_u128.pow(9));
+        let start_usdc = U256::from(2_000 * 10_u128.pow(6));
+        let wallet = Wallet {
+            token_a_addr: "SOL".to_string(),
+            token_b_addr: "USDC".to_string(),
+            amount_token_a: start_sol,
+            amount_token_b: start_usdc,
+            token_a_decimals: 9,
+            token_b_decimals: 6,
+            amount_a_fees_collected: U256::zero(),
+            amount_b_fees_collected: U256::zero(),
+        };
+        let mut backtest = Backtest::new(
+            start_sol,
+            start_usdc,
+            liquidity_arr,
+            wallet,
+            Box::new(MockStrategy),
+        );
+
+        backtest
+            .execute_actions(vec![Action::CreatePositionWithTargetLiquidity {
+                position_id: "sol_usdc".to_string(),
+                lower_tick,
+                upper_tick,
+                liquidity: my_liquidity,
+            }])
+            .unwrap();
+        reset_initialized_tick_caches(&mut backtest.liquidity_arr);
+
+        let human = |amount: U256, decimals: f64| amount.as_u128() as f64 / decimals;
+        let close = |actual: f64, expected: f64| (actual - expected).abs() <= expected.abs() * 1e-6;
+
+        // Deposit: x = L * (1/sqrtP - 1/sqrtPb), y = L * (sqrtP - sqrtPa).
+        let l = my_liquidity as f64;
+        let deposit_sol = l * (1.0 / sqrt_p - 1.0 / sqrt_pb) / sol;
+        let deposit_usdc = l * (sqrt_p - sqrt_pa) / usdc;
+        assert!(close(
+            10.0 - human(backtest.wallet.amount_token_a, sol),
+            deposit_sol
+        ));
+        assert!(close(
+            2_000.0 - human(backtest.wallet.amount_token_b, usdc),
+            deposit_usdc
+        ));
+
+        // Sell 1 SOL. The fee comes off the input, the rest moves 1/sqrtP by amount / active liquidity.
+        let total_l = (pool_liquidity + my_liquidity) as f64;
+        let sol_in_net = 1e9 * (1.0 - fee_rate as f64 / 1e6);
+        let sqrt_p_after = 1.0 / (1.0 / sqrt_p + sol_in_net / total_l);
+        let expected_usdc_out = total_l * (sqrt_p - sqrt_p_after) / usdc;
+
+        let usdc_out = backtest
+            .liquidity_arr
+            .simulate_swap(U256::from(1_000_000_000_u64), true)
+            .unwrap();
+        assert!(close(human(usdc_out, usdc), expected_usdc_out));
+        assert!(
+            (backtest.liquidity_arr.current_price(9, 6) / human_price(sqrt_p_after) - 1.0).abs()
+                < 1e-9
+        );
+
+        backtest
+            .execute_actions(vec![Action::ClosePosition {
+                position_id: "sol_usdc".to_string(),
+            }])
+            .unwrap();
+
+        // Our share of the fee, paid in SOL, and the principal back at the new price.
+        let expected_fee_sol = 1.0 * fee_rate as f64 / 1e6 * l / total_l;
+        let withdrawn_sol = l * (1.0 / sqrt_p_after - 1.0 / sqrt_pb) / sol;
+        let withdrawn_usdc = l * (sqrt_p_after - sqrt_pa) / usdc;
+
+        // Only ~20k raw units, so allow for the rounding down of the fee growth instead of a relative tolerance.
+        let fee_sol = human(backtest.wallet.amount_a_fees_collected, sol);
+        assert!((fee_sol - expected_fee_sol).abs() <= 2.0 / sol);
+        assert!(backtest.wallet.amount_b_fees_collected.is_zero());
+
+        let end_sol = human(backtest.wallet.amount_token_a, sol);
+        let end_usdc = human(backtest.wallet.amount_token_b, usdc);
+        assert!(close(
+            end_sol,
+            10.0 - deposit_sol + withdrawn_sol + expected_fee_sol
+        ));
+        assert!(close(end_usdc, 2_000.0 - deposit_usdc + withdrawn_usdc));
+
+        // USD value with USDC pegged, the way the report prices it.
+        let end_price = pool_price(backtest.liquidity_arr.current_sqrt_price, 9, 6);
+        let (sol_usd, usdc_usd) = pegged_prices_usd(StableToken::B, end_price);
+        assert_eq!(usdc_usd, 1.0);
+        assert!(close(sol_usd, human_price(sqrt_p_after)));
+        assert!(close(
+            end_sol * sol_usd + end_usdc * usdc_usd,
+            (10.0 - deposit_sol + withdrawn_sol + expected_fee_sol) * human_price(sqrt_p_after)
+                + 2_000.0
+                - deposit_usdc
+                + withdrawn_usdc
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fee_rate_change_mid_window_changes_fees() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(20, 1_000_000, "TokenA", "TokenB"),
+        };
+
+        let run = |fee_rate_history: Vec<FeeRateChange>| {
+            let mut liquidity_arr = create_test_liquidity_array(0);
+            liquidity_arr.set_fee_rate_history(fee_rate_history);
+
+            let wallet = test_wallet(U256::zero(), U256::zero());
+
+            Backtest::new(
+                U256::zero(),
+                U256::zero(),
+                liquidity_arr,
+                wallet,
+                Box::new(MockStrategy),
+            )
+        };
+
+        let mut single_rate = run(vec![]);
+        single_rate
+            .sync_forward(&repo, 21, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        // Same 500 rate for the first half, then the pool moves to a 3000 tier.
+        let mut fee_change = run(vec![
+            FeeRateChange {
+                effective_from: 0,
+                fee_rate: 500,
+            },
+            FeeRateChange {
+                effective_from: 1_700_000_010,
+                fee_rate: 3000,
+            },
+        ]);
+        fee_change
+            .sync_forward(&repo, 21, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        let total_growth = |backtest: &Backtest| {
+            backtest.liquidity_arr.fee_growth_global_a + backtest.liquidity_arr.fee_growth_global_b
+        };
+
+        assert!(total_growth(&fee_change) > total_growth(&single_rate));
+        assert_eq!(fee_change.liquidity_arr.fee_rate, 3000);
+        assert_eq!(single_rate.liquidity_arr.fee_rate, 500);
+    }
+
+    #[tokio::test]
+    async fn test_sync_forward_samples_position_timeline() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(20, 1_000, "TokenA", "TokenB"),
+        };
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(LadderStrategy::new(-200, 200, 2, 10).unwrap()),
+        );
+        backtest.sample_interval_seconds = Some(5);
+
+        backtest
+            .sync_forward(&repo, 21, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        // Swaps are one second apart starting at 1_700_000_001, so one sample every 5th swap.
+        let timeline = backtest.data_logger.position_timeline();
+        let timestamps: Vec<i64> = timeline
+            .iter()
+            .map(|sample| sample.current_block_time)
+            .collect();
+        assert_eq!(
+            timestamps,
+            vec![1_700_000_001, 1_700_000_006, 1_700_000_011, 1_700_000_016]
+        );
+        let swap_nmrs: Vec<u128> = timeline
+            .iter()
+            .map(|sample| sample.current_swap_nmr)
+            .collect();
+        assert_eq!(swap_nmrs, vec![1, 6, 11, 16]);
+
+        for sample in timeline {
+            let ranges: Vec<(&str, i32, i32)> = sample
+                .positions
+                .iter()
+                .map(|p| (p.position_id.as_str(), p.lower_tick, p.upper_tick))
+                .collect();
+            assert_eq!(ranges, vec![("ladder_0", -200, 0), ("ladder_1", 0, 200)]);
+        }
+    }
+
+    // Recreates its position on every transaction, whatever the price does.
+    struct TwitchyStrategy;
+
+    impl Strategy for TwitchyStrategy {
+        fn initialize_strategy(&self) -> Vec<Action> {
+            vec![Action::CreatePosition {
+                position_id: "twitchy".to_string(),
+                lower_tick: -100,
+                upper_tick: 100,
+            }]
+        }
+
+        fn update(
+            &mut self,
+            _liquidity_array: &LiquidityArray,
+            _transaction: TransactionModelFromDB,
+        ) -> Vec<Action> {
+            vec![
+                Action::ClosePosition {
+                    position_id: "twitchy".to_string(),
+                },
+                Action::CreatePosition {
+                    position_id: "twitchy".to_string(),
+                    lower_tick: -100,
+                    upper_tick: 100,
+                },
+            ]
+        }
+
+        fn on_window_end(&self, liquidity_array: &LiquidityArray) -> Vec<Action> {
+            liquidity_array
+                .positions
+                .keys()
+                .map(|position_id| Action::ClosePosition {
+                    position_id: position_id.clone(),
+                })
+                .collect()
+        }
+
+        fn get_ticks(&self) -> (i32, i32) {
+            (-100, 100)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sync_forward_stops_at_max_actions() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(20, 1_000, "TokenA", "TokenB"),
+        };
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(TwitchyStrategy),
+        );
+        backtest.max_actions = Some(7);
+
+        backtest
+            .sync_forward(&repo, 21, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        // 2 actions per transaction, the 4th rebalance would go past 7.
+        assert!(backtest.max_actions_hit);
+        assert_eq!(backtest.strategy_actions_executed, 6);
+        assert_eq!(backtest.data.current_swap_nmr, 4);
+
+        // Still finalized, nothing left open.
+        assert!(backtest.liquidity_arr.positions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_saved_state_resumes_with_open_positions() {
+        let mut transactions = create_test_swap_transactions(20, 1_000, "TokenA", "TokenB");
+        // tx 12 and tx 11 share a block, the first run stops in the middle of it.
+        transactions[9].block_time = transactions[8].block_time;
+        transactions[9].block_time_utc = transactions[8].block_time_utc;
+        let uninterrupted_repo = InMemoryTransactionRepo {
+            transactions: transactions.clone(),
+        };
+        // The first run only had the first sync, tx 20 down to 11. The sync after it appended the rest, with tx_ids
+        // above everything the first run saw (21 up to 30 in time order).
+        let first_sync_repo = InMemoryTransactionRepo {
+            transactions: transactions[..10].to_vec(),
+        };
+        for transaction in &mut transactions[10..] {
+            transaction.tx_id = 31 - transaction.tx_id;
+        }
+        let end_time = transactions[19].block_time;
+        let synced_again_repo = InMemoryTransactionRepo { transactions };
+        let path = std::env::temp_dir().join(format!("backtest_state_{}.json", std::process::id()));
+        let path_str = path.to_str().unwrap();
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let new_backtest = || {
+            let mut liquidity_arr = create_test_liquidity_array(0);
+            liquidity_arr.current_block_time = 1_700_000_001;
+
+            Backtest::new(
+                amount,
+                amount,
+                liquidity_arr,
+                test_wallet(amount, amount),
+                Box::new(SimpleRebalanceStrategy::new(100, 200)),
+            )
+        };
+
+        let mut uninterrupted = new_backtest();
+        uninterrupted
+            .sync_forward(&uninterrupted_repo, 21, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        // Replays the 9 oldest transactions, 20 down to 12.
+        let mut backtest = new_backtest();
+        backtest.save_state_to = Some(path_str.to_string());
+        backtest
+            .sync_forward(&first_sync_repo, 21, 12, "test_pool", 100)
+            .await
+            .unwrap();
+        assert!(backtest.liquidity_arr.positions.is_empty());
+
+        // Built around another tick, resume_strategy takes the range from the saved position.
+        let (mut resumed, resume) = Backtest::load_state(
+            path_str,
+            Box::new(SimpleRebalanceStrategy::new(5_000, 200)),
+            &synced_again_repo,
+            "test_pool",
+        )
+        .await
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(resumed.is_resumed);
+        assert_eq!(resumed.strategy.get_ticks(), (-100, 100));
+        let position = &resumed.liquidity_arr.positions["simple_rebalance"];
+        assert_eq!((position.lower_tick, position.upper_tick), (-100, 100));
+
+        assert_eq!(resumed.data.current_swap_nmr, 9);
+        assert_eq!(resumed.start_info.token_a_amount, amount);
+        assert_eq!(resumed.data_logger.position_records().len(), 1);
+
+        // The report still measures from the first transaction, the replay goes on with tx 11 in the saved block.
+        assert_eq!(resume.window_start_tx.tx_id, 20);
+        assert_eq!(
+            resume.replay_position,
+            ReplayPosition {
+                block_time: 1_700_000_009,
+                tx_ids: vec![12],
+            }
+        );
+
+        resumed
+            .resume_forward(
+                &synced_again_repo,
+                &resume.replay_position,
+                end_time,
+                "test_pool",
+                4,
+            )
+            .await
+            .unwrap();
+
+        // Picking the saved state back up ends where the replay in one go does.
+        assert_eq!(resumed.data.current_swap_nmr, 20);
+        assert_eq!(
+            resumed.liquidity_arr.current_sqrt_price,
+            uninterrupted.liquidity_arr.current_sqrt_price
+        );
+        assert_eq!(
+            resumed.liquidity_arr.current_block_time,
+            uninterrupted.liquidity_arr.current_block_time
+        );
+        assert_eq!(
+            resumed.wallet.amount_a_fees_collected,
+            uninterrupted.wallet.amount_a_fees_collected
+        );
+        assert_eq!(
+            resumed.wallet.amount_b_fees_collected,
+            uninterrupted.wallet.amount_b_fees_collected
+        );
+        assert_eq!(
+            resumed.data_logger.position_records().len(),
+            uninterrupted.data_logger.position_records().len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sync_forward_single_transaction_window() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(5, 1_000, "TokenA", "TokenB"),
+        };
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+
+        // The start transaction is the starting state, the window has nothing after it to replay. The transactions
+        // below tx 3 are outside the window and stay untouched.
+        backtest
+            .sync_forward(&repo, 3, 3, "test_pool", 100)
+            .await
+            .unwrap();
+
+        assert_eq!(backtest.data.current_swap_nmr, 0);
+        assert_eq!(backtest.liquidity_arr.current_tick, 0);
+    }
+
+    #[tokio::test]
+    async fn test_sync_forward_checks_the_reconstruction_checkpoint() {
+        let transactions = create_test_swap_transactions(20, 50_000_000, "TokenA", "TokenB");
+
+        // The pool right after the 10th swap (tx 11), the last transaction at or before the checkpoint.
+        let mut expected = create_test_liquidity_array(0);
+        for transaction in &transactions[..10] {
+            let swap_data = transaction.data.to_swap_data().unwrap();
+            expected
+                .simulate_swap(
+                    U256::from(swap_data.amount_in),
+                    swap_data.token_in == "TokenA",
+                )
+                .unwrap();
+        }
+        let checkpoint = ReconstructionCheckpoint {
+            timestamp: transactions[9].block_time_utc,
+            expected_tick: expected.current_tick,
+            expected_liquidity: expected.active_liquidity.as_u128(),
+            tick_tolerance: 0,
+            liquidity_tolerance_bps: 0,
+            strict: true,
+        };
+        let repo = InMemoryTransactionRepo { transactions };
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        for (tick_offset, should_match) in [(0, true), (1, false)] {
+            let wallet = test_wallet(amount, amount);
+            let mut backtest = Backtest::new(
+                amount,
+                amount,
+                create_test_liquidity_array(0),
+                wallet,
+                Box::new(MockStrategy),
+            );
+            backtest.reconstruction_checkpoint = Some(ReconstructionCheckpoint {
+                expected_tick: checkpoint.expected_tick + tick_offset,
+                ..checkpoint.clone()
+            });
+
+            let result = backtest.sync_forward(&repo, 21, 1, "test_pool", 100).await;
+
+            if should_match {
+                assert!(result.is_ok());
+            } else {
+                assert!(matches!(result, Err(SyncError::CheckpointMismatch(_))));
+                // Stopped at the checkpoint.
+                assert_eq!(backtest.data.current_swap_nmr, 10);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_window_end_closes_are_tagged_forced() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(3, 1_000, "TokenA", "TokenB"),
+        };
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(TwitchyStrategy),
+        );
+
+        backtest
+            .sync_forward(&repo, 4, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        let close_reasons: Vec<String> = backtest
+            .data_logger
+            .entries()
+            .iter()
+            .map(|entry| serde_json::to_value(entry).unwrap())
+            .filter(|entry| entry["action"] == "ClosePosition")
+            .map(|entry| entry["close_reason"].as_str().unwrap().to_string())
+            .collect();
+
+        // One rebalance per swap, then the position left open when the window ran out.
+        assert_eq!(
+            close_reasons,
+            vec!["strategy", "strategy", "strategy", "forced"]
+        );
+        assert!(backtest.liquidity_arr.positions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_source_program_filter_skips_tagged_swaps() {
+        async fn run(
+            transactions: Vec<TransactionModelFromDB>,
+            filter: Option<SourceProgramFilter>,
+        ) -> Backtest {
+            let amount = U256::from(100 * 10_i32.pow(6));
+            let wallet = test_wallet(amount, amount);
+
+            let mut backtest = Backtest::new(
+                amount,
+                amount,
+                create_test_liquidity_array(0),
+                wallet,
+                Box::new(NoRebalanceStrategy::new(-100, 100)),
+            );
+            backtest.source_program_filter = filter;
+
+            backtest
+                .sync_forward(
+                    &InMemoryTransactionRepo { transactions },
+                    5,
+                    1,
+                    "test_pool",
+                    100,
+                )
+                .await
+                .unwrap();
+
+            backtest
+        }
+
+        // The two newest swaps (lowest tx_ids) come from a bot.
+        let mut transactions = create_test_swap_transactions(4, 10_000_000, "TokenA", "TokenB");
+        for transaction in transactions.iter_mut().filter(|t| t.tx_id <= 2) {
+            if let crate::models::transactions_model::TransactionData::Swap(swap_data) =
+                &mut transaction.data
+            {
+                swap_data.source_program = Some("BotProgram".to_string());
+            }
+        }
+        let organic: Vec<TransactionModelFromDB> = transactions
+            .iter()
+            .filter(|t| t.tx_id > 2)
+            .cloned()
+            .collect();
+
+        let everything = run(transactions.clone(), None).await;
+        let filtered = run(
+            transactions,
+            Some(SourceProgramFilter::Exclude(vec!["BotProgram".to_string()])),
+        )
+        .await;
+        let organic_only = run(organic, None).await;
+
+        assert_eq!(everything.data.current_swap_nmr, 4);
+        assert_eq!(filtered.data.current_swap_nmr, 2);
+        assert_eq!(filtered.data.swaps_filtered_by_source, 2);
+
+        // Filtering is the same as the bot's swaps never happening.
+        assert_eq!(
+            filtered.data.current_token_a_volume,
+            organic_only.data.current_token_a_volume
+        );
+        assert_eq!(
+            filtered.data.current_token_b_volume,
+            organic_only.data.current_token_b_volume
+        );
+        assert_eq!(
+            filtered.wallet.amount_a_fees_collected,
+            organic_only.wallet.amount_a_fees_collected
+        );
+        assert_eq!(
+            filtered.wallet.amount_b_fees_collected,
+            organic_only.wallet.amount_b_fees_collected
+        );
+        assert!(
+            filtered.wallet.amount_a_fees_collected + filtered.wallet.amount_b_fees_collected
+                < everything.wallet.amount_a_fees_collected
+                    + everything.wallet.amount_b_fees_collected
+        );
+
+        let only = SourceProgramFilter::Only(vec!["BotProgram".to_string()]);
+        assert!(only.allows(Some("BotProgram")));
+        assert!(!only.allows(None));
+        assert!(SourceProgramFilter::Exclude(vec!["BotProgram".to_string()]).allows(None));
+    }
+
+    #[tokio::test]
+    async fn test_dust_swaps_below_min_amount_are_skipped() {
+        async fn run(
+            transactions: Vec<TransactionModelFromDB>,
+            min_swap_amount_in: u64,
+        ) -> Backtest {
+            let amount = U256::from(100 * 10_i32.pow(6));
+            let wallet = test_wallet(amount, amount);
+
+            let mut backtest = Backtest::new(
+                amount,
+                amount,
+                create_test_liquidity_array(0),
+                wallet,
+                Box::new(NoRebalanceStrategy::new(-100, 100)),
+            );
+            backtest.min_swap_amount_in = min_swap_amount_in;
+
+            backtest
+                .sync_forward(
+                    &InMemoryTransactionRepo { transactions },
+                    5,
+                    1,
+                    "test_pool",
+                    100,
+                )
+                .await
+                .unwrap();
+
+            backtest
+        }
+
+        let dust = create_test_swap_transactions(2, 1_000, "TokenA", "TokenB");
+
+        let replayed = run(dust.clone(), 0).await;
+        let skipped = run(dust, 1_001).await;
+        let no_swaps = run(Vec::new(), 0).await;
+
+        assert_eq!(replayed.data.current_swap_nmr, 2);
+        assert_ne!(
+            replayed.liquidity_arr.current_sqrt_price,
+            no_swaps.liquidity_arr.current_sqrt_price
+        );
+
+        assert_eq!(skipped.data.dust_swaps_skipped, 2);
+        assert_eq!(skipped.data.current_swap_nmr, 0);
+        assert_eq!(skipped.data.current_token_a_volume, 0);
+        assert_eq!(skipped.data.current_token_b_volume, 0);
+        assert_eq!(
+            skipped.liquidity_arr.current_sqrt_price,
+            no_swaps.liquidity_arr.current_sqrt_price
+        );
+    }
+
+    #[tokio::test]
+    async fn test_window_end_fees_can_be_left_uncollected() {
+        let mut fees_at_end = Vec::new();
+
+        for count_fees_at_end in [true, false] {
+            let repo = InMemoryTransactionRepo {
+                transactions: create_test_swap_transactions(3, 1_000_000, "TokenA", "TokenB"),
+            };
+
+            let amount = U256::from(100 * 10_i32.pow(6));
+            let wallet = test_wallet(amount, amount);
+
+            let mut backtest = Backtest::new(
+                amount,
+                amount,
+                create_test_liquidity_array(0),
+                wallet,
+                Box::new(NoRebalanceStrategy::new(-100, 100)),
+            );
+            backtest.count_fees_at_end = count_fees_at_end;
+
+            backtest
+                .sync_forward(&repo, 4, 1, "test_pool", 100)
+                .await
+                .unwrap();
+
+            fees_at_end.push((
+                backtest.wallet.amount_a_fees_collected.as_u128(),
+                backtest.wallet.amount_b_fees_collected.as_u128(),
+                backtest.data.fees_a_uncollected_at_end,
+                backtest.data.fees_b_uncollected_at_end,
+                backtest.wallet.amount_token_a.as_u128(),
+            ));
+        }
+
+        let (collected_a, collected_b, none_a, none_b, wallet_a_with_fees) = fees_at_end[0];
+        assert!(collected_a > 0 && collected_b > 0);
+        assert_eq!((none_a, none_b), (0, 0));
+
+        // Same fees, just moved out of the wallet.
+        let (zero_a, zero_b, uncollected_a, uncollected_b, wallet_a) = fees_at_end[1];
+        assert_eq!((zero_a, zero_b), (0, 0));
+        assert_eq!((uncollected_a, uncollected_b), (collected_a, collected_b));
+        assert_eq!(wallet_a + collected_a, wallet_a_with_fees);
+    }
+
+    #[test]
+    fn test_run_fees_are_split_into_a_basket_at_the_collection_price() {
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+        // Tick 6932 is a price of ~2 B per A.
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(6932),
+            wallet,
+            Box::new(MockStrategy),
+        );
+        let price = backtest.liquidity_arr.current_price(0, 0);
+
+        // Worth 2000 + 1000 B, half of it ends up on each side.
+        backtest.add_fees_to_basket(U256::from(1000), U256::from(1000));
+
+        assert!((backtest.data.fee_basket_b - 1500.0).abs() < 5.0);
+        assert!((backtest.data.fee_basket_a * price - 1500.0).abs() < 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_sync_forward_throttles_between_swaps() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(10, 1_000, "TokenA", "TokenB"),
+        };
+
+        let amount = U256::from(100 * 10_i32.pow(6));
+        let wallet = test_wallet(amount, amount);
+
+        let mut backtest = Backtest::new(
+            amount,
+            amount,
+            create_test_liquidity_array(0),
+            wallet,
+            Box::new(MockStrategy),
+        );
+        backtest.throttle = Some(Duration::from_millis(10));
+
+        let started = std::time::Instant::now();
+        backtest
+            .sync_forward(&repo, 11, 1, "test_pool", 100)
+            .await
+            .unwrap();
+
+        assert_eq!(backtest.data.current_swap_nmr, 10);
+        assert!(started.elapsed() >= Duration::from_millis(100));
+    }
 }
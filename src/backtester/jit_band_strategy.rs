@@ -0,0 +1,136 @@
+use anyhow::{anyhow, Result};
+
+use crate::models::transactions_model::TransactionModelFromDB;
+
+use super::{
+    backtester_core::{Action, Strategy},
+    liquidity_array::LiquidityArray,
+};
+
+const POSITION_ID: &str = "jit_band";
+
+// Stays in the wallet and only deploys while the price is inside the band [band_lower_tick, band_upper_tick).
+// On entering it opens a position centered on the current tick, on leaving it closes it and waits for the next entry.
+pub struct JitBandStrategy {
+    band_lower_tick: i32,
+    band_upper_tick: i32,
+    position_half_width: i32,
+    tick_spacing: i32,
+    // Ticks of the open position, None while sitting in the wallet.
+    open_ticks: Option<(i32, i32)>,
+}
+
+impl JitBandStrategy {
+    pub fn new(
+        band_lower_tick: i32,
+        band_upper_tick: i32,
+        position_half_width: i32,
+        tick_spacing: i32,
+    ) -> Result<Self> {
+        if band_lower_tick >= band_upper_tick {
+            return Err(anyhow!(
+                "Band lower tick {} must be below upper tick {}",
+                band_lower_tick,
+                band_upper_tick
+            ));
+        }
+        if position_half_width <= 0 {
+            return Err(anyhow!(
+                "Position half width must be positive, got {}",
+                position_half_width
+            ));
+        }
+        if tick_spacing <= 0 {
+            return Err(anyhow!(
+                "Tick spacing must be positive, got {}",
+                tick_spacing
+            ));
+        }
+
+        Ok(Self {
+            band_lower_tick,
+            band_upper_tick,
+            position_half_width,
+            tick_spacing,
+            open_ticks: None,
+        })
+    }
+
+    // ±position_half_width around tick, widened to the nearest tick_spacing multiples since positions can only start
+    // and end on those on-chain.
+    fn position_ticks(&self, tick: i32) -> (i32, i32) {
+        let lower_tick = (tick - self.position_half_width).div_euclid(self.tick_spacing);
+        let upper_tick = -(-(tick + self.position_half_width)).div_euclid(self.tick_spacing);
+
+        (
+            lower_tick * self.tick_spacing,
+            upper_tick * self.tick_spacing,
+        )
+    }
+
+    fn in_band(&self, tick: i32) -> bool {
+        tick >= self.band_lower_tick && tick < self.band_upper_tick
+    }
+}
+
+impl Strategy for JitBandStrategy {
+    // Starts in the wallet, the first swap inside the band opens the position.
+    fn initialize_strategy(&self) -> Vec<Action> {
+        vec![]
+    }
+
+    fn update(
+        &mut self,
+        liquidity_array: &LiquidityArray,
+        transaction: TransactionModelFromDB,
+    ) -> Vec<Action> {
+        if transaction.transaction_type != "Swap" {
+            return vec![];
+        }
+
+        let current_tick = liquidity_array.current_tick;
+
+        match (self.in_band(current_tick), self.open_ticks) {
+            (true, None) => {
+                let (lower_tick, upper_tick) = self.position_ticks(current_tick);
+                self.open_ticks = Some((lower_tick, upper_tick));
+
+                vec![Action::CreatePosition {
+                    position_id: String::from(POSITION_ID),
+                    lower_tick,
+                    upper_tick,
+                }]
+            }
+            (false, Some(_)) => {
+                self.open_ticks = None;
+
+                vec![Action::ClosePosition {
+                    position_id: String::from(POSITION_ID),
+                }]
+            }
+            _ => vec![],
+        }
+    }
+
+    fn resume_strategy(&mut self, liquidity_array: &LiquidityArray) {
+        self.open_ticks = liquidity_array
+            .positions
+            .get(POSITION_ID)
+            .map(|position| (position.lower_tick, position.upper_tick));
+    }
+
+    fn on_window_end(&self, liquidity_array: &LiquidityArray) -> Vec<Action> {
+        let position_id = String::from(POSITION_ID);
+
+        if liquidity_array.positions.contains_key(&position_id) {
+            vec![Action::ClosePosition { position_id }]
+        } else {
+            vec![]
+        }
+    }
+
+    // An empty range while idle, so no swap counts as in position.
+    fn get_ticks(&self) -> (i32, i32) {
+        self.open_ticks.unwrap_or((i32::MAX, i32::MIN))
+    }
+}
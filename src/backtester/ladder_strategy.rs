@@ -0,0 +1,122 @@
+use anyhow::{anyhow, Result};
+
+use crate::models::transactions_model::TransactionModelFromDB;
+
+use super::{
+    backtester_core::{Action, Strategy},
+    liquidity_array::LiquidityArray,
+};
+
+// Tiles the range with num_rungs equal, contiguous positions (a "liquidity ladder") instead of one wide position.
+// The wallet is split evenly between the rungs and nothing is rebalanced until the end.
+pub struct LadderStrategy {
+    lower_tick: i32,
+    upper_tick: i32,
+    num_rungs: u32,
+    tick_spacing: i32,
+}
+
+impl LadderStrategy {
+    // The range is widened to the nearest tick_spacing multiples, positions can only start and end on those on-chain.
+    // Every rung has to be at least one tick_spacing wide.
+    pub fn new(
+        lower_tick: i32,
+        upper_tick: i32,
+        num_rungs: u32,
+        tick_spacing: i32,
+    ) -> Result<Self> {
+        if num_rungs == 0 {
+            return Err(anyhow!("Ladder needs at least one rung"));
+        }
+        if tick_spacing <= 0 {
+            return Err(anyhow!(
+                "Tick spacing must be positive, got {}",
+                tick_spacing
+            ));
+        }
+        if lower_tick >= upper_tick {
+            return Err(anyhow!(
+                "Ladder lower tick {} must be below upper tick {}",
+                lower_tick,
+                upper_tick
+            ));
+        }
+
+        let lower_tick = lower_tick.div_euclid(tick_spacing) * tick_spacing;
+        let upper_tick = -(-upper_tick).div_euclid(tick_spacing) * tick_spacing;
+
+        let spacings = (upper_tick - lower_tick) / tick_spacing;
+        if spacings < num_rungs as i32 {
+            return Err(anyhow!(
+                "Ladder range [{}, {}] is {} tick spacings wide, too narrow for {} rungs",
+                lower_tick,
+                upper_tick,
+                spacings,
+                num_rungs
+            ));
+        }
+
+        Ok(Self {
+            lower_tick,
+            upper_tick,
+            num_rungs,
+            tick_spacing,
+        })
+    }
+
+    fn position_id(rung: u32) -> String {
+        format!("ladder_{}", rung)
+    }
+
+    // (lower, upper) ticks for every rung, all on tick_spacing multiples. Spacings that don't divide evenly are spread
+    // over the rungs, so widths differ by at most one tick_spacing and the ladder ends exactly at upper_tick.
+    pub fn rung_ticks(&self) -> Vec<(i32, i32)> {
+        let spacings = ((self.upper_tick - self.lower_tick) / self.tick_spacing) as i64;
+        let boundary = |rung: u32| {
+            self.lower_tick
+                + (spacings * rung as i64 / self.num_rungs as i64) as i32 * self.tick_spacing
+        };
+
+        (0..self.num_rungs)
+            .map(|rung| (boundary(rung), boundary(rung + 1)))
+            .collect()
+    }
+}
+
+impl Strategy for LadderStrategy {
+    fn initialize_strategy(&self) -> Vec<Action> {
+        // Shares are of what is left in the wallet at that point, so 1/n, 1/(n-1), ... 1/1 splits it evenly.
+        self.rung_ticks()
+            .into_iter()
+            .enumerate()
+            .map(
+                |(rung, (lower_tick, upper_tick))| Action::CreatePositionWithShare {
+                    position_id: Self::position_id(rung as u32),
+                    lower_tick,
+                    upper_tick,
+                    wallet_share: 1.0 / (self.num_rungs - rung as u32) as f64,
+                },
+            )
+            .collect()
+    }
+
+    fn update(
+        &mut self,
+        _liquidity_array: &LiquidityArray,
+        _transaction: TransactionModelFromDB,
+    ) -> Vec<Action> {
+        vec![]
+    }
+
+    fn on_window_end(&self, liquidity_array: &LiquidityArray) -> Vec<Action> {
+        (0..self.num_rungs)
+            .map(Self::position_id)
+            .filter(|position_id| liquidity_array.positions.contains_key(position_id))
+            .map(|position_id| Action::ClosePosition { position_id })
+            .collect()
+    }
+
+    fn get_ticks(&self) -> (i32, i32) {
+        (self.lower_tick, self.upper_tick)
+    }
+}
@@ -1,11 +1,22 @@
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
 
 use crate::utils::{
-    core_math::{calculate_amounts, calculate_new_sqrt_price, tick_to_sqrt_price_u256, Q128, U256},
+    core_math::{
+        calculate_amount_a_delta, calculate_amount_b_delta, calculate_amounts, calculate_liquidity,
+        calculate_new_sqrt_price, fee_growth_to_onchain_q64, mul_div_round_up, sqrt_price_to_price,
+        tick_to_sqrt_price_u256, u256_to_f64, Q128, U256,
+    },
     error::LiquidityArrayError,
 };
 
-#[derive(Debug, Clone, Copy)]
+// Safety limit on the initialized ticks a single swap may cross, see max_tick_crossings.
+pub const DEFAULT_MAX_TICK_CROSSINGS: usize = 10_000;
+
+// Swaps kept for recent_fee_growth_per_second, older ones drop out.
+pub const FEE_VELOCITY_WINDOW: usize = 64;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct TickData {
     pub tick: i32,
     // FEES SCALED BY Q128.
@@ -15,10 +26,26 @@ pub struct TickData {
     pub net_liquidity: i128,
     // created this so we can keep a static array to make an easier architecture.
     pub is_initialized: bool,
-    pub gross_liquidity: i128,
+    // u128 like on-chain, deep pools can have more than i128::MAX referencing a tick.
+    pub gross_liquidity: u128,
 }
 
-#[derive(Debug, Clone)]
+// Global fee growth right after a swap, see recent_fee_growth_per_second.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FeeGrowthSample {
+    pub block_time: i64,
+    pub fee_growth_global_a: U256,
+    pub fee_growth_global_b: U256,
+}
+
+// Fee tier in effect for swaps from effective_from (unix seconds, compared against block_time) onwards.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FeeRateChange {
+    pub effective_from: i64,
+    pub fee_rate: i16,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LiquidityArray {
     pub data: Vec<TickData>,
     pub positions: HashMap<String, OwnersPosition>,
@@ -28,6 +55,7 @@ pub struct LiquidityArray {
     pub fee_growth_global_a: U256,
     pub fee_growth_global_b: U256,
     pub min_tick: i32,
+    pub max_tick: i32,
     pub fee_rate: i16,
     pub tick_spacing: i32,
     pub current_tick: i32,
@@ -36,27 +64,49 @@ pub struct LiquidityArray {
     pub current_sqrt_price: U256,
     pub cached_upper_initialized_tick: Option<i32>,
     pub cached_lower_initialized_tick: Option<i32>,
+    // Sorted by effective_from. Empty means fee_rate applies to the whole window.
+    pub fee_rate_history: Vec<FeeRateChange>,
+    // A swap crossing more initialized ticks than this errors out instead of grinding through them. None = no limit.
+    pub max_tick_crossings: Option<usize>,
+    // The last FEE_VELOCITY_WINDOW swaps' fee growth, oldest first.
+    pub recent_fee_growth: VecDeque<FeeGrowthSample>,
+    // Swaps that ran into an array edge with input left over, that part was never swapped (see simulate_swap).
+    #[serde(default)]
+    pub swaps_stopped_at_edge: u64,
+    // Ticks that were emptied with net liquidity left over, which was reset to zero (see uninitialize_tick).
+    #[serde(default)]
+    pub ticks_net_liquidity_reset: u64,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OwnersPosition {
     pub owner: String,
     pub lower_tick: i32,
     pub upper_tick: i32,
-    pub liquidity: i128,
+    pub liquidity: u128,
     pub fee_growth_inside_a_last: U256,
     pub fee_growth_inside_b_last: U256,
 }
 
-// The liquidity array is a static array where we create 1M  indices (except for testing), to represent form -500k to 500k tick range.
+// What merge_positions hands back to the caller (the wallet), on top of the merged position itself.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MergeOutcome {
+    pub fees_a: U256,
+    pub fees_b: U256,
+    // Tokens of the old positions that didn't fit the merged range's ratio at the current price.
+    pub leftover_a: U256,
+    pub leftover_b: U256,
+    pub liquidity: U256,
+}
+
+// The liquidity array is a static array with one index per tick from min_tick to max_tick (see TickBounds for how the range is picked).
 // Each index contains the TickData where u have tick, fee growth, net liquidity.
 impl LiquidityArray {
     pub fn new(min_tick: i32, max_tick: i32, tick_spacing: i32, fee_rate: i16) -> Self {
         let size = ((max_tick - min_tick) as usize) + 1; // +1 due to arr nature
         let mut data = Vec::with_capacity(size);
-        let mut current_tick = min_tick;
 
-        for _ in 0..size {
+        for current_tick in min_tick..=max_tick {
             data.push(TickData {
                 net_liquidity: 0,
                 gross_liquidity: 0,
@@ -65,14 +115,13 @@ impl LiquidityArray {
                 fee_growth_outside_a: U256::zero(),
                 fee_growth_outside_b: U256::zero(),
             });
-
-            current_tick += 1;
         }
 
         LiquidityArray {
             data,
             positions: HashMap::new(),
             min_tick,
+            max_tick,
             fee_rate,
             tick_spacing,
             current_tick: 0,
@@ -83,12 +132,107 @@ impl LiquidityArray {
             current_sqrt_price: U256::zero(),
             cached_lower_initialized_tick: None,
             cached_upper_initialized_tick: None,
+            fee_rate_history: Vec::new(),
+            max_tick_crossings: Some(DEFAULT_MAX_TICK_CROSSINGS),
+            recent_fee_growth: VecDeque::with_capacity(FEE_VELOCITY_WINDOW),
+            swaps_stopped_at_edge: 0,
+            ticks_net_liquidity_reset: 0,
+        }
+    }
+
+    pub fn set_fee_rate_history(&mut self, mut fee_rate_history: Vec<FeeRateChange>) {
+        fee_rate_history.sort_by_key(|change| change.effective_from);
+        self.fee_rate_history = fee_rate_history;
+    }
+
+    // Fee rate for a swap at block_time. Before the first change we use the first entry's rate since nothing older is known.
+    pub fn fee_rate_at(&self, block_time: i64) -> i16 {
+        self.fee_rate_history
+            .iter()
+            .rev()
+            .find(|change| change.effective_from <= block_time)
+            .or(self.fee_rate_history.first())
+            .map(|change| change.fee_rate)
+            .unwrap_or(self.fee_rate)
+    }
+
+    // Called by the replay loops before each swap so fee growth uses the tier that was live at the time.
+    pub fn apply_fee_rate_at(&mut self, block_time: i64) {
+        self.fee_rate = self.fee_rate_at(block_time);
+    }
+
+    // Global fee growth in the whirlpool account's Q64.64 format, to compare against fee_growth_global_a/b on-chain.
+    pub fn fee_growth_global_onchain(&self) -> (u128, u128) {
+        (
+            fee_growth_to_onchain_q64(self.fee_growth_global_a),
+            fee_growth_to_onchain_q64(self.fee_growth_global_b),
+        )
+    }
+
+    // How fast the pool has been earning lately: fee growth per unit of liquidity per second over the recent swaps, as
+    // (token A, token B) in raw units. Times a position's liquidity that's roughly what it earns per second while in range.
+    // None until two swaps at different block times were seen.
+    pub fn recent_fee_growth_per_second(&self) -> Option<(f64, f64)> {
+        let (oldest, newest) = (
+            self.recent_fee_growth.front()?,
+            self.recent_fee_growth.back()?,
+        );
+
+        let seconds = newest.block_time - oldest.block_time;
+        if seconds <= 0 {
+            return None;
         }
+
+        let per_second = |newest: U256, oldest: U256| {
+            u256_to_f64(newest.saturating_sub(oldest)) / u256_to_f64(Q128) / seconds as f64
+        };
+
+        Some((
+            per_second(newest.fee_growth_global_a, oldest.fee_growth_global_a),
+            per_second(newest.fee_growth_global_b, oldest.fee_growth_global_b),
+        ))
+    }
+
+    fn record_fee_growth(&mut self) {
+        if self.recent_fee_growth.len() == FEE_VELOCITY_WINDOW {
+            self.recent_fee_growth.pop_front();
+        }
+
+        self.recent_fee_growth.push_back(FeeGrowthSample {
+            block_time: self.current_block_time,
+            fee_growth_global_a: self.fee_growth_global_a,
+            fee_growth_global_b: self.fee_growth_global_b,
+        });
+    }
+
+    // Human price (token B per token A, decimal adjusted), e.g. ~150 for SOL/USDC.
+    pub fn current_price(&self, token_a_decimals: i16, token_b_decimals: i16) -> f64 {
+        sqrt_price_to_price(self.current_sqrt_price)
+            * 10_f64.powi(token_a_decimals as i32 - token_b_decimals as i32)
+    }
+
+    pub fn contains_tick(&self, tick: i32) -> bool {
+        tick >= self.min_tick && tick <= self.max_tick
+    }
+
+    // Position ticks outside the array are pinned to its edges. Liquidity stays correct for every price inside the array,
+    // which is all we can simulate anyway.
+    pub fn clamp_tick(&self, tick: i32) -> i32 {
+        tick.clamp(self.min_tick, self.max_tick)
     }
 
-    pub fn get_index(&self, tick: i32) -> usize {
+    pub fn get_index(&self, tick: i32) -> Result<usize, LiquidityArrayError> {
+        if !self.contains_tick(tick) {
+            return Err(LiquidityArrayError::TickOutOfRange(tick));
+        }
+
         // Offset the tick by min_tick to get a positive index
-        ((tick - self.min_tick) as usize).clamp(0, self.data.len() - 1)
+        Ok((tick - self.min_tick) as usize)
+    }
+
+    // Index of a position bound, pinned to the array edges like clamp_tick so it can't fall outside.
+    fn clamped_index(&self, tick: i32) -> usize {
+        (self.clamp_tick(tick) - self.min_tick) as usize
     }
 
     pub fn get_next_initialized_tick(
@@ -98,9 +242,11 @@ impl LiquidityArray {
     ) -> Result<TickData, LiquidityArrayError> {
         // the current_tick is used up so we need to +1/-1
         let start_index = if direction_up {
-            self.get_index(tick) + 1
+            self.get_index(tick)? + 1
         } else {
-            self.get_index(tick) - 1
+            self.get_index(tick)?
+                .checked_sub(1)
+                .ok_or(LiquidityArrayError::InitializedTickNotFound)?
         };
 
         let end_index = if direction_up { self.data.len() } else { 0 };
@@ -114,7 +260,7 @@ impl LiquidityArray {
             }
         } else {
             // range here goes from mid to lower end. reverse loop sinc we start from start index and go down.
-            for i in (0..start_index).rev() {
+            for i in (0..=start_index).rev() {
                 if self.data[i].is_initialized {
                     return Ok(self.data[i]);
                 }
@@ -130,7 +276,11 @@ impl LiquidityArray {
         current_tick: i32,
         direction_up: bool,
     ) -> Result<(TickData, TickData), LiquidityArrayError> {
-        let current_init_tick = self.data[self.get_index(current_tick)];
+        if !self.contains_tick(current_tick) {
+            return Err(LiquidityArrayError::TickOutOfRange(current_tick));
+        }
+
+        let current_init_tick = self.data[self.get_index(current_tick)?];
 
         // curr_tick initialized
         if current_init_tick.is_initialized {
@@ -158,22 +308,36 @@ impl LiquidityArray {
 
     // ALSO initializes/uninitializes ticks.
     // ONLY USED FOR LIQ TRANSACTIONS AND LIVE POSITIONS SET UP.
-    // Liquidity delta is always a positive number
+    // Liquidity delta is always a positive number, the direction comes from is_increase.
     pub fn update_liquidity(
         &mut self,
         lower_tick: i32,
         upper_tick: i32,
-        liquidity_delta: i128,
+        liquidity_delta: u128,
         is_increase: bool,
     ) {
-        let lower_tick_index = self.get_index(lower_tick);
-        let upper_tick_index = self.get_index(upper_tick);
+        let lower_tick = self.clamp_tick(lower_tick);
+        let upper_tick = self.clamp_tick(upper_tick);
 
-        let delta = liquidity_delta.abs();
+        let lower_tick_index = self.clamped_index(lower_tick);
+        let upper_tick_index = self.clamped_index(upper_tick);
 
         let lower_tick_gross_liq_before = self.data[lower_tick_index].gross_liquidity;
         let upper_tick_gross_liq_before = self.data[upper_tick_index].gross_liquidity;
 
+        // A recorded decrease can slightly exceed what we track at those ticks (rounding in the tick distribution, partial
+        // data). Clamp it to what's there instead of driving the liquidity negative.
+        let available = lower_tick_gross_liq_before.min(upper_tick_gross_liq_before);
+        let delta = if !is_increase && liquidity_delta > available {
+            eprintln!(
+                "WARNING: Decrease of {} at ticks [{}, {}] exceeds the {} tracked there, clamped.",
+                liquidity_delta, lower_tick, upper_tick, available
+            );
+            available
+        } else {
+            liquidity_delta
+        };
+
         // Adjust gross liquidity
         if is_increase {
             // Increase liquidity
@@ -186,30 +350,36 @@ impl LiquidityArray {
                 .expect("Overflow in gross_liquidity at upper tick");
         } else {
             // Decrease liquidity
-            if delta > lower_tick_gross_liq_before {
-                panic!("Underflow: gross_liquidity cannot be negative at lower tick");
-            }
             self.data[lower_tick_index].gross_liquidity = lower_tick_gross_liq_before
                 .checked_sub(delta)
                 .expect("Underflow in gross_liquidity at lower tick");
 
-            if delta > upper_tick_gross_liq_before {
-                panic!("Underflow: gross_liquidity cannot be negative at upper tick");
-            }
             self.data[upper_tick_index].gross_liquidity = upper_tick_gross_liq_before
                 .checked_sub(delta)
                 .expect("Underflow in gross_liquidity at upper tick");
         }
 
-        // adjust net liquidity
+        // adjust net liquidity. It is i128 on-chain too, so overflowing it is a data error and must not wrap silently.
         if is_increase {
             // Increase liquidity
-            self.data[lower_tick_index].net_liquidity += delta;
-            self.data[upper_tick_index].net_liquidity -= delta;
+            self.data[lower_tick_index].net_liquidity = self.data[lower_tick_index]
+                .net_liquidity
+                .checked_add_unsigned(delta)
+                .expect("Overflow in net_liquidity at lower tick");
+            self.data[upper_tick_index].net_liquidity = self.data[upper_tick_index]
+                .net_liquidity
+                .checked_sub_unsigned(delta)
+                .expect("Underflow in net_liquidity at upper tick");
         } else {
             // Decrease liquidity
-            self.data[lower_tick_index].net_liquidity -= delta;
-            self.data[upper_tick_index].net_liquidity += delta;
+            self.data[lower_tick_index].net_liquidity = self.data[lower_tick_index]
+                .net_liquidity
+                .checked_sub_unsigned(delta)
+                .expect("Underflow in net_liquidity at lower tick");
+            self.data[upper_tick_index].net_liquidity = self.data[upper_tick_index]
+                .net_liquidity
+                .checked_add_unsigned(delta)
+                .expect("Overflow in net_liquidity at upper tick");
         }
 
         // Handle tick initialization/uninitialization for LOWER TICK
@@ -218,6 +388,7 @@ impl LiquidityArray {
 
         if flipped_lower_tick {
             self.data[lower_tick_index].is_initialized = true;
+            self.tighten_cached_ticks(lower_tick);
             if lower_tick <= self.current_tick {
                 self.data[lower_tick_index].fee_growth_outside_a = self.fee_growth_global_a;
                 self.data[lower_tick_index].fee_growth_outside_b = self.fee_growth_global_b;
@@ -225,7 +396,7 @@ impl LiquidityArray {
         } else if self.data[lower_tick_index].gross_liquidity == 0
             && lower_tick_gross_liq_before > 0
         {
-            self.data[lower_tick_index].is_initialized = false;
+            self.uninitialize_tick(lower_tick_index);
         }
 
         // Handle tick initialization/uninitialization for UPPER TICK
@@ -234,6 +405,7 @@ impl LiquidityArray {
 
         if flipped_upper_tick {
             self.data[upper_tick_index].is_initialized = true;
+            self.tighten_cached_ticks(upper_tick);
             if upper_tick <= self.current_tick {
                 self.data[upper_tick_index].fee_growth_outside_a = self.fee_growth_global_a;
                 self.data[upper_tick_index].fee_growth_outside_b = self.fee_growth_global_b;
@@ -241,7 +413,7 @@ impl LiquidityArray {
         } else if self.data[upper_tick_index].gross_liquidity == 0
             && upper_tick_gross_liq_before > 0
         {
-            self.data[upper_tick_index].is_initialized = false;
+            self.uninitialize_tick(upper_tick_index);
         }
 
         // Update active liquidity if the current price is within the range
@@ -251,25 +423,116 @@ impl LiquidityArray {
             if is_increase {
                 self.active_liquidity = self
                     .active_liquidity
-                    .checked_add(U256::from(liquidity_delta as u128))
+                    .checked_add(U256::from(delta))
                     .expect("Active liquidity overflow");
             } else {
-                self.active_liquidity = self
-                    .active_liquidity
-                    .checked_sub(U256::from(liquidity_delta as u128))
-                    .expect("Active liquidity underflow");
+                // Same clamp for the active side, it floors at zero.
+                if U256::from(delta) > self.active_liquidity {
+                    eprintln!(
+                        "WARNING: Decrease of {} exceeds the {} active liquidity, clamped.",
+                        delta, self.active_liquidity
+                    );
+                }
+                self.active_liquidity = self.active_liquidity.saturating_sub(U256::from(delta));
             }
         }
+
+        debug_assert!(
+            self.tick_is_consistent(lower_tick_index) && self.tick_is_consistent(upper_tick_index),
+            "Ticks [{}, {}] out of sync after a liquidity update: {:?} {:?}",
+            lower_tick,
+            upper_tick,
+            self.data[lower_tick_index],
+            self.data[upper_tick_index]
+        );
+    }
+
+    // A decrease that doesn't mirror an earlier increase (e.g. a clamped one, or a tick used as lower by one position and
+    // upper by another in the recorded data) can empty a tick while its net liquidity is still off zero. Nothing
+    // references the tick anymore, so the leftover net is dropped instead of being applied on the next crossing, and
+    // counted in ticks_net_liquidity_reset for the report.
+    fn uninitialize_tick(&mut self, index: usize) {
+        let tick_data = &mut self.data[index];
+
+        if tick_data.net_liquidity != 0 {
+            tick_data.net_liquidity = 0;
+            self.ticks_net_liquidity_reset += 1;
+        }
+        tick_data.is_initialized = false;
+    }
+
+    // Initialized means some position still references the tick (gross > 0). Net liquidity can cancel out to zero on an
+    // initialized tick (one position's upper is another's lower), but an uninitialized tick must not carry any.
+    fn tick_is_consistent(&self, index: usize) -> bool {
+        let tick_data = &self.data[index];
+
+        tick_data.is_initialized == (tick_data.gross_liquidity > 0)
+            && (tick_data.is_initialized || tick_data.net_liquidity == 0)
     }
 
-    pub fn add_owners_position(&mut self, position: OwnersPosition, position_id: String) {
-        self.positions.insert(position_id.clone(), position.clone());
+    // A newly initialized tick can land between the current tick and the cached neighbours (e.g. our own positions).
+    // Pull the caches in so the next swap doesn't skip over it.
+    fn tighten_cached_ticks(&mut self, tick: i32) {
+        if let Some(upper) = self.cached_upper_initialized_tick {
+            if tick > self.current_tick && tick < upper {
+                self.cached_upper_initialized_tick = Some(tick);
+            }
+        }
+
+        if let Some(lower) = self.cached_lower_initialized_tick {
+            if tick < self.current_tick && tick > lower {
+                self.cached_lower_initialized_tick = Some(tick);
+            }
+        }
+    }
+
+    // Like opening a position on-chain, a new position's fee checkpoint starts at the current fee growth inside its range,
+    // so growth from before it existed is never attributed to it. An id that is already open is replaced and keeps the
+    // fee_growth_inside_last passed in.
+    pub fn add_owners_position(
+        &mut self,
+        mut position: OwnersPosition,
+        position_id: String,
+    ) -> Result<(), LiquidityArrayError> {
+        let is_new = !self.positions.contains_key(&position_id);
+        if !is_new {
+            self.remove_owners_position(&position_id)?;
+        }
+
         self.update_liquidity(
             position.lower_tick,
             position.upper_tick,
             position.liquidity,
             true,
         );
+
+        if is_new {
+            let lower_tick_index = self.clamped_index(position.lower_tick);
+            let upper_tick_index = self.clamped_index(position.upper_tick);
+            position.fee_growth_inside_a_last =
+                self.calculate_fee_growth_inside(lower_tick_index, upper_tick_index, true)?;
+            position.fee_growth_inside_b_last =
+                self.calculate_fee_growth_inside(lower_tick_index, upper_tick_index, false)?;
+        }
+
+        self.positions.insert(position_id, position);
+
+        Ok(())
+    }
+
+    // Zeroes the global and every tick's fee growth outside. All zero is a consistent state (no growth on any side of
+    // any tick yet), so fees are counted from this point on.
+    pub fn reset_fee_growth(&mut self) {
+        self.fee_growth_global_a = U256::zero();
+        self.fee_growth_global_b = U256::zero();
+
+        for tick_data in self.data.iter_mut() {
+            tick_data.fee_growth_outside_a = U256::zero();
+            tick_data.fee_growth_outside_b = U256::zero();
+        }
+
+        // Samples from before the reset would make the deltas negative.
+        self.recent_fee_growth.clear();
     }
 
     pub fn remove_owners_position(
@@ -292,19 +555,145 @@ impl LiquidityArray {
         }
     }
 
+    // Merges two adjacent or overlapping positions into one spanning both ranges.
+    // A single position can't reproduce two different liquidity levels, so the merge is value based:
+    // 1. fees are collected on both (their fee checkpoints differ, so they can't be carried over)
+    // 2. both are removed and their token amounts at the current price are summed
+    // 3. the new position gets the max liquidity those amounts support on the combined range, the rest is returned as leftovers
+    // The new position's fee checkpoint starts at the current fee growth inside its range (set by add_owners_position).
+    pub fn merge_positions(
+        &mut self,
+        id_a: &str,
+        id_b: &str,
+        new_id: String,
+    ) -> Result<MergeOutcome, LiquidityArrayError> {
+        self.ensure_price_initialized()?;
+
+        if id_a == id_b {
+            return Err(LiquidityArrayError::IncompatiblePositions(format!(
+                "cannot merge position {} with itself",
+                id_a
+            )));
+        }
+
+        let position_a = self
+            .positions
+            .get(id_a)
+            .ok_or_else(|| LiquidityArrayError::PositionNotFound(id_a.to_string()))?;
+        let position_b = self
+            .positions
+            .get(id_b)
+            .ok_or_else(|| LiquidityArrayError::PositionNotFound(id_b.to_string()))?;
+
+        // Adjacent (shared boundary) or overlapping, a gap would leave a hole the merged position fills with new liquidity.
+        if position_a.upper_tick < position_b.lower_tick
+            || position_b.upper_tick < position_a.lower_tick
+        {
+            return Err(LiquidityArrayError::IncompatiblePositions(format!(
+                "ranges [{}, {}] and [{}, {}] do not touch",
+                position_a.lower_tick,
+                position_a.upper_tick,
+                position_b.lower_tick,
+                position_b.upper_tick
+            )));
+        }
+
+        let lower_tick = position_a.lower_tick.min(position_b.lower_tick);
+        let upper_tick = position_a.upper_tick.max(position_b.upper_tick);
+        let owner = position_a.owner.clone();
+
+        let (fees_a_1, fees_b_1) = self.collect_fees(id_a)?;
+        let (fees_a_2, fees_b_2) = self.collect_fees(id_b)?;
+
+        let mut total_a = U256::zero();
+        let mut total_b = U256::zero();
+
+        for position_id in [id_a, id_b] {
+            let position = self.remove_owners_position(position_id)?;
+            let (amount_a, amount_b) = calculate_amounts(
+                U256::from(position.liquidity),
+                self.current_sqrt_price,
+                tick_to_sqrt_price_u256(position.lower_tick),
+                tick_to_sqrt_price_u256(position.upper_tick),
+            );
+
+            total_a += amount_a;
+            total_b += amount_b;
+        }
+
+        let lower_sqrt_price = tick_to_sqrt_price_u256(lower_tick);
+        let upper_sqrt_price = tick_to_sqrt_price_u256(upper_tick);
+
+        let liquidity = calculate_liquidity(
+            total_a,
+            total_b,
+            self.current_sqrt_price,
+            lower_sqrt_price,
+            upper_sqrt_price,
+        );
+        let (amount_a_used, amount_b_used) = calculate_amounts(
+            liquidity,
+            self.current_sqrt_price,
+            lower_sqrt_price,
+            upper_sqrt_price,
+        );
+
+        self.add_owners_position(
+            OwnersPosition {
+                owner,
+                lower_tick,
+                upper_tick,
+                liquidity: liquidity.as_u128(),
+                fee_growth_inside_a_last: U256::zero(),
+                fee_growth_inside_b_last: U256::zero(),
+            },
+            new_id,
+        )?;
+
+        Ok(MergeOutcome {
+            fees_a: fees_a_1 + fees_a_2,
+            fees_b: fees_b_1 + fees_b_2,
+            leftover_a: total_a.saturating_sub(amount_a_used),
+            leftover_b: total_b.saturating_sub(amount_b_used),
+            liquidity,
+        })
+    }
+
     pub fn collect_fees(&mut self, position_id: &str) -> Result<(U256, U256), LiquidityArrayError> {
+        self.collect_fees_partial(position_id, 10_000)
+    }
+
+    // Collects bps / 10_000 of the accrued fees (capped at all of them). The checkpoint is a single fee growth value,
+    // so it moves forward by the same share of the fee growth delta and the rest stays claimable later.
+    // Each collection rounds down, so split collections can come out 1 unit below a single full one.
+    pub fn collect_fees_partial(
+        &mut self,
+        position_id: &str,
+        bps: u32,
+    ) -> Result<(U256, U256), LiquidityArrayError> {
+        self.ensure_price_initialized()?;
+
         let position = self
             .positions
             .get(position_id)
             .ok_or_else(|| LiquidityArrayError::PositionNotFound(position_id.to_string()))?;
 
-        let (fees_a, fees_b, new_fee_growth_inside_a, new_fee_growth_inside_b) =
+        let (_, _, fee_growth_inside_a, fee_growth_inside_b) =
             self.calculate_fees_for_position(position)?;
 
+        let share = U256::from(bps.min(10_000));
+        let collected_growth_a =
+            (fee_growth_inside_a - position.fee_growth_inside_a_last) * share / 10_000;
+        let collected_growth_b =
+            (fee_growth_inside_b - position.fee_growth_inside_b_last) * share / 10_000;
+
+        let fees_a = (U256::from(position.liquidity) * collected_growth_a) / Q128;
+        let fees_b = (U256::from(position.liquidity) * collected_growth_b) / Q128;
+
         // update fee growth inside the position.
         if let Some(position) = self.positions.get_mut(position_id) {
-            position.fee_growth_inside_a_last = new_fee_growth_inside_a;
-            position.fee_growth_inside_b_last = new_fee_growth_inside_b;
+            position.fee_growth_inside_a_last += collected_growth_a;
+            position.fee_growth_inside_b_last += collected_growth_b;
         }
 
         Ok((fees_a, fees_b))
@@ -314,8 +703,8 @@ impl LiquidityArray {
         &self,
         position: &OwnersPosition,
     ) -> Result<(U256, U256, U256, U256), LiquidityArrayError> {
-        let lower_tick_index = self.get_index(position.lower_tick);
-        let upper_tick_index = self.get_index(position.upper_tick);
+        let lower_tick_index = self.clamped_index(position.lower_tick);
+        let upper_tick_index = self.clamped_index(position.upper_tick);
 
         let fee_growth_inside_a =
             self.calculate_fee_growth_inside(lower_tick_index, upper_tick_index, true)?;
@@ -377,6 +766,93 @@ impl LiquidityArray {
         }
     }
 
+    // new() leaves the price at zero, it has to be set from real pool data before any swap/fee math.
+    fn ensure_price_initialized(&self) -> Result<(), LiquidityArrayError> {
+        if self.current_sqrt_price.is_zero() {
+            return Err(LiquidityArrayError::PriceNotInitialized);
+        }
+
+        Ok(())
+    }
+
+    // Amount in (fee included) that moves the current price to target_tick, and whether it's a sell. Walks the initialized
+    // ticks like simulate_swap without changing anything, the price stops at the array edges.
+    pub fn amount_in_to_reach_tick(
+        &self,
+        target_tick: i32,
+    ) -> Result<(U256, bool), LiquidityArrayError> {
+        self.ensure_price_initialized()?;
+
+        let target_sqrt_price = tick_to_sqrt_price_u256(self.clamp_tick(target_tick));
+        let is_sell = target_sqrt_price < self.current_sqrt_price;
+
+        let mut liquidity = self.active_liquidity;
+        let mut sqrt_price = self.current_sqrt_price;
+        let mut next_tick = if is_sell {
+            self.cached_lower_initialized_tick
+        } else {
+            self.cached_upper_initialized_tick
+        }
+        .ok_or(LiquidityArrayError::InitializedTickNotFound)?;
+        let mut amount_in = U256::zero();
+
+        loop {
+            let next_sqrt_price = tick_to_sqrt_price_u256(next_tick);
+
+            let reaches_target = if is_sell {
+                target_sqrt_price >= next_sqrt_price || next_tick == self.min_tick
+            } else {
+                target_sqrt_price <= next_sqrt_price || next_tick == self.max_tick
+            };
+
+            if reaches_target {
+                let stop_sqrt_price = if is_sell {
+                    target_sqrt_price.max(next_sqrt_price)
+                } else {
+                    target_sqrt_price.min(next_sqrt_price)
+                };
+                let amount_net = if is_sell {
+                    calculate_amount_a_delta(liquidity, sqrt_price, stop_sqrt_price)
+                } else {
+                    calculate_amount_b_delta(liquidity, sqrt_price, stop_sqrt_price)
+                };
+
+                // simulate_swap takes the fee off the last step before moving the price.
+                let fee_complement = U256::from(1_000_000 - self.fee_rate as u64);
+                amount_in += mul_div_round_up(amount_net, U256::from(1_000_000), fee_complement);
+
+                return Ok((amount_in, is_sell));
+            }
+
+            // Same max_in as simulate_swap, anything past it crosses the tick.
+            let (amount_a_in_range, amount_b_in_range) = if is_sell {
+                calculate_amounts(liquidity, next_sqrt_price, next_sqrt_price, sqrt_price)
+            } else {
+                calculate_amounts(liquidity, next_sqrt_price, sqrt_price, next_sqrt_price)
+            };
+            amount_in += if is_sell {
+                amount_a_in_range
+            } else {
+                amount_b_in_range
+            };
+
+            let net_liquidity = self.data[self.get_index(next_tick)?].net_liquidity;
+            let entering_liquidity = if is_sell {
+                -net_liquidity
+            } else {
+                net_liquidity
+            };
+            liquidity = if entering_liquidity > 0 {
+                liquidity + U256::from(entering_liquidity as u128)
+            } else {
+                liquidity.saturating_sub(U256::from(entering_liquidity.unsigned_abs()))
+            };
+
+            sqrt_price = next_sqrt_price;
+            next_tick = self.get_next_initialized_tick(next_tick, !is_sell)?.tick;
+        }
+    }
+
     // is_sell represents the directional movement of token_a. In SOL/USDC case is_sell represents selling SOL for USDC.
     // High level explanation: we use active liquidity as our main liquidity nmr. we use initialized ticks as our ranges for how much can be swapped. after crossing we update liq/feegrowth etc.
     pub fn simulate_swap(
@@ -384,11 +860,14 @@ impl LiquidityArray {
         amount_in: U256,
         is_sell: bool,
     ) -> Result<U256, LiquidityArrayError> {
+        self.ensure_price_initialized()?;
+
         let mut current_tick = self.current_tick;
         let mut current_sqrt_price = self.current_sqrt_price;
 
         let mut remaining_amount = amount_in;
         let mut amount_out = U256::zero();
+        let mut tick_crossings = 0;
 
         while remaining_amount > U256::zero() {
             let liquidity = self.active_liquidity;
@@ -443,47 +922,75 @@ impl LiquidityArray {
                     is_sell,
                 );
 
-                let (old_amount_a, old_amount_b) = calculate_amounts(
-                    liquidity,
-                    old_sqrt_price,
-                    lower_sqrt_price,
-                    upper_sqrt_price,
-                );
-                let (new_amount_a, new_amount_b) = calculate_amounts(
-                    liquidity,
-                    new_sqrt_price,
-                    lower_sqrt_price,
-                    upper_sqrt_price,
-                );
-
+                // Straight from the price move, rounded down once like on-chain outputs.
                 if is_sell {
-                    amount_out += new_amount_b.abs_diff(old_amount_b);
+                    amount_out +=
+                        calculate_amount_b_delta(liquidity, old_sqrt_price, new_sqrt_price);
                     self.fee_growth_global_a += fee_growth;
                 } else {
-                    amount_out += new_amount_a.abs_diff(old_amount_a);
+                    amount_out +=
+                        calculate_amount_a_delta(liquidity, old_sqrt_price, new_sqrt_price);
                     self.fee_growth_global_b += fee_growth;
                 }
 
                 current_sqrt_price = new_sqrt_price;
+                remaining_amount = U256::zero();
+            } else if (is_sell && lower_initialized_tick == self.min_tick)
+                || (!is_sell && upper_initialized_tick == self.max_tick)
+            {
+                // The array edges hold the positions reaching past them (see clamp_tick), there is no tick beyond to cross into.
+                // Price stops at the edge and the rest of the input is left unswapped, counted in swaps_stopped_at_edge.
+                self.swaps_stopped_at_edge += 1;
+                if is_sell {
+                    amount_out +=
+                        calculate_amount_b_delta(liquidity, current_sqrt_price, lower_sqrt_price);
+                    self.fee_growth_global_a += fee_growth;
+
+                    current_tick = lower_initialized_tick;
+                    current_sqrt_price = lower_sqrt_price;
+                } else {
+                    amount_out +=
+                        calculate_amount_a_delta(liquidity, current_sqrt_price, upper_sqrt_price);
+                    self.fee_growth_global_b += fee_growth;
+
+                    // One below, positions ending at the edge are still in range (their liquidity was never removed).
+                    current_tick = upper_initialized_tick - 1;
+                    current_sqrt_price = upper_sqrt_price;
+                }
+
                 remaining_amount = U256::zero();
             } else {
+                // Checked before anything of this step is applied. The array is left mid swap, the caller aborts the run.
+                tick_crossings += 1;
+                if let Some(max) = self.max_tick_crossings {
+                    if tick_crossings > max {
+                        return Err(LiquidityArrayError::TooManyTickCrossings(max));
+                    }
+                }
+
+                // Only the array edges may have no initialized tick beyond them. Looked up before anything of this step is
+                // applied, like the crossings limit above.
+                let next_initialized_tick = if is_sell {
+                    self.get_next_initialized_tick(lower_initialized_tick, false)?
+                } else {
+                    self.get_next_initialized_tick(upper_initialized_tick, true)?
+                }
+                .tick;
+
                 // Swap will cross into the next tick
                 let mut relevant_tick: TickData;
 
                 if is_sell {
                     self.fee_growth_global_a += fee_growth;
 
-                    let (_, amount_b) = calculate_amounts(
-                        liquidity,
-                        current_sqrt_price,
-                        lower_sqrt_price,
-                        current_sqrt_price,
-                    );
-                    amount_out += amount_b;
+                    amount_out +=
+                        calculate_amount_b_delta(liquidity, current_sqrt_price, lower_sqrt_price);
 
-                    let lower_init_tick_index = self.get_index(lower_initialized_tick);
+                    let lower_init_tick_index = self.get_index(lower_initialized_tick)?;
 
-                    current_tick = lower_initialized_tick;
+                    // Like on-chain, the price sits just below the crossed tick. Positions starting at it are out of range,
+                    // same as the active liquidity that just dropped them.
+                    current_tick = lower_initialized_tick - 1;
                     current_sqrt_price = lower_sqrt_price;
                     relevant_tick = self.data[lower_init_tick_index];
 
@@ -505,23 +1012,14 @@ impl LiquidityArray {
                     }
 
                     self.cached_upper_initialized_tick = Some(lower_initialized_tick);
-                    self.cached_lower_initialized_tick = Some(
-                        self.get_next_initialized_tick(current_tick, false)
-                            .unwrap()
-                            .tick,
-                    );
+                    self.cached_lower_initialized_tick = Some(next_initialized_tick);
                 } else {
                     self.fee_growth_global_b += fee_growth;
 
-                    let (amount_a, _) = calculate_amounts(
-                        liquidity,
-                        current_sqrt_price,
-                        current_sqrt_price,
-                        upper_sqrt_price,
-                    );
-                    amount_out += amount_a;
+                    amount_out +=
+                        calculate_amount_a_delta(liquidity, current_sqrt_price, upper_sqrt_price);
 
-                    let upper_init_tick_index = self.get_index(upper_initialized_tick);
+                    let upper_init_tick_index = self.get_index(upper_initialized_tick)?;
 
                     current_tick = upper_initialized_tick;
                     current_sqrt_price = upper_sqrt_price;
@@ -544,15 +1042,11 @@ impl LiquidityArray {
                             U256::from(relevant_tick.net_liquidity.unsigned_abs());
                     }
 
-                    self.cached_upper_initialized_tick = Some(
-                        self.get_next_initialized_tick(current_tick, true)
-                            .unwrap()
-                            .tick,
-                    );
+                    self.cached_upper_initialized_tick = Some(next_initialized_tick);
                     self.cached_lower_initialized_tick = Some(upper_initialized_tick);
                 }
 
-                let index = self.get_index(relevant_tick.tick);
+                let index = self.get_index(relevant_tick.tick)?;
                 self.data[index] = relevant_tick;
 
                 remaining_amount -= step_amount;
@@ -561,13 +1055,21 @@ impl LiquidityArray {
 
         self.current_tick = current_tick;
         self.current_sqrt_price = current_sqrt_price;
+        self.record_fee_growth();
         Ok(amount_out)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::utils::core_math::{calculate_liquidity, price_to_tick};
+    use crate::{
+        backtester::test_helpers::{
+            create_laddered_test_liquidity_array, create_test_liquidity_array,
+            reset_initialized_tick_caches, TestLiquidityArrayBuilder,
+        },
+        utils::core_math::{price_to_tick, within_tolerance, Q64},
+    };
+    use proptest::prelude::*;
 
     use super::*;
 
@@ -577,34 +1079,25 @@ mod tests {
         amount_a: u128,
         amount_b: u128,
     ) -> LiquidityArray {
-        let mut array = LiquidityArray::new(-30000, 30000, 2, 300);
         let current_tick = price_to_tick(price as f64 / 10f64.powi(decimal_diff as i32));
 
-        array.current_tick = current_tick;
-        array.current_sqrt_price = tick_to_sqrt_price_u256(array.current_tick);
-
         let lower_tick = -21204 - 3000;
         let upper_tick = -21204 + 3000;
 
         let liquidity_1 = calculate_liquidity(
             U256::from(amount_a * 10_u128.pow(9)),
             U256::from(amount_b * 10_u128.pow(6)),
-            array.current_sqrt_price,
+            tick_to_sqrt_price_u256(current_tick),
             tick_to_sqrt_price_u256(lower_tick),
             tick_to_sqrt_price_u256(upper_tick),
         );
 
-        array.update_liquidity(lower_tick, upper_tick, liquidity_1.as_u128() as i128, true);
-
-        // for test_get_upper_and_lower_tick
-        array.update_liquidity(current_tick - 5, current_tick + 5, 20 as i128, true);
-
-        let (upper_tick_data, lower_tick_data) =
-            array.get_upper_and_lower_ticks(current_tick, true).unwrap();
-        array.cached_lower_initialized_tick = Some(lower_tick_data.tick);
-        array.cached_upper_initialized_tick = Some(upper_tick_data.tick);
-
-        array
+        TestLiquidityArrayBuilder::new(-30000, 30000, 2, 300)
+            .price_at_tick(current_tick)
+            .position(lower_tick, upper_tick, liquidity_1.as_u128())
+            // for test_get_upper_and_lower_tick
+            .position(current_tick - 5, current_tick + 5, 20)
+            .build()
     }
 
     #[test]
@@ -711,17 +1204,19 @@ mod tests {
 
         // Add Alice's position
         let alice_liquidity = 4_000_000_000_u128;
-        array.add_owners_position(
-            OwnersPosition {
-                owner: "Alice".to_string(),
-                lower_tick: array.current_tick - 3000,
-                upper_tick: array.current_tick + 3000,
-                liquidity: alice_liquidity as i128,
-                fee_growth_inside_a_last: U256::zero(),
-                fee_growth_inside_b_last: U256::zero(),
-            },
-            "Alice_position".to_string(),
-        );
+        array
+            .add_owners_position(
+                OwnersPosition {
+                    owner: "Alice".to_string(),
+                    lower_tick: array.current_tick - 3000,
+                    upper_tick: array.current_tick + 3000,
+                    liquidity: alice_liquidity,
+                    fee_growth_inside_a_last: U256::zero(),
+                    fee_growth_inside_b_last: U256::zero(),
+                },
+                "Alice_position".to_string(),
+            )
+            .unwrap();
 
         // Calculate Alice's liquidity share
         let alice_liquidity_share: f64 =
@@ -754,7 +1249,7 @@ mod tests {
         );
 
         // Perform a second swap (buy direction)
-        let swap_amount_b = U256::from(1 * 10_i32.pow(6) as u128);
+        let swap_amount_b = U256::from(10_i32.pow(6) as u128);
         array.simulate_swap(swap_amount_b, false).unwrap();
 
         // Calculate expected fee for second swap
@@ -791,4 +1286,791 @@ mod tests {
             "Should not be able to collect token B fees again"
         );
     }
+
+    #[test]
+    fn test_partial_fee_collection_leaves_the_rest_claimable() {
+        let mut array = setup_liquidity_array(120, 3, 5, 5 * 120);
+        array
+            .add_owners_position(
+                OwnersPosition {
+                    owner: "Alice".to_string(),
+                    lower_tick: array.current_tick - 3000,
+                    upper_tick: array.current_tick + 3000,
+                    liquidity: 4_000_000_000,
+                    fee_growth_inside_a_last: U256::zero(),
+                    fee_growth_inside_b_last: U256::zero(),
+                },
+                "Alice_position".to_string(),
+            )
+            .unwrap();
+
+        array
+            .simulate_swap(U256::from(20_000_000_u128), true)
+            .unwrap();
+        array
+            .simulate_swap(U256::from(1_000_000_u128), false)
+            .unwrap();
+
+        let (full_a, full_b) = array.clone().collect_fees("Alice_position").unwrap();
+        assert!(full_a > U256::zero() && full_b > U256::zero());
+
+        let (partial_a, partial_b) = array.collect_fees_partial("Alice_position", 3_000).unwrap();
+        assert!(partial_a.abs_diff(full_a * 3 / 10) <= U256::one());
+        assert!(partial_b.abs_diff(full_b * 3 / 10) <= U256::one());
+
+        // Rounding down twice can lose at most one unit against the single collection.
+        let (rest_a, rest_b) = array.collect_fees("Alice_position").unwrap();
+        assert!(full_a - (partial_a + rest_a) <= U256::one());
+        assert!(full_b - (partial_b + rest_b) <= U256::one());
+
+        assert_eq!(
+            array.collect_fees_partial("Alice_position", 5_000).unwrap(),
+            (U256::zero(), U256::zero())
+        );
+    }
+
+    #[test]
+    fn test_readding_a_position_keeps_its_fee_checkpoint() {
+        let mut array = setup_liquidity_array(120, 3, 5, 5 * 120);
+        let position = OwnersPosition {
+            owner: "Alice".to_string(),
+            lower_tick: array.current_tick - 3000,
+            upper_tick: array.current_tick + 3000,
+            liquidity: 4_000_000_000,
+            fee_growth_inside_a_last: U256::zero(),
+            fee_growth_inside_b_last: U256::zero(),
+        };
+        array
+            .add_owners_position(position.clone(), "Alice_position".to_string())
+            .unwrap();
+        array
+            .simulate_swap(U256::from(20_000_000_u128), true)
+            .unwrap();
+        let opened = array.positions["Alice_position"].clone();
+        let active_liquidity = array.active_liquidity;
+
+        // Replacing the open position keeps the checkpoint it comes with and doesn't count its liquidity twice.
+        array
+            .add_owners_position(opened.clone(), "Alice_position".to_string())
+            .unwrap();
+        assert_eq!(
+            array.positions["Alice_position"].fee_growth_inside_a_last,
+            opened.fee_growth_inside_a_last
+        );
+        assert_eq!(array.active_liquidity, active_liquidity);
+        assert!(array.collect_fees("Alice_position").unwrap().0 > U256::zero());
+
+        // A new id starts at the current growth inside, whatever checkpoint is passed in.
+        array
+            .add_owners_position(position, "Bob_position".to_string())
+            .unwrap();
+        assert_eq!(
+            array.collect_fees("Bob_position").unwrap(),
+            (U256::zero(), U256::zero())
+        );
+    }
+
+    #[test]
+    fn test_update_liquidity_pins_ticks_outside_range() {
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 2, 300)
+            .price_at_tick(0)
+            .build();
+
+        // Full range style position, both ticks way past the array edges.
+        array.update_liquidity(-443_636, 443_636, 1_000, true);
+
+        assert_eq!(array.active_liquidity, U256::from(1_000));
+        assert!(array.data[array.get_index(-1000).unwrap()].is_initialized);
+        assert!(array.data[array.get_index(1000).unwrap()].is_initialized);
+        assert_eq!(
+            array.data[array.get_index(-1000).unwrap()].net_liquidity,
+            1_000
+        );
+        assert_eq!(
+            array.data[array.get_index(1000).unwrap()].net_liquidity,
+            -1_000
+        );
+
+        assert!(matches!(
+            array.get_upper_and_lower_ticks(2000, true),
+            Err(LiquidityArrayError::TickOutOfRange(2000))
+        ));
+        assert!(matches!(
+            array.get_index(-1001),
+            Err(LiquidityArrayError::TickOutOfRange(-1001))
+        ));
+    }
+
+    #[test]
+    fn test_update_liquidity_above_i128_max() {
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 2, 300)
+            .price_at_tick(0)
+            .build();
+
+        // Each position fits in i128 on its own, together they are past 2^127.
+        let liquidity = (1_u128 << 126) + 1_000;
+        array.update_liquidity(-200, 200, liquidity, true);
+        array.update_liquidity(-100, 100, liquidity, true);
+
+        let total = U256::from(liquidity) * 2;
+        assert!(total > U256::from(1_u128 << 127));
+        assert_eq!(array.active_liquidity, total);
+        assert_eq!(
+            array.data[array.get_index(-200).unwrap()].gross_liquidity,
+            liquidity
+        );
+
+        array.update_liquidity(-100, 100, liquidity, false);
+
+        assert_eq!(array.active_liquidity, U256::from(liquidity));
+        assert!(!array.data[array.get_index(-100).unwrap()].is_initialized);
+    }
+
+    #[test]
+    fn test_equal_increase_and_decrease_leaves_ticks_empty() {
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 2, 300)
+            .price_at_tick(0)
+            .build();
+
+        array.update_liquidity(-100, 100, 1_000_000, true);
+        array.update_liquidity(-100, 100, 1_000_000, false);
+
+        for tick in [-100, 100] {
+            let tick_data = &array.data[array.get_index(tick).unwrap()];
+            assert!(!tick_data.is_initialized);
+            assert_eq!(tick_data.net_liquidity, 0);
+            assert_eq!(tick_data.gross_liquidity, 0);
+        }
+        assert_eq!(array.active_liquidity, U256::zero());
+    }
+
+    #[test]
+    fn test_mismatched_decrease_doesnt_leave_net_on_an_empty_tick() {
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 2, 300)
+            .price_at_tick(0)
+            .build();
+        let liquidity = 1_000_000_u128;
+
+        // Tick 100 is only ever an upper tick, then a decrease removes it as a lower one.
+        array.update_liquidity(-100, 100, liquidity, true);
+        array.update_liquidity(-50, 300, liquidity, true);
+        array.update_liquidity(100, 300, liquidity, false);
+
+        let tick_data = &array.data[array.get_index(100).unwrap()];
+        assert!(!tick_data.is_initialized);
+        assert_eq!(tick_data.gross_liquidity, 0);
+        assert_eq!(tick_data.net_liquidity, 0);
+        assert_eq!(array.ticks_net_liquidity_reset, 1);
+    }
+
+    #[test]
+    fn test_oversized_decrease_is_clamped_to_zero() {
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 2, 300)
+            .price_at_tick(0)
+            .build();
+        let liquidity = 1_000_000_u128;
+
+        array.update_liquidity(-100, 100, liquidity, true);
+        // Slightly more than was ever added, e.g. rounding in the recorded amount.
+        array.update_liquidity(-100, 100, liquidity + 10, false);
+
+        assert_eq!(array.active_liquidity, U256::zero());
+        for tick in [-100, 100] {
+            let tick_data = &array.data[array.get_index(tick).unwrap()];
+            assert_eq!(tick_data.gross_liquidity, 0);
+            assert_eq!(tick_data.net_liquidity, 0);
+            assert!(!tick_data.is_initialized);
+        }
+
+        // Still usable afterwards.
+        array.update_liquidity(-100, 100, liquidity, true);
+        assert_eq!(array.active_liquidity, U256::from(liquidity));
+    }
+
+    #[test]
+    #[should_panic(expected = "Overflow in net_liquidity at lower tick")]
+    fn test_update_liquidity_net_overflow_does_not_wrap() {
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 2, 300)
+            .price_at_tick(0)
+            .build();
+
+        // Can't be represented in the i128 net liquidity, used to wrap to a negative value.
+        array.update_liquidity(-100, 100, (1_u128 << 127) + 1, true);
+    }
+
+    #[test]
+    fn test_micro_swaps_match_single_swap() {
+        for is_sell in [true, false] {
+            let mut micro = TestLiquidityArrayBuilder::new(-1000, 1000, 10, 0)
+                .price_at_tick(0)
+                .position(-500, 500, 10_u128.pow(12))
+                .cached_ticks(-500, 500)
+                .build();
+            let mut single = micro.clone();
+
+            // Small enough that all of it stays inside the position.
+            let swap_count = 1_000;
+            let micro_amount = U256::from(10_000);
+
+            let micro_total = (0..swap_count).fold(U256::zero(), |total, _| {
+                total + micro.simulate_swap(micro_amount, is_sell).unwrap()
+            });
+            let single_total = single
+                .simulate_swap(micro_amount * swap_count, is_sell)
+                .unwrap();
+
+            // Every output is rounded down once, so the micro swaps can only lose under a unit each.
+            assert!(micro_total <= single_total);
+            assert!(
+                single_total - micro_total < U256::from(swap_count),
+                "is_sell {}: micro {} vs single {}",
+                is_sell,
+                micro_total,
+                single_total
+            );
+        }
+    }
+
+    #[test]
+    fn test_simulate_swap_without_price_errors() {
+        // No price_at_tick, the sqrt price stays at zero.
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 10, 300)
+            .position(-100, 100, 1_000_000)
+            .build();
+
+        let result = array.simulate_swap(U256::from(1000), true);
+        assert!(matches!(
+            result,
+            Err(LiquidityArrayError::PriceNotInitialized)
+        ));
+    }
+
+    #[test]
+    fn test_merge_positions_conserves_value() {
+        let mut array = crate::backtester::test_helpers::create_test_liquidity_array(5);
+
+        let owners_position = |lower_tick, upper_tick, liquidity| OwnersPosition {
+            owner: String::from(""),
+            lower_tick,
+            upper_tick,
+            liquidity,
+            fee_growth_inside_a_last: U256::zero(),
+            fee_growth_inside_b_last: U256::zero(),
+        };
+        array
+            .add_owners_position(
+                owners_position(-100, 0, 3_000_000_000),
+                "rung_0".to_string(),
+            )
+            .unwrap();
+        array
+            .add_owners_position(owners_position(0, 100, 1_000_000_000), "rung_1".to_string())
+            .unwrap();
+
+        // Accrue some fees on both rungs.
+        for _ in 0..5 {
+            let out = array.simulate_swap(U256::from(5_000_000), true).unwrap();
+            array.simulate_swap(out, false).unwrap();
+        }
+
+        let price = array.current_price(0, 0);
+        let value_in_b = |amount_a: U256, amount_b: U256| {
+            amount_a.as_u128() as f64 * price + amount_b.as_u128() as f64
+        };
+
+        let mut value_before = 0.0;
+        for position_id in ["rung_0", "rung_1"] {
+            let position = array.positions[position_id].clone();
+            let (fees_a, fees_b, _, _) = array.calculate_fees_for_position(&position).unwrap();
+            let (amount_a, amount_b) = calculate_amounts(
+                U256::from(position.liquidity),
+                array.current_sqrt_price,
+                tick_to_sqrt_price_u256(position.lower_tick),
+                tick_to_sqrt_price_u256(position.upper_tick),
+            );
+            value_before += value_in_b(amount_a + fees_a, amount_b + fees_b);
+        }
+
+        let outcome = array
+            .merge_positions("rung_0", "rung_1", "merged".to_string())
+            .unwrap();
+
+        assert!(!outcome.fees_a.is_zero() || !outcome.fees_b.is_zero());
+        assert!(!array.positions.contains_key("rung_0"));
+
+        let merged = array.positions["merged"].clone();
+        assert_eq!((merged.lower_tick, merged.upper_tick), (-100, 100));
+
+        let (amount_a, amount_b) = calculate_amounts(
+            U256::from(merged.liquidity),
+            array.current_sqrt_price,
+            tick_to_sqrt_price_u256(merged.lower_tick),
+            tick_to_sqrt_price_u256(merged.upper_tick),
+        );
+        let value_after = value_in_b(
+            amount_a + outcome.fees_a + outcome.leftover_a,
+            amount_b + outcome.fees_b + outcome.leftover_b,
+        );
+
+        assert!((value_after - value_before).abs() / value_before < 1e-6);
+
+        // Fresh checkpoint, nothing owed right after the merge.
+        let (fees_a, fees_b, _, _) = array.calculate_fees_for_position(&merged).unwrap();
+        assert!(fees_a.is_zero() && fees_b.is_zero());
+
+        // Disjoint ranges can't be merged.
+        array
+            .add_owners_position(owners_position(200, 300, 1_000), "far".to_string())
+            .unwrap();
+        assert!(matches!(
+            array.merge_positions("merged", "far", "x".to_string()),
+            Err(LiquidityArrayError::IncompatiblePositions(_))
+        ));
+    }
+
+    #[test]
+    fn test_first_position_fees_reset_vs_carried_forward() {
+        // Swaps back and forth across the ladder so the ticks carry fee growth outside from before the backtest.
+        let mut history = create_laddered_test_liquidity_array(0, 10, 20);
+        for _ in 0..3 {
+            history.simulate_swap(U256::from(5_000_000), true).unwrap();
+            history.simulate_swap(U256::from(5_000_000), false).unwrap();
+        }
+
+        let mut reset = history.clone();
+        reset.reset_fee_growth();
+        let mut carried = history;
+
+        let open_first_position = |arr: &mut LiquidityArray| {
+            arr.add_owners_position(
+                OwnersPosition {
+                    owner: String::from(""),
+                    lower_tick: -50,
+                    upper_tick: 50,
+                    liquidity: 1_000_000_000,
+                    fee_growth_inside_a_last: U256::zero(),
+                    fee_growth_inside_b_last: U256::zero(),
+                },
+                "first".to_string(),
+            )
+            .unwrap();
+            arr.positions["first"].fee_growth_inside_a_last
+        };
+
+        // The range was crossed before the open. Reset starts its checkpoint at zero, carried forward at the growth
+        // already inside the range, which a zero checkpoint would have paid out to the position.
+        assert!(open_first_position(&mut reset).is_zero());
+        assert!(!open_first_position(&mut carried).is_zero());
+
+        let mut first_fees = Vec::new();
+        for arr in [&mut reset, &mut carried] {
+            arr.simulate_swap(U256::from(3_000_000), true).unwrap();
+            arr.simulate_swap(U256::from(3_000_000), false).unwrap();
+            first_fees.push(arr.collect_fees("first").unwrap());
+        }
+
+        // Only growth after the open counts, so both modes pay the first position the same.
+        let (reset_fees, carried_fees) = (first_fees[0], first_fees[1]);
+        assert!(!reset_fees.0.is_zero() && !reset_fees.1.is_zero());
+        assert!(reset_fees.0.abs_diff(carried_fees.0) <= U256::one());
+        assert!(reset_fees.1.abs_diff(carried_fees.1) <= U256::one());
+        assert!(carried.fee_growth_global_a > reset.fee_growth_global_a);
+    }
+
+    #[test]
+    fn test_whale_swap_matches_per_range_amounts() {
+        // 1e9 in every 10 tick rung from -300 to 310, so the active liquidity is the same everywhere but every
+        // multiple of 10 is an initialized tick the swap has to cross.
+        let mut array = create_laddered_test_liquidity_array(5, 10, 61);
+        array.fee_rate = 0;
+        let liquidity = array.active_liquidity;
+        let start_sqrt_price = array.current_sqrt_price;
+
+        let mut capped = array.clone();
+
+        let amount_out = array.simulate_swap(U256::from(10_000_000), true).unwrap();
+        let end_tick = array.current_tick;
+        assert!(
+            end_tick < -150,
+            "should cross a lot of ticks, ended at {}",
+            end_tick
+        );
+
+        // Sum the output of every rung the price went through, from the start price down to where it ended.
+        let mut expected = U256::zero();
+        let mut range_upper = start_sqrt_price;
+        let mut crossings = 0;
+        for boundary in (end_tick + 1..=0).rev().filter(|tick| tick % 10 == 0) {
+            let range_lower = tick_to_sqrt_price_u256(boundary);
+            expected += calculate_amount_b_delta(liquidity, range_upper, range_lower);
+            range_upper = range_lower;
+            crossings += 1;
+        }
+        expected += calculate_amount_b_delta(liquidity, range_upper, array.current_sqrt_price);
+
+        assert_eq!(amount_out, expected);
+        // One rounding unit per range at most versus the whole move in one go.
+        let single_range =
+            calculate_amount_b_delta(liquidity, start_sqrt_price, array.current_sqrt_price);
+        assert!(single_range.abs_diff(amount_out) <= U256::from(crossings + 1));
+
+        // The same swap with a cap below the crossings it needs errors instead.
+        capped.max_tick_crossings = Some(5);
+        assert!(matches!(
+            capped.simulate_swap(U256::from(10_000_000), true),
+            Err(LiquidityArrayError::TooManyTickCrossings(5))
+        ));
+    }
+
+    #[test]
+    fn test_swap_stops_at_full_range_boundary() {
+        let liquidity = 1_000_000_000_u128;
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 10, 300)
+            .price_at_tick(0)
+            .position(-1000, 1000, liquidity)
+            .build();
+
+        // Far more than the range holds, price is pushed all the way down to the edge instead of erroring.
+        let (_, amount_b_in_range) = calculate_amounts(
+            U256::from(liquidity),
+            array.current_sqrt_price,
+            tick_to_sqrt_price_u256(-1000),
+            tick_to_sqrt_price_u256(1000),
+        );
+        let amount_out = array
+            .simulate_swap(U256::from(10_u128.pow(15)), true)
+            .unwrap();
+
+        assert_eq!(array.current_tick, -1000);
+        assert_eq!(array.current_sqrt_price, tick_to_sqrt_price_u256(-1000));
+        assert!(amount_out <= amount_b_in_range);
+        assert!(amount_out > amount_b_in_range * 99 / 100);
+        assert_eq!(array.active_liquidity, U256::from(liquidity));
+
+        // Pushing further at the edge is a no-op.
+        assert!(array
+            .simulate_swap(U256::from(1_000_000), true)
+            .unwrap()
+            .is_zero());
+
+        // Same at the upper edge, the position stays in range.
+        array
+            .simulate_swap(U256::from(10_u128.pow(15)), false)
+            .unwrap();
+
+        assert_eq!(array.current_tick, 999);
+        assert_eq!(array.current_sqrt_price, tick_to_sqrt_price_u256(1000));
+        assert_eq!(array.active_liquidity, U256::from(liquidity));
+        assert_eq!(array.swaps_stopped_at_edge, 3);
+    }
+
+    #[test]
+    fn test_swap_past_the_last_interior_initialized_tick_errors() {
+        // The position ends inside the array, below it there is no initialized tick left to cross into.
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 10, 300)
+            .price_at_tick(0)
+            .position(-500, 500, 1_000_000_000)
+            .build();
+
+        assert!(matches!(
+            array.simulate_swap(U256::from(10_u128.pow(15)), true),
+            Err(LiquidityArrayError::InitializedTickNotFound)
+        ));
+        assert_eq!(array.swaps_stopped_at_edge, 0);
+    }
+
+    #[test]
+    fn test_current_price_sol_usdc() {
+        // SOL (9 decimals) / USDC (6 decimals) at $150, 0.15 raw units of USDC per lamport.
+        let array = TestLiquidityArrayBuilder::new(-30000, 30000, 2, 300)
+            .price_at_tick(price_to_tick(0.15))
+            .build();
+
+        // Tick rounding is at most 1 bp.
+        let price = array.current_price(9, 6);
+        assert!((price - 150.0).abs() / 150.0 < 1e-4, "price {}", price);
+        assert!((array.current_price(0, 0) - 0.15).abs() / 0.15 < 1e-4);
+    }
+
+    #[test]
+    fn test_no_fees_for_positions_never_in_range() {
+        // Up through 150..190 and back, then down through -150..-180 and back, so the reused ticks below already
+        // carry fee growth outside when the positions open.
+        let mut array = create_laddered_test_liquidity_array(0, 10, 40);
+        for (amount, is_sell) in [
+            (10_000_000, false),
+            (10_000_000, true),
+            (9_000_000, true),
+            (9_000_000, false),
+        ] {
+            array.simulate_swap(U256::from(amount), is_sell).unwrap();
+        }
+
+        let positions = [
+            // Reused rung ticks above and below the price, then fresh ticks further out.
+            ("above", 150, 190),
+            ("below", -190, -150),
+            ("far_above", 215, 235),
+            ("far_below", -235, -215),
+        ];
+        for (id, lower_tick, upper_tick) in positions {
+            array
+                .add_owners_position(
+                    OwnersPosition {
+                        owner: String::from(""),
+                        lower_tick,
+                        upper_tick,
+                        liquidity: 1_000_000_000,
+                        fee_growth_inside_a_last: U256::zero(),
+                        fee_growth_inside_b_last: U256::zero(),
+                    },
+                    id.to_string(),
+                )
+                .unwrap();
+        }
+        reset_initialized_tick_caches(&mut array);
+
+        // The price moves around (crossing ticks) but stays well inside -150..150.
+        for _ in 0..5 {
+            array.simulate_swap(U256::from(5_000_000), true).unwrap();
+            array.simulate_swap(U256::from(5_000_000), false).unwrap();
+            assert!(array.current_tick > -150 && array.current_tick < 150);
+        }
+
+        for (id, _, _) in positions {
+            assert_eq!(
+                array.collect_fees(id).unwrap(),
+                (U256::zero(), U256::zero()),
+                "{} earned fees without being in range",
+                id
+            );
+        }
+    }
+
+    #[test]
+    fn test_fee_attribution_full_range_and_narrow_positions() {
+        // Only owners' positions, so every fee charged belongs to one of them. The full range one spans the whole array,
+        // so its fee growth inside reads the very first and last ticks.
+        let liquidity = 1_000_000_000_000_u128;
+        let mut array = TestLiquidityArrayBuilder::new(-1000, 1000, 10, 300)
+            .price_at_tick(0)
+            .build();
+
+        let positions = [
+            ("full_range", -1000, 1000),
+            ("around_price", -50, 50),
+            ("above", 200, 300),
+            ("below", -300, -200),
+        ];
+        for (id, lower_tick, upper_tick) in positions {
+            array
+                .add_owners_position(
+                    OwnersPosition {
+                        owner: String::from(""),
+                        lower_tick,
+                        upper_tick,
+                        liquidity,
+                        fee_growth_inside_a_last: U256::zero(),
+                        fee_growth_inside_b_last: U256::zero(),
+                    },
+                    id.to_string(),
+                )
+                .unwrap();
+        }
+        reset_initialized_tick_caches(&mut array);
+
+        let collect = |array: &mut LiquidityArray| {
+            positions
+                .map(|(id, _, _)| array.collect_fees(id).unwrap())
+                .map(|(fees_a, fees_b)| (fees_a.as_u128(), fees_b.as_u128()))
+        };
+
+        // Inside -50..50 the fees split evenly between the full range and the narrow position around the price.
+        array.simulate_swap(U256::from(100_000_000), true).unwrap();
+        array.simulate_swap(U256::from(100_000_000), false).unwrap();
+        assert!(array.current_tick > -50 && array.current_tick < 50);
+
+        let fees = collect(&mut array);
+        assert!(fees[0].0.abs_diff(fees[1].0) <= 1 && fees[0].1.abs_diff(fees[1].1) <= 1);
+        assert_eq!(fees[2], (0, 0));
+        assert_eq!(fees[3], (0, 0));
+        // Each position's share is rounded down.
+        let charged_a = 100_000_000 * 300 / 1_000_000;
+        assert!(fees[0].0 + fees[1].0 <= charged_a && charged_a - (fees[0].0 + fees[1].0) <= 2);
+
+        // Up into 200..300, trade there, and back down. The position below is never in range.
+        let mut charged_b = 0;
+        for (amount, is_sell) in [
+            (15_000_000_000_u128, false),
+            (200_000_000, true),
+            (200_000_000, false),
+            (20_000_000_000, true),
+        ] {
+            array.simulate_swap(U256::from(amount), is_sell).unwrap();
+            if !is_sell {
+                charged_b += amount * 300 / 1_000_000;
+            }
+        }
+        assert!(array.current_tick > -200 && array.current_tick < 200);
+
+        let fees = collect(&mut array);
+        assert!(fees[2].0 > 0 && fees[2].1 > 0);
+        assert_eq!(fees[3], (0, 0));
+        // The full range position was in range all along, so it earned at least as much as any narrow one.
+        for narrow in &fees[1..] {
+            assert!(fees[0].0 >= narrow.0 && fees[0].1 >= narrow.1);
+        }
+        // Nothing is lost or created across the ranges, apart from per-step rounding.
+        let total_b: u128 = fees.iter().map(|(_, fees_b)| fees_b).sum();
+        assert!(
+            total_b <= charged_b && charged_b - total_b <= 10,
+            "{} of {}",
+            total_b,
+            charged_b
+        );
+    }
+
+    #[test]
+    fn test_round_trip_across_tick_hands_back_no_extra() {
+        // Found by the round trip property: with the sell price rounded down this returned 23_000_001.
+        let mut array = TestLiquidityArrayBuilder::new(-200_000, 200_000, 10, 0)
+            .price_at_tick(-749)
+            .position(-100_000, 100_000, 1_000_000_000)
+            .position(-1170, -750, 1_000_000)
+            .build();
+
+        let amount_out = array.simulate_swap(U256::from(23_000_000), true).unwrap();
+        let amount_back = array.simulate_swap(amount_out, false).unwrap();
+
+        assert!(amount_back <= U256::from(23_000_000));
+    }
+
+    #[test]
+    fn test_amount_in_to_reach_tick_lands_on_the_target() {
+        // Every 10 ticks is initialized, both moves cross several of them.
+        for target_tick in [-1073, -1000, -912, -995] {
+            let mut array = create_laddered_test_liquidity_array(-1000, 10, 40);
+
+            let (amount_in, is_sell) = array.amount_in_to_reach_tick(target_tick).unwrap();
+            assert_eq!(is_sell, target_tick < -1000);
+
+            array.simulate_swap(amount_in, is_sell).unwrap();
+            let reached_tick = price_to_tick(sqrt_price_to_price(array.current_sqrt_price));
+            assert!(
+                (reached_tick - target_tick).abs() <= 1,
+                "aimed for {}, reached {}",
+                target_tick,
+                reached_tick
+            );
+        }
+    }
+
+    #[test]
+    fn test_recent_fee_growth_per_second() {
+        let mut array = create_test_liquidity_array(0);
+        assert_eq!(array.active_liquidity, U256::from(2_000_000_000_u128));
+        assert_eq!(array.recent_fee_growth_per_second(), None);
+
+        // Token B in, 500 of fees each (fee rate 500) spread over 2e9 liquidity.
+        for block_time in [100, 110] {
+            array.current_block_time = block_time;
+            array.simulate_swap(U256::from(1_000_000), false).unwrap();
+        }
+
+        let (per_second_a, per_second_b) = array.recent_fee_growth_per_second().unwrap();
+        assert_eq!(per_second_a, 0.0);
+        // The first sample is taken after the first swap, so only the second one's fees count.
+        assert!((per_second_b - 500.0 / 2e9 / 10.0).abs() < 1e-15);
+
+        for block_time in 111..200 {
+            array.current_block_time = block_time;
+            array.simulate_swap(U256::from(10_000), true).unwrap();
+        }
+        assert_eq!(array.recent_fee_growth.len(), FEE_VELOCITY_WINDOW);
+        assert!(array.recent_fee_growth_per_second().unwrap().0 > 0.0);
+
+        array.reset_fee_growth();
+        assert_eq!(array.recent_fee_growth_per_second(), None);
+    }
+
+    #[test]
+    fn test_lower_initialized_tick_right_below_current() {
+        // Found by the round trip property: the downward search skipped the tick right below, so the sell crossed -610
+        // with the liquidity ending at 770 still counted and underflowed.
+        let mut array = TestLiquidityArrayBuilder::new(-200_000, 200_000, 10, 0)
+            .price_at_tick(771)
+            .position(-100_000, 100_000, 1_000_000_000)
+            .position(-610, 770, 1_000_000)
+            .build();
+
+        assert_eq!(
+            array.get_next_initialized_tick(771, false).unwrap().tick,
+            770
+        );
+        assert_eq!(array.cached_lower_initialized_tick, Some(770));
+
+        let amount_out = array.simulate_swap(U256::from(69_000_000), true).unwrap();
+        assert!(!amount_out.is_zero());
+    }
+
+    fn random_liquidity_array() -> impl Strategy<Value = LiquidityArray> {
+        (
+            -100..100_i32,
+            1..10_i32,
+            0..=10_000_i16,
+            1_000_000_000..1_000_000_000_000_u128,
+            prop::collection::vec(
+                (-200..200_i32, 1..200_i32, 1_000_000..100_000_000_000_u128),
+                0..6,
+            ),
+        )
+            .prop_map(
+                |(current_range, tick_offset, fee_rate, base_liquidity, positions)| {
+                    // The wide base position keeps liquidity around the price wherever the swap goes. The price sits between
+                    // position bounds, at an initialized tick the builder's active liquidity wouldn't match the caches.
+                    let mut builder =
+                        TestLiquidityArrayBuilder::new(-200_000, 200_000, 10, fee_rate)
+                            .price_at_tick(current_range * 10 + tick_offset)
+                            .position(-100_000, 100_000, base_liquidity);
+
+                    for (lower, width, liquidity) in positions {
+                        builder = builder.position(lower * 10, (lower + width) * 10, liquidity);
+                    }
+
+                    builder.build()
+                },
+            )
+    }
+
+    proptest! {
+        // Building the array dominates, 64 cases keeps this in line with the rest of the suite.
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        #[test]
+        fn prop_round_trip_swap_creates_no_value(
+            mut array in random_liquidity_array(),
+            amount_per_mille in 1..100_u128,
+        ) {
+            let start_sqrt_price = array.current_sqrt_price;
+            let fee_bps = (array.fee_rate as u32).div_ceil(100);
+            let amount_in = U256::from(
+                array.active_liquidity.as_u128() / 1000 * amount_per_mille,
+            );
+
+            let amount_out = array.simulate_swap(amount_in, true).unwrap();
+            prop_assume!(!amount_out.is_zero());
+            let amount_back = array.simulate_swap(amount_out, false).unwrap();
+
+            // Fees are only ever taken, so the round trip can't hand back more than went in
+            // and the price can't end above where it started.
+            prop_assert!(amount_back <= amount_in, "{} back for {} in", amount_back, amount_in);
+            prop_assert!(array.current_sqrt_price <= start_sqrt_price);
+            // The buy back is short by the fees, which is at most fee_rate of the move.
+            prop_assert!(within_tolerance(
+                start_sqrt_price,
+                array.current_sqrt_price,
+                fee_bps + 1
+            ));
+        }
+    }
 }
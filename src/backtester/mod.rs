@@ -1,5 +1,10 @@
+pub mod backtest_utils;
 pub mod backtester_core;
+pub mod jit_band_strategy;
+pub mod ladder_strategy;
 pub mod liquidity_array;
 pub mod no_rebalance_strategy;
 pub mod simple_rebalance_strategy;
-pub mod backtest_utils;
+pub mod synthetic_source;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_helpers;
@@ -36,10 +36,14 @@ impl Strategy for NoRebalanceStrategy {
         vec![]
     }
 
-    fn finalize_strategy(&self) -> Vec<Action> {
-        vec![Action::ClosePosition {
-            position_id: String::from("no_rebalance"),
-        }]
+    fn on_window_end(&self, liquidity_array: &LiquidityArray) -> Vec<Action> {
+        let position_id = String::from("no_rebalance");
+
+        if liquidity_array.positions.contains_key(&position_id) {
+            vec![Action::ClosePosition { position_id }]
+        } else {
+            vec![]
+        }
     }
 
     fn get_ticks(&self) -> (i32, i32) {
@@ -66,13 +66,24 @@ impl Strategy for SimpleRebalanceStrategy {
         }
     }
 
-    fn finalize_strategy(&self) -> Vec<Action> {
-        vec![Action::ClosePosition {
-            position_id: String::from("simple_rebalance"),
-        }]
+    fn resume_strategy(&mut self, liquidity_array: &LiquidityArray) {
+        if let Some(position) = liquidity_array.positions.get("simple_rebalance") {
+            self.current_lower_tick = position.lower_tick;
+            self.current_upper_tick = position.upper_tick;
+        }
+    }
+
+    fn on_window_end(&self, liquidity_array: &LiquidityArray) -> Vec<Action> {
+        let position_id = String::from("simple_rebalance");
+
+        if liquidity_array.positions.contains_key(&position_id) {
+            vec![Action::ClosePosition { position_id }]
+        } else {
+            vec![]
+        }
     }
 
     fn get_ticks(&self) -> (i32, i32) {
-        return (self.current_lower_tick, self.current_upper_tick);
+        (self.current_lower_tick, self.current_upper_tick)
     }
 }
@@ -0,0 +1,513 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    backtester::liquidity_array::LiquidityArray,
+    models::transactions_model::{SwapData, TransactionData, TransactionModelFromDB},
+    repositories::transactions_repo::{OrderDirection, TransactionRepoTrait},
+    utils::core_math::{MAX_TICK, MIN_TICK, U256},
+};
+
+// Every step is sized and simulated up front when generating, so the path length is capped.
+pub const MAX_SYNTHETIC_STEPS: usize = 10_000;
+
+// A price path to replay instead of the synced history. Moves are in pct of the price per step, the path runs in log
+// price (ticks) so a -30% jump is the same move at any price.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntheticScenario {
+    pub name: String,
+    pub steps: usize,
+    pub step_seconds: i64,
+    pub drift_pct: f64,
+    // Standard deviation of the random part of each step.
+    pub volatility_pct: f64,
+    // Share of the distance back to the starting price recovered each step, 0 is a plain random walk.
+    pub mean_reversion: f64,
+    // One off move of jump_pct at that step, e.g. -30 for a sudden 30% dump.
+    pub jump_step: Option<usize>,
+    pub jump_pct: f64,
+    pub seed: u64,
+}
+
+impl SyntheticScenario {
+    pub fn preset(name: &str) -> Option<Self> {
+        let base = SyntheticScenario {
+            name: name.to_string(),
+            steps: 1_000,
+            step_seconds: 60,
+            drift_pct: 0.0,
+            volatility_pct: 0.05,
+            mean_reversion: 0.0,
+            jump_step: None,
+            jump_pct: 0.0,
+            seed: 42,
+        };
+
+        match name {
+            "dump" => Some(SyntheticScenario {
+                jump_step: Some(500),
+                jump_pct: -30.0,
+                ..base
+            }),
+            // About +10% over the 1000 steps.
+            "grind_up" => Some(SyntheticScenario {
+                drift_pct: 0.01,
+                volatility_pct: 0.03,
+                ..base
+            }),
+            "chop" => Some(SyntheticScenario {
+                volatility_pct: 0.2,
+                mean_reversion: 0.1,
+                ..base
+            }),
+            _ => None,
+        }
+    }
+
+    // Target tick after every step, starting from start_tick. A path running off the tick range stays at its edge.
+    pub fn tick_path(&self, start_tick: i32) -> Vec<i32> {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let mut offset = 0.0;
+
+        (0..self.steps)
+            .map(|step| {
+                offset += pct_to_ticks(self.drift_pct)
+                    + pct_to_ticks(self.volatility_pct) * standard_normal(&mut rng)
+                    - self.mean_reversion * offset;
+
+                if self.jump_step == Some(step) {
+                    offset += pct_to_ticks(self.jump_pct);
+                }
+
+                (start_tick as f64 + offset.round()).clamp(MIN_TICK as f64, MAX_TICK as f64) as i32
+            })
+            .collect()
+    }
+}
+
+// A preset name, optionally with overrides: "dump" or "dump:jump_pct=-50,steps=2000,seed=7".
+impl FromStr for SyntheticScenario {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let (name, overrides) = match s.split_once(':') {
+            Some((name, overrides)) => (name, overrides),
+            None => (s, ""),
+        };
+
+        let mut scenario = SyntheticScenario::preset(name).ok_or_else(|| {
+            anyhow!(
+                "Unknown synthetic scenario: {}. Use dump, grind_up or chop",
+                name
+            )
+        })?;
+
+        for assignment in overrides.split(',').filter(|a| !a.is_empty()) {
+            let (key, value) = assignment
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Expected key=value, got {}", assignment))?;
+            let context = || format!("Invalid value for {}: {}", key, value);
+
+            match key {
+                "steps" => scenario.steps = value.parse().with_context(context)?,
+                "step_seconds" => scenario.step_seconds = value.parse().with_context(context)?,
+                "drift_pct" => scenario.drift_pct = value.parse().with_context(context)?,
+                "volatility_pct" => {
+                    scenario.volatility_pct = value.parse().with_context(context)?
+                }
+                "mean_reversion" => {
+                    scenario.mean_reversion = value.parse().with_context(context)?
+                }
+                "jump_step" => scenario.jump_step = Some(value.parse().with_context(context)?),
+                "jump_pct" => scenario.jump_pct = value.parse().with_context(context)?,
+                "seed" => scenario.seed = value.parse().with_context(context)?,
+                _ => return Err(anyhow!("Unknown synthetic scenario parameter: {}", key)),
+            }
+        }
+
+        if scenario.steps == 0 || scenario.steps > MAX_SYNTHETIC_STEPS {
+            return Err(anyhow!(
+                "steps must be between 1 and {}",
+                MAX_SYNTHETIC_STEPS
+            ));
+        }
+        if scenario.step_seconds <= 0 {
+            return Err(anyhow!("step_seconds must be positive"));
+        }
+        // A move of -100% or more has no price (no tick) to move to.
+        for (key, pct) in [
+            ("drift_pct", scenario.drift_pct),
+            ("jump_pct", scenario.jump_pct),
+        ] {
+            if !pct.is_finite() || pct <= -100.0 {
+                return Err(anyhow!("{} must be above -100, got {}", key, pct));
+            }
+        }
+        if !scenario.volatility_pct.is_finite() || scenario.volatility_pct < 0.0 {
+            return Err(anyhow!("volatility_pct can't be negative"));
+        }
+
+        Ok(scenario)
+    }
+}
+
+fn pct_to_ticks(pct: f64) -> f64 {
+    (1.0 + pct / 100.0).ln() / 1.0001f64.ln()
+}
+
+// Box-Muller, rand alone has no normal distribution.
+fn standard_normal(rng: &mut StdRng) -> f64 {
+    let u1: f64 = rng.gen_range(f64::EPSILON..1.0);
+    let u2: f64 = rng.gen();
+
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+// Swaps that walk the pool price along a synthetic path, served like the synced transactions. Each swap's amount_in is
+// sized against the pool liquidity (without the strategy's positions) to land on the next tick of the path, so large
+// strategy positions dampen the moves like they would for real swaps.
+pub struct SyntheticTransactionSource {
+    // In time order. tx_ids descend with time like the synced table (the last step is tx 1), so the window is replayed
+    // from start_tx down to end_tx like the synced transactions.
+    pub transactions: Vec<TransactionModelFromDB>,
+    // Not replayed, marks the start of the path (start_time) for the cursor and the starting prices.
+    pub start_tx: TransactionModelFromDB,
+}
+
+impl SyntheticTransactionSource {
+    pub fn generate(
+        scenario: &SyntheticScenario,
+        liquidity_arr: &LiquidityArray,
+        pool_address: &str,
+        token_a_address: &str,
+        token_b_address: &str,
+        start_time: i64,
+    ) -> Result<Self> {
+        let mut pool = liquidity_arr.clone();
+        let path = scenario.tick_path(pool.current_tick);
+        let mut transactions = Vec::with_capacity(path.len());
+
+        let steps = path.len() as i64;
+
+        for (step, target_tick) in (1..=steps).zip(path) {
+            let tx_id = steps + 1 - step;
+            let block_time = start_time + step * scenario.step_seconds;
+
+            pool.apply_fee_rate_at(block_time);
+            let (amount_in, is_sell) = pool
+                .amount_in_to_reach_tick(target_tick)
+                .context("Failed to size a synthetic swap")?;
+            if amount_in.is_zero() {
+                continue;
+            }
+
+            // The swaps table stores u64 amounts.
+            let amount_in = amount_in.min(U256::from(u64::MAX));
+            let amount_out = pool
+                .simulate_swap(amount_in, is_sell)
+                .context(format!("Failed to apply synthetic swap {}", tx_id))?;
+
+            let (token_in, token_out) = if is_sell {
+                (token_a_address, token_b_address)
+            } else {
+                (token_b_address, token_a_address)
+            };
+
+            transactions.push(synthetic_transaction(
+                tx_id,
+                pool_address,
+                block_time,
+                TransactionData::Swap(SwapData {
+                    token_in: token_in.to_string(),
+                    token_out: token_out.to_string(),
+                    amount_in: amount_in.as_u64(),
+                    amount_out: amount_out.min(U256::from(u64::MAX)).as_u64(),
+                    source_program: None,
+                }),
+            ));
+        }
+
+        let first_swap = transactions
+            .first()
+            .ok_or_else(|| anyhow!("The synthetic path never moves the price"))?;
+        // Older than every step.
+        let start_tx =
+            synthetic_transaction(steps + 1, pool_address, start_time, first_swap.data.clone());
+
+        Ok(SyntheticTransactionSource {
+            transactions,
+            start_tx,
+        })
+    }
+
+    // The last swap of the path, where the replay stops.
+    pub fn end_tx(&self) -> &TransactionModelFromDB {
+        self.transactions.last().unwrap()
+    }
+}
+
+fn synthetic_transaction(
+    tx_id: i64,
+    pool_address: &str,
+    block_time: i64,
+    data: TransactionData,
+) -> TransactionModelFromDB {
+    TransactionModelFromDB {
+        tx_id,
+        signature: format!("synthetic{}", tx_id),
+        pool_address: pool_address.to_string(),
+        block_time,
+        block_time_utc: DateTime::from_timestamp(block_time, 0).unwrap_or_default(),
+        transaction_type: "Swap".to_string(),
+        ready_for_backtesting: true,
+        data,
+    }
+}
+
+#[async_trait]
+impl TransactionRepoTrait for SyntheticTransactionSource {
+    async fn fetch_transactions(
+        &self,
+        _pool_address: &str,
+        cursor: Option<i64>,
+        limit: i64,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        let in_cursor = |tx: &&TransactionModelFromDB| match (&order, cursor) {
+            (OrderDirection::Descending, Some(c)) => tx.tx_id < c,
+            (OrderDirection::Ascending, Some(c)) => tx.tx_id > c,
+            (_, None) => true,
+        };
+
+        // Stored in descending tx_id order.
+        let transactions: Vec<TransactionModelFromDB> = match order {
+            OrderDirection::Descending => self
+                .transactions
+                .iter()
+                .filter(in_cursor)
+                .take(limit as usize)
+                .cloned()
+                .collect(),
+            OrderDirection::Ascending => self
+                .transactions
+                .iter()
+                .rev()
+                .filter(in_cursor)
+                .take(limit as usize)
+                .cloned()
+                .collect(),
+        };
+
+        Ok(transactions)
+    }
+
+    async fn fetch_transactions_in_time_range(
+        &self,
+        _pool_address: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        // One swap per step, stored in time order.
+        let mut transactions: Vec<TransactionModelFromDB> = self
+            .transactions
+            .iter()
+            .filter(|tx| tx.block_time_utc >= start && tx.block_time_utc < end)
+            .cloned()
+            .collect();
+
+        if let OrderDirection::Descending = order {
+            transactions.reverse();
+        }
+
+        Ok(transactions)
+    }
+
+    async fn tx_id_at_or_after(
+        &self,
+        _pool_address: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<i64>> {
+        Ok(self
+            .transactions
+            .iter()
+            .find(|tx| tx.block_time_utc >= time)
+            .map(|tx| tx.tx_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        backtester::{
+            backtester_core::{Action, Backtest, Strategy},
+            test_helpers::{create_test_liquidity_array, test_wallet},
+        },
+        utils::core_math::{price_to_tick, sqrt_price_to_price},
+    };
+
+    struct Passive;
+
+    impl Strategy for Passive {
+        fn initialize_strategy(&self) -> Vec<Action> {
+            vec![]
+        }
+
+        fn update(
+            &mut self,
+            _liquidity_array: &LiquidityArray,
+            _transaction: TransactionModelFromDB,
+        ) -> Vec<Action> {
+            vec![]
+        }
+
+        fn on_window_end(&self, _liquidity_array: &LiquidityArray) -> Vec<Action> {
+            vec![]
+        }
+
+        fn get_ticks(&self) -> (i32, i32) {
+            (0, 0)
+        }
+    }
+
+    #[test]
+    fn test_scenario_parsing() {
+        let scenario: SyntheticScenario = "dump:jump_pct=-50,steps=200".parse().unwrap();
+        assert_eq!(scenario.jump_pct, -50.0);
+        assert_eq!(scenario.steps, 200);
+        assert_eq!(scenario.jump_step, Some(500));
+
+        assert!("moon".parse::<SyntheticScenario>().is_err());
+        assert!("chop:vol=1".parse::<SyntheticScenario>().is_err());
+        assert!("chop:steps=0".parse::<SyntheticScenario>().is_err());
+        assert!("dump:jump_pct=-100".parse::<SyntheticScenario>().is_err());
+        assert!("grind_up:drift_pct=-150"
+            .parse::<SyntheticScenario>()
+            .is_err());
+        assert!("chop:volatility_pct=NaN"
+            .parse::<SyntheticScenario>()
+            .is_err());
+    }
+
+    #[test]
+    fn test_tick_path_stays_in_the_tick_range() {
+        let scenario: SyntheticScenario = "dump:jump_pct=-99.9999,steps=600,volatility_pct=0"
+            .parse()
+            .unwrap();
+        let path = scenario.tick_path(MIN_TICK + 10);
+        assert_eq!(path[499], MIN_TICK + 10);
+        assert_eq!(path[500], MIN_TICK);
+
+        let scenario: SyntheticScenario = "dump:jump_pct=100,steps=600,volatility_pct=0"
+            .parse()
+            .unwrap();
+        assert_eq!(scenario.tick_path(MAX_TICK - 10)[599], MAX_TICK);
+    }
+
+    #[tokio::test]
+    async fn test_synthetic_dump_moves_the_replayed_price() {
+        let scenario: SyntheticScenario = "dump:steps=20,jump_step=10,volatility_pct=0"
+            .parse()
+            .unwrap();
+        let liquidity_arr = create_test_liquidity_array(0);
+
+        let path = scenario.tick_path(0);
+        assert_eq!(path[9], 0);
+        // ln(0.7) / ln(1.0001)
+        assert_eq!(path[10], -3567);
+
+        let source = SyntheticTransactionSource::generate(
+            &scenario,
+            &liquidity_arr,
+            "test_pool",
+            "TokenA",
+            "TokenB",
+            1_700_000_000,
+        )
+        .unwrap();
+
+        // Flat steps don't need a swap, only the jump does.
+        assert_eq!(source.transactions.len(), 1);
+        assert_eq!(source.start_tx.block_time, 1_700_000_000);
+        assert_eq!(source.end_tx().block_time, 1_700_000_000 + 11 * 60);
+
+        // Replayed over the window main.rs passes, the price lands on the path's last tick.
+        let mut backtest = Backtest::new(
+            U256::zero(),
+            U256::zero(),
+            liquidity_arr,
+            test_wallet(U256::zero(), U256::zero()),
+            Box::new(Passive),
+        );
+        backtest
+            .sync_forward(
+                &source,
+                source.start_tx.tx_id,
+                source.end_tx().tx_id,
+                "test_pool",
+                10_000,
+            )
+            .await
+            .unwrap();
+        let pool = &backtest.liquidity_arr;
+
+        let reached_tick = price_to_tick(sqrt_price_to_price(pool.current_sqrt_price));
+        assert!((reached_tick - path[19]).abs() <= 1);
+    }
+
+    #[tokio::test]
+    async fn test_synthetic_path_replays_every_swap_in_step_order() {
+        let scenario: SyntheticScenario = "chop:steps=100".parse().unwrap();
+        let liquidity_arr = create_test_liquidity_array(0);
+        let path = scenario.tick_path(0);
+
+        let source = SyntheticTransactionSource::generate(
+            &scenario,
+            &liquidity_arr,
+            "test_pool",
+            "TokenA",
+            "TokenB",
+            1_700_000_000,
+        )
+        .unwrap();
+        assert!(source.transactions.len() > 50);
+        assert_eq!(source.end_tx().tx_id, 1);
+
+        let mut backtest = Backtest::new(
+            U256::zero(),
+            U256::zero(),
+            liquidity_arr,
+            test_wallet(U256::zero(), U256::zero()),
+            Box::new(Passive),
+        );
+        // Small batches, the path has to come out in step order across them too.
+        backtest
+            .sync_forward(
+                &source,
+                source.start_tx.tx_id,
+                source.end_tx().tx_id,
+                "test_pool",
+                16,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            backtest.data.current_swap_nmr as usize,
+            source.transactions.len()
+        );
+        assert_eq!(
+            backtest.liquidity_arr.current_block_time,
+            source.end_tx().block_time
+        );
+        let reached_tick = price_to_tick(sqrt_price_to_price(
+            backtest.liquidity_arr.current_sqrt_price,
+        ));
+        assert!((reached_tick - path[99]).abs() <= 1);
+    }
+}
@@ -0,0 +1,330 @@
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use std::collections::HashMap;
+
+use crate::{
+    api::{
+        price_api::{PriceApi, PricePoint, PriceSource, PriceToken},
+        token_metadata_api::TokenMetadataApiTrait,
+    },
+    models::transactions_model::{SwapData, TransactionData, TransactionModelFromDB},
+    repositories::transactions_repo::{OrderDirection, TransactionRepoTrait},
+    utils::core_math::{tick_to_sqrt_price_u256, U256},
+};
+
+use super::{backtester_core::Wallet, liquidity_array::LiquidityArray};
+
+// Fixtures shared by the unit tests, the benches in benches/ and tests/. Only built for tests or with the test-utils feature.
+
+// Fluent setup for liquidity arrays in tests. Positions are added at build(), after the price is set, so the ones
+// covering the price count as active liquidity whatever order the calls come in. The initialized tick caches are
+// derived from the final state unless set explicitly.
+pub struct TestLiquidityArrayBuilder {
+    liquidity_arr: LiquidityArray,
+    positions: Vec<(i32, i32, u128)>,
+    cached_ticks: Option<(i32, i32)>,
+}
+
+impl TestLiquidityArrayBuilder {
+    pub fn new(min_tick: i32, max_tick: i32, tick_spacing: i32, fee_rate: i16) -> Self {
+        Self {
+            liquidity_arr: LiquidityArray::new(min_tick, max_tick, tick_spacing, fee_rate),
+            positions: Vec::new(),
+            cached_ticks: None,
+        }
+    }
+
+    pub fn price_at_tick(mut self, tick: i32) -> Self {
+        self.liquidity_arr.current_tick = tick;
+        self.liquidity_arr.current_sqrt_price = tick_to_sqrt_price_u256(tick);
+        self
+    }
+
+    pub fn position(mut self, lower_tick: i32, upper_tick: i32, liquidity: u128) -> Self {
+        self.positions.push((lower_tick, upper_tick, liquidity));
+        self
+    }
+
+    pub fn cached_ticks(mut self, lower_tick: i32, upper_tick: i32) -> Self {
+        self.cached_ticks = Some((lower_tick, upper_tick));
+        self
+    }
+
+    pub fn build(mut self) -> LiquidityArray {
+        for (lower_tick, upper_tick, liquidity) in &self.positions {
+            self.liquidity_arr
+                .update_liquidity(*lower_tick, *upper_tick, *liquidity, true);
+        }
+
+        match self.cached_ticks {
+            Some((lower_tick, upper_tick)) => {
+                self.liquidity_arr.cached_lower_initialized_tick = Some(lower_tick);
+                self.liquidity_arr.cached_upper_initialized_tick = Some(upper_tick);
+            }
+            // Nothing to search for without any initialized ticks.
+            None if !self.positions.is_empty() => {
+                reset_initialized_tick_caches(&mut self.liquidity_arr)
+            }
+            None => {}
+        }
+
+        self.liquidity_arr
+    }
+}
+
+// Two wide overlapping positions, swaps of a few million units stay inside the current tick range.
+// TokenA/TokenB wallet with 6 decimals on both sides and nothing collected yet, what most backtest tests start from.
+pub fn test_wallet(amount_a: U256, amount_b: U256) -> Wallet {
+    Wallet {
+        token_a_addr: "TokenA".to_string(),
+        token_b_addr: "TokenB".to_string(),
+        amount_token_a: amount_a,
+        amount_token_b: amount_b,
+        token_a_decimals: 6,
+        token_b_decimals: 6,
+        amount_a_fees_collected: U256::zero(),
+        amount_b_fees_collected: U256::zero(),
+    }
+}
+
+pub fn create_test_liquidity_array(current_tick: i32) -> LiquidityArray {
+    TestLiquidityArrayBuilder::new(-500_000, 500_000, 10, 500)
+        .price_at_tick(current_tick)
+        .position(-100_000, 100_000, 1_000_000_000)
+        .position(-200_000, 200_000, 1_000_000_000)
+        .build()
+}
+
+// num_positions back to back positions, each one tick_spacing wide, centered on current_tick.
+// Every position boundary is an initialized tick, so any meaningful swap crosses ticks.
+pub fn create_laddered_test_liquidity_array(
+    current_tick: i32,
+    tick_spacing: i32,
+    num_positions: i32,
+) -> LiquidityArray {
+    let half_width = tick_spacing * num_positions / 2;
+    let padding = tick_spacing * 10;
+
+    let mut builder = TestLiquidityArrayBuilder::new(
+        current_tick - half_width - padding,
+        current_tick + half_width + padding,
+        tick_spacing,
+        500,
+    )
+    .price_at_tick(current_tick);
+
+    let first_tick = current_tick - half_width;
+    for i in 0..num_positions {
+        let lower_tick = first_tick + i * tick_spacing;
+        builder = builder.position(lower_tick, lower_tick + tick_spacing, 1_000_000_000);
+    }
+
+    builder.build()
+}
+
+// Same as create_full_liquidity_range, the caches can only be set once all the liquidity is in.
+// Also needed after a test opens positions next to the price, which adds initialized ticks in between.
+pub fn reset_initialized_tick_caches(liquidity_arr: &mut LiquidityArray) {
+    let (upper_tick_data, lower_tick_data) = liquidity_arr
+        .get_upper_and_lower_ticks(liquidity_arr.current_tick, false)
+        .unwrap();
+
+    liquidity_arr.cached_lower_initialized_tick = Some(lower_tick_data.tick);
+    liquidity_arr.cached_upper_initialized_tick = Some(upper_tick_data.tick);
+}
+
+// Alternating sell/buy swaps of amount_in with tx_ids 1..=count, one second apart. Numbered like the synced table
+// (newest first), tx 1 is the latest and tx count the oldest. Returned in time order.
+pub fn create_test_swap_transactions(
+    count: i64,
+    amount_in: u64,
+    token_a_addr: &str,
+    token_b_addr: &str,
+) -> Vec<TransactionModelFromDB> {
+    (1..=count)
+        .map(|step| {
+            let tx_id = count + 1 - step;
+            let (token_in, token_out) = if tx_id % 2 == 0 {
+                (token_a_addr, token_b_addr)
+            } else {
+                (token_b_addr, token_a_addr)
+            };
+
+            TransactionModelFromDB {
+                tx_id,
+                signature: format!("sig{}", tx_id),
+                pool_address: "test_pool".to_string(),
+                block_time: 1_700_000_000 + step,
+                block_time_utc: Utc.timestamp_opt(1_700_000_000 + step, 0).unwrap(),
+                transaction_type: "Swap".to_string(),
+                ready_for_backtesting: true,
+                data: TransactionData::Swap(SwapData {
+                    token_in: token_in.to_string(),
+                    token_out: token_out.to_string(),
+                    amount_in,
+                    amount_out: amount_in,
+                    source_program: None,
+                }),
+            }
+        })
+        .collect()
+}
+
+// Serves a fixed set of transactions with the same cursor/order semantics as the postgres TransactionRepo.
+pub struct InMemoryTransactionRepo {
+    pub transactions: Vec<TransactionModelFromDB>,
+}
+
+#[async_trait]
+impl TransactionRepoTrait for InMemoryTransactionRepo {
+    async fn fetch_transactions(
+        &self,
+        _pool_address: &str,
+        cursor: Option<i64>,
+        limit: i64,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        let mut transactions: Vec<TransactionModelFromDB> = self
+            .transactions
+            .iter()
+            .filter(|tx| match (&order, cursor) {
+                (OrderDirection::Descending, Some(c)) => tx.tx_id < c,
+                (OrderDirection::Ascending, Some(c)) => tx.tx_id > c,
+                (_, None) => true,
+            })
+            .cloned()
+            .collect();
+
+        match order {
+            OrderDirection::Descending => {
+                transactions.sort_by_key(|tx| std::cmp::Reverse(tx.tx_id))
+            }
+            OrderDirection::Ascending => transactions.sort_by_key(|tx| tx.tx_id),
+        }
+
+        transactions.truncate(limit as usize);
+
+        Ok(transactions)
+    }
+
+    async fn fetch_transactions_in_time_range(
+        &self,
+        _pool_address: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        let mut transactions: Vec<TransactionModelFromDB> = self
+            .transactions
+            .iter()
+            .filter(|tx| tx.block_time_utc >= start && tx.block_time_utc < end)
+            .cloned()
+            .collect();
+
+        transactions.sort_by_key(|tx| (tx.block_time_utc, std::cmp::Reverse(tx.tx_id)));
+        if let OrderDirection::Descending = order {
+            transactions.reverse();
+        }
+
+        Ok(transactions)
+    }
+
+    async fn tx_id_at_or_after(
+        &self,
+        _pool_address: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<i64>> {
+        Ok(self
+            .transactions
+            .iter()
+            .filter(|tx| tx.block_time_utc >= time)
+            .min_by_key(|tx| (tx.block_time_utc, std::cmp::Reverse(tx.tx_id)))
+            .map(|tx| tx.tx_id))
+    }
+}
+
+// Fixed USD price per token symbol, the same at any time.
+pub struct MockPriceApi {
+    pub prices_usd: HashMap<String, f64>,
+}
+
+#[async_trait]
+impl PriceApi for MockPriceApi {
+    fn source(&self) -> PriceSource {
+        PriceSource::Binance
+    }
+
+    async fn get_price(&self, token: &PriceToken, timestamp: DateTime<Utc>) -> Result<PricePoint> {
+        let price = self
+            .prices_usd
+            .get(&token.symbol)
+            .copied()
+            .ok_or_else(|| anyhow!("No mock price for {}", token.symbol))?;
+
+        Ok(PricePoint { price, timestamp })
+    }
+}
+
+// Symbols by mint address instead of the Jupiter token list.
+pub struct MockTokenMetadataApi {
+    pub symbols: HashMap<String, String>,
+}
+
+#[async_trait]
+impl TokenMetadataApiTrait for MockTokenMetadataApi {
+    async fn get_token_symbols_for_addresses(&self, addresses: &[String]) -> Result<Vec<String>> {
+        addresses
+            .iter()
+            .map(|address| {
+                self.symbols
+                    .get(address)
+                    .cloned()
+                    .ok_or_else(|| anyhow!("No mock symbol for {}", address))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_time_range_queries_use_replay_order() {
+        let mut transactions = create_test_swap_transactions(5, 1_000, "TokenA", "TokenB");
+        // Same block for tx 2 and 3: the higher tx_id is the older one.
+        transactions[3].block_time_utc = transactions[2].block_time_utc;
+        let repo = InMemoryTransactionRepo { transactions };
+
+        let start = Utc.timestamp_opt(1_700_000_002, 0).unwrap();
+        let end = Utc.timestamp_opt(1_700_000_005, 0).unwrap();
+
+        let ascending = repo
+            .fetch_transactions_in_time_range("test_pool", start, end, OrderDirection::Ascending)
+            .await
+            .unwrap();
+        let ids: Vec<i64> = ascending.iter().map(|tx| tx.tx_id).collect();
+        assert_eq!(ids, vec![4, 3, 2]);
+
+        let descending = repo
+            .fetch_transactions_in_time_range("test_pool", start, end, OrderDirection::Descending)
+            .await
+            .unwrap();
+        let ids: Vec<i64> = descending.iter().map(|tx| tx.tx_id).collect();
+        assert_eq!(ids, vec![2, 3, 4]);
+
+        let at_block = Utc.timestamp_opt(1_700_000_003, 0).unwrap();
+        assert_eq!(
+            repo.tx_id_at_or_after("test_pool", at_block).await.unwrap(),
+            Some(3)
+        );
+        let after_all = Utc.timestamp_opt(1_700_000_010, 0).unwrap();
+        assert_eq!(
+            repo.tx_id_at_or_after("test_pool", after_all)
+                .await
+                .unwrap(),
+            None
+        );
+    }
+}
@@ -1,53 +1,490 @@
 use anyhow::{anyhow, Context, Result};
-use serde::de::DeserializeOwned;
-use std::{collections::HashMap, env};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{collections::HashMap, env, fs, str::FromStr};
 
-#[derive(Clone)]
+use crate::{
+    api::price_api::{PriceSource, StableToken},
+    backtester::{
+        backtest_utils::{ReconstructionCheckpoint, StartPriceMethod, TickBounds},
+        backtester_core::{SandwichPenalty, SourceProgramFilter},
+        liquidity_array::{FeeRateChange, DEFAULT_MAX_TICK_CROSSINGS},
+    },
+    utils::{
+        core_math::DEFAULT_TICK_PRICE_CACHE_SIZE,
+        number_format::NumberFormat,
+        profit_calcs::{FeeHandling, PriceTimeCheck, TokenYields},
+    },
+};
+
+// Room left around the positions' tick extremes so the price can move during the backtest (~148x in either direction).
+const DEFAULT_TICK_RANGE_PADDING: i32 = 50_000;
+// The replay always zeroed the fee growth before RESET_FEE_GROWTH existed, it stays that way unless turned off.
+const DEFAULT_RESET_FEE_GROWTH: bool = true;
+
+#[derive(Clone, Debug)]
 pub enum SyncMode {
     Update,
     Historical,
     FullRange,
+    // Update when the pool already has transactions stored, full range otherwise. Meant for scheduled syncs.
+    Incremental,
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum StrategyType {
     NoRebalance,
     SimpleRebalance,
+    Ladder,
+    JitBand,
 }
 
+#[derive(Clone)]
 pub struct AppConfig {
     pub database_url: String,
     pub pool_address: String,
     pub strategy: StrategyType,
+    // STRATEGY_DETAILS parsed into the fields of the chosen strategy up front.
+    pub strategy_config: StrategyConfig,
     pub strategy_details: HashMap<String, serde_json::Value>,
     pub sync_days: i64,
     pub sync_mode: SyncMode,
     pub pool_address_to_backtest: String,
+    pub show_fee_breakdown: bool,
+    pub show_capital_efficiency: bool,
+    pub tick_bounds: TickBounds,
+    pub fee_rate_history: Vec<FeeRateChange>,
+    pub profile: Option<String>,
+    pub verify_replay: bool,
+    pub replay_tick_tolerance: i32,
+    pub price_source: PriceSource,
+    // Pool side that is a USD stable (STABLE_TOKEN=A or B). Unset prices both tokens through the price api.
+    pub stable_token: Option<StableToken>,
+    // How far off the asked for time a provider's price may be before the report warns (PRICE_TIME_TOLERANCE_SECONDS), or
+    // fails with FAIL_ON_PRICE_TIME_MISMATCH.
+    pub price_time_check: PriceTimeCheck,
+    // Staking yield of LST sides in bps per day (TOKEN_A_YIELD_BPS_PER_DAY / TOKEN_B_YIELD_BPS_PER_DAY), 0 by default.
+    pub token_yields: TokenYields,
+    // Block time between position_timeline samples. None (TIMELINE_SAMPLE_SECONDS=0) turns sampling off.
+    pub timeline_sample_seconds: Option<i64>,
+    // Cap on the actions a strategy can return during the run (MAX_ACTIONS). None = no cap.
+    pub max_actions: Option<usize>,
+    // Write the run's records to simulation_results.jsonl as they happen instead of keeping them in memory (STREAM_RESULTS).
+    pub stream_results: bool,
+    // Report the pool price as token A per token B instead of B per A (INVERT_PRICE_DISPLAY).
+    pub invert_price_display: bool,
+    // Zero the reconstructed array's fee growth before the replay, otherwise carry it forward (RESET_FEE_GROWTH).
+    pub reset_fee_growth: bool,
+    // Initialized ticks a single swap may cross before it errors (MAX_TICK_CROSSINGS). None (0) = no limit.
+    pub max_tick_crossings: Option<usize>,
+    // Ticks whose sqrt price is memoized during the run (TICK_PRICE_CACHE_SIZE), 0 turns the cache off.
+    pub tick_price_cache_size: usize,
+    // Most recent swaps the starting price is averaged over without a live pool price (START_PRICE_SWAPS), 1 = the
+    // latest swap alone.
+    pub start_price_swaps: usize,
+    pub start_price_method: StartPriceMethod,
+    // Parquet export of the transactions replayed instead of the database (TRANSACTIONS_PARQUET), needs the parquet
+    // feature.
+    pub transactions_parquet: Option<String>,
+    // Max age of the pool row and latest swap before a backtest warns (STALE_DATA_HOURS). None (0) skips the check.
+    pub stale_data_hours: Option<i64>,
+    // Abort the backtest on stale data instead of warning (FAIL_ON_STALE_DATA).
+    pub fail_on_stale_data: bool,
+    // Max % the reconstructed starting price may be off the price source's before the backtest aborts
+    // (MAX_START_PRICE_DRIFT_PCT). None (0) skips the check.
+    pub max_start_price_drift_pct: Option<f64>,
+    // Count the fees pending at the window end as collected (COUNT_FEES_AT_END). Off reports them apart, realized only PnL.
+    pub count_fees_at_end: bool,
+    // Whether the fees collected during the run are valued as held or as a 50/50 basket from collection on (FEE_HANDLING).
+    pub fee_handling: FeeHandling,
+    // Swaps replayed by source program (ONLY_SOURCE_PROGRAMS / EXCLUDE_SOURCE_PROGRAMS). None replays every swap.
+    pub source_program_filter: Option<SourceProgramFilter>,
+    // Days before the latest synced swap the backtest starts at (BACKTEST_DAYS), the backwards sync stops there.
+    // None (unset or 0) rewinds through the whole synced history.
+    pub backtest_days: Option<i64>,
+    // Swaps paying in less than this (raw units of the token paid in) are skipped as dust (MIN_SWAP_AMOUNT_IN). 0 replays all.
+    pub min_swap_amount_in: u64,
+    // Decimals, thousands separator and currency symbol of the report (REPORT_DECIMALS, REPORT_THOUSANDS_SEPARATOR,
+    // REPORT_CURRENCY_SYMBOL).
+    pub number_format: NumberFormat,
+    // On-chain tick and active liquidity at a known time that both syncs are checked against (RECONSTRUCTION_CHECKPOINT,
+    // CHECKPOINT_TICK_TOLERANCE, CHECKPOINT_LIQUIDITY_TOLERANCE_BPS, FAIL_ON_CHECKPOINT_MISMATCH). None skips the check.
+    pub reconstruction_checkpoint: Option<ReconstructionCheckpoint>,
+    // Extra loss on rebalance swaps big enough to get sandwiched (SANDWICH_PENALTY_BPS, SANDWICH_MIN_SWAP_SHARE). None (0)
+    // executes them at the modeled slippage only.
+    pub sandwich_penalty: Option<SandwichPenalty>,
+}
+
+// A named bundle of endpoints, keys and rate limits (free rpc, paid rpc, local validator...) selected with PROFILE.
+// Every field is optional, anything left out falls back to the regular env var. Unknown keys fail the parse so a misspelled
+// limit isn't silently ignored.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub rpc_url: Option<String>,
+    pub rpc_api_key: Option<String>,
+    pub coingecko_api_url: Option<String>,
+    pub coingecko_api_key: Option<String>,
+    pub coingecko_header: Option<String>,
+    pub price_api_url: Option<String>,
+    // Transaction batches requested from the rpc at once (RPC_CONCURRENCY).
+    pub rpc_concurrency: Option<usize>,
+    // Orca archive days downloaded at once with FEATURE_FLAG_OPTIMIZATION (ARCHIVE_CONCURRENCY).
+    pub archive_concurrency: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ProfilesFile {
+    profiles: HashMap<String, Profile>,
+}
+
+impl Profile {
+    // Reads the [profiles.<name>] block from a TOML file.
+    pub fn load(path: &str, name: &str) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).context(format!("Failed to read profiles file {}", path))?;
+
+        Self::from_toml(&contents, name)
+    }
+
+    pub fn from_toml(contents: &str, name: &str) -> Result<Self> {
+        let mut file: ProfilesFile =
+            toml::from_str(contents).context("Failed to parse profiles file")?;
+
+        file.profiles
+            .remove(name)
+            .ok_or_else(|| anyhow!("Profile '{}' not found", name))
+    }
+
+    // The api clients read their endpoints/keys from env in ::new(), so the active profile is applied by overriding those vars.
+    pub fn apply_to_env(&self) {
+        let overrides = [
+            ("ALCHEMY_API_URL", self.rpc_url.clone()),
+            ("ALCHEMY_API_KEY", self.rpc_api_key.clone()),
+            ("COINGECKO_API_URL", self.coingecko_api_url.clone()),
+            ("COINGECKO_API_KEY", self.coingecko_api_key.clone()),
+            ("COINGECKO_HEADER", self.coingecko_header.clone()),
+            ("PRICE_API_URL", self.price_api_url.clone()),
+            (
+                "RPC_CONCURRENCY",
+                self.rpc_concurrency.map(|n| n.to_string()),
+            ),
+            (
+                "ARCHIVE_CONCURRENCY",
+                self.archive_concurrency.map(|n| n.to_string()),
+            ),
+        ];
+
+        for (key, value) in overrides {
+            if let Some(value) = value {
+                env::set_var(key, value);
+            }
+        }
+    }
+}
+
+// Strategy section of a config file. Each strategy lists exactly the fields it needs, so a missing or misspelled one fails
+// when the file is parsed instead of deep in the backtest. Token amounts are whole tokens, u64 since TOML has no u128.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "SCREAMING_SNAKE_CASE", deny_unknown_fields)]
+pub enum StrategyConfig {
+    NoRebalance {
+        lower_tick: i32,
+        upper_tick: i32,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    },
+    SimpleRebalance {
+        range: i32,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    },
+    Ladder {
+        lower_tick: i32,
+        upper_tick: i32,
+        num_rungs: u32,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    },
+    JitBand {
+        band_lower_tick: i32,
+        band_upper_tick: i32,
+        position_half_width: i32,
+        token_a_amount: u64,
+        token_b_amount: u64,
+    },
+}
+
+impl StrategyConfig {
+    pub fn strategy_type(&self) -> StrategyType {
+        match self {
+            StrategyConfig::NoRebalance { .. } => StrategyType::NoRebalance,
+            StrategyConfig::SimpleRebalance { .. } => StrategyType::SimpleRebalance,
+            StrategyConfig::Ladder { .. } => StrategyType::Ladder,
+            StrategyConfig::JitBand { .. } => StrategyType::JitBand,
+        }
+    }
+
+    // Only the keys the strategy uses are read, STRATEGY_DETAILS may hold others (it's often shared between strategies).
+    pub fn from_details(
+        strategy: &StrategyType,
+        details: &HashMap<String, serde_json::Value>,
+    ) -> Result<Self> {
+        let mut fields: serde_json::Map<String, serde_json::Value> = details
+            .iter()
+            .filter(|(key, _)| strategy.fields().contains(&key.as_str()))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect();
+        fields.insert("type".to_string(), serde_json::json!(strategy.name()));
+
+        serde_json::from_value(serde_json::Value::Object(fields))
+            .context(format!("Invalid STRATEGY_DETAILS for {}", strategy.name()))
+    }
+
+    // Whole tokens, scaled by the decimals when the wallet is funded.
+    pub fn token_amounts(&self) -> (u128, u128) {
+        let (token_a_amount, token_b_amount) = match *self {
+            StrategyConfig::NoRebalance {
+                token_a_amount,
+                token_b_amount,
+                ..
+            }
+            | StrategyConfig::SimpleRebalance {
+                token_a_amount,
+                token_b_amount,
+                ..
+            }
+            | StrategyConfig::Ladder {
+                token_a_amount,
+                token_b_amount,
+                ..
+            }
+            | StrategyConfig::JitBand {
+                token_a_amount,
+                token_b_amount,
+                ..
+            } => (token_a_amount, token_b_amount),
+        };
+
+        (token_a_amount as u128, token_b_amount as u128)
+    }
+
+    // Same strategy with both token amounts multiplied, for sweeping the capital deployed into a fixed range.
+    pub fn with_capital_multiplier(&self, multiplier: f64) -> Self {
+        let mut scaled = self.clone();
+
+        let (token_a_amount, token_b_amount) = match &mut scaled {
+            StrategyConfig::NoRebalance {
+                token_a_amount,
+                token_b_amount,
+                ..
+            }
+            | StrategyConfig::SimpleRebalance {
+                token_a_amount,
+                token_b_amount,
+                ..
+            }
+            | StrategyConfig::Ladder {
+                token_a_amount,
+                token_b_amount,
+                ..
+            }
+            | StrategyConfig::JitBand {
+                token_a_amount,
+                token_b_amount,
+                ..
+            } => (token_a_amount, token_b_amount),
+        };
+        *token_a_amount = (*token_a_amount as f64 * multiplier).round() as u64;
+        *token_b_amount = (*token_b_amount as f64 * multiplier).round() as u64;
+
+        scaled
+    }
+
+    // The fields as STRATEGY_DETAILS keys, without the type tag.
+    pub fn details(&self) -> Result<HashMap<String, serde_json::Value>> {
+        let mut details: HashMap<String, serde_json::Value> =
+            serde_json::from_value(serde_json::to_value(self)?)?;
+        details.remove("type");
+
+        Ok(details)
+    }
+}
+
+// Typed alternative to the env vars, loaded with CONFIG_FILE. Anything left out falls back to the matching env var.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigFile {
+    pub database_url: Option<String>,
+    pub pool_address: Option<String>,
+    pub pool_address_to_backtest: Option<String>,
+    pub sync_days: Option<i64>,
+    pub sync_mode: Option<String>,
+    pub price_source: Option<String>,
+    pub stable_token: Option<String>,
+    pub timeline_sample_seconds: Option<i64>,
+    pub max_actions: Option<usize>,
+    pub verify_replay: Option<bool>,
+    pub stream_results: Option<bool>,
+    pub reset_fee_growth: Option<bool>,
+    pub sandwich_penalty_bps: Option<u32>,
+    pub strategy: Option<StrategyConfig>,
+}
+
+impl ConfigFile {
+    // TOML, or JSON when the path ends in .json.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents =
+            fs::read_to_string(path).context(format!("Failed to read config file {}", path))?;
+
+        if path.ends_with(".json") {
+            serde_json::from_str(&contents).context(format!("Failed to parse config file {}", path))
+        } else {
+            Self::from_toml(&contents).context(format!("Failed to parse config file {}", path))
+        }
+    }
+
+    pub fn from_toml(contents: &str) -> Result<Self> {
+        Ok(toml::from_str(contents)?)
+    }
+
+    // Same approach as profiles: the file wins by overriding the env vars from_env reads.
+    pub fn apply_to_env(&self) -> Result<()> {
+        let flag = |value: bool| if value { "TRUE" } else { "FALSE" }.to_string();
+
+        let overrides = [
+            ("DATABASE_URL", self.database_url.clone()),
+            ("POOL_ADDRESS", self.pool_address.clone()),
+            (
+                "POOL_ADDRESS_TO_BACKTEST",
+                self.pool_address_to_backtest.clone(),
+            ),
+            ("SYNC_DAYS", self.sync_days.map(|days| days.to_string())),
+            ("SYNC_MODE", self.sync_mode.clone()),
+            ("PRICE_SOURCE", self.price_source.clone()),
+            ("STABLE_TOKEN", self.stable_token.clone()),
+            (
+                "TIMELINE_SAMPLE_SECONDS",
+                self.timeline_sample_seconds
+                    .map(|seconds| seconds.to_string()),
+            ),
+            (
+                "MAX_ACTIONS",
+                self.max_actions.map(|max_actions| max_actions.to_string()),
+            ),
+            ("VERIFY_REPLAY", self.verify_replay.map(flag)),
+            ("STREAM_RESULTS", self.stream_results.map(flag)),
+            ("RESET_FEE_GROWTH", self.reset_fee_growth.map(flag)),
+            (
+                "SANDWICH_PENALTY_BPS",
+                self.sandwich_penalty_bps.map(|bps| bps.to_string()),
+            ),
+        ];
+
+        for (key, value) in overrides {
+            if let Some(value) = value {
+                env::set_var(key, value);
+            }
+        }
+
+        if let Some(strategy) = &self.strategy {
+            env::set_var("STRATEGY", strategy.strategy_type().name());
+            env::set_var(
+                "STRATEGY_DETAILS",
+                serde_json::to_string(&strategy.details()?)?,
+            );
+        }
+
+        Ok(())
+    }
 }
 
-impl SyncMode {
-    pub fn from_str(s: &str) -> Result<Self> {
+impl FromStr for SyncMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s.to_lowercase().as_str() {
             "update" => Ok(SyncMode::Update),
             "historical" => Ok(SyncMode::Historical),
             "full_range" => Ok(SyncMode::FullRange),
+            "incremental" => Ok(SyncMode::Incremental),
             _ => Err(anyhow!("Invalid sync mode: {}", s)),
         }
     }
 }
 
-impl StrategyType {
-    pub fn from_str(s: &str) -> Result<Self> {
+impl FromStr for StrategyType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         match s.to_uppercase().as_str() {
             "NO_REBALANCE" => Ok(StrategyType::NoRebalance),
             "SIMPLE_REBALANCE" => Ok(StrategyType::SimpleRebalance),
+            "LADDER" => Ok(StrategyType::Ladder),
+            "JIT_BAND" => Ok(StrategyType::JitBand),
             _ => Err(anyhow!("Invalid strategy type: {}", s)),
         }
     }
 }
 
+impl StrategyType {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StrategyType::NoRebalance => "NO_REBALANCE",
+            StrategyType::SimpleRebalance => "SIMPLE_REBALANCE",
+            StrategyType::Ladder => "LADDER",
+            StrategyType::JitBand => "JIT_BAND",
+        }
+    }
+
+    // The STRATEGY_DETAILS keys the strategy reads, matching its StrategyConfig variant.
+    pub fn fields(&self) -> &'static [&'static str] {
+        match self {
+            StrategyType::NoRebalance => &[
+                "lower_tick",
+                "upper_tick",
+                "token_a_amount",
+                "token_b_amount",
+            ],
+            StrategyType::SimpleRebalance => &["range", "token_a_amount", "token_b_amount"],
+            StrategyType::Ladder => &[
+                "lower_tick",
+                "upper_tick",
+                "num_rungs",
+                "token_a_amount",
+                "token_b_amount",
+            ],
+            StrategyType::JitBand => &[
+                "band_lower_tick",
+                "band_upper_tick",
+                "position_half_width",
+                "token_a_amount",
+                "token_b_amount",
+            ],
+        }
+    }
+}
+
 impl AppConfig {
+    // Loads the config file over the env, then reads everything as usual.
+    pub fn from_file(path: &str) -> Result<Self> {
+        ConfigFile::load(path)?.apply_to_env()?;
+
+        Self::from_env()
+    }
+
     pub fn from_env() -> Result<Self> {
+        // Apply the profile first so everything below (and the api clients) see its values.
+        let profile = match env::var("PROFILE") {
+            Ok(name) => {
+                let path =
+                    env::var("PROFILES_FILE").unwrap_or_else(|_| "profiles.toml".to_string());
+                let profile = Profile::load(&path, &name)?;
+                profile.apply_to_env();
+                Some(name)
+            }
+            Err(_) => None,
+        };
+
         let strategy =
             StrategyType::from_str(&env::var("STRATEGY").context("STRATEGY must be set")?)?;
 
@@ -58,10 +495,13 @@ impl AppConfig {
             serde_json::from_str(&strategy_details_str)
                 .context("Failed to parse STRATEGY_DETAILS JSON")?;
 
+        let strategy_config = StrategyConfig::from_details(&strategy, &strategy_details)?;
+
         let config = Self {
             database_url: env::var("DATABASE_URL").context("DATABASE_URL must be set")?,
             pool_address: env::var("POOL_ADDRESS").context("POOL_ADDRESS must be set")?,
             strategy,
+            strategy_config,
             sync_days: env::var("SYNC_DAYS")
                 .unwrap_or_else(|_| "30".to_string())
                 .parse()
@@ -72,28 +512,121 @@ impl AppConfig {
             pool_address_to_backtest: env::var("POOL_ADDRESS_TO_BACKTEST")
                 .context("POOL_ADDRESS_TO_BACKTEST must be set")?,
             strategy_details,
+            show_fee_breakdown: env_flag("SHOW_FEE_BREAKDOWN", false),
+            show_capital_efficiency: env_flag("SHOW_CAPITAL_EFFICIENCY", true),
+            tick_bounds: tick_bounds_from_env()?,
+            fee_rate_history: parse_fee_rate_history(
+                &env::var("FEE_RATE_HISTORY").unwrap_or_default(),
+            )?,
+            profile,
+            verify_replay: env_flag("VERIFY_REPLAY", false),
+            replay_tick_tolerance: env::var("REPLAY_TICK_TOLERANCE")
+                .unwrap_or_else(|_| "100".to_string())
+                .parse()
+                .context("Failed to parse REPLAY_TICK_TOLERANCE")?,
+            price_source: PriceSource::from_str(
+                &env::var("PRICE_SOURCE").unwrap_or_else(|_| "BINANCE".to_string()),
+            )?,
+            stable_token: env::var("STABLE_TOKEN")
+                .ok()
+                .map(|token| StableToken::from_str(&token))
+                .transpose()?,
+            price_time_check: PriceTimeCheck {
+                tolerance_seconds: env::var("PRICE_TIME_TOLERANCE_SECONDS")
+                    .map(|seconds| seconds.parse())
+                    .unwrap_or(Ok(PriceTimeCheck::default().tolerance_seconds))
+                    .context("Failed to parse PRICE_TIME_TOLERANCE_SECONDS")?,
+                strict: env_flag("FAIL_ON_PRICE_TIME_MISMATCH", false),
+            },
+            token_yields: TokenYields {
+                token_a_bps_per_day: env::var("TOKEN_A_YIELD_BPS_PER_DAY")
+                    .unwrap_or_else(|_| "0".to_string())
+                    .parse()
+                    .context("Failed to parse TOKEN_A_YIELD_BPS_PER_DAY")?,
+                token_b_bps_per_day: env::var("TOKEN_B_YIELD_BPS_PER_DAY")
+                    .unwrap_or_else(|_| "0".to_string())
+                    .parse()
+                    .context("Failed to parse TOKEN_B_YIELD_BPS_PER_DAY")?,
+            },
+            timeline_sample_seconds: Some(
+                env::var("TIMELINE_SAMPLE_SECONDS")
+                    .unwrap_or_else(|_| "3600".to_string())
+                    .parse::<i64>()
+                    .context("Failed to parse TIMELINE_SAMPLE_SECONDS")?,
+            )
+            .filter(|seconds| *seconds > 0),
+            max_actions: env::var("MAX_ACTIONS")
+                .ok()
+                .map(|max_actions| max_actions.parse())
+                .transpose()
+                .context("Failed to parse MAX_ACTIONS")?,
+            stream_results: env_flag("STREAM_RESULTS", false),
+            invert_price_display: env_flag("INVERT_PRICE_DISPLAY", false),
+            reset_fee_growth: env_flag("RESET_FEE_GROWTH", DEFAULT_RESET_FEE_GROWTH),
+            max_tick_crossings: Some(
+                env::var("MAX_TICK_CROSSINGS")
+                    .map(|max| max.parse())
+                    .unwrap_or(Ok(DEFAULT_MAX_TICK_CROSSINGS))
+                    .context("Failed to parse MAX_TICK_CROSSINGS")?,
+            )
+            .filter(|max| *max > 0),
+            tick_price_cache_size: env::var("TICK_PRICE_CACHE_SIZE")
+                .map(|size| size.parse())
+                .unwrap_or(Ok(DEFAULT_TICK_PRICE_CACHE_SIZE))
+                .context("Failed to parse TICK_PRICE_CACHE_SIZE")?,
+            start_price_swaps: env::var("START_PRICE_SWAPS")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse::<usize>()
+                .context("Failed to parse START_PRICE_SWAPS")?
+                .max(1),
+            start_price_method: StartPriceMethod::from_str(
+                &env::var("START_PRICE_METHOD").unwrap_or_else(|_| "MEDIAN".to_string()),
+            )?,
+            transactions_parquet: env::var("TRANSACTIONS_PARQUET")
+                .ok()
+                .filter(|path| !path.is_empty()),
+            stale_data_hours: Some(
+                env::var("STALE_DATA_HOURS")
+                    .unwrap_or_else(|_| "24".to_string())
+                    .parse::<i64>()
+                    .context("Failed to parse STALE_DATA_HOURS")?,
+            )
+            .filter(|hours| *hours > 0),
+            fail_on_stale_data: env_flag("FAIL_ON_STALE_DATA", false),
+            max_start_price_drift_pct: Some(
+                env::var("MAX_START_PRICE_DRIFT_PCT")
+                    .unwrap_or_else(|_| "5".to_string())
+                    .parse::<f64>()
+                    .context("Failed to parse MAX_START_PRICE_DRIFT_PCT")?,
+            )
+            .filter(|pct| *pct > 0.0),
+            count_fees_at_end: env_flag("COUNT_FEES_AT_END", true),
+            fee_handling: FeeHandling::from_str(
+                &env::var("FEE_HANDLING").unwrap_or_else(|_| "HOLD".to_string()),
+            )?,
+            source_program_filter: parse_source_program_filter(
+                &env::var("ONLY_SOURCE_PROGRAMS").unwrap_or_default(),
+                &env::var("EXCLUDE_SOURCE_PROGRAMS").unwrap_or_default(),
+            )?,
+            backtest_days: env::var("BACKTEST_DAYS")
+                .ok()
+                .map(|days| days.parse::<i64>())
+                .transpose()
+                .context("Failed to parse BACKTEST_DAYS")?
+                .filter(|days| *days > 0),
+            min_swap_amount_in: env::var("MIN_SWAP_AMOUNT_IN")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .context("Failed to parse MIN_SWAP_AMOUNT_IN")?,
+            number_format: number_format_from_env()?,
+            reconstruction_checkpoint: reconstruction_checkpoint_from_env()?,
+            sandwich_penalty: sandwich_penalty_from_env()?,
         };
 
-        config.validate_strategy_details()?;
-
         Ok(config)
     }
 
-    fn validate_strategy_details(&self) -> Result<()> {
-        let required_keys = match self.strategy {
-            StrategyType::NoRebalance => vec!["lower_tick", "upper_tick", "token_a_amount", "token_b_amount"],
-            StrategyType::SimpleRebalance => vec!["range", "token_a_amount", "token_b_amount"],
-        };
-
-        for key in required_keys {
-            if !self.strategy_details.contains_key(key) {
-                return Err(anyhow!("Missing required strategy detail: {}", key));
-            }
-        }
-
-        Ok(())
-    }
-
+    #[deprecated(note = "use the typed fields of strategy_config")]
     pub fn get_strategy_detail<T: DeserializeOwned>(&self, key: &str) -> Result<T> {
         self.strategy_details
             .get(key)
@@ -104,3 +637,341 @@ impl AppConfig {
             })
     }
 }
+
+fn reconstruction_checkpoint_from_env() -> Result<Option<ReconstructionCheckpoint>> {
+    let Some(checkpoint) = env::var("RECONSTRUCTION_CHECKPOINT")
+        .ok()
+        .filter(|checkpoint| !checkpoint.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+
+    let mut checkpoint = ReconstructionCheckpoint::from_str(&checkpoint)
+        .context("Failed to parse RECONSTRUCTION_CHECKPOINT")?;
+
+    if let Ok(tolerance) = env::var("CHECKPOINT_TICK_TOLERANCE") {
+        checkpoint.tick_tolerance = tolerance
+            .parse()
+            .context("Failed to parse CHECKPOINT_TICK_TOLERANCE")?;
+    }
+    if let Ok(tolerance) = env::var("CHECKPOINT_LIQUIDITY_TOLERANCE_BPS") {
+        checkpoint.liquidity_tolerance_bps = tolerance
+            .parse()
+            .context("Failed to parse CHECKPOINT_LIQUIDITY_TOLERANCE_BPS")?;
+    }
+    checkpoint.strict = env_flag("FAIL_ON_CHECKPOINT_MISMATCH", false);
+
+    Ok(Some(checkpoint))
+}
+
+fn sandwich_penalty_from_env() -> Result<Option<SandwichPenalty>> {
+    let penalty_bps: u32 = env::var("SANDWICH_PENALTY_BPS")
+        .unwrap_or_else(|_| "0".to_string())
+        .parse()
+        .context("Failed to parse SANDWICH_PENALTY_BPS")?;
+    if penalty_bps == 0 {
+        return Ok(None);
+    }
+
+    // Share of the virtual reserve of the token sold, 1% by default.
+    let min_swap_share: f64 = env::var("SANDWICH_MIN_SWAP_SHARE")
+        .unwrap_or_else(|_| "0.01".to_string())
+        .parse()
+        .context("Failed to parse SANDWICH_MIN_SWAP_SHARE")?;
+
+    Ok(Some(SandwichPenalty {
+        penalty_bps,
+        min_swap_share,
+    }))
+}
+
+// MIN_TICK and MAX_TICK pin the liquidity array range. Otherwise it is derived from positions with TICK_RANGE_PADDING.
+fn tick_bounds_from_env() -> Result<TickBounds> {
+    match (env::var("MIN_TICK"), env::var("MAX_TICK")) {
+        (Ok(min_tick), Ok(max_tick)) => {
+            let min_tick: i32 = min_tick.parse().context("Failed to parse MIN_TICK")?;
+            let max_tick: i32 = max_tick.parse().context("Failed to parse MAX_TICK")?;
+
+            if min_tick >= max_tick {
+                return Err(anyhow!("MIN_TICK must be lower than MAX_TICK"));
+            }
+
+            Ok(TickBounds::Fixed { min_tick, max_tick })
+        }
+        (Err(_), Err(_)) => Ok(TickBounds::Derived {
+            padding: env::var("TICK_RANGE_PADDING")
+                .map(|padding| padding.parse())
+                .unwrap_or(Ok(DEFAULT_TICK_RANGE_PADDING))
+                .context("Failed to parse TICK_RANGE_PADDING")?,
+        }),
+        _ => Err(anyhow!("MIN_TICK and MAX_TICK must be set together")),
+    }
+}
+
+fn number_format_from_env() -> Result<NumberFormat> {
+    let default = NumberFormat::default();
+
+    let thousands_separator = match env::var("REPORT_THOUSANDS_SEPARATOR") {
+        Ok(separator) if !separator.is_empty() => {
+            let mut chars = separator.chars();
+            match (chars.next(), chars.next()) {
+                (Some(separator), None) => Some(separator),
+                _ => {
+                    return Err(anyhow!(
+                        "REPORT_THOUSANDS_SEPARATOR must be a single character, got {:?}",
+                        separator
+                    ))
+                }
+            }
+        }
+        _ => None,
+    };
+
+    Ok(NumberFormat {
+        decimals: env::var("REPORT_DECIMALS")
+            .map(|decimals| decimals.parse())
+            .unwrap_or(Ok(default.decimals))
+            .context("Failed to parse REPORT_DECIMALS")?,
+        thousands_separator,
+        currency_symbol: env::var("REPORT_CURRENCY_SYMBOL").unwrap_or(default.currency_symbol),
+    })
+}
+
+// "2024-01-01:3000,2024-06-01:400" -> fee rate 3000 from Jan 1st and 400 from Jun 1st (midnight UTC).
+pub fn parse_fee_rate_history(value: &str) -> Result<Vec<FeeRateChange>> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (date, fee_rate) = entry
+                .split_once(':')
+                .ok_or_else(|| anyhow!("Invalid FEE_RATE_HISTORY entry: {}", entry))?;
+
+            let effective_from = chrono::NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+                .context(format!("Invalid FEE_RATE_HISTORY date: {}", date))?
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_utc()
+                .timestamp();
+
+            Ok(FeeRateChange {
+                effective_from,
+                fee_rate: fee_rate
+                    .trim()
+                    .parse()
+                    .context(format!("Invalid FEE_RATE_HISTORY fee rate: {}", fee_rate))?,
+            })
+        })
+        .collect()
+}
+
+// Comma separated program ids, at most one of the two lists can be set.
+pub fn parse_source_program_filter(
+    only: &str,
+    exclude: &str,
+) -> Result<Option<SourceProgramFilter>> {
+    let parse_list = |value: &str| -> Vec<String> {
+        value
+            .split(',')
+            .map(str::trim)
+            .filter(|program| !program.is_empty())
+            .map(str::to_string)
+            .collect()
+    };
+
+    match (parse_list(only), parse_list(exclude)) {
+        (only, exclude) if only.is_empty() && exclude.is_empty() => Ok(None),
+        (only, exclude) if exclude.is_empty() => Ok(Some(SourceProgramFilter::Only(only))),
+        (only, exclude) if only.is_empty() => Ok(Some(SourceProgramFilter::Exclude(exclude))),
+        _ => Err(anyhow!(
+            "ONLY_SOURCE_PROGRAMS and EXCLUDE_SOURCE_PROGRAMS can't be set together"
+        )),
+    }
+}
+
+// Boolean env flags follow the FEATURE_FLAG_OPTIMIZATION convention: only "TRUE" (any case) enables them.
+pub fn env_flag(key: &str, default: bool) -> bool {
+    match env::var(key) {
+        Ok(value) => value.trim().to_uppercase() == "TRUE",
+        Err(_) => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profile_from_toml() {
+        let contents = r#"
+            [profiles.free]
+            rpc_url = "https://free.rpc"
+
+            [profiles.fast]
+            rpc_url = "https://paid.rpc"
+            rpc_api_key = "paid_key"
+            rpc_concurrency = 8
+        "#;
+
+        let profile = Profile::from_toml(contents, "fast").unwrap();
+        assert_eq!(profile.rpc_url.as_deref(), Some("https://paid.rpc"));
+        assert_eq!(profile.rpc_api_key.as_deref(), Some("paid_key"));
+        assert_eq!(profile.rpc_concurrency, Some(8));
+        assert!(profile.coingecko_api_key.is_none());
+        assert!(profile.archive_concurrency.is_none());
+
+        assert!(Profile::from_toml(contents, "local").is_err());
+
+        // A limit under a name that isn't read fails instead of being ignored.
+        let misspelled = r#"
+            [profiles.free]
+            rate_limit = 5
+        "#;
+        assert!(Profile::from_toml(misspelled, "free").is_err());
+    }
+
+    #[test]
+    fn test_config_file_strategy_is_typed() {
+        let file = ConfigFile::from_toml(
+            r#"
+            pool_address_to_backtest = "pool"
+            sync_days = 7
+
+            [strategy]
+            type = "LADDER"
+            lower_tick = -20000
+            upper_tick = -18000
+            num_rungs = 4
+            token_a_amount = 10
+            token_b_amount = 1500
+        "#,
+        )
+        .unwrap();
+
+        let strategy = file.strategy.unwrap();
+        assert_eq!(strategy.strategy_type(), StrategyType::Ladder);
+        assert_eq!(file.sync_days, Some(7));
+
+        let details = strategy.details().unwrap();
+        assert_eq!(details.len(), 5);
+        assert_eq!(details["num_rungs"], serde_json::json!(4));
+
+        // Missing and misspelled fields fail at parse time.
+        let missing = ConfigFile::from_toml(
+            r#"
+            [strategy]
+            type = "SIMPLE_REBALANCE"
+            token_a_amount = 10
+            token_b_amount = 1500
+        "#,
+        );
+        assert!(missing.unwrap_err().to_string().contains("range"));
+
+        let misspelled = ConfigFile::from_toml(
+            r#"
+            [strategy]
+            type = "SIMPLE_REBALANCE"
+            rnage = 100
+            range = 100
+            token_a_amount = 10
+            token_b_amount = 1500
+        "#,
+        );
+        assert!(misspelled.is_err());
+    }
+
+    #[test]
+    fn test_strategy_config_from_details() {
+        let details: HashMap<String, serde_json::Value> = serde_json::from_str(
+            r#"{"token_a_amount": 10, "token_b_amount": 1470, "range": 100, "upper_tick": -18142, "lower_tick": -20142}"#,
+        )
+        .unwrap();
+
+        // Keys other strategies use are ignored.
+        assert_eq!(
+            StrategyConfig::from_details(&StrategyType::SimpleRebalance, &details).unwrap(),
+            StrategyConfig::SimpleRebalance {
+                range: 100,
+                token_a_amount: 10,
+                token_b_amount: 1470,
+            }
+        );
+
+        // A missing field or a wrong type fails up front, naming the strategy and the field.
+        let missing = StrategyConfig::from_details(&StrategyType::Ladder, &details).unwrap_err();
+        let message = format!("{:#}", missing);
+        assert!(message.contains("Invalid STRATEGY_DETAILS for LADDER"));
+        assert!(message.contains("num_rungs"));
+
+        let mut malformed = details.clone();
+        malformed.insert("range".to_string(), serde_json::json!("wide"));
+        let message = format!(
+            "{:#}",
+            StrategyConfig::from_details(&StrategyType::SimpleRebalance, &malformed).unwrap_err()
+        );
+        assert!(message.contains("Invalid STRATEGY_DETAILS for SIMPLE_REBALANCE"));
+        assert!(message.contains("invalid type"));
+    }
+
+    #[test]
+    fn test_with_capital_multiplier_keeps_range() {
+        let strategy = StrategyConfig::NoRebalance {
+            lower_tick: -20000,
+            upper_tick: -18000,
+            token_a_amount: 10,
+            token_b_amount: 1500,
+        };
+
+        assert_eq!(
+            strategy.with_capital_multiplier(2.5),
+            StrategyConfig::NoRebalance {
+                lower_tick: -20000,
+                upper_tick: -18000,
+                token_a_amount: 25,
+                token_b_amount: 3750,
+            }
+        );
+        assert_eq!(strategy.with_capital_multiplier(1.0), strategy);
+    }
+
+    #[test]
+    fn test_parse_fee_rate_history() {
+        let history = parse_fee_rate_history("2024-01-01:3000, 2024-06-01:400").unwrap();
+
+        assert_eq!(
+            history,
+            vec![
+                FeeRateChange {
+                    effective_from: 1_704_067_200,
+                    fee_rate: 3000
+                },
+                FeeRateChange {
+                    effective_from: 1_717_200_000,
+                    fee_rate: 400
+                },
+            ]
+        );
+        assert!(parse_fee_rate_history("").unwrap().is_empty());
+        assert!(parse_fee_rate_history("2024-01-01").is_err());
+    }
+
+    #[test]
+    fn test_parse_source_program_filter() {
+        assert_eq!(parse_source_program_filter("", " ").unwrap(), None);
+        assert_eq!(
+            parse_source_program_filter("whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc", "").unwrap(),
+            Some(SourceProgramFilter::Only(vec![
+                "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc".to_string()
+            ]))
+        );
+        assert_eq!(
+            parse_source_program_filter("", "BotA, BotB").unwrap(),
+            Some(SourceProgramFilter::Exclude(vec![
+                "BotA".to_string(),
+                "BotB".to_string()
+            ]))
+        );
+        assert!(parse_source_program_filter("BotA", "BotB").is_err());
+    }
+}
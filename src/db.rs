@@ -41,6 +41,23 @@ pub async fn initialize_sol_amm_backtester_database(pool: &PgPool) -> Result<()>
         "CREATE INDEX IF NOT EXISTS idx_transactions_pool_address ON transactions(pool_address)",
         "CREATE INDEX IF NOT EXISTS idx_transactions_block_time ON transactions(block_time)",
         "CREATE INDEX IF NOT EXISTS idx_transactions_block_time_utc ON transactions(block_time_utc)",
+        // Time range queries always filter by pool too.
+        "CREATE INDEX IF NOT EXISTS idx_transactions_pool_block_time_utc ON transactions(pool_address, block_time_utc)",
+
+        // Decimals the first sync of a pool converted its transactions with, the pool row can change afterwards.
+        r#"
+        CREATE TABLE IF NOT EXISTS sync_decimals (
+            pool_address TEXT PRIMARY KEY REFERENCES pools(address),
+            token_a_decimals SMALLINT NOT NULL,
+            token_b_decimals SMALLINT NOT NULL,
+            token_a_address TEXT,
+            token_b_address TEXT,
+            created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+        )
+        "#,
+        // The token order of the sync, null for pools recorded before it was.
+        "ALTER TABLE sync_decimals ADD COLUMN IF NOT EXISTS token_a_address TEXT",
+        "ALTER TABLE sync_decimals ADD COLUMN IF NOT EXISTS token_b_address TEXT",
 
 
         r#"
@@ -0,0 +1,10 @@
+#![allow(dead_code, unused_variables, unused_imports, renamed_and_removed_lints)]
+
+pub mod api;
+pub mod backtester;
+pub mod config;
+pub mod db;
+pub mod models;
+pub mod repositories;
+pub mod services;
+pub mod utils;
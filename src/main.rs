@@ -1,71 +1,277 @@
 #![allow(dead_code, unused_variables, unused_imports, renamed_and_removed_lints)]
 
-mod api;
-mod backtester;
-mod config;
-mod db;
-mod models;
-mod repositories;
-mod services;
-mod utils;
-
-use crate::{
+use src::{
     api::pool_api::PoolApi,
     db::initialize_sol_amm_backtester_database,
     repositories::pool_repo::PoolRepo,
     services::{
         pool_service::PoolService,
-        transactions_sync_amm_service::{AMMPlatforms, AMMService},
+        transactions_sync_amm_service::{
+            detect_platform, resolve_platform, rpc_concurrency, AMMPlatforms, AMMService,
+        },
     },
 };
 
 use anyhow::{Context, Result};
-use api::{
-    positions_api::PositionsApi, price_api::PriceApi, token_metadata_api::TokenMetadataApi,
+use async_trait::async_trait;
+use src::api::{
+    positions_api::PositionsApi,
+    price_api::{create_price_api, PriceToken},
+    token_metadata_api::{TokenMetadataApi, TokenMetadataApiTrait},
     transactions_api::TransactionApi,
 };
-use backtester::{
-    backtest_utils::{create_full_liquidity_range, sync_backwards},
-    backtester_core::{Backtest, Strategy, Wallet},
+use src::backtester::{
+    backtest_utils::{
+        calculate_total_position_amounts, check_decimals_match, check_liquidity_health,
+        check_start_price_drift, create_full_liquidity_range, fee_tier_warning, sync_backwards,
+        StartPrice,
+    },
+    backtester_core::{Backtest, ReplayVerification, Strategy, Wallet},
+    jit_band_strategy::JitBandStrategy,
+    ladder_strategy::LadderStrategy,
+    liquidity_array::LiquidityArray,
     no_rebalance_strategy::NoRebalanceStrategy,
     simple_rebalance_strategy::SimpleRebalanceStrategy,
+    synthetic_source::{SyntheticScenario, SyntheticTransactionSource},
 };
 
-use chrono::{Duration, Utc};
-use config::{AppConfig, StrategyType};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use src::config::{AppConfig, ConfigFile, StrategyConfig};
+use src::models::{
+    pool_model::{PoolModel, PoolPrice},
+    transactions_model::TransactionModelFromDB,
+};
 
 use colored::*;
 use dotenv::dotenv;
-use repositories::{positions_repo::PositionsRepo, transactions_repo::TransactionRepo};
-use services::{
+use sqlx::postgres::{PgPool, PgPoolOptions};
+#[cfg(feature = "parquet")]
+use src::repositories::parquet_transactions_repo::ParquetTransactionRepo;
+use src::repositories::{
+    positions_repo::PositionsRepo,
+    transactions_repo::{OrderDirection, TransactionRepo, TransactionRepoTrait},
+};
+use src::services::{
     positions_service::PositionsService, transactions_service::TransactionsService,
     transactions_sync_amm_service::create_amm_service,
 };
-use sqlx::postgres::PgPoolOptions;
-use std::{env, sync::Arc};
-use utils::{core_math::U256, profit_calcs::calculate_prices_and_pnl};
+use src::utils::{
+    core_math::{
+        optimal_entry_amounts, set_tick_price_cache_size, sqrt_price_to_price,
+        tick_to_sqrt_price_u256, within_tolerance, U256,
+    },
+    daily_stats::{aggregate_daily_stats, write_daily_stats_csv},
+    data_logger::DataLogger,
+    doctor::{diagnose, diagnose_staleness, ReadinessTotals},
+    profit_calcs::{calculate_prices_and_pnl, pool_price, OutputFormat, PriceCalculationResult},
+    stats::{fetch_swap_price_series, realized_volatility},
+    tx_export::export_transactions_jsonl,
+};
+use std::{env, fs::File, io::BufWriter, sync::Arc};
+
+const UNDERFUNDED_WARNING_PCT: f64 = 90.0;
+const STREAMED_RESULTS_FILE: &str = "simulation_results.jsonl";
+// How far the forward replay can end from the present price the backwards sync started from before it's flagged.
+const RECONSTRUCTION_TOLERANCE_BPS: u32 = 10;
+// A resumed replay fetches an hour of transactions at a time.
+const RESUME_BATCH_SECONDS: i64 = 3_600;
+// Always listed in the sync summary, also when none were added.
+const SYNCED_TRANSACTION_TYPES: [&str; 5] = [
+    "Swap",
+    "IncreaseLiquidity",
+    "DecreaseLiquidity",
+    "ClosePosition",
+    "TwoHopSwap",
+];
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
 
-    let config = AppConfig::from_env()?;
+    let config = match env::var("CONFIG_FILE") {
+        Ok(path) => AppConfig::from_file(&path)?,
+        Err(_) => AppConfig::from_env()?,
+    };
+    set_tick_price_cache_size(config.tick_price_cache_size);
     let args: Vec<String> = env::args().collect();
 
     if args.len() < 2 {
-        println!("Usage: cargo run [sync|backtest]");
+        println!("Usage: cargo run [sync [--dry-run]|backtest [--as-of <date>] [--throttle-ms <ms>] [--format pretty|tsv|json] [--synthetic <scenario>] [--save-state <file>] [--resume-from <file>]|compare <config_a> <config_b> [--as-of <date>]|sweep --capital <x,y,..> [--as-of <date>]|scenario [--steps <n>]|entry <lower_tick> <upper_tick> --value <token B amount>|verify-tvl|volatility [pool] [days]|export-txs <pool> --from <date> --to <date> --out <file>|daily <pool> [--out <file>]|doctor [pool]|detect [pool]|pools]");
         return Ok(());
     }
 
     match args[1].as_str() {
         "sync" => {
-            sync_data(&config, config.sync_days).await?;
+            if args.iter().any(|arg| arg == "--dry-run") {
+                dry_run_sync(&config).await?;
+            } else {
+                sync_data(&config, config.sync_days).await?;
+            }
         }
         "backtest" => {
-            run_backtest(&config).await?;
+            let as_of = match flag_value(&args, "--as-of") {
+                Some(value) => Some(parse_date_arg("--as-of", value)?),
+                None => None,
+            };
+
+            // 0 (default) means no pause between swaps.
+            let throttle = flag_value(&args, "--throttle-ms")
+                .map(|ms| ms.parse::<u64>())
+                .transpose()
+                .context("--throttle-ms must be a number of milliseconds")?
+                .filter(|ms| *ms > 0)
+                .map(std::time::Duration::from_millis);
+            let format = match flag_value(&args, "--format") {
+                Some(format) => format.parse::<OutputFormat>()?,
+                None => OutputFormat::Pretty,
+            };
+
+            let synthetic = flag_value(&args, "--synthetic")
+                .map(|scenario| scenario.parse::<SyntheticScenario>())
+                .transpose()?;
+
+            let save_state = flag_value(&args, "--save-state").cloned();
+            let resume_from = flag_value(&args, "--resume-from").cloned();
+
+            run_backtest(
+                &config,
+                as_of,
+                throttle,
+                format,
+                synthetic,
+                save_state,
+                resume_from,
+            )
+            .await?;
+        }
+        "compare" => {
+            let config_paths: Vec<&str> = args[2..]
+                .iter()
+                .take_while(|arg| !arg.starts_with("--"))
+                .map(String::as_str)
+                .collect();
+            let [first, second] = config_paths[..] else {
+                return Err("compare needs exactly two strategy config files".into());
+            };
+            let as_of = match flag_value(&args, "--as-of") {
+                Some(value) => Some(parse_date_arg("--as-of", value)?),
+                None => None,
+            };
+
+            compare_strategies(&config, [first, second], as_of).await?;
+        }
+        "sweep" => {
+            let multipliers = flag_value(&args, "--capital")
+                .context("sweep needs --capital with a list of multipliers, e.g. 1,10,100")?
+                .split(',')
+                .map(|multiplier| multiplier.trim().parse::<f64>())
+                .collect::<Result<Vec<f64>, _>>()
+                .context("--capital must be a comma separated list of numbers")?;
+            let as_of = match flag_value(&args, "--as-of") {
+                Some(value) => Some(parse_date_arg("--as-of", value)?),
+                None => None,
+            };
+
+            sweep_capital(&config, &multipliers, as_of).await?;
+        }
+        "scenario" => {
+            let steps = flag_value(&args, "--steps")
+                .map(|steps| steps.parse::<i32>())
+                .transpose()
+                .context("--steps must be a number")?
+                .unwrap_or(20)
+                .max(1);
+
+            show_scenario(&config, steps).await?;
+        }
+        "entry" => {
+            let ticks: Vec<i32> = args[2..]
+                .iter()
+                .take_while(|arg| !arg.starts_with("--"))
+                .map(|tick| tick.parse::<i32>())
+                .collect::<Result<_, _>>()
+                .context("entry ticks must be numbers")?;
+            let [lower_tick, upper_tick] = ticks[..] else {
+                return Err("entry needs a lower and an upper tick".into());
+            };
+            if lower_tick >= upper_tick {
+                return Err("entry lower tick must be below the upper tick".into());
+            }
+            let value_in_b = flag_value(&args, "--value")
+                .context("entry needs --value, the amount to deploy in whole token B")?
+                .parse::<f64>()
+                .context("--value must be a number")?;
+
+            show_entry_amounts(&config, lower_tick, upper_tick, value_in_b).await?;
+        }
+        "verify-tvl" => {
+            verify_tvl(&config).await?;
+        }
+        "volatility" => {
+            let pool_address = args
+                .get(2)
+                .cloned()
+                .unwrap_or_else(|| config.pool_address_to_backtest.clone());
+            let days = match args.get(3) {
+                Some(days) => days.parse::<i64>().context("days must be a number")?,
+                None => config.sync_days,
+            };
+
+            show_volatility(&config, &pool_address, days).await?;
+        }
+        "export-txs" => {
+            let pool_address = args
+                .get(2)
+                .filter(|arg| !arg.starts_with("--"))
+                .context("export-txs needs a pool address")?;
+            let from = parse_date_arg(
+                "--from",
+                flag_value(&args, "--from").context("--from needs a date")?,
+            )?;
+            let to = parse_date_arg(
+                "--to",
+                flag_value(&args, "--to").context("--to needs a date")?,
+            )?;
+            let out = flag_value(&args, "--out").context("--out needs a file path")?;
+
+            export_txs(&config, pool_address, from, to, out).await?;
+        }
+        "daily" => {
+            let pool_address = args
+                .get(2)
+                .filter(|arg| !arg.starts_with("--"))
+                .context("daily needs a pool address")?;
+            let out = flag_value(&args, "--out")
+                .map(String::as_str)
+                .unwrap_or("daily_stats.csv");
+
+            export_daily_stats(&config, pool_address, out).await?;
+        }
+        "doctor" => {
+            let pool_address = args
+                .get(2)
+                .cloned()
+                .unwrap_or_else(|| config.pool_address_to_backtest.clone());
+
+            run_doctor(&config, &pool_address).await?;
+        }
+        "detect" => {
+            let pool_address = args
+                .get(2)
+                .cloned()
+                .unwrap_or_else(|| config.pool_address.clone());
+
+            let platform = detect_platform(&PoolApi::new()?, &pool_address).await?;
+            println!("{}: {}", pool_address, platform);
+            if platform == AMMPlatforms::Meteora {
+                println!("Meteora pools are recognized but can't be synced yet.");
+            }
+        }
+        "pools" => {
+            list_pools(&config).await?;
         }
         _ => {
-            println!("Unknown command. Use 'sync' or 'backtest'.");
+            println!("Unknown command. Use 'sync', 'backtest', 'compare', 'sweep', 'scenario', 'entry', 'verify-tvl', 'volatility', 'export-txs', 'daily', 'doctor', 'detect' or 'pools'.");
         }
     }
 
@@ -75,10 +281,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 async fn sync_data(config: &AppConfig, days: i64) -> Result<()> {
     println!("Syncing data for the last {} days", days);
 
-    let platform = env::var("POOL_PLATFORM")
-        .context("POOL_PLATFORM environment variable not set")?
-        .parse::<AMMPlatforms>()?;
-
     let pool = PgPoolOptions::new()
         .max_connections(5)
         .connect(&config.database_url)
@@ -90,6 +292,7 @@ async fn sync_data(config: &AppConfig, days: i64) -> Result<()> {
 
     let pool_repo = PoolRepo::new(pool.clone());
     let pool_api = PoolApi::new()?;
+    let platform = resolve_platform(&pool_api, &config.pool_address).await?;
     let pool_service = PoolService::new(pool_repo.clone(), pool_api);
 
     match pool_service
@@ -100,7 +303,12 @@ async fn sync_data(config: &AppConfig, days: i64) -> Result<()> {
         Err(e) => eprintln!("Pool fetching related error: {}", e),
     }
 
-    let pool_data = pool_service.get_pool_data(&config.pool_address).await?;
+    // Normalize token order against the on-chain pool so is_sell is derived consistently in every service.
+    let pool_data = pool_service
+        .get_normalized_pool_data(&config.pool_address)
+        .await?;
+
+    warn_on_unknown_fee_tier(platform, &pool_data);
 
     let positions_repo = PositionsRepo::new(pool.clone());
     let positions_api = PositionsApi::new()?;
@@ -136,6 +344,28 @@ async fn sync_data(config: &AppConfig, days: i64) -> Result<()> {
         }
     };
 
+    // Only the first sync is recorded. A later one with other decimals mixes two conversions in the table, the backtest refuses those.
+    tx_repo
+        .record_sync_decimals(
+            &config.pool_address,
+            &pool_data.token_a_address,
+            &pool_data.token_b_address,
+            pool_data.token_a_decimals,
+            pool_data.token_b_decimals,
+        )
+        .await?;
+    if let Some(sync_decimals) = tx_repo.fetch_sync_decimals(&config.pool_address).await? {
+        if let Err(e) = check_decimals_match(
+            (pool_data.token_a_decimals, pool_data.token_b_decimals),
+            (
+                sync_decimals.token_a_decimals,
+                sync_decimals.token_b_decimals,
+            ),
+        ) {
+            eprintln!("WARNING: {}", e);
+        }
+    }
+
     println!("Transaction sync kick off!");
 
     // Sync transactions
@@ -145,12 +375,31 @@ async fn sync_data(config: &AppConfig, days: i64) -> Result<()> {
         .sync_transactions(&config.pool_address, start_time, config.sync_mode.clone())
         .await
     {
-        Ok(_f) => println!("Synced transactions successfully"),
+        Ok(summary) => {
+            println!(
+                "Synced transactions successfully, {} new transactions added",
+                summary.total()
+            );
+            // Zero liquidity transactions next to new swaps means they aren't captured, the backtest later misses ticks.
+            for transaction_type in SYNCED_TRANSACTION_TYPES {
+                println!(
+                    "  {:<18} {}",
+                    transaction_type,
+                    summary.count(transaction_type)
+                );
+            }
+            for (transaction_type, count) in &summary.new_by_type {
+                if !SYNCED_TRANSACTION_TYPES.contains(&transaction_type.as_str()) {
+                    println!("  {:<18} {}", transaction_type, count);
+                }
+            }
+        }
         Err(e) => eprintln!("Error syncing transactions: {}", e),
     }
 
     // Update transactions since not all data can be retrieved during sync. Updates will happen using position_data, to fill in liquidity info.
-    let transactions_service = TransactionsService::new(tx_repo, tx_api, positions_repo);
+    let transactions_service =
+        TransactionsService::new(tx_repo, tx_api, positions_repo, rpc_concurrency()?);
 
     match transactions_service
         .create_closed_positions_from_txs(&config.pool_address)
@@ -171,8 +420,10 @@ async fn sync_data(config: &AppConfig, days: i64) -> Result<()> {
     Ok(())
 }
 
-async fn run_backtest(config: &AppConfig) -> Result<()> {
-    println!("Running backtest with strategy: {:?}", &config.strategy);
+// Resolves the pool and samples the latest batch of transactions to project how much a sync would pull.
+// Nothing is inserted or upserted, the db is only read to find the existing sync boundaries.
+async fn dry_run_sync(config: &AppConfig) -> Result<()> {
+    println!("Dry run: nothing will be written to the database.");
 
     let pool = PgPoolOptions::new()
         .max_connections(5)
@@ -181,92 +432,631 @@ async fn run_backtest(config: &AppConfig) -> Result<()> {
 
     let pool_repo = PoolRepo::new(pool.clone());
     let pool_api = PoolApi::new()?;
-    let pool_service = PoolService::new(pool_repo.clone(), pool_api);
+    let platform = resolve_platform(&pool_api, &config.pool_address).await?;
+    let pool_service = PoolService::new(pool_repo, pool_api);
 
     let pool_data = pool_service
-        .get_pool_data(&config.pool_address_to_backtest)
+        .fetch_pool_model(&config.pool_address)
+        .await
+        .context("Pool address did not resolve to a whirlpool")?;
+
+    println!(
+        "Pool {} ({:?}): {}, fee rate {}, tick spacing {}",
+        pool_data.address, platform, pool_data.name, pool_data.fee_rate, pool_data.tick_spacing
+    );
+
+    let tx_repo = TransactionRepo::new(pool);
+    let tx_api = TransactionApi::new()?;
+
+    let amm_service = create_amm_service(
+        platform,
+        tx_repo,
+        tx_api,
+        &pool_data.token_a_address,
+        &pool_data.token_b_address,
+        &pool_data.token_a_vault,
+        &pool_data.token_b_vault,
+        pool_data.token_a_decimals,
+        pool_data.token_b_decimals,
+    )
+    .await
+    .context("Failed to create AMM service")?;
+
+    let start_time = Utc::now() - Duration::days(config.sync_days);
+    let preview = amm_service
+        .preview_sync(&config.pool_address, start_time, config.sync_mode.clone())
+        .await?;
+
+    println!(
+        "Sync mode {:?} would cover {} -> {} ({:.1} days)",
+        config.sync_mode,
+        preview.range_start,
+        preview.range_end,
+        preview.range_days()
+    );
+    println!(
+        "First batch: {} transactions",
+        preview.first_batch_transactions
+    );
+
+    if let (Some(start), Some(end)) = (preview.first_batch_start, preview.first_batch_end) {
+        println!("First batch spans {} -> {}", start, end);
+    }
+
+    match preview.projected_transactions() {
+        Some(projected) => println!("Projected transactions for the range: ~{}", projected),
+        None => println!("Not enough data in the first batch to project a transaction count."),
+    }
+
+    Ok(())
+}
+
+// Value following a --flag, if the flag was passed.
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a String> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .and_then(|idx| args.get(idx + 1))
+}
+
+// Accepts RFC 3339 ("2024-08-01T12:00:00Z") or a plain date ("2024-08-01", midnight UTC).
+fn parse_date_arg(flag: &str, value: &str) -> Result<DateTime<Utc>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(value) {
+        return Ok(datetime.with_timezone(&Utc));
+    }
+
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .context(format!("Invalid {} date: {}", flag, value))?;
+
+    Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+}
+
+// The pool to backtest, with the token order checked against the on-chain pool account when it can be fetched.
+async fn load_pool_data(config: &AppConfig, pool: PgPool) -> Result<PoolModel> {
+    let pool_repo = PoolRepo::new(pool);
+    let pool_api = PoolApi::new()?;
+    let pool_service = PoolService::new(pool_repo.clone(), pool_api);
+
+    let pool_data = match pool_service
+        .get_normalized_pool_data(&config.pool_address_to_backtest)
+        .await
+    {
+        Ok(pool_data) => pool_data,
+        Err(e) => {
+            eprintln!(
+                "WARNING: Could not verify token order against on-chain pool ({}). Using stored order.",
+                e
+            );
+            pool_service
+                .get_pool_data(&config.pool_address_to_backtest)
+                .await?
+        }
+    };
+
+    if let Some(Ok(platform)) = pool_repo
+        .get_pool_platform(&config.pool_address_to_backtest)
+        .await?
+        .map(|platform| platform.parse::<AMMPlatforms>())
+    {
+        warn_on_unknown_fee_tier(platform, &pool_data);
+    }
+
+    Ok(pool_data)
+}
+
+fn warn_on_unknown_fee_tier(platform: AMMPlatforms, pool_data: &PoolModel) {
+    if let Some(warning) = fee_tier_warning(platform, pool_data.tick_spacing, pool_data.fee_rate) {
+        println!("WARNING: {}", warning);
+    }
+}
+
+// The transactions the backwards and forward syncs replay: the database, or a Parquet export of its transactions table
+// (TRANSACTIONS_PARQUET) to skip the round trips. The pool, positions and latest swap always come from the database.
+enum ReplayRepo {
+    Database(TransactionRepo),
+    #[cfg(feature = "parquet")]
+    Parquet(ParquetTransactionRepo),
+}
+
+fn open_replay_repo(config: &AppConfig, tx_repo: TransactionRepo) -> Result<ReplayRepo> {
+    match &config.transactions_parquet {
+        None => Ok(ReplayRepo::Database(tx_repo)),
+        #[cfg(feature = "parquet")]
+        Some(path) => {
+            let parquet_repo = ParquetTransactionRepo::open(path)?;
+            println!(
+                "Replaying {} transactions from {}.",
+                parquet_repo.transaction_count(),
+                path
+            );
+
+            Ok(ReplayRepo::Parquet(parquet_repo))
+        }
+        #[cfg(not(feature = "parquet"))]
+        Some(path) => Err(anyhow::anyhow!(
+            "TRANSACTIONS_PARQUET={} needs a build with the parquet feature (cargo run --features parquet)",
+            path
+        )),
+    }
+}
+
+#[async_trait]
+impl TransactionRepoTrait for ReplayRepo {
+    async fn fetch_transactions(
+        &self,
+        pool_address: &str,
+        cursor: Option<i64>,
+        limit: i64,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        match self {
+            ReplayRepo::Database(repo) => {
+                repo.fetch_transactions(pool_address, cursor, limit, order)
+                    .await
+            }
+            #[cfg(feature = "parquet")]
+            ReplayRepo::Parquet(repo) => {
+                repo.fetch_transactions(pool_address, cursor, limit, order)
+                    .await
+            }
+        }
+    }
+
+    async fn fetch_transactions_in_time_range(
+        &self,
+        pool_address: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        match self {
+            ReplayRepo::Database(repo) => {
+                repo.fetch_transactions_in_time_range(pool_address, start, end, order)
+                    .await
+            }
+            #[cfg(feature = "parquet")]
+            ReplayRepo::Parquet(repo) => {
+                repo.fetch_transactions_in_time_range(pool_address, start, end, order)
+                    .await
+            }
+        }
+    }
+
+    async fn tx_id_at_or_after(
+        &self,
+        pool_address: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<i64>> {
+        match self {
+            ReplayRepo::Database(repo) => repo.tx_id_at_or_after(pool_address, time).await,
+            #[cfg(feature = "parquet")]
+            ReplayRepo::Parquet(repo) => repo.tx_id_at_or_after(pool_address, time).await,
+        }
+    }
+}
+
+// Everything the strategies of a run share: the pool, the starting array (synced backwards once) and the replay window.
+struct BacktestSetup {
+    pool_data: PoolModel,
+    tx_repo: ReplayRepo,
+    starting_liquidity_arr: LiquidityArray,
+    highest_tx: TransactionModelFromDB,
+    tx_to_sync_from: TransactionModelFromDB,
+    // (tick, sqrt price) at present, only known when the start was reached by syncing backwards.
+    present_state: Option<(i32, U256)>,
+    // Replayed instead of tx_repo when set (--synthetic).
+    synthetic: Option<SyntheticTransactionSource>,
+}
+
+async fn prepare_backtest(
+    config: &AppConfig,
+    as_of: Option<DateTime<Utc>>,
+) -> Result<BacktestSetup> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
         .await?;
 
+    let mut pool_data = load_pool_data(config, pool.clone()).await?;
+
     let positions_repo = PositionsRepo::new(pool.clone());
     let positions_api = PositionsApi::new()?;
     let positions_service = PositionsService::new(positions_repo, positions_api);
 
     let tx_repo = TransactionRepo::new(pool);
+    let replay_repo = open_replay_repo(config, tx_repo.clone())?;
+
+    check_synced_data(config, &mut pool_data, &tx_repo).await?;
 
     let (positions_data, tx_to_sync_from) = positions_service
         .get_live_position_data_for_transaction(tx_repo.clone(), &config.pool_address)
         .await?;
 
-    // Create the liquidity range "at present" from db.
-    let liquidity_range_arr = create_full_liquidity_range(
-        pool_data.tick_spacing,
-        positions_data,
-        pool_data.clone(),
-        tx_to_sync_from.clone(),
-        pool_data.fee_rate,
-    )?;
+    // Only known when the start was reached by syncing backwards from the present.
+    let mut present_state = None;
 
-    println!("Current liquidity range recreated! Time to sync it backwards for the backtester.");
+    let (starting_liquidity_arr, highest_tx) = match as_of {
+        // Build the position set at as_of directly and start the backtest there, no backwards sync needed.
+        Some(as_of) => {
+            let (as_of_positions, start_tx) = positions_service
+                .get_position_data_as_of(
+                    &tx_repo,
+                    &config.pool_address,
+                    positions_data,
+                    &tx_to_sync_from,
+                    as_of,
+                )
+                .await?;
 
-    // Sync it backwards using all transactions to get the original liquidity range that we start our backtest from.
-    let (original_starting_liquidity_arr, highest_tx) = sync_backwards(
-        &tx_repo,
-        liquidity_range_arr,
-        pool_data.clone(),
-        tx_to_sync_from.clone(),
-        10_000,
-    )
-    .await?;
+            println!(
+                "Reconstructed {} positions as of {}, starting from swap {}.",
+                as_of_positions.len(),
+                as_of,
+                start_tx.signature
+            );
+
+            let start_price_swaps = fetch_start_price_swaps(config, &tx_repo, &start_tx).await?;
+            let mut liquidity_range_arr = create_full_liquidity_range(
+                pool_data.tick_spacing,
+                as_of_positions,
+                pool_data.clone(),
+                start_tx.clone(),
+                StartPrice::over(&start_price_swaps, config.start_price_method),
+                None,
+                pool_data.fee_rate,
+                config.tick_bounds,
+            )?;
+            liquidity_range_arr.set_fee_rate_history(config.fee_rate_history.clone());
+            liquidity_range_arr.max_tick_crossings = config.max_tick_crossings;
+
+            (liquidity_range_arr, start_tx)
+        }
+        None => {
+            // Create the liquidity range "at present" from db.
+            let start_price_swaps =
+                fetch_start_price_swaps(config, &tx_repo, &tx_to_sync_from).await?;
+            let mut liquidity_range_arr = create_full_liquidity_range(
+                pool_data.tick_spacing,
+                positions_data,
+                pool_data.clone(),
+                tx_to_sync_from.clone(),
+                StartPrice::over(&start_price_swaps, config.start_price_method),
+                fetch_live_pool_price(&config.pool_address).await,
+                pool_data.fee_rate,
+                config.tick_bounds,
+            )?;
+            liquidity_range_arr.set_fee_rate_history(config.fee_rate_history.clone());
+            liquidity_range_arr.max_tick_crossings = config.max_tick_crossings;
+
+            println!(
+                "Current liquidity range recreated! Time to sync it backwards for the backtester."
+            );
+
+            present_state = Some((
+                liquidity_range_arr.current_tick,
+                liquidity_range_arr.current_sqrt_price,
+            ));
+
+            check_liquidity_health(&liquidity_range_arr)
+                .context("The present liquidity range can't be synced backwards")?;
+
+            // Sync it backwards to get the original liquidity range that we start our backtest from. Only as far as
+            // BACKTEST_DAYS when it's set, through all transactions otherwise.
+            let start_time = config
+                .backtest_days
+                .map(|days| tx_to_sync_from.block_time_utc - Duration::days(days));
+
+            sync_backwards(
+                &replay_repo,
+                liquidity_range_arr,
+                pool_data.clone(),
+                tx_to_sync_from.clone(),
+                start_time,
+                config.min_swap_amount_in,
+                config.reconstruction_checkpoint.as_ref(),
+                10_000,
+            )
+            .await?
+        }
+    };
+
+    check_liquidity_health(&starting_liquidity_arr)
+        .context("The starting liquidity range can't be replayed")?;
+
+    if let Some(max_drift_pct) = config.max_start_price_drift_pct {
+        check_start_price(
+            config,
+            &pool_data,
+            &starting_liquidity_arr,
+            &highest_tx,
+            max_drift_pct,
+        )
+        .await?;
+    }
+
+    println!("Starting liquidity range ready! Time to add position, sync forwards and calculate results!");
+
+    Ok(BacktestSetup {
+        pool_data,
+        tx_repo: replay_repo,
+        starting_liquidity_arr,
+        highest_tx,
+        tx_to_sync_from,
+        present_state,
+        synthetic: None,
+    })
+}
+
+// The guards both fresh and resumed runs go through before replaying: the token order and decimals the sync converted the
+// transactions with, and how recent the synced data is.
+async fn check_synced_data(
+    config: &AppConfig,
+    pool_data: &mut PoolModel,
+    tx_repo: &TransactionRepo,
+) -> Result<()> {
+    match tx_repo.fetch_sync_decimals(&config.pool_address).await? {
+        Some(sync_decimals) => {
+            // The sync ran on the on-chain token order. When load_pool_data fell back to the stored order it can be flipped,
+            // which flips the decimals too.
+            if let Some((token_mint_a, token_mint_b)) = &sync_decimals.token_mints {
+                pool_data.normalize_token_order(token_mint_a, token_mint_b)?;
+            }
+
+            check_decimals_match(
+                (pool_data.token_a_decimals, pool_data.token_b_decimals),
+                (sync_decimals.token_a_decimals, sync_decimals.token_b_decimals),
+            )?
+        }
+        None => println!(
+            "WARNING: No sync decimals recorded for this pool (synced before they were), can't cross-check the token decimals."
+        ),
+    }
+
+    if let Some(hours) = config.stale_data_hours {
+        check_data_freshness(config, pool_data, tx_repo, hours).await?;
+    }
+
+    Ok(())
+}
 
-    println!("Sync backwards complete! Time to add position, sync forwards and calculate results!");
+// Live price from the pool account for the present liquidity range. None falls back to the latest swap's price.
+async fn fetch_live_pool_price(pool_address: &str) -> Option<PoolPrice> {
+    let pool_price = match PoolApi::new() {
+        Ok(pool_api) => pool_api.fetch_pool_price(pool_address).await,
+        Err(e) => Err(e),
+    };
+
+    match pool_price {
+        Ok(pool_price) => Some(pool_price),
+        Err(e) => {
+            println!(
+                "WARNING: Couldn't read the price from the pool account ({}), reconstructing it from the latest swap.",
+                e
+            );
+            None
+        }
+    }
+}
+
+// The START_PRICE_SWAPS most recent swaps up to latest_swap, the starting price is averaged over them. Empty when it's 1.
+async fn fetch_start_price_swaps(
+    config: &AppConfig,
+    tx_repo: &TransactionRepo,
+    latest_swap: &TransactionModelFromDB,
+) -> Result<Vec<TransactionModelFromDB>> {
+    if config.start_price_swaps <= 1 {
+        return Ok(Vec::new());
+    }
 
-    let mut sync_forward_liq_arr = original_starting_liquidity_arr.clone();
+    tx_repo
+        .fetch_recent_swaps(
+            &latest_swap.pool_address,
+            latest_swap.block_time_utc,
+            config.start_price_swaps as i64,
+        )
+        .await
+}
+
+// The backtest runs up to the present, so the reconstructed present state has to be recent. Warns, or errors with FAIL_ON_STALE_DATA.
+async fn check_data_freshness(
+    config: &AppConfig,
+    pool_data: &PoolModel,
+    tx_repo: &TransactionRepo,
+    hours: i64,
+) -> Result<()> {
+    let latest_swap = tx_repo.fetch_most_recent_swap(&config.pool_address).await?;
+
+    let findings = diagnose_staleness(
+        pool_data.last_updated_at,
+        latest_swap.map(|swap| swap.block_time_utc),
+        Utc::now(),
+        Duration::hours(hours),
+    );
+
+    if findings.is_empty() {
+        return Ok(());
+    }
 
-    // since backward sync accrued fees, we need to reset all fee data
-    sync_forward_liq_arr.fee_growth_global_a = U256::zero();
-    sync_forward_liq_arr.fee_growth_global_b = U256::zero();
+    for finding in &findings {
+        eprintln!("WARNING: Stale data. {} {}", finding.problem, finding.fix);
+    }
 
-    // Reset fee growth outside for all ticks
-    for tick_data in sync_forward_liq_arr.data.iter_mut() {
-        tick_data.fee_growth_outside_a = U256::zero();
-        tick_data.fee_growth_outside_b = U256::zero();
+    if config.fail_on_stale_data {
+        return Err(anyhow::anyhow!(
+            "Stale data, aborting the backtest (FAIL_ON_STALE_DATA is set)."
+        ));
     }
 
-    sync_forward_liq_arr.current_block_time = highest_tx.block_time;
+    Ok(())
+}
+
+// Compares the reconstructed starting price against the price source's at the start transaction's time.
+async fn check_start_price(
+    config: &AppConfig,
+    pool_data: &PoolModel,
+    starting_liquidity_arr: &LiquidityArray,
+    start_tx: &TransactionModelFromDB,
+    max_drift_pct: f64,
+) -> Result<()> {
+    let token_metadata_api = TokenMetadataApi::new()?;
+    let price_api = create_price_api(config.price_source)?;
+
+    let symbols = token_metadata_api
+        .get_token_symbols_for_addresses(&[
+            pool_data.token_a_address.clone(),
+            pool_data.token_b_address.clone(),
+        ])
+        .await?;
+    let token_a_price_usd = price_api
+        .get_price(
+            &PriceToken {
+                symbol: symbols[0].clone(),
+                address: pool_data.token_a_address.clone(),
+            },
+            start_tx.block_time_utc,
+        )
+        .await
+        .context("Failed to fetch the token A price for the start price check")?
+        .price;
+    let token_b_price_usd = price_api
+        .get_price(
+            &PriceToken {
+                symbol: symbols[1].clone(),
+                address: pool_data.token_b_address.clone(),
+            },
+            start_tx.block_time_utc,
+        )
+        .await
+        .context("Failed to fetch the token B price for the start price check")?
+        .price;
+
+    let reconstructed_price = pool_price(
+        starting_liquidity_arr.current_sqrt_price,
+        pool_data.token_a_decimals,
+        pool_data.token_b_decimals,
+    );
+
+    check_start_price_drift(
+        reconstructed_price,
+        token_a_price_usd / token_b_price_usd,
+        max_drift_pct,
+    )
+    .context(format!(
+        "Start price check at {} (tick {})",
+        start_tx.block_time_utc, starting_liquidity_arr.current_tick
+    ))
+}
 
-    let token_a_amount: u128 = config.get_strategy_detail("token_a_amount")?;
-    let token_b_amount: u128 = config.get_strategy_detail("token_b_amount")?;
+// The strategy's token amounts (whole tokens) as a wallet in raw units.
+fn starting_wallet(pool_data: &PoolModel, strategy_config: &StrategyConfig) -> Wallet {
+    let (token_a_amount, token_b_amount) = strategy_config.token_amounts();
 
     let amount_token_a =
         U256::from(token_a_amount * 10_u128.pow(pool_data.token_a_decimals as u32));
     let amount_token_b =
         U256::from(token_b_amount * 10_u128.pow(pool_data.token_b_decimals as u32));
 
-    let wallet = Wallet {
-        token_a_addr: pool_data.token_a_address,
-        token_b_addr: pool_data.token_b_address,
+    Wallet {
+        token_a_addr: pool_data.token_a_address.clone(),
+        token_b_addr: pool_data.token_b_address.clone(),
         amount_token_a,
         amount_token_b,
         token_a_decimals: pool_data.token_a_decimals,
         token_b_decimals: pool_data.token_b_decimals,
         amount_a_fees_collected: U256::zero(),
         amount_b_fees_collected: U256::zero(),
-    };
+    }
+}
 
-    let strategy: Box<dyn Strategy> = match config.strategy {
-        StrategyType::NoRebalance => {
-            let lower_tick: i32 = config.get_strategy_detail("lower_tick")?;
-            let upper_tick: i32 = config.get_strategy_detail("upper_tick")?;
-            Box::new(NoRebalanceStrategy::new(lower_tick, upper_tick))
+fn build_strategy(
+    strategy_config: &StrategyConfig,
+    current_tick: i32,
+    tick_spacing: i32,
+) -> Result<Box<dyn Strategy>> {
+    Ok(match *strategy_config {
+        StrategyConfig::NoRebalance {
+            lower_tick,
+            upper_tick,
+            ..
+        } => Box::new(NoRebalanceStrategy::new(lower_tick, upper_tick)),
+        StrategyConfig::SimpleRebalance { range, .. } => {
+            Box::new(SimpleRebalanceStrategy::new(current_tick, range))
         }
-        StrategyType::SimpleRebalance => {
-            let range: i32 = config.get_strategy_detail("range")?;
-            Box::new(SimpleRebalanceStrategy::new(
-                original_starting_liquidity_arr.current_tick,
-                range,
-            ))
-        }
-    };
+        StrategyConfig::Ladder {
+            lower_tick,
+            upper_tick,
+            num_rungs,
+            ..
+        } => Box::new(LadderStrategy::new(
+            lower_tick,
+            upper_tick,
+            num_rungs,
+            tick_spacing,
+        )?),
+        StrategyConfig::JitBand {
+            band_lower_tick,
+            band_upper_tick,
+            position_half_width,
+            ..
+        } => Box::new(JitBandStrategy::new(
+            band_lower_tick,
+            band_upper_tick,
+            position_half_width,
+            tick_spacing,
+        )?),
+    })
+}
+
+// The replay settings from the config, shared by fresh and resumed runs.
+fn apply_run_settings(
+    config: &AppConfig,
+    backtest: &mut Backtest,
+    throttle: Option<std::time::Duration>,
+    data_logger: DataLogger,
+) {
+    if config.verify_replay {
+        backtest.replay_verification = Some(ReplayVerification::new(config.replay_tick_tolerance));
+    }
+
+    backtest.sample_interval_seconds = config.timeline_sample_seconds;
+    backtest.max_actions = config.max_actions;
+    backtest.count_fees_at_end = config.count_fees_at_end;
+    backtest.source_program_filter = config.source_program_filter.clone();
+    backtest.min_swap_amount_in = config.min_swap_amount_in;
+    backtest.sandwich_penalty = config.sandwich_penalty;
+    backtest.throttle = throttle;
+    backtest.data_logger = data_logger;
+}
+
+// Replays the window for one strategy on a copy of the starting array.
+async fn simulate_strategy(
+    config: &AppConfig,
+    setup: &BacktestSetup,
+    strategy_config: &StrategyConfig,
+    throttle: Option<std::time::Duration>,
+    data_logger: DataLogger,
+    save_state: Option<String>,
+) -> Result<(Backtest, PriceCalculationResult)> {
+    let mut sync_forward_liq_arr = setup.starting_liquidity_arr.clone();
+
+    // The backward sync accrued fees. Either start every tick from zero or keep the reconstructed fee growth outside values,
+    // positions checkpoint the growth inside their range when opened so both only count fees earned after the open.
+    if config.reset_fee_growth {
+        sync_forward_liq_arr.reset_fee_growth();
+    }
+
+    sync_forward_liq_arr.current_block_time = setup.highest_tx.block_time;
+    // Only the replay's own edge stops are reported, not the ones of the backwards sync.
+    sync_forward_liq_arr.swaps_stopped_at_edge = 0;
+    // The backwards sync sampled the fee growth while rewinding, out of time order for the replay whatever RESET_FEE_GROWTH is.
+    sync_forward_liq_arr.recent_fee_growth.clear();
+
+    let wallet = starting_wallet(&setup.pool_data, strategy_config);
+    let (amount_token_a, amount_token_b) = (wallet.amount_token_a, wallet.amount_token_b);
+    let strategy = build_strategy(
+        strategy_config,
+        setup.starting_liquidity_arr.current_tick,
+        setup.pool_data.tick_spacing as i32,
+    )?;
 
     let mut backtest = Backtest::new(
         amount_token_a,
@@ -276,117 +1066,1180 @@ async fn run_backtest(config: &AppConfig) -> Result<()> {
         strategy,
     );
 
-    backtest
-        .sync_forward(
-            &tx_repo,
-            highest_tx.tx_id, // the higher, the more in the past it is.
-            tx_to_sync_from.tx_id,
-            &config.pool_address,
-            10_000,
-        )
-        .await
-        .unwrap();
+    apply_run_settings(config, &mut backtest, throttle, data_logger);
+    // Only the real transactions follow the on-chain snapshot.
+    if setup.synthetic.is_none() {
+        backtest.reconstruction_checkpoint = config.reconstruction_checkpoint.clone();
+    }
+    backtest.save_state_to = save_state;
+
+    match &setup.synthetic {
+        Some(source) => {
+            backtest
+                .sync_forward(
+                    source,
+                    setup.highest_tx.tx_id,
+                    setup.tx_to_sync_from.tx_id,
+                    &config.pool_address,
+                    10_000,
+                )
+                .await
+        }
+        None => {
+            backtest
+                .sync_forward(
+                    &setup.tx_repo,
+                    setup.highest_tx.tx_id, // the higher, the more in the past it is.
+                    setup.tx_to_sync_from.tx_id,
+                    &config.pool_address,
+                    10_000,
+                )
+                .await
+        }
+    }
+    .context("Failed to sync strategy forward")?;
+
+    // The forward replay ends on the swap the backwards sync started from, so both reconstructions of that price should
+    // agree. Large strategy positions dampen the replayed price moves, so some drift is expected for those.
+    if let Some((present_tick, present_sqrt_price)) = setup.present_state {
+        if !within_tolerance(
+            backtest.liquidity_arr.current_sqrt_price,
+            present_sqrt_price,
+            RECONSTRUCTION_TOLERANCE_BPS,
+        ) {
+            eprintln!(
+                "WARNING: Forward replay ended at tick {} but the backwards sync started from tick {} (tolerance {} bps). The reconstruction drifted.",
+                backtest.liquidity_arr.current_tick, present_tick, RECONSTRUCTION_TOLERANCE_BPS
+            );
+        }
+    }
 
     let token_metadata_api = TokenMetadataApi::new()?;
-    let price_api = PriceApi::new()?;
+    let price_api = create_price_api(config.price_source)?;
 
     let result = calculate_prices_and_pnl(
         &token_metadata_api,
-        &price_api,
+        price_api.as_ref(),
+        config.stable_token,
+        config.token_yields,
+        config.fee_handling,
+        config.price_time_check,
         &backtest,
-        &highest_tx,
-        &tx_to_sync_from,
+        &setup.highest_tx,
+        &setup.tx_to_sync_from,
     )
     .await
-    .unwrap();
+    .map_err(|e| anyhow::anyhow!("Failed to price the backtest: {}", e))?;
 
-    println!("\n{}", "Strategy Results".bold().underline());
-    println!("{}", "=================".bold());
+    Ok((backtest, result))
+}
 
-    println!("\n{}", "Timespan of strategy".underline());
-    println!("  From:        {}", result.start_time);
-    println!("  To:          {}", result.end_time);
+// Loads a state saved with --save-state and replays from there up to the most recent synced swap. The report covers the
+// original window start through the new end, fees and counters carry over.
+async fn resume_backtest(
+    config: &AppConfig,
+    state_path: &str,
+    throttle: Option<std::time::Duration>,
+    mut data_logger: DataLogger,
+    save_state: Option<String>,
+) -> Result<(Backtest, PriceCalculationResult)> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
 
-    println!("\n{}", "Price Changes".underline());
-    println!(
-        "  Token A price change (vs USD):     {}%",
-        format!("{:.3}", result.token_a_price_change_pct).yellow()
-    );
-    println!(
-        "  Token B price change (vs USD):     {}%",
-        format!("{:.3}", result.token_b_price_change_pct).yellow()
-    );
+    let mut pool_data = load_pool_data(config, pool.clone()).await?;
+    let tx_repo = TransactionRepo::new(pool);
+    let replay_repo = open_replay_repo(config, tx_repo.clone())?;
 
-    println!("\n{}", "Holding Analysis".underline());
-    println!(
-        "  PnL if held (no LPing):           ${}",
-        format!("{:.3}", result.pnl_no_lping).blue()
-    );
-    println!(
-        "  PnL if held pct (no LPing):        {}%",
-        format!("{:.3}", result.pnl_no_lping_pct).blue()
-    );
+    check_synced_data(config, &mut pool_data, &tx_repo).await?;
+
+    let end_tx = tx_repo
+        .fetch_most_recent_swap(&config.pool_address)
+        .await?
+        .context("No swaps synced for this pool")?;
+
+    // The starting tick only matters for initialize_strategy, resume_strategy takes the open range from the positions.
+    let strategy = build_strategy(&config.strategy_config, 0, pool_data.tick_spacing as i32)?;
+    let (mut backtest, resume) =
+        Backtest::load_state(state_path, strategy, &replay_repo, &config.pool_address).await?;
+
+    if resume.replay_position.applied(&end_tx) {
+        return Err(anyhow::anyhow!(
+            "Nothing synced after the saved state in {}. Sync the pool again before resuming.",
+            state_path
+        ));
+    }
 
-    println!("\n{}", "Total Value Analysis".underline());
-    println!(
-        "  Starting value in USD:            ${:.3}",
-        result.starting_total_value_in_usd
-    );
-    println!(
-        "  Ending value in USD:              ${:.3}",
-        result.ending_total_value_in_usd
-    );
-    println!(
-        "  Total PnL in USD:                 ${}",
-        format!("{:.3}", result.final_value_total).green()
-    );
     println!(
-        "  Total PnL in pct:                  {}%",
-        format!("{:.3}", result.total_pnl_pct).green()
+        "Resuming the backtest from {} (window started {}).",
+        state_path, resume.window_start_tx.block_time_utc
     );
 
-    println!("\n{}", "LPing analysis".underline());
+    data_logger.carry_over(&backtest.data_logger);
+    apply_run_settings(config, &mut backtest, throttle, data_logger);
+    backtest.save_state_to = save_state;
+
+    backtest
+        .resume_forward(
+            &replay_repo,
+            &resume.replay_position,
+            end_tx.block_time,
+            &config.pool_address,
+            RESUME_BATCH_SECONDS,
+        )
+        .await
+        .context("Failed to sync strategy forward")?;
+
+    let token_metadata_api = TokenMetadataApi::new()?;
+    let price_api = create_price_api(config.price_source)?;
+
+    let result = calculate_prices_and_pnl(
+        &token_metadata_api,
+        price_api.as_ref(),
+        config.stable_token,
+        config.token_yields,
+        config.fee_handling,
+        config.price_time_check,
+        &backtest,
+        &resume.window_start_tx,
+        &end_tx,
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to price the backtest: {}", e))?;
+
+    Ok((backtest, result))
+}
+
+// A run over the synced window (or a synthetic path), starting from the reconstructed array.
+async fn fresh_backtest(
+    config: &AppConfig,
+    as_of: Option<DateTime<Utc>>,
+    throttle: Option<std::time::Duration>,
+    synthetic: Option<SyntheticScenario>,
+    data_logger: DataLogger,
+    save_state: Option<String>,
+) -> Result<(Backtest, PriceCalculationResult)> {
+    let mut setup = prepare_backtest(config, as_of).await?;
+
+    // The synthetic path starts from the reconstructed starting range and replaces the synced swaps after it.
+    if let Some(scenario) = synthetic {
+        let source = SyntheticTransactionSource::generate(
+            &scenario,
+            &setup.starting_liquidity_arr,
+            &config.pool_address,
+            &setup.pool_data.token_a_address,
+            &setup.pool_data.token_b_address,
+            setup.highest_tx.block_time,
+        )?;
+
+        println!(
+            "Replaying synthetic scenario {} ({} swaps) instead of the synced transactions.",
+            scenario.name,
+            source.transactions.len()
+        );
+        if config.stable_token.is_none() {
+            println!(
+                "WARNING: Without STABLE_TOKEN the USD prices come from {} at the synthetic timestamps and don't follow the synthetic path.",
+                config.price_source
+            );
+        }
+
+        setup.highest_tx = source.start_tx.clone();
+        setup.tx_to_sync_from = source.end_tx().clone();
+        setup.present_state = None;
+        setup.synthetic = Some(source);
+    }
+
+    // The window starts after highest_tx, with the same tx on both ends there is nothing to replay and every per-time
+    // figure would divide by zero.
+    if setup.highest_tx.tx_id == setup.tx_to_sync_from.tx_id {
+        return Err(anyhow::anyhow!(
+            "The backtest window only holds transaction {} ({}), nothing to replay. Increase SYNC_DAYS or move --as-of further from the start of the synced data.",
+            setup.highest_tx.signature,
+            setup.highest_tx.block_time_utc
+        ));
+    }
+
+    simulate_strategy(
+        config,
+        &setup,
+        &config.strategy_config,
+        throttle,
+        data_logger,
+        save_state,
+    )
+    .await
+}
+
+// Tsv and Json print the result as the last stdout lines (after the progress logs) and skip the report sections.
+async fn run_backtest(
+    config: &AppConfig,
+    as_of: Option<DateTime<Utc>>,
+    throttle: Option<std::time::Duration>,
+    format: OutputFormat,
+    synthetic: Option<SyntheticScenario>,
+    save_state: Option<String>,
+    resume_from: Option<String>,
+) -> Result<PriceCalculationResult> {
+    println!("Running backtest with strategy: {:?}", &config.strategy);
+
+    let data_logger = if config.stream_results {
+        DataLogger::new_streaming(STREAMED_RESULTS_FILE)
+            .context("Failed to create the streamed results file")?
+    } else {
+        DataLogger::new()
+    };
+
+    let (mut backtest, result) = match resume_from {
+        Some(state_path) => {
+            // The saved state follows the synced transactions, a synthetic path can't continue it.
+            if synthetic.is_some() || as_of.is_some() {
+                return Err(anyhow::anyhow!(
+                    "--resume-from can't be combined with --synthetic or --as-of"
+                ));
+            }
+
+            resume_backtest(config, &state_path, throttle, data_logger, save_state).await?
+        }
+        None => fresh_backtest(config, as_of, throttle, synthetic, data_logger, save_state).await?,
+    };
+
+    if format == OutputFormat::Pretty {
+        print_report(&result, config);
+
+        let worst_funded = backtest.worst_funded_positions(3);
+        if !worst_funded.is_empty() {
+            println!("\n{}", "Position Funding (worst first)".underline());
+            for funding in worst_funded {
+                let line = format!(
+                    "  {}: {:.1}% of token A, {:.1}% of token B deployed",
+                    funding.position_id, funding.pct_a_deployed, funding.pct_b_deployed
+                );
+
+                // Well under full deployment on one side means the starting split was poor for the range.
+                if funding.least_deployed_pct() < UNDERFUNDED_WARNING_PCT {
+                    println!("{}", line.yellow());
+                } else {
+                    println!("{}", line);
+                }
+            }
+        }
+
+        if let Some(verification) = &backtest.replay_verification {
+            println!("\n{}", "Replay Verification".underline());
+            println!(
+                "  Swaps checked:                     {}",
+                verification.swaps_checked
+            );
+            println!(
+                "  Swaps over {} tick tolerance:      {}",
+                verification.tick_tolerance,
+                verification.swaps_diverged.to_string().yellow()
+            );
+            println!(
+                "  Avg / max tick divergence:         {:.1} / {}",
+                verification.average_divergence(),
+                verification.max_divergence
+            );
+        }
+    }
+
+    if backtest.data_logger.is_streaming() {
+        backtest
+            .data_logger
+            .finish()
+            .context("Failed to write the streamed results")?;
+        println!(
+            "\n Simulation actions and detailed results streamed to {}",
+            STREAMED_RESULTS_FILE
+        );
+    } else {
+        let _ = backtest
+            .data_logger
+            .export_to_json("simulation_results.json");
+        println!("\n Simulation actions and detailed results exported to simulation_results.json");
+    }
+
+    match format {
+        OutputFormat::Pretty => {}
+        OutputFormat::Tsv => println!("{}", result.to_tsv()?),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&result)?),
+    }
+
+    Ok(result)
+}
+
+// Human readable report of a run. Display only, the numbers all come from result.
+fn print_report(result: &PriceCalculationResult, config: &AppConfig) {
+    let fmt = &config.number_format;
+
+    println!("\n{}", "Strategy Results".bold().underline());
+    println!("{}", "=================".bold());
+
+    println!("\n{}", "Timespan of strategy".underline());
+    println!("  From:        {}", result.start_time);
+    println!("  To:          {}", result.end_time);
+    match result.stable_token {
+        Some(stable_token) => {
+            println!("  Prices from: pool price ({} pegged to USD)", stable_token)
+        }
+        None => println!("  Prices from: {}", result.price_source),
+    }
+    if result.max_actions_hit {
+        println!(
+            "  {}",
+            format!(
+                "Stopped early: the strategy hit MAX_ACTIONS ({} actions), results only cover the replay up to that point.",
+                result.strategy_actions_executed
+            )
+            .red()
+        );
+    }
+    if let Some(filter) = &config.source_program_filter {
+        println!(
+            "  Source filter: {:?}, {} swaps left out of the replay",
+            filter, result.swaps_filtered_by_source
+        );
+    }
+    if config.min_swap_amount_in > 0 {
+        println!(
+            "  Dust filter: {} swaps below {} left out of the replay",
+            result.dust_swaps_skipped, config.min_swap_amount_in
+        );
+    }
+    if result.swaps_stopped_at_edge > 0 {
+        println!(
+            "  {}",
+            format!(
+                "{} swaps ran into the edge of the liquidity array and were only partially filled, widen the array with TICK_RANGE_PADDING or MIN_TICK/MAX_TICK.",
+                result.swaps_stopped_at_edge
+            )
+            .yellow()
+        );
+    }
+    if result.ticks_net_liquidity_reset > 0 {
+        println!(
+            "  {}",
+            format!(
+                "{} ticks were emptied with net liquidity left over, reset to zero. The synced liquidity data is likely incomplete.",
+                result.ticks_net_liquidity_reset
+            )
+            .yellow()
+        );
+    }
+
+    println!("\n{}", "Price Changes".underline());
+    let (start_price, end_price, price_label) =
+        result.pool_prices_for_display(config.invert_price_display);
     println!(
-        "  Tokens A earned:                   {:.6}",
-        result.token_a_collected_fees
+        "  Pool price ({}): {} -> {}",
+        price_label,
+        fmt.number(start_price, 6).yellow(),
+        fmt.number(end_price, 6).yellow()
     );
     println!(
-        "  Tokens B earned:                   {:.6}",
-        result.token_b_collected_fees
+        "  Token A price change (vs USD):     {}",
+        fmt.pct(result.token_a_price_change_pct).yellow()
     );
     println!(
-        "  Capital earned (in token A):       {:.6}",
-        result.capital_earned_in_token_a
+        "  Token B price change (vs USD):     {}",
+        fmt.pct(result.token_b_price_change_pct).yellow()
     );
+    if result.token_a_yield_factor != 1.0 || result.token_b_yield_factor != 1.0 {
+        println!(
+            "  Staking yield in the end prices:   A x{:.6}, B x{:.6}",
+            result.token_a_yield_factor, result.token_b_yield_factor
+        );
+    }
+
+    println!("\n{}", "Holding Analysis".underline());
     println!(
-        "  Capital earned in pct:             {}%",
-        format!("{:.3}", result.capital_earned_in_token_a_in_pct).red()
+        "  PnL if held (no LPing):            {}",
+        fmt.usd(result.pnl_no_lping).blue()
     );
     println!(
-        "  Fees in USD:                      ${}",
-        format!("{:.3}", result.total_fees_collected_in_usd).red()
+        "  PnL if held pct (no LPing):        {}",
+        fmt.pct(result.pnl_no_lping_pct).blue()
     );
+
+    println!("\n{}", "Total Value Analysis".underline());
     println!(
-        "  Fees in pct:                       {}%",
-        format!("{:.3}", result.total_fees_in_pct).red()
+        "  Starting value in USD:             {}",
+        fmt.usd(result.starting_total_value_in_usd)
     );
     println!(
-        "  Pct of swaps in position:          {:.1}%",
-        result.range_efficiency
+        "  Ending value in USD:               {}",
+        fmt.usd(result.ending_total_value_in_usd)
     );
     println!(
-        "  Fee APR in pct:                    {:.2}%",
-        result.fee_apr_percentage
+        "  Total PnL in USD:                  {}",
+        fmt.usd(result.final_value_total).green()
     );
     println!(
-        "  Impermanent loss in pct:          {:.2}%",
-        result.impermanent_loss
+        "  Entry swap cost in USD:            {}",
+        fmt.usd(result.entry_cost_usd).yellow()
     );
+    println!(
+        "  PnL before entry cost in USD:      {}",
+        fmt.usd(result.pnl_before_entry_cost)
+    );
+    println!(
+        "  Rebalance swap cost in USD:        {} over {} rebalances",
+        fmt.usd(result.rebalance_cost_usd).yellow(),
+        result.rebalance_count
+    );
+    if let Some(sandwich_penalty) = config.sandwich_penalty {
+        println!(
+            "  Of which sandwich loss in USD:     {} ({} bps above {} of the reserve)",
+            fmt.usd(result.sandwich_loss_usd).yellow(),
+            sandwich_penalty.penalty_bps,
+            sandwich_penalty.min_swap_share
+        );
+    }
+    println!(
+        "  Realized PnL in USD:               {}",
+        fmt.usd(result.realized_pnl_usd)
+    );
+    println!(
+        "  Unrealized PnL in USD:             {}",
+        fmt.usd(result.unrealized_pnl_usd)
+    );
+    println!(
+        "  Max drawdown in pct:               {}",
+        fmt.pct(result.max_drawdown_pct).yellow()
+    );
+    println!(
+        "  Total PnL in pct:                  {}",
+        fmt.pct(result.total_pnl_pct).green()
+    );
+
+    println!("\n{}", "LPing analysis".underline());
+    println!(
+        "  Tokens A earned:                   {}",
+        fmt.number(result.token_a_collected_fees, 6)
+    );
+    println!(
+        "  Tokens B earned:                   {}",
+        fmt.number(result.token_b_collected_fees, 6)
+    );
+    println!(
+        "  Capital earned (in token A):       {}",
+        fmt.number(result.capital_earned_in_token_a, 6)
+    );
+    println!(
+        "  Capital earned in pct:             {}",
+        fmt.pct(result.capital_earned_in_token_a_in_pct).red()
+    );
+    println!(
+        "  Fees in USD:                       {}",
+        fmt.usd(result.total_fees_collected_in_usd).red()
+    );
+    if config.show_fee_breakdown {
+        println!(
+            "  Fees collected during run:         {}",
+            fmt.usd(result.fees_collected_during_run_in_usd).red()
+        );
+        println!(
+            "  Fees collected at end:             {}",
+            fmt.usd(result.fees_collected_at_end_in_usd).red()
+        );
+    }
+    if result.fees_during_run_held_usd > 0.0 {
+        println!(
+            "  Run fees held / as 50/50 basket:   {} / {} ({} difference, {:?} in PnL)",
+            fmt.usd(result.fees_during_run_held_usd),
+            fmt.usd(result.fees_during_run_as_basket_usd),
+            fmt.usd(result.fee_handling_difference_usd).yellow(),
+            result.fee_handling
+        );
+    }
+    if !config.count_fees_at_end {
+        println!(
+            "  Uncollected fees at end:           {} (not in PnL)",
+            fmt.usd(result.uncollected_fees_at_end_usd).red()
+        );
+    }
+    println!(
+        "  Fees in pct:                       {}",
+        fmt.pct(result.total_fees_in_pct).red()
+    );
+    println!(
+        "  Avg share of active liquidity:     {}",
+        fmt.pct(result.avg_liquidity_share_pct)
+    );
+    match (
+        result.avg_idle_capital_usd,
+        result.avg_idle_capital_pct,
+        result.cash_drag_usd,
+    ) {
+        (Some(avg_idle_capital_usd), Some(avg_idle_capital_pct), Some(cash_drag_usd)) => println!(
+            "  Avg idle capital:                  {} ({} of value, {} cash drag)",
+            fmt.usd(avg_idle_capital_usd),
+            fmt.pct(avg_idle_capital_pct),
+            fmt.usd(cash_drag_usd).yellow()
+        ),
+        _ => println!(
+            "  Avg idle capital:                  n/a (no timeline samples, TIMELINE_SAMPLE_SECONDS is off or longer than the window)"
+        ),
+    }
+    if config.show_capital_efficiency {
+        println!(
+            "  Full range fees in USD:            {}",
+            fmt.usd(result.full_range_fees_in_usd).red()
+        );
+        println!(
+            "  Capital efficiency vs full range:  {}x",
+            fmt.number(result.capital_efficiency_multiple, 2).red()
+        );
+    }
+    println!(
+        "  Pct of swaps in position:          {}%",
+        fmt.number(result.range_efficiency, 1)
+    );
+    println!(
+        "  Fee APR in pct:                    {}%",
+        fmt.number(result.fee_apr_percentage, 2)
+    );
+    println!(
+        "  Fees cover the swap costs after:   {}",
+        match result.fee_breakeven_days {
+            Some(days) => format!("{} days", fmt.number(days, 1)),
+            None => "never".to_string(),
+        }
+    );
+    println!(
+        "  Impermanent loss in pct:          {}%",
+        fmt.number(result.impermanent_loss, 2)
+    );
+}
+
+// Runs two strategies over the same starting array and transactions, the backwards sync only runs once.
+// Each config file only contributes its [strategy] section, everything else comes from the env as usual.
+async fn compare_strategies(
+    config: &AppConfig,
+    config_paths: [&str; 2],
+    as_of: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let mut strategies = Vec::new();
+    for path in config_paths {
+        let strategy_config = ConfigFile::load(path)?
+            .strategy
+            .context(format!("{} has no [strategy] section", path))?;
+        let label = std::path::Path::new(path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| path.to_string());
+
+        strategies.push((label, strategy_config));
+    }
+
+    let setup = prepare_backtest(config, as_of).await?;
+
+    let mut results = Vec::new();
+    for (label, strategy_config) in &strategies {
+        println!(
+            "\nReplaying {} ({})",
+            label,
+            strategy_config.strategy_type().name()
+        );
+        let (_, result) = simulate_strategy(
+            config,
+            &setup,
+            strategy_config,
+            None,
+            DataLogger::new(),
+            None,
+        )
+        .await?;
+        results.push(result);
+    }
+
+    let row = |name: &'static str, value: fn(&PriceCalculationResult) -> f64| {
+        (name, value(&results[0]), value(&results[1]))
+    };
+    let rows = [
+        row("Fees in USD", |result| result.total_fees_collected_in_usd),
+        row("Impermanent loss in pct", |result| result.impermanent_loss),
+        row("Entry swap cost in USD", |result| result.entry_cost_usd),
+        row("Rebalance swap cost in USD", |result| {
+            result.rebalance_cost_usd
+        }),
+        row("Sandwich loss in USD", |result| result.sandwich_loss_usd),
+        row("Rebalances", |result| result.rebalance_count as f64),
+        row("Max drawdown in pct", |result| result.max_drawdown_pct),
+        row("Avg liquidity share in pct", |result| {
+            result.avg_liquidity_share_pct
+        }),
+        // NaN without timeline samples.
+        row("Cash drag in USD", |result| {
+            result.cash_drag_usd.unwrap_or(f64::NAN)
+        }),
+        row("Net PnL in USD", |result| result.final_value_total),
+        row("Net PnL in pct", |result| result.total_pnl_pct),
+    ];
+
+    println!("\n{}", "Strategy Comparison".bold().underline());
+    println!("  {:<28}{:>18}{:>18}", "", strategies[0].0, strategies[1].0);
+    for (name, first, second) in rows {
+        println!("  {:<28}{:>18.3}{:>18.3}", name, first, second);
+    }
+
+    Ok(())
+}
+
+// Replays the configured strategy once per capital multiplier, range unchanged, to show where rebalance slippage
+// starts eating the returns for this pool's depth.
+async fn sweep_capital(
+    config: &AppConfig,
+    multipliers: &[f64],
+    as_of: Option<DateTime<Utc>>,
+) -> Result<()> {
+    let setup = prepare_backtest(config, as_of).await?;
+
+    let mut rows = Vec::new();
+    for multiplier in multipliers {
+        let strategy_config = config.strategy_config.with_capital_multiplier(*multiplier);
+        let (token_a_amount, token_b_amount) = strategy_config.token_amounts();
+        println!(
+            "\nReplaying with {}x capital ({} token A, {} token B)",
+            multiplier, token_a_amount, token_b_amount
+        );
+
+        let (_, result) = simulate_strategy(
+            config,
+            &setup,
+            &strategy_config,
+            None,
+            DataLogger::new(),
+            None,
+        )
+        .await?;
+        rows.push((*multiplier, result));
+    }
+
+    println!("\n{}", "Capital Sweep".bold().underline());
+    println!(
+        "  {:<12}{:>18}{:>18}{:>18}{:>18}",
+        "Capital", "Start value USD", "Swap cost USD", "Fees in pct", "Net PnL in pct"
+    );
+    for (multiplier, result) in rows {
+        println!(
+            "  {:<12}{:>18.3}{:>18.3}{:>18.3}{:>18.3}",
+            format!("{}x", multiplier),
+            result.starting_total_value_in_usd,
+            result.entry_cost_usd + result.rebalance_cost_usd,
+            result.total_fees_in_pct,
+            result.total_pnl_pct
+        );
+    }
+
+    Ok(())
+}
+
+// Payoff diagram of the strategy's starting positions: what they'd hold at each tick of a grid around their ranges.
+// Opens the positions on the present pool state, no swaps are replayed.
+async fn show_scenario(config: &AppConfig, steps: i32) -> Result<()> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
+
+    let pool_data = load_pool_data(config, pool.clone()).await?;
+
+    let positions_service =
+        PositionsService::new(PositionsRepo::new(pool.clone()), PositionsApi::new()?);
+    let tx_repo = TransactionRepo::new(pool);
+    let (positions_data, tx_to_sync_from) = positions_service
+        .get_live_position_data_for_transaction(tx_repo.clone(), &config.pool_address)
+        .await?;
+    let start_price_swaps = fetch_start_price_swaps(config, &tx_repo, &tx_to_sync_from).await?;
+
+    let mut liquidity_arr = create_full_liquidity_range(
+        pool_data.tick_spacing,
+        positions_data,
+        pool_data.clone(),
+        tx_to_sync_from,
+        StartPrice::over(&start_price_swaps, config.start_price_method),
+        fetch_live_pool_price(&config.pool_address).await,
+        pool_data.fee_rate,
+        config.tick_bounds,
+    )?;
+    liquidity_arr.set_fee_rate_history(config.fee_rate_history.clone());
+
+    let current_tick = liquidity_arr.current_tick;
+    let wallet = starting_wallet(&pool_data, &config.strategy_config);
+    let strategy = build_strategy(
+        &config.strategy_config,
+        current_tick,
+        pool_data.tick_spacing as i32,
+    )?;
+
+    let mut backtest = Backtest::new(
+        wallet.amount_token_a,
+        wallet.amount_token_b,
+        liquidity_arr,
+        wallet,
+        strategy,
+    );
+    backtest
+        .initialize()
+        .context("Failed to open the strategy's positions")?;
+
+    let mut position_ids: Vec<String> = backtest.liquidity_arr.positions.keys().cloned().collect();
+    position_ids.sort();
+
+    let ranges: Vec<(i32, i32)> = position_ids
+        .iter()
+        .map(|id| {
+            let position = &backtest.liquidity_arr.positions[id];
+            (position.lower_tick, position.upper_tick)
+        })
+        .collect();
+
+    let (Some(lowest_tick), Some(highest_tick)) = (
+        ranges.iter().map(|(lower, _)| *lower).min(),
+        ranges.iter().map(|(_, upper)| *upper).max(),
+    ) else {
+        println!(
+            "The strategy opens no positions at the current tick ({}).",
+            current_tick
+        );
+        return Ok(());
+    };
 
-    let _ = backtest
-        .data_logger
-        .export_to_json("simulation_results.json");
-    println!("\n Simulation actions and detailed results exported to simulation_results.json");
+    // Half the covered width on either side, so the flat all A / all B parts of the payoff show up too.
+    let padding = (highest_tick - lowest_tick) / 2;
+    let grid_start = lowest_tick - padding;
+    let grid_step = ((highest_tick + padding - grid_start) / steps).max(1);
+
+    let decimal_scale = |decimals: i16| 10_f64.powi(decimals as i32);
+    let scale_a = decimal_scale(pool_data.token_a_decimals);
+    let scale_b = decimal_scale(pool_data.token_b_decimals);
+
+    println!("\n{}", "Position Scenario".bold().underline());
+    println!("  Pool:          {}", pool_data.name);
+    println!("  Current tick:  {}", current_tick);
+    for (id, (lower, upper)) in position_ids.iter().zip(&ranges) {
+        println!("  {}: [{}, {}]", id, lower, upper);
+    }
+
+    println!(
+        "\n  {:>10}{:>16}{:>20}{:>20}{:>20}",
+        "Tick", "Price", pool_data.token_a_name, pool_data.token_b_name, "Value in B"
+    );
+
+    for step in 0..=steps {
+        let tick = grid_start + step * grid_step;
+
+        let (amount_a, amount_b) = position_ids.iter().try_fold(
+            (U256::zero(), U256::zero()),
+            |(total_a, total_b), id| {
+                backtest
+                    .position_value_at_tick(id, tick)
+                    .map(|(amount_a, amount_b)| (total_a + amount_a, total_b + amount_b))
+            },
+        )?;
+
+        let price = sqrt_price_to_price(tick_to_sqrt_price_u256(tick)) * scale_a / scale_b;
+        let amount_a = amount_a.as_u128() as f64 / scale_a;
+        let amount_b = amount_b.as_u128() as f64 / scale_b;
+
+        println!(
+            "  {:>10}{:>16.6}{:>20.6}{:>20.6}{:>20.6}",
+            tick,
+            price,
+            amount_a,
+            amount_b,
+            amount_a * price + amount_b
+        );
+    }
+
+    Ok(())
+}
+
+// Token amounts that open the range at the live pool price without a rebalance swap, for funding the wallet up front.
+async fn show_entry_amounts(
+    config: &AppConfig,
+    lower_tick: i32,
+    upper_tick: i32,
+    value_in_b: f64,
+) -> Result<()> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
+
+    let pool_data = load_pool_data(config, pool).await?;
+    let pool_price = PoolApi::new()?
+        .fetch_pool_price(&config.pool_address_to_backtest)
+        .await
+        .context("Failed to read the price from the pool account")?;
+
+    let scale_a = 10_f64.powi(pool_data.token_a_decimals as i32);
+    let scale_b = 10_f64.powi(pool_data.token_b_decimals as i32);
+    let current_sqrt_price = U256::from(pool_price.sqrt_price);
+
+    let (amount_a, amount_b) = optimal_entry_amounts(
+        U256::from((value_in_b * scale_b) as u128),
+        current_sqrt_price,
+        tick_to_sqrt_price_u256(lower_tick),
+        tick_to_sqrt_price_u256(upper_tick),
+    );
+
+    println!("\n{}", "Entry Amounts".bold().underline());
+    println!("  Pool:          {}", pool_data.name);
+    println!(
+        "  Current tick:  {} (price {:.6} {} per {})",
+        pool_price.tick,
+        sqrt_price_to_price(current_sqrt_price) * scale_a / scale_b,
+        pool_data.token_b_name,
+        pool_data.token_a_name
+    );
+    println!("  Range:         [{}, {}]", lower_tick, upper_tick);
+    println!(
+        "  {}:{:>20.6}",
+        pool_data.token_a_name,
+        amount_a.as_u128() as f64 / scale_a
+    );
+    println!(
+        "  {}:{:>20.6}",
+        pool_data.token_b_name,
+        amount_b.as_u128() as f64 / scale_b
+    );
+
+    Ok(())
+}
+
+// Sanity check: the TVL of the reconstructed positions should roughly match what the pool vaults hold on-chain.
+// A large gap means positions are missing from the reconstruction.
+async fn verify_tvl(config: &AppConfig) -> Result<()> {
+    println!(
+        "Verifying reconstructed TVL for pool: {}",
+        &config.pool_address_to_backtest
+    );
+
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
+
+    let pool_repo = PoolRepo::new(pool.clone());
+    let pool_api = PoolApi::new()?;
+    let pool_service = PoolService::new(pool_repo, pool_api);
+
+    let pool_data = pool_service
+        .get_normalized_pool_data(&config.pool_address_to_backtest)
+        .await?;
+
+    let positions_repo = PositionsRepo::new(pool.clone());
+    let positions_api = PositionsApi::new()?;
+    let positions_service = PositionsService::new(positions_repo, positions_api);
+
+    let tx_repo = TransactionRepo::new(pool);
+
+    let (positions_data, latest_tx) = positions_service
+        .get_live_position_data_for_transaction(tx_repo.clone(), &pool_data.address)
+        .await?;
+    let start_price_swaps = fetch_start_price_swaps(config, &tx_repo, &latest_tx).await?;
+
+    let liquidity_arr = create_full_liquidity_range(
+        pool_data.tick_spacing,
+        positions_data.clone(),
+        pool_data.clone(),
+        latest_tx,
+        StartPrice::over(&start_price_swaps, config.start_price_method),
+        fetch_live_pool_price(&pool_data.address).await,
+        pool_data.fee_rate,
+        config.tick_bounds,
+    )?;
+
+    let (reconstructed_a, reconstructed_b) =
+        calculate_total_position_amounts(&positions_data, liquidity_arr.current_sqrt_price);
+    let (onchain_a, onchain_b) = pool_service.fetch_vault_balances(&pool_data).await?;
+
+    let token_metadata_api = TokenMetadataApi::new()?;
+    let price_api = create_price_api(config.price_source)?;
+
+    let symbols = token_metadata_api
+        .get_token_symbols_for_addresses(&[
+            pool_data.token_a_address.clone(),
+            pool_data.token_b_address.clone(),
+        ])
+        .await?;
+    let now = Utc::now();
+    let token_a_price_usd = price_api
+        .get_price(
+            &PriceToken {
+                symbol: symbols[0].clone(),
+                address: pool_data.token_a_address.clone(),
+            },
+            now,
+        )
+        .await?
+        .price;
+    let token_b_price_usd = price_api
+        .get_price(
+            &PriceToken {
+                symbol: symbols[1].clone(),
+                address: pool_data.token_b_address.clone(),
+            },
+            now,
+        )
+        .await?
+        .price;
+
+    let to_usd = |amount_a: u128, amount_b: u128| {
+        (amount_a as f64 / 10.0f64.powi(pool_data.token_a_decimals as i32)) * token_a_price_usd
+            + (amount_b as f64 / 10.0f64.powi(pool_data.token_b_decimals as i32))
+                * token_b_price_usd
+    };
+
+    let reconstructed_tvl = to_usd(reconstructed_a.as_u128(), reconstructed_b.as_u128());
+    let onchain_tvl = to_usd(onchain_a, onchain_b);
+    let discrepancy = reconstructed_tvl - onchain_tvl;
+
+    println!("\n{}", "TVL Verification".bold().underline());
+    println!(
+        "Positions reconstructed: {}",
+        positions_data.len().to_string().cyan()
+    );
+    println!(
+        "Reconstructed amounts: {} {} / {} {}",
+        reconstructed_a, pool_data.token_a_name, reconstructed_b, pool_data.token_b_name
+    );
+    println!(
+        "On-chain vault amounts: {} {} / {} {}",
+        onchain_a, pool_data.token_a_name, onchain_b, pool_data.token_b_name
+    );
+    println!(
+        "Reconstructed TVL: {}",
+        format!("${:.2}", reconstructed_tvl).cyan()
+    );
+    println!("On-chain TVL: {}", format!("${:.2}", onchain_tvl).cyan());
+    println!("Prices from: {}", config.price_source);
+    println!(
+        "Discrepancy: {} ({:.2}%)",
+        format!("${:.2}", discrepancy).yellow(),
+        (discrepancy / onchain_tvl) * 100.0
+    );
+    println!(
+        "Note: vaults also hold uncollected LP fees, so a small negative discrepancy is expected."
+    );
+
+    Ok(())
+}
+
+// Read-only: annualized realized volatility of the pool price over the last `days`, from the synced swaps.
+async fn show_volatility(config: &AppConfig, pool_address: &str, days: i64) -> Result<()> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
+
+    let pool_data = PoolRepo::new(pool.clone())
+        .get_pool_by_address(pool_address)
+        .await?
+        .context(format!("Pool not found for address: {}", pool_address))?;
+
+    let tx_repo = TransactionRepo::new(pool);
+
+    let end_time = Utc::now();
+    let start_time = end_time - Duration::days(days);
+
+    let prices = fetch_swap_price_series(
+        &tx_repo,
+        pool_address,
+        &pool_data.token_a_address,
+        start_time,
+        end_time,
+    )
+    .await?;
+
+    println!("\n{}", "Realized Volatility".underline());
+    println!("  Pool:                              {}", pool_data.name);
+    println!("  Window:                            last {} days", days);
+    println!("  Swaps in window:                   {}", prices.len());
+
+    match realized_volatility(&prices, 3600) {
+        Some(volatility) => println!(
+            "  Annualized (hourly returns):       {}%",
+            format!("{:.2}", volatility * 100.0).yellow()
+        ),
+        None => println!("  Not enough swaps in the window to estimate volatility."),
+    }
+
+    Ok(())
+}
+
+// Dumps the transactions the backtester would replay for the pool and window as JSON lines, for reproducible bug reports.
+async fn export_txs(
+    config: &AppConfig,
+    pool_address: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    out_path: &str,
+) -> Result<()> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
+
+    let tx_repo = TransactionRepo::new(pool);
+
+    let file = File::create(out_path).context(format!("Failed to create {}", out_path))?;
+    let mut writer = BufWriter::new(file);
+
+    let written = export_transactions_jsonl(&tx_repo, pool_address, from, to, &mut writer).await?;
+
+    println!(
+        "Exported {} transactions from {} to {} into {}",
+        written, from, to, out_path
+    );
+
+    Ok(())
+}
+
+// Daily volume, fees and active liquidity of the pool over the synced window, as CSV for dashboards.
+async fn export_daily_stats(config: &AppConfig, pool_address: &str, out_path: &str) -> Result<()> {
+    // The active liquidity needs the pool state at the window start, reconstructed the same way as for a backtest.
+    let mut pool_config = config.clone();
+    pool_config.pool_address = pool_address.to_string();
+    pool_config.pool_address_to_backtest = pool_address.to_string();
+
+    let setup = prepare_backtest(&pool_config, None).await?;
+    let mut liquidity_arr = setup.starting_liquidity_arr;
+
+    let daily_stats = aggregate_daily_stats(
+        &setup.tx_repo,
+        pool_address,
+        &setup.pool_data.token_a_address,
+        &mut liquidity_arr,
+        setup.highest_tx.block_time_utc,
+        Utc::now(),
+    )
+    .await?;
+
+    let file = File::create(out_path).context(format!("Failed to create {}", out_path))?;
+    let mut writer = BufWriter::new(file);
+
+    write_daily_stats_csv(
+        &daily_stats,
+        setup.pool_data.token_a_decimals,
+        setup.pool_data.token_b_decimals,
+        &mut writer,
+    )?;
+
+    println!(
+        "Exported {} days of {} into {}",
+        daily_stats.len(),
+        setup.pool_data.name,
+        out_path
+    );
+
+    Ok(())
+}
+
+// Read-only readiness check of the synced data, so a partially processed dataset doesn't quietly skew a backtest.
+// Read-only overview of every synced pool: the pair, how much history is stored and whether it's ready to backtest.
+async fn list_pools(config: &AppConfig) -> Result<()> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
+
+    let pools = PoolRepo::new(pool.clone()).get_all_pools().await?;
+    let tx_repo = TransactionRepo::new(pool.clone());
+    let positions_repo = PositionsRepo::new(pool);
+
+    if pools.is_empty() {
+        println!("No pools synced yet. Set POOL_ADDRESS and run `cargo run sync`.");
+        return Ok(());
+    }
+
+    println!("\n{}", "Synced Pools".bold().underline());
+    println!(
+        "  {:<46}{:<20}{:<20}{:<20}{:>12}  Backfilled",
+        "Address", "Pair", "Earliest tx", "Latest tx", "Txs"
+    );
+
+    for pool_data in pools {
+        let totals = ReadinessTotals::from_counts(
+            &tx_repo
+                .fetch_transaction_type_counts(&pool_data.address)
+                .await?,
+        );
+        let (earliest, latest) = match tx_repo.fetch_block_time_range(&pool_data.address).await? {
+            Some((earliest, latest)) => (
+                earliest.format("%Y-%m-%d %H:%M").to_string(),
+                latest.format("%Y-%m-%d %H:%M").to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let has_live_positions = positions_repo
+            .get_latest_version_for_live_pool(&pool_data.address)
+            .await?
+            > 0;
+
+        // The details of what's missing are in the doctor command.
+        let backfilled = match (totals.fully_backfilled(), has_live_positions) {
+            (true, true) => "yes".green(),
+            (true, false) => "no live positions".yellow(),
+            (false, _) => "no, see doctor".yellow(),
+        };
+
+        println!(
+            "  {:<46}{:<20}{:<20}{:<20}{:>12}  {}",
+            pool_data.address,
+            format!("{}/{}", pool_data.token_a_name, pool_data.token_b_name),
+            earliest,
+            latest,
+            totals.total,
+            backfilled
+        );
+    }
+
+    Ok(())
+}
+
+async fn run_doctor(config: &AppConfig, pool_address: &str) -> Result<()> {
+    let pool = PgPoolOptions::new()
+        .max_connections(5)
+        .connect(&config.database_url)
+        .await?;
+
+    let counts = TransactionRepo::new(pool)
+        .fetch_transaction_type_counts(pool_address)
+        .await?;
+
+    println!("\n{}", "Transactions by type".underline());
+    println!("  Pool: {}", pool_address);
+    for count in &counts {
+        println!(
+            "  {:<20} {:>10} total {:>10} ready {:>10} missing ticks",
+            count.transaction_type, count.total, count.ready, count.missing_ticks
+        );
+    }
+
+    let totals = ReadinessTotals::from_counts(&counts);
+    if totals.two_hop_swaps > 0 {
+        println!(
+            "  {} two hop swaps are stored for reference only, the replay uses the regular swaps.",
+            totals.two_hop_swaps
+        );
+    }
+
+    let findings = diagnose(&totals);
+
+    println!("\n{}", "Readiness".underline());
+    if findings.is_empty() {
+        println!("  {}", "Looks ready to backtest.".green());
+    }
+
+    for finding in findings {
+        println!("  {} {}", "Problem:".red(), finding.problem);
+        println!("  {}     {}", "Fix:".yellow(), finding.fix);
+    }
 
     Ok(())
 }
@@ -1,5 +1,5 @@
+pub mod kline_model;
 pub mod pool_model;
+pub mod positions_model;
 pub mod token_metadata;
 pub mod transactions_model;
-pub mod positions_model;
-pub mod kline_model;
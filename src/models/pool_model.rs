@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
@@ -30,6 +30,13 @@ pub struct Whirlpool {
     pub fee_growth_global_b: u128,
 }
 
+// Live price stored in the pool account. sqrt_price is Q64, same as LiquidityArray::current_sqrt_price.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PoolPrice {
+    pub sqrt_price: u128,
+    pub tick: i32,
+}
+
 #[derive(Debug, FromRow, Serialize, Deserialize, Clone)]
 pub struct PoolModel {
     pub address: String,
@@ -99,4 +106,31 @@ impl PoolModel {
             whirlpool.fee_rate as i16,
         ))
     }
+
+    // The swap direction logic (is_sell) everywhere assumes token A is the on-chain token A (mint_a) of the pool.
+    // If the row was populated in the opposite order, we flip all token specific fields so the rest of the code stays consistent.
+    pub fn normalize_token_order(&mut self, token_mint_a: &str, token_mint_b: &str) -> Result<()> {
+        if self.token_a_address == token_mint_a && self.token_b_address == token_mint_b {
+            return Ok(());
+        }
+
+        if self.token_a_address != token_mint_b || self.token_b_address != token_mint_a {
+            return Err(anyhow!(
+                "Pool {} tokens ({}, {}) do not match on-chain mints ({}, {})",
+                self.address,
+                self.token_a_address,
+                self.token_b_address,
+                token_mint_a,
+                token_mint_b
+            ));
+        }
+
+        std::mem::swap(&mut self.token_a_address, &mut self.token_b_address);
+        std::mem::swap(&mut self.token_a_name, &mut self.token_b_name);
+        std::mem::swap(&mut self.token_a_vault, &mut self.token_b_vault);
+        std::mem::swap(&mut self.token_a_decimals, &mut self.token_b_decimals);
+        self.name = format!("{}/{}", self.token_a_name, self.token_b_name);
+
+        Ok(())
+    }
 }
@@ -24,7 +24,7 @@ pub struct TransactionModelFromDB {
     pub block_time_utc: DateTime<Utc>,
     pub transaction_type: String,
     pub ready_for_backtesting: bool,
-    #[serde(flatten)]
+    // Not flattened, the enum tag would clash with transaction_type above and exported JSON couldn't be read back.
     pub data: TransactionData,
 }
 
@@ -44,6 +44,10 @@ pub struct SwapData {
     // DB only supports up to 2^64
     pub amount_in: u64,
     pub amount_out: u64,
+    // Program of the outer instruction behind the swap: the whirlpool program itself for direct swaps, an aggregator or vault
+    // otherwise. None when the source doesn't tell (the optimized archive only has the whirlpool instructions).
+    #[serde(default)]
+    pub source_program: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -57,6 +61,20 @@ pub struct LiquidityData {
     pub tick_lower: Option<i32>,
     pub tick_upper: Option<i32>,
     pub position_address: String,
+    // Other account keys of the instruction that could be the position when the layout is ambiguous (standard/hawksight parsing).
+    // Tried in order when position_address doesn't resolve to a known position.
+    #[serde(default)]
+    pub possible_positions: Vec<String>,
+}
+
+// Per transaction type counts of a pool, for checking a dataset is ready to backtest (doctor command).
+#[derive(Debug, Clone)]
+pub struct TransactionTypeCounts {
+    pub transaction_type: String,
+    pub total: i64,
+    pub ready: i64,
+    // Liquidity transactions without tick_lower/tick_upper, sync_forward skips them.
+    pub missing_ticks: i64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -100,6 +118,17 @@ impl TransactionModelFromDB {
     }
 }
 
+impl SwapData {
+    // Price of token A in token B (raw units, no decimals) at which the swap executed.
+    pub fn implied_price(&self, is_sell: bool) -> f64 {
+        if is_sell {
+            self.amount_out as f64 / self.amount_in as f64
+        } else {
+            self.amount_in as f64 / self.amount_out as f64
+        }
+    }
+}
+
 impl TransactionData {
     pub fn to_liquidity_data(&self) -> Result<&LiquidityData> {
         match self {
@@ -123,6 +152,11 @@ impl TransactionData {
                 tick_lower: data.tick_lower,
                 tick_upper: data.tick_upper,
                 position_address: data.position_address.trim_matches('"').to_string(), // JSON string needs to be trimmed
+                possible_positions: data
+                    .possible_positions
+                    .iter()
+                    .map(|address| address.trim_matches('"').to_string())
+                    .collect(),
             })
         } else {
             TransactionData::DecreaseLiquidity(LiquidityData {
@@ -134,6 +168,11 @@ impl TransactionData {
                 tick_lower: data.tick_lower,
                 tick_upper: data.tick_upper,
                 position_address: data.position_address.trim_matches('"').to_string(), // JSON string needs to be trimmed
+                possible_positions: data
+                    .possible_positions
+                    .iter()
+                    .map(|address| address.trim_matches('"').to_string())
+                    .collect(),
             })
         }
     }
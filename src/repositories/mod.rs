@@ -1,3 +1,5 @@
+#[cfg(feature = "parquet")]
+pub mod parquet_transactions_repo;
 pub mod pool_repo;
 pub mod positions_repo;
 pub mod transactions_repo;
@@ -0,0 +1,546 @@
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use parquet::{
+    basic::Compression,
+    data_type::{BoolType, ByteArray, ByteArrayType, Int64Type},
+    file::{
+        metadata::RowGroupMetaData,
+        properties::WriterProperties,
+        reader::{FileReader, SerializedFileReader},
+        statistics::Statistics,
+        writer::SerializedFileWriter,
+    },
+    record::{Field, Row},
+    schema::parser::parse_message_type,
+};
+use std::{
+    fs::File,
+    sync::{Arc, Mutex},
+};
+
+use crate::models::transactions_model::TransactionModelFromDB;
+
+use super::transactions_repo::{OrderDirection, TransactionRepoTrait};
+
+// One row per transaction, the columns of the transactions table. data is the JSON of its data column, block_time_utc
+// follows from block_time. Other columns in the file are ignored.
+const SCHEMA: &str = "
+    message transaction {
+        REQUIRED INT64 tx_id;
+        REQUIRED BYTE_ARRAY signature (UTF8);
+        REQUIRED BYTE_ARRAY pool_address (UTF8);
+        REQUIRED INT64 block_time;
+        REQUIRED BYTE_ARRAY transaction_type (UTF8);
+        REQUIRED BOOLEAN ready_for_backtesting;
+        REQUIRED BYTE_ARRAY data (UTF8);
+    }
+";
+
+// Row groups kept in memory at once. A replay batch usually spans one or two, the backwards and forward syncs walk
+// through the file in order.
+const CACHED_ROW_GROUPS: usize = 4;
+
+// tx_id and block_time range of a row group, from the column statistics in the file footer. A full range when the writer
+// left them out, so the group is always read.
+struct RowGroupRange {
+    tx_ids: (i64, i64),
+    block_times: (i64, i64),
+}
+
+// Serves the backwards and forward syncs from a Parquet export of the transactions instead of Postgres. Only the footer
+// is read when opening, a query reads the row groups whose tx_id or block_time range can hold its transactions (the
+// last few stay cached), so a cursor query doesn't need the whole file in memory. Same cursor semantics as
+// TransactionRepo (exclusive, lower tx_id = newer).
+pub struct ParquetTransactionRepo {
+    path: String,
+    reader: SerializedFileReader<File>,
+    row_groups: Vec<RowGroupRange>,
+    // Most recently used last.
+    cache: Mutex<Vec<(usize, Arc<Vec<TransactionModelFromDB>>)>>,
+}
+
+impl ParquetTransactionRepo {
+    pub fn open(path: &str) -> Result<Self> {
+        let file = File::open(path).context(format!("Failed to open {}", path))?;
+        let reader =
+            SerializedFileReader::new(file).context(format!("{} is not a Parquet file", path))?;
+
+        let row_groups = reader
+            .metadata()
+            .row_groups()
+            .iter()
+            .map(|row_group| RowGroupRange {
+                tx_ids: column_range(row_group, "tx_id"),
+                block_times: column_range(row_group, "block_time"),
+            })
+            .collect();
+
+        Ok(Self {
+            path: path.to_string(),
+            reader,
+            row_groups,
+            cache: Mutex::new(Vec::with_capacity(CACHED_ROW_GROUPS)),
+        })
+    }
+
+    // Every row of the file, other pools included.
+    pub fn transaction_count(&self) -> usize {
+        self.reader.metadata().file_metadata().num_rows() as usize
+    }
+
+    fn row_group(&self, index: usize) -> Result<Arc<Vec<TransactionModelFromDB>>> {
+        let mut cache = self.cache.lock().unwrap();
+
+        if let Some(position) = cache.iter().position(|(cached, _)| *cached == index) {
+            let entry = cache.remove(position);
+            let transactions = entry.1.clone();
+            cache.push(entry);
+
+            return Ok(transactions);
+        }
+
+        let transactions = Arc::new(
+            self.reader
+                .get_row_group(index)?
+                .get_row_iter(None)?
+                .map(|row| row_to_transaction_model(&row?))
+                .collect::<Result<Vec<_>>>()
+                .context(format!(
+                    "Failed to read row group {} of {}",
+                    index, self.path
+                ))?,
+        );
+
+        if cache.len() == CACHED_ROW_GROUPS {
+            cache.remove(0);
+        }
+        cache.push((index, transactions.clone()));
+
+        Ok(transactions)
+    }
+}
+
+fn column_range(row_group: &RowGroupMetaData, name: &str) -> (i64, i64) {
+    let statistics = row_group
+        .columns()
+        .iter()
+        .find(|column| column.column_path().string() == name)
+        .and_then(|column| column.statistics());
+
+    let range = match statistics {
+        Some(Statistics::Int64(values)) => values.min_opt().copied().zip(values.max_opt().copied()),
+        Some(Statistics::Int32(values)) => values
+            .min_opt()
+            .map(|min| *min as i64)
+            .zip(values.max_opt().map(|max| *max as i64)),
+        _ => None,
+    };
+
+    range.unwrap_or((i64::MIN, i64::MAX))
+}
+
+// Writes transactions in the layout ParquetTransactionRepo reads, snappy compressed, row_group_size rows per row group.
+pub fn write_transactions_parquet(
+    path: &str,
+    transactions: &[TransactionModelFromDB],
+    row_group_size: usize,
+) -> Result<()> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let properties = Arc::new(
+        WriterProperties::builder()
+            .set_compression(Compression::SNAPPY)
+            .build(),
+    );
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+
+    for row_group_transactions in transactions.chunks(row_group_size.max(1)) {
+        write_row_group(&mut writer, row_group_transactions)?;
+    }
+    writer.close()?;
+
+    Ok(())
+}
+
+fn write_row_group(
+    writer: &mut SerializedFileWriter<File>,
+    transactions: &[TransactionModelFromDB],
+) -> Result<()> {
+    let strings = |value: fn(&TransactionModelFromDB) -> String| -> Vec<ByteArray> {
+        transactions
+            .iter()
+            .map(|tx| ByteArray::from(value(tx).as_str()))
+            .collect()
+    };
+    let data = transactions
+        .iter()
+        .map(|tx| Ok(ByteArray::from(serde_json::to_string(&tx.data)?.as_str())))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut row_group = writer.next_row_group()?;
+    let mut column_index = 0;
+    while let Some(mut column) = row_group.next_column()? {
+        match column_index {
+            0 => column.typed::<Int64Type>().write_batch(
+                &transactions.iter().map(|tx| tx.tx_id).collect::<Vec<_>>(),
+                None,
+                None,
+            )?,
+            1 => column.typed::<ByteArrayType>().write_batch(
+                &strings(|tx| tx.signature.clone()),
+                None,
+                None,
+            )?,
+            2 => column.typed::<ByteArrayType>().write_batch(
+                &strings(|tx| tx.pool_address.clone()),
+                None,
+                None,
+            )?,
+            3 => column.typed::<Int64Type>().write_batch(
+                &transactions
+                    .iter()
+                    .map(|tx| tx.block_time)
+                    .collect::<Vec<_>>(),
+                None,
+                None,
+            )?,
+            4 => column.typed::<ByteArrayType>().write_batch(
+                &strings(|tx| tx.transaction_type.clone()),
+                None,
+                None,
+            )?,
+            5 => column.typed::<BoolType>().write_batch(
+                &transactions
+                    .iter()
+                    .map(|tx| tx.ready_for_backtesting)
+                    .collect::<Vec<_>>(),
+                None,
+                None,
+            )?,
+            _ => column
+                .typed::<ByteArrayType>()
+                .write_batch(&data, None, None)?,
+        };
+        column.close()?;
+        column_index += 1;
+    }
+    row_group.close()?;
+
+    Ok(())
+}
+
+fn row_to_transaction_model(row: &Row) -> Result<TransactionModelFromDB> {
+    let block_time = long_column(row, "block_time")?;
+
+    Ok(TransactionModelFromDB {
+        tx_id: long_column(row, "tx_id")?,
+        signature: string_column(row, "signature")?,
+        pool_address: string_column(row, "pool_address")?,
+        block_time,
+        block_time_utc: DateTime::from_timestamp(block_time, 0)
+            .ok_or_else(|| anyhow!("block_time {} is out of range", block_time))?,
+        transaction_type: string_column(row, "transaction_type")?,
+        ready_for_backtesting: match column(row, "ready_for_backtesting")? {
+            Field::Bool(value) => *value,
+            other => {
+                return Err(anyhow!(
+                    "ready_for_backtesting is {:?}, expected a boolean",
+                    other
+                ))
+            }
+        },
+        data: serde_json::from_str(&string_column(row, "data")?)
+            .context("Failed to deserialize data")?,
+    })
+}
+
+fn column<'a>(row: &'a Row, name: &str) -> Result<&'a Field> {
+    row.get_column_iter()
+        .find(|(column, _)| column.as_str() == name)
+        .map(|(_, field)| field)
+        .ok_or_else(|| anyhow!("No {} column", name))
+}
+
+fn long_column(row: &Row, name: &str) -> Result<i64> {
+    match column(row, name)? {
+        Field::Long(value) => Ok(*value),
+        Field::Int(value) => Ok(*value as i64),
+        other => Err(anyhow!("{} is {:?}, expected an integer", name, other)),
+    }
+}
+
+fn string_column(row: &Row, name: &str) -> Result<String> {
+    match column(row, name)? {
+        Field::Str(value) => Ok(value.clone()),
+        other => Err(anyhow!("{} is {:?}, expected a string", name, other)),
+    }
+}
+
+#[async_trait]
+impl TransactionRepoTrait for ParquetTransactionRepo {
+    async fn fetch_transactions(
+        &self,
+        pool_address: &str,
+        cursor: Option<i64>,
+        limit: i64,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        let limit = limit.max(0) as usize;
+        let descending = matches!(order, OrderDirection::Descending);
+
+        let in_cursor = |tx_id: i64| match cursor {
+            Some(cursor) if descending => tx_id < cursor,
+            Some(cursor) => tx_id > cursor,
+            None => true,
+        };
+        // Ahead in the query's order, the closest to the cursor first.
+        let ahead = |a: i64, b: i64| if descending { a > b } else { a < b };
+        // The tx_id of a row group that comes first in the query's order.
+        let nearest = |range: &RowGroupRange| {
+            if descending {
+                range.tx_ids.1
+            } else {
+                range.tx_ids.0
+            }
+        };
+
+        let mut row_groups: Vec<(usize, &RowGroupRange)> = self
+            .row_groups
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| {
+                in_cursor(if descending {
+                    range.tx_ids.0
+                } else {
+                    range.tx_ids.1
+                })
+            })
+            .collect();
+        row_groups.sort_by(|(_, a), (_, b)| {
+            if descending {
+                nearest(b).cmp(&nearest(a))
+            } else {
+                nearest(a).cmp(&nearest(b))
+            }
+        });
+
+        let mut transactions: Vec<TransactionModelFromDB> = Vec::new();
+        for (index, range) in row_groups {
+            // Row groups can overlap, stop once the next one can't beat what's collected.
+            if transactions.len() >= limit
+                && !transactions
+                    .last()
+                    .is_some_and(|last| ahead(nearest(range), last.tx_id))
+            {
+                break;
+            }
+
+            transactions.extend(
+                self.row_group(index)?
+                    .iter()
+                    .filter(|tx| tx.pool_address == pool_address && in_cursor(tx.tx_id))
+                    .cloned(),
+            );
+            transactions.sort_by(|a, b| {
+                if descending {
+                    b.tx_id.cmp(&a.tx_id)
+                } else {
+                    a.tx_id.cmp(&b.tx_id)
+                }
+            });
+            transactions.truncate(limit);
+        }
+
+        Ok(transactions)
+    }
+
+    async fn fetch_transactions_in_time_range(
+        &self,
+        pool_address: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        let mut transactions: Vec<TransactionModelFromDB> = Vec::new();
+
+        for (index, range) in self.row_groups.iter().enumerate() {
+            if range.block_times.1 < start.timestamp() || range.block_times.0 >= end.timestamp() {
+                continue;
+            }
+
+            transactions.extend(
+                self.row_group(index)?
+                    .iter()
+                    .filter(|tx| {
+                        tx.pool_address == pool_address
+                            && tx.block_time_utc >= start
+                            && tx.block_time_utc < end
+                    })
+                    .cloned(),
+            );
+        }
+
+        transactions.sort_by_key(|tx| (tx.block_time_utc, std::cmp::Reverse(tx.tx_id)));
+        if let OrderDirection::Descending = order {
+            transactions.reverse();
+        }
+
+        Ok(transactions)
+    }
+
+    async fn tx_id_at_or_after(
+        &self,
+        pool_address: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<i64>> {
+        let mut row_groups: Vec<(usize, &RowGroupRange)> = self
+            .row_groups
+            .iter()
+            .enumerate()
+            .filter(|(_, range)| range.block_times.1 >= time.timestamp())
+            .collect();
+        row_groups.sort_by_key(|(_, range)| range.block_times.0);
+
+        let mut first: Option<(i64, i64)> = None;
+        for (index, range) in row_groups {
+            // Starts after the best so far, and so does every group after it.
+            if first.is_some_and(|(block_time, _)| range.block_times.0 > block_time) {
+                break;
+            }
+
+            let candidate = self
+                .row_group(index)?
+                .iter()
+                .filter(|tx| tx.pool_address == pool_address && tx.block_time_utc >= time)
+                .map(|tx| (tx.block_time, tx.tx_id))
+                .min_by_key(|(block_time, tx_id)| (*block_time, std::cmp::Reverse(*tx_id)));
+
+            first = match (first, candidate) {
+                (Some(best), Some(candidate)) => Some(std::cmp::min_by_key(
+                    best,
+                    candidate,
+                    |(block_time, tx_id)| (*block_time, std::cmp::Reverse(*tx_id)),
+                )),
+                (best, candidate) => best.or(candidate),
+            };
+        }
+
+        Ok(first.map(|(_, tx_id)| tx_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtester::test_helpers::{create_test_swap_transactions, InMemoryTransactionRepo};
+
+    #[tokio::test]
+    async fn test_parquet_repo_matches_in_memory_repo() {
+        let transactions = create_test_swap_transactions(25, 1_000_000, "TokenA", "TokenB");
+        // A second pool in the same file is filtered out.
+        let mut other_pool = create_test_swap_transactions(5, 1_000_000, "TokenA", "TokenB");
+        for tx in other_pool.iter_mut() {
+            tx.tx_id += 100;
+            tx.pool_address = "other_pool".to_string();
+        }
+
+        let path =
+            std::env::temp_dir().join(format!("transactions_{}.parquet", std::process::id()));
+        let path = path.to_str().unwrap();
+        // Small row groups, every query below spans several.
+        write_transactions_parquet(path, &[transactions.clone(), other_pool].concat(), 4).unwrap();
+        let parquet_repo = ParquetTransactionRepo::open(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        assert_eq!(parquet_repo.transaction_count(), 30);
+        assert_eq!(parquet_repo.row_groups.len(), 8);
+
+        let memory_repo = InMemoryTransactionRepo {
+            transactions: transactions.clone(),
+        };
+        let ids = |transactions: Vec<TransactionModelFromDB>| -> Vec<i64> {
+            transactions.iter().map(|tx| tx.tx_id).collect()
+        };
+
+        for cursor in [None, Some(1), Some(10), Some(26)] {
+            for descending in [true, false] {
+                let order = || {
+                    if descending {
+                        OrderDirection::Descending
+                    } else {
+                        OrderDirection::Ascending
+                    }
+                };
+
+                assert_eq!(
+                    ids(parquet_repo
+                        .fetch_transactions("test_pool", cursor, 7, order())
+                        .await
+                        .unwrap()),
+                    ids(memory_repo
+                        .fetch_transactions("test_pool", cursor, 7, order())
+                        .await
+                        .unwrap()),
+                    "cursor {:?}, descending {}",
+                    cursor,
+                    descending
+                );
+            }
+        }
+
+        // Every column survives the round trip.
+        let first = &parquet_repo
+            .fetch_transactions("test_pool", Some(4), 1, OrderDirection::Descending)
+            .await
+            .unwrap()[0];
+        let expected = transactions.iter().find(|tx| tx.tx_id == 3).unwrap();
+        assert_eq!(first.signature, expected.signature);
+        assert_eq!(first.block_time, expected.block_time);
+        assert_eq!(first.block_time_utc, expected.block_time_utc);
+        assert_eq!(first.transaction_type, expected.transaction_type);
+        assert_eq!(
+            first.data.to_swap_data().unwrap().amount_in,
+            expected.data.to_swap_data().unwrap().amount_in
+        );
+
+        let at = transactions[5].block_time_utc;
+        assert_eq!(
+            parquet_repo
+                .tx_id_at_or_after("test_pool", at)
+                .await
+                .unwrap(),
+            memory_repo
+                .tx_id_at_or_after("test_pool", at)
+                .await
+                .unwrap()
+        );
+
+        let (start, end) = (
+            transactions[3].block_time_utc,
+            transactions[17].block_time_utc,
+        );
+        assert_eq!(
+            ids(parquet_repo
+                .fetch_transactions_in_time_range(
+                    "test_pool",
+                    start,
+                    end,
+                    OrderDirection::Ascending
+                )
+                .await
+                .unwrap()),
+            ids(memory_repo
+                .fetch_transactions_in_time_range(
+                    "test_pool",
+                    start,
+                    end,
+                    OrderDirection::Ascending
+                )
+                .await
+                .unwrap())
+        );
+
+        // Only the last few row groups read stay in memory.
+        assert!(parquet_repo.cache.lock().unwrap().len() <= CACHED_ROW_GROUPS);
+    }
+}
@@ -1,5 +1,5 @@
 use crate::{models::pool_model::PoolModel, services::transactions_sync_amm_service::AMMPlatforms};
-use sqlx::{query, query_as, Pool, Postgres};
+use sqlx::{query, query_as, query_scalar, Pool, Postgres};
 
 #[derive(Clone)]
 pub struct PoolRepo {
@@ -68,4 +68,18 @@ impl PoolRepo {
             .fetch_optional(&self.db)
             .await
     }
+
+    // Stored by upsert, None for an unknown pool.
+    pub async fn get_pool_platform(&self, address: &str) -> Result<Option<String>, sqlx::Error> {
+        query_scalar::<_, String>("SELECT platform FROM pools WHERE address = $1")
+            .bind(address)
+            .fetch_optional(&self.db)
+            .await
+    }
+
+    pub async fn get_all_pools(&self) -> Result<Vec<PoolModel>, sqlx::Error> {
+        query_as::<_, PoolModel>("SELECT * FROM pools ORDER BY name, address")
+            .fetch_all(&self.db)
+            .await
+    }
 }
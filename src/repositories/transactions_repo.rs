@@ -3,8 +3,11 @@ use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use sqlx::postgres::PgPool;
 use sqlx::Row;
+use std::collections::BTreeMap;
 
-use crate::models::transactions_model::{TransactionModel, TransactionModelFromDB};
+use crate::models::transactions_model::{
+    TransactionModel, TransactionModelFromDB, TransactionTypeCounts,
+};
 
 #[derive(Clone)]
 pub struct TransactionRepo {
@@ -15,6 +18,14 @@ pub enum OrderDirection {
     Descending,
 }
 
+// Decimals the first sync of a pool converted its transactions with.
+pub struct SyncDecimals {
+    pub token_a_decimals: i16,
+    pub token_b_decimals: i16,
+    // (token A, token B) mints in the order of the sync, None when recorded before the order was.
+    pub token_mints: Option<(String, String)>,
+}
+
 #[async_trait]
 pub trait TransactionRepoTrait {
     async fn fetch_transactions(
@@ -24,6 +35,23 @@ pub trait TransactionRepoTrait {
         limit: i64,
         order: OrderDirection,
     ) -> Result<Vec<TransactionModelFromDB>>;
+
+    // Transactions with start <= block_time_utc < end, in time order (replay order when Ascending).
+    async fn fetch_transactions_in_time_range(
+        &self,
+        pool_address: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>>;
+
+    // tx_id of the first transaction at or after time, to start a tx_id cursor from a timestamp.
+    // tx_ids descend with time (newest synced first), so same-block transactions go highest tx_id first.
+    async fn tx_id_at_or_after(
+        &self,
+        pool_address: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<i64>>;
 }
 
 #[async_trait]
@@ -95,6 +123,126 @@ impl TransactionRepoTrait for TransactionRepo {
             .map(|row| self.row_to_transaction_model(&row))
             .collect()
     }
+
+    async fn fetch_transactions_in_time_range(
+        &self,
+        pool_address: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        order: OrderDirection,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        // Older transactions of the same block have the higher tx_id.
+        let order_by = match order {
+            OrderDirection::Ascending => "block_time_utc ASC, tx_id DESC",
+            OrderDirection::Descending => "block_time_utc DESC, tx_id ASC",
+        };
+
+        let query = format!(
+            r#"
+            SELECT
+                tx_id, signature, pool_address, block_time, block_time_utc,
+                transaction_type, ready_for_backtesting, data
+            FROM transactions
+            WHERE
+                pool_address = $1
+                AND block_time_utc >= $2
+                AND block_time_utc < $3
+            ORDER BY {}
+            "#,
+            order_by
+        );
+
+        let rows = sqlx::query(&query)
+            .bind(pool_address)
+            .bind(start)
+            .bind(end)
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.into_iter()
+            .map(|row| self.row_to_transaction_model(&row))
+            .collect()
+    }
+
+    async fn tx_id_at_or_after(
+        &self,
+        pool_address: &str,
+        time: DateTime<Utc>,
+    ) -> Result<Option<i64>> {
+        let row = sqlx::query(
+            r#"
+            SELECT tx_id FROM transactions
+            WHERE pool_address = $1 AND block_time_utc >= $2
+            ORDER BY block_time_utc ASC, tx_id DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(pool_address)
+        .bind(time)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| row.get("tx_id")))
+    }
+}
+
+// The writes and bounds the sync services need, so an AMMService can run against something other than postgres.
+#[async_trait]
+pub trait TransactionSyncRepoTrait: Send + Sync {
+    async fn insert(&self, transactions: &[TransactionModel]) -> Result<usize>;
+
+    async fn count_transactions(&self, pool_address: &str) -> Result<i64>;
+
+    // Stored transactions per transaction_type.
+    async fn count_transactions_by_type(&self, pool_address: &str)
+        -> Result<BTreeMap<String, i64>>;
+
+    async fn fetch_highest_block_time_transaction(
+        &self,
+        pool_address: &str,
+    ) -> Result<Option<TransactionModelFromDB>>;
+
+    async fn fetch_lowest_block_time_transaction(
+        &self,
+        pool_address: &str,
+    ) -> Result<Option<TransactionModelFromDB>>;
+}
+
+#[async_trait]
+impl TransactionSyncRepoTrait for TransactionRepo {
+    async fn insert(&self, transactions: &[TransactionModel]) -> Result<usize> {
+        TransactionRepo::insert(self, transactions).await
+    }
+
+    async fn count_transactions(&self, pool_address: &str) -> Result<i64> {
+        TransactionRepo::count_transactions(self, pool_address).await
+    }
+
+    async fn count_transactions_by_type(
+        &self,
+        pool_address: &str,
+    ) -> Result<BTreeMap<String, i64>> {
+        Ok(self
+            .fetch_transaction_type_counts(pool_address)
+            .await?
+            .into_iter()
+            .map(|counts| (counts.transaction_type, counts.total))
+            .collect())
+    }
+
+    async fn fetch_highest_block_time_transaction(
+        &self,
+        pool_address: &str,
+    ) -> Result<Option<TransactionModelFromDB>> {
+        TransactionRepo::fetch_highest_block_time_transaction(self, pool_address).await
+    }
+
+    async fn fetch_lowest_block_time_transaction(
+        &self,
+        pool_address: &str,
+    ) -> Result<Option<TransactionModelFromDB>> {
+        TransactionRepo::fetch_lowest_block_time_transaction(self, pool_address).await
+    }
 }
 
 impl TransactionRepo {
@@ -139,6 +287,59 @@ impl TransactionRepo {
         Ok(inserted_count)
     }
 
+    // Only the first sync of a pool is recorded, its transactions stay converted with those decimals. The sync runs on the
+    // on-chain token order, which is recorded with them.
+    pub async fn record_sync_decimals(
+        &self,
+        pool_address: &str,
+        token_a_address: &str,
+        token_b_address: &str,
+        token_a_decimals: i16,
+        token_b_decimals: i16,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO sync_decimals (pool_address, token_a_decimals, token_b_decimals, token_a_address, token_b_address)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT (pool_address) DO NOTHING
+            "#,
+        )
+        .bind(pool_address)
+        .bind(token_a_decimals)
+        .bind(token_b_decimals)
+        .bind(token_a_address)
+        .bind(token_b_address)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // None for pools synced before the decimals were recorded.
+    pub async fn fetch_sync_decimals(&self, pool_address: &str) -> Result<Option<SyncDecimals>> {
+        let row = sqlx::query(
+            r#"
+            SELECT token_a_decimals, token_b_decimals, token_a_address, token_b_address
+            FROM sync_decimals
+            WHERE pool_address = $1
+            "#,
+        )
+        .bind(pool_address)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| {
+            let token_a_address: Option<String> = row.get("token_a_address");
+            let token_b_address: Option<String> = row.get("token_b_address");
+
+            SyncDecimals {
+                token_a_decimals: row.get("token_a_decimals"),
+                token_b_decimals: row.get("token_b_decimals"),
+                token_mints: token_a_address.zip(token_b_address),
+            }
+        }))
+    }
+
     pub async fn fetch_most_recent_swap(
         &self,
         pool_address: &str,
@@ -161,6 +362,99 @@ impl TransactionRepo {
             .transpose()
     }
 
+    pub async fn fetch_swap_at_or_before_timestamp(
+        &self,
+        pool_address: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Result<Option<TransactionModelFromDB>> {
+        let result = sqlx::query(
+            r#"
+            SELECT
+                tx_id, signature, pool_address, block_time, block_time_utc,
+                transaction_type, ready_for_backtesting, data
+            FROM transactions
+            WHERE
+                pool_address = $1
+                AND transaction_type = 'Swap'
+                AND block_time_utc <= $2
+            ORDER BY block_time_utc DESC, tx_id ASC
+            LIMIT 1
+            "#,
+        )
+        .bind(pool_address)
+        .bind(timestamp)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        result
+            .map(|row| self.row_to_transaction_model(&row))
+            .transpose()
+    }
+
+    // Up to limit swaps at or before timestamp, newest first.
+    pub async fn fetch_recent_swaps(
+        &self,
+        pool_address: &str,
+        timestamp: DateTime<Utc>,
+        limit: i64,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                tx_id, signature, pool_address, block_time, block_time_utc,
+                transaction_type, ready_for_backtesting, data
+            FROM transactions
+            WHERE
+                pool_address = $1
+                AND transaction_type = 'Swap'
+                AND block_time_utc <= $2
+            ORDER BY block_time_utc DESC, tx_id ASC
+            LIMIT $3
+            "#,
+        )
+        .bind(pool_address)
+        .bind(timestamp)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.iter()
+            .map(|row| self.row_to_transaction_model(row))
+            .collect()
+    }
+
+    // Liquidity transactions with start_time < block_time_utc <= end_time, oldest first.
+    pub async fn fetch_liquidity_transactions_between(
+        &self,
+        pool_address: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<TransactionModelFromDB>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                tx_id, signature, pool_address, block_time, block_time_utc,
+                transaction_type, ready_for_backtesting, data
+            FROM transactions
+            WHERE
+                pool_address = $1
+                AND transaction_type IN ('IncreaseLiquidity', 'DecreaseLiquidity')
+                AND block_time_utc > $2
+                AND block_time_utc <= $3
+            ORDER BY block_time_utc ASC
+            "#,
+        )
+        .bind(pool_address)
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await?;
+
+        rows.into_iter()
+            .map(|row| self.row_to_transaction_model(&row))
+            .collect()
+    }
+
     pub async fn upsert_liquidity_transactions(
         &self,
         transactions: &Vec<TransactionModelFromDB>,
@@ -344,6 +638,76 @@ impl TransactionRepo {
             .collect()
     }
 
+    // data holds the serialized TransactionData, so the tick fields sit under data->'data'.
+    pub async fn fetch_transaction_type_counts(
+        &self,
+        pool_address: &str,
+    ) -> Result<Vec<TransactionTypeCounts>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT
+                transaction_type,
+                COUNT(*) AS total,
+                COUNT(*) FILTER (WHERE ready_for_backtesting) AS ready,
+                COUNT(*) FILTER (
+                    WHERE transaction_type IN ('IncreaseLiquidity', 'DecreaseLiquidity')
+                    AND (data->'data'->>'tick_lower' IS NULL OR data->'data'->>'tick_upper' IS NULL)
+                ) AS missing_ticks
+            FROM transactions
+            WHERE pool_address = $1
+            GROUP BY transaction_type
+            ORDER BY transaction_type
+            "#,
+        )
+        .bind(pool_address)
+        .fetch_all(&self.pool)
+        .await
+        .context("Failed to count transactions by type")?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TransactionTypeCounts {
+                transaction_type: row.get("transaction_type"),
+                total: row.get("total"),
+                ready: row.get("ready"),
+                missing_ticks: row.get("missing_ticks"),
+            })
+            .collect())
+    }
+
+    // Block time of the oldest and newest stored transaction, None when the pool has none.
+    pub async fn fetch_block_time_range(
+        &self,
+        pool_address: &str,
+    ) -> Result<Option<(DateTime<Utc>, DateTime<Utc>)>> {
+        let row = sqlx::query(
+            r#"
+            SELECT MIN(block_time_utc) AS earliest, MAX(block_time_utc) AS latest
+            FROM transactions
+            WHERE pool_address = $1
+            "#,
+        )
+        .bind(pool_address)
+        .fetch_one(&self.pool)
+        .await
+        .context("Failed to fetch the transaction time range")?;
+
+        let earliest: Option<DateTime<Utc>> = row.get("earliest");
+        let latest: Option<DateTime<Utc>> = row.get("latest");
+
+        Ok(earliest.zip(latest))
+    }
+
+    pub async fn count_transactions(&self, pool_address: &str) -> Result<i64> {
+        let row = sqlx::query("SELECT COUNT(*) AS total FROM transactions WHERE pool_address = $1")
+            .bind(pool_address)
+            .fetch_one(&self.pool)
+            .await
+            .context("Failed to count transactions")?;
+
+        Ok(row.get("total"))
+    }
+
     pub async fn update_ready_for_backtesting(&self, tx_ids: &[i64]) -> Result<()> {
         sqlx::query(
             r#"
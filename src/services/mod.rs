@@ -1,7 +1,7 @@
-pub mod pool_service;
-pub mod transactions_sync_amm_service;
-pub mod orca_amm_standard;
 pub mod orca_amm_optimized;
-pub mod raydium_amm;
+pub mod orca_amm_standard;
+pub mod pool_service;
 pub mod positions_service;
+pub mod raydium_amm;
 pub mod transactions_service;
+pub mod transactions_sync_amm_service;
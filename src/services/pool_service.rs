@@ -22,17 +22,21 @@ impl PoolService {
         pool_address: &str,
         pool_platform: AMMPlatforms,
     ) -> Result<()> {
-        let whirlpool = self.fetch_and_decode_pool_data(pool_address).await?;
-
-        let pool = self
-            .convert_whirlpool_to_pool(pool_address.to_string(), whirlpool)
-            .await?;
+        let pool = self.fetch_pool_model(pool_address).await?;
 
         self.repo.upsert(&pool, pool_platform).await?;
 
         Ok(())
     }
 
+    // The pool as it would be stored, straight from chain. Nothing is written to the db.
+    pub async fn fetch_pool_model(&self, pool_address: &str) -> Result<PoolModel> {
+        let whirlpool = self.fetch_and_decode_pool_data(pool_address).await?;
+
+        self.convert_whirlpool_to_pool(pool_address.to_string(), whirlpool)
+            .await
+    }
+
     pub async fn fetch_and_decode_pool_data(&self, pool_address: &str) -> Result<Whirlpool> {
         let result = self.api.fetch_pool_data(pool_address).await?;
 
@@ -78,4 +82,36 @@ impl PoolService {
             .await?
             .ok_or_else(|| anyhow!("Pool not found for address: {}", pool_address))
     }
+
+    // Same as get_pool_data, but token A/B are guaranteed to follow the on-chain pool account ordering.
+    pub async fn get_normalized_pool_data(&self, pool_address: &str) -> Result<PoolModel> {
+        let mut pool = self.get_pool_data(pool_address).await?;
+        let whirlpool = self.fetch_and_decode_pool_data(pool_address).await?;
+
+        pool.normalize_token_order(
+            &whirlpool.token_mint_a.to_string(),
+            &whirlpool.token_mint_b.to_string(),
+        )?;
+
+        Ok(pool)
+    }
+
+    // On-chain vault balances for token A/B, minus protocol fees owed since those are not LP liquidity.
+    pub async fn fetch_vault_balances(&self, pool: &PoolModel) -> Result<(u128, u128)> {
+        let whirlpool = self.fetch_and_decode_pool_data(&pool.address).await?;
+
+        let vault_a_balance = self
+            .api
+            .fetch_token_account_balance(&pool.token_a_vault)
+            .await?;
+        let vault_b_balance = self
+            .api
+            .fetch_token_account_balance(&pool.token_b_vault)
+            .await?;
+
+        Ok((
+            vault_a_balance.saturating_sub(whirlpool.protocol_fee_owed_a as u128),
+            vault_b_balance.saturating_sub(whirlpool.protocol_fee_owed_b as u128),
+        ))
+    }
 }
@@ -3,6 +3,8 @@ use crate::models::transactions_model::TransactionModelFromDB;
 use crate::repositories::positions_repo::PositionsRepo;
 use crate::{api::positions_api::PositionsApi, repositories::transactions_repo::TransactionRepo};
 use anyhow::{anyhow, Context, Result};
+use chrono::{DateTime, Utc};
+use std::collections::HashMap;
 
 pub struct PositionsService {
     positions_repo: PositionsRepo,
@@ -119,4 +121,186 @@ impl PositionsService {
             Err(anyhow!("No positions found for the given pool address"))
         }
     }
+
+    // Position set valid at as_of, built from the live snapshot and the liquidity transactions between as_of and the snapshot.
+    // Positions opened after as_of drop out and positions closed after it come back from their DecreaseLiquidity, so no tick level rewind (sync_backwards) is needed.
+    // Returns the positions and the last swap at or before as_of, which seeds the price.
+    pub async fn get_position_data_as_of(
+        &self,
+        tx_repo: &TransactionRepo,
+        pool_address: &str,
+        live_positions: Vec<LivePositionModel>,
+        live_transaction: &TransactionModelFromDB,
+        as_of: DateTime<Utc>,
+    ) -> Result<(Vec<LivePositionModel>, TransactionModelFromDB)> {
+        if as_of >= live_transaction.block_time_utc {
+            return Err(anyhow!(
+                "as_of {} must be before the live positions snapshot at {}",
+                as_of,
+                live_transaction.block_time_utc
+            ));
+        }
+
+        let liquidity_transactions = tx_repo
+            .fetch_liquidity_transactions_between(
+                pool_address,
+                as_of,
+                live_transaction.block_time_utc,
+            )
+            .await
+            .context("Failed to fetch liquidity transactions after as_of")?;
+
+        let start_transaction = tx_repo
+            .fetch_swap_at_or_before_timestamp(pool_address, as_of)
+            .await?
+            .ok_or_else(|| anyhow!("No swap found at or before {}", as_of))?;
+
+        Ok((
+            rewind_positions(live_positions, &liquidity_transactions, as_of),
+            start_transaction,
+        ))
+    }
+}
+
+// Undoes every liquidity change in liquidity_transactions (all after as_of) on top of the live positions.
+// Transactions without tick data can't be placed and are skipped, same as in sync_backwards.
+pub fn rewind_positions(
+    live_positions: Vec<LivePositionModel>,
+    liquidity_transactions: &[TransactionModelFromDB],
+    as_of: DateTime<Utc>,
+) -> Vec<LivePositionModel> {
+    // (tick_lower, tick_upper, liquidity added back, liquidity taken away). Kept apart so u128 amounts never go through i128
+    // and the order of the transactions doesn't matter.
+    let mut positions: HashMap<String, (i32, i32, u128, u128)> = live_positions
+        .into_iter()
+        .map(|p| (p.address, (p.tick_lower, p.tick_upper, p.liquidity, 0)))
+        .collect();
+
+    for transaction in liquidity_transactions {
+        let liquidity_data = match transaction.data.to_liquidity_data() {
+            Ok(data) => data,
+            Err(_) => continue,
+        };
+
+        let (tick_lower, tick_upper, liquidity_amount) = match (
+            liquidity_data.tick_lower,
+            liquidity_data.tick_upper,
+            liquidity_data.liquidity_amount.parse::<u128>(),
+        ) {
+            (Some(lower), Some(upper), Ok(amount)) => (lower, upper, amount),
+            _ => continue,
+        };
+
+        let position = positions
+            .entry(liquidity_data.position_address.clone())
+            .or_insert((tick_lower, tick_upper, 0, 0));
+
+        if transaction.transaction_type == "IncreaseLiquidity" {
+            position.3 = position.3.saturating_add(liquidity_amount);
+        } else {
+            position.2 = position.2.saturating_add(liquidity_amount);
+        }
+    }
+
+    positions
+        .into_iter()
+        .filter_map(|(address, (tick_lower, tick_upper, added, removed))| {
+            let liquidity = added.checked_sub(removed).filter(|l| *l > 0)?;
+
+            Some(LivePositionModel {
+                address,
+                liquidity,
+                tick_lower,
+                tick_upper,
+                created_at: as_of,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::transactions_model::{LiquidityData, TransactionData};
+
+    fn liquidity_tx(
+        position_address: &str,
+        is_increase: bool,
+        amount: u128,
+    ) -> TransactionModelFromDB {
+        let data = LiquidityData {
+            token_a: "TokenA".to_string(),
+            token_b: "TokenB".to_string(),
+            amount_a: 0,
+            amount_b: 0,
+            liquidity_amount: amount.to_string(),
+            tick_lower: Some(-100),
+            tick_upper: Some(100),
+            position_address: position_address.to_string(),
+            possible_positions: vec![],
+        };
+
+        TransactionModelFromDB {
+            tx_id: 1,
+            signature: format!("sig_{}", position_address),
+            pool_address: "pool".to_string(),
+            block_time: 0,
+            block_time_utc: Utc::now(),
+            transaction_type: if is_increase {
+                "IncreaseLiquidity".to_string()
+            } else {
+                "DecreaseLiquidity".to_string()
+            },
+            ready_for_backtesting: true,
+            data: TransactionData::from_liquidity_data(data, is_increase),
+        }
+    }
+
+    fn live_position(address: &str, liquidity: u128) -> LivePositionModel {
+        LivePositionModel {
+            address: address.to_string(),
+            liquidity,
+            tick_lower: -100,
+            tick_upper: 100,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_rewind_positions() {
+        let live_positions = vec![
+            live_position("kept", 1000),
+            live_position("opened_after", 500),
+        ];
+
+        let liquidity_txs = vec![
+            liquidity_tx("opened_after", true, 500),
+            liquidity_tx("kept", true, 200),
+            liquidity_tx("closed_after", false, 700),
+        ];
+
+        let as_of = Utc::now();
+        let mut positions = rewind_positions(live_positions, &liquidity_txs, as_of);
+        positions.sort_by(|a, b| a.address.cmp(&b.address));
+
+        assert_eq!(positions.len(), 2);
+        assert_eq!(positions[0].address, "closed_after");
+        assert_eq!(positions[0].liquidity, 700);
+        assert_eq!(positions[1].address, "kept");
+        assert_eq!(positions[1].liquidity, 800);
+        assert!(positions.iter().all(|p| p.created_at == as_of));
+    }
+
+    #[test]
+    fn test_rewind_positions_above_i128_max() {
+        let deep_liquidity = (1_u128 << 127) + 5;
+
+        let live_positions = vec![live_position("deep", deep_liquidity)];
+        let liquidity_txs = vec![liquidity_tx("deep", true, 5)];
+
+        let positions = rewind_positions(live_positions, &liquidity_txs, Utc::now());
+
+        assert_eq!(positions.len(), 1);
+        assert_eq!(positions[0].liquidity, 1_u128 << 127);
+    }
 }
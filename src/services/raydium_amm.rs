@@ -1,6 +1,6 @@
 use crate::api::transactions_api::{SignatureInfo, TransactionApi};
 use crate::models::transactions_model::TransactionModel;
-use crate::repositories::transactions_repo::TransactionRepo;
+use crate::repositories::transactions_repo::{TransactionRepo, TransactionSyncRepoTrait};
 use crate::services::transactions_sync_amm_service::{constants, AMMService};
 use crate::utils::transaction_utils::retry_with_backoff;
 use anyhow::{anyhow, Result};
@@ -55,7 +55,7 @@ impl RaydiumAMM {
 
 #[async_trait]
 impl AMMService for RaydiumAMM {
-    fn repo(&self) -> &TransactionRepo {
+    fn repo(&self) -> &dyn TransactionSyncRepoTrait {
         &self.transaction_repo
     }
 
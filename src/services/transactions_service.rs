@@ -2,7 +2,7 @@ use std::collections::{HashMap, HashSet};
 
 use crate::api::transactions_api::{SignatureInfo, TransactionApi};
 use crate::models::positions_model::ClosedPositionModel;
-use crate::models::transactions_model::{TransactionData, TransactionModelFromDB};
+use crate::models::transactions_model::{LiquidityData, TransactionData, TransactionModelFromDB};
 use crate::repositories::{positions_repo::PositionsRepo, transactions_repo::TransactionRepo};
 use crate::utils::decode::{
     decode_open_position_data, decode_open_position_with_metadata_data,
@@ -22,6 +22,8 @@ pub struct TransactionsService {
     tx_repo: TransactionRepo,
     tx_api: TransactionApi,
     positions_repo: PositionsRepo,
+    // Transaction batches requested at once.
+    rpc_concurrency: usize,
 }
 
 #[derive(Debug)]
@@ -35,11 +37,13 @@ impl TransactionsService {
         tx_repo: TransactionRepo,
         tx_api: TransactionApi,
         positions_repo: PositionsRepo,
+        rpc_concurrency: usize,
     ) -> Self {
         Self {
             tx_repo,
             tx_api,
             positions_repo,
+            rpc_concurrency: rpc_concurrency.max(1),
         }
     }
 
@@ -133,7 +137,7 @@ impl TransactionsService {
             });
 
             let all_tx_data: Vec<Value> = stream::iter(fetch_futures)
-                .buffer_unordered(3)
+                .buffer_unordered(self.rpc_concurrency)
                 .flat_map(|result| stream::iter(result.unwrap_or_default()))
                 .collect()
                 .await;
@@ -173,15 +177,12 @@ impl TransactionsService {
                 .as_array()
                 .ok_or_else(|| anyhow!("Instructions not found in transaction data"))?;
 
-            let has_open_position = log_messages.iter().any(|msg| {
-                msg.as_str()
-                    .map_or(false, |s| s == "Program log: Instruction: OpenPosition")
-            });
+            let has_open_position = log_messages
+                .iter()
+                .any(|msg| msg.as_str() == Some("Program log: Instruction: OpenPosition"));
 
             let has_open_position_with_metadata = log_messages.iter().any(|msg| {
-                msg.as_str().map_or(false, |s| {
-                    s == "Program log: Instruction: OpenPositionWithMetadata"
-                })
+                msg.as_str() == Some("Program log: Instruction: OpenPositionWithMetadata")
             });
 
             let discriminant = if is_hawksight_tx {
@@ -367,8 +368,11 @@ impl TransactionsService {
 
                     let mut updated_data = liquidity_data.clone();
 
-                    if let Some(position) = position_map.get(&liquidity_data.position_address) {
+                    if let Some((position_address, position)) =
+                        resolve_position(liquidity_data, &position_map)
+                    {
                         // Update tick_lower and tick_upper if the position is found
+                        updated_data.position_address = position_address;
                         updated_data.tick_lower = Some(position.tick_lower);
                         updated_data.tick_upper = Some(position.tick_upper);
                     }
@@ -401,3 +405,71 @@ impl TransactionsService {
         Ok(())
     }
 }
+
+// Tries position_address first, then each of the possible_positions, and picks the first one that is a known position with a valid tick range.
+fn resolve_position<'a>(
+    liquidity_data: &LiquidityData,
+    position_map: &'a HashMap<String, PositionData>,
+) -> Option<(String, &'a PositionData)> {
+    std::iter::once(&liquidity_data.position_address)
+        .chain(liquidity_data.possible_positions.iter())
+        .map(|address| address.trim_matches('"'))
+        .find_map(|address| {
+            position_map
+                .get(address)
+                .filter(|position| position.tick_lower < position.tick_upper)
+                .map(|position| (address.to_string(), position))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn liquidity_data(position_address: &str, possible_positions: Vec<&str>) -> LiquidityData {
+        LiquidityData {
+            token_a: "TokenA".to_string(),
+            token_b: "TokenB".to_string(),
+            amount_a: 100,
+            amount_b: 100,
+            liquidity_amount: "1000".to_string(),
+            tick_lower: None,
+            tick_upper: None,
+            position_address: position_address.to_string(),
+            possible_positions: possible_positions.into_iter().map(String::from).collect(),
+        }
+    }
+
+    #[test]
+    fn test_resolve_position_falls_back_to_possible_positions() {
+        let mut position_map = HashMap::new();
+        // A known account with a broken range should be skipped.
+        position_map.insert(
+            "BrokenPosition".to_string(),
+            PositionData {
+                tick_lower: 100,
+                tick_upper: 100,
+            },
+        );
+        position_map.insert(
+            "RealPosition".to_string(),
+            PositionData {
+                tick_lower: -200,
+                tick_upper: 200,
+            },
+        );
+
+        // account keys come with JSON quotes from extract_common_data.
+        let ambiguous = liquidity_data(
+            "\"WhirlpoolProgram\"",
+            vec!["\"BrokenPosition\"", "\"RealPosition\"", "\"TokenVault\""],
+        );
+
+        let (address, position) = resolve_position(&ambiguous, &position_map).unwrap();
+        assert_eq!(address, "RealPosition");
+        assert_eq!((position.tick_lower, position.tick_upper), (-200, 200));
+
+        let unknown = liquidity_data("Unknown", vec!["AlsoUnknown"]);
+        assert!(resolve_position(&unknown, &position_map).is_none());
+    }
+}
@@ -1,14 +1,19 @@
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
 use chrono::{DateTime, Utc};
-use constants::ORCA_OPTIMIZED_PATH_BASE_URL;
+use constants::{
+    DEFAULT_ARCHIVE_CONCURRENCY, DEFAULT_RPC_CONCURRENCY, ORCA_OPTIMIZED_PATH_BASE_URL,
+};
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::Duration;
 
 use crate::{
-    api::transactions_api::TransactionApi, config::SyncMode,
-    models::transactions_model::TransactionModel, repositories::transactions_repo::TransactionRepo,
+    api::{pool_api::PoolApi, transactions_api::TransactionApi},
+    config::SyncMode,
+    models::transactions_model::TransactionModel,
+    repositories::transactions_repo::{TransactionRepo, TransactionSyncRepoTrait},
 };
 
 use super::{
@@ -23,13 +28,81 @@ pub mod constants {
     pub const SIGNATURE_BATCH_SIZE: u32 = 1000;
     pub const TX_BATCH_SIZE: usize = 25;
     pub const ORCA_OPTIMIZED_PATH_BASE_URL: &str = "https://whirlpool-replay.pleiades.dev/alpha";
+    // Daily archive files fetched at once by the optimized Orca sync (ARCHIVE_CONCURRENCY overrides it).
+    pub const DEFAULT_ARCHIVE_CONCURRENCY: usize = 4;
+    // Transaction batches requested from the rpc at once by the standard Orca sync and the position sync
+    // (RPC_CONCURRENCY overrides it).
+    pub const DEFAULT_RPC_CONCURRENCY: usize = 3;
+    // Owner programs of the pool accounts, used to detect the platform of a pool address.
+    pub const ORCA_WHIRLPOOL_PROGRAM_ID: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+    pub const RAYDIUM_CLMM_PROGRAM_ID: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+    pub const METEORA_DLMM_PROGRAM_ID: &str = "LBUZKhRxPF3XUpBCjp4YzTDA8ZhkuVGmHkq8UUqZVfD";
 }
 
-// Platforms supported.
-#[derive(Debug, Clone, Copy)]
+// Platforms supported. Meteora is only detected so far, there is no service to sync it.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum AMMPlatforms {
     Orca,
     Raydium,
+    Meteora,
+}
+
+// Orca's fee tiers as (tick_spacing, fee_rate), the fee rate in hundredths of a bp like the pool row.
+const ORCA_FEE_TIERS: &[(i16, i16)] = &[
+    (1, 100),
+    (2, 200),
+    (4, 400),
+    (8, 500),
+    (16, 1600),
+    (64, 3000),
+    (96, 6500),
+    (128, 10000),
+    (256, 20000),
+];
+
+impl AMMPlatforms {
+    // The (tick_spacing, fee_rate) pairs pools are created with. None when there's no table for the platform.
+    pub fn known_fee_tiers(&self) -> Option<&'static [(i16, i16)]> {
+        match self {
+            AMMPlatforms::Orca => Some(ORCA_FEE_TIERS),
+            AMMPlatforms::Raydium | AMMPlatforms::Meteora => None,
+        }
+    }
+
+    pub fn from_program_id(program_id: &str) -> Result<Self> {
+        match program_id {
+            constants::ORCA_WHIRLPOOL_PROGRAM_ID => Ok(AMMPlatforms::Orca),
+            constants::RAYDIUM_CLMM_PROGRAM_ID => Ok(AMMPlatforms::Raydium),
+            constants::METEORA_DLMM_PROGRAM_ID => Ok(AMMPlatforms::Meteora),
+            _ => Err(anyhow!(
+                "Pool account is owned by {}, which isn't a known CLMM program",
+                program_id
+            )),
+        }
+    }
+}
+
+// Platform from the owner program of the pool account.
+pub async fn detect_platform(pool_api: &PoolApi, pool_address: &str) -> Result<AMMPlatforms> {
+    let owner = pool_api.fetch_account_owner(pool_address).await?;
+
+    AMMPlatforms::from_program_id(&owner)
+}
+
+// POOL_PLATFORM when set (explicit override), otherwise detected from chain.
+pub async fn resolve_platform(pool_api: &PoolApi, pool_address: &str) -> Result<AMMPlatforms> {
+    match std::env::var("POOL_PLATFORM") {
+        Ok(platform) => platform.parse::<AMMPlatforms>(),
+        Err(_) => {
+            let platform = detect_platform(pool_api, pool_address).await?;
+            println!(
+                "POOL_PLATFORM not set, detected {} from the pool account",
+                platform
+            );
+
+            Ok(platform)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -38,9 +111,70 @@ pub enum Cursor {
     OptionalSignature(Option<String>),
 }
 
+// Read-only estimate of what a sync would pull, built from the first fetched batch. Used by `sync --dry-run`.
+#[derive(Debug, Clone)]
+pub struct SyncPreview {
+    pub range_start: DateTime<Utc>,
+    pub range_end: DateTime<Utc>,
+    pub first_batch_transactions: usize,
+    pub first_batch_start: Option<DateTime<Utc>>,
+    pub first_batch_end: Option<DateTime<Utc>>,
+}
+
+impl SyncPreview {
+    pub fn range_days(&self) -> f64 {
+        (self.range_end - self.range_start).num_seconds() as f64 / 86_400.0
+    }
+
+    // Extrapolates the first batch's tx rate over the whole range. None if the batch doesnt span any time.
+    pub fn projected_transactions(&self) -> Option<u64> {
+        let batch_seconds = (self.first_batch_end? - self.first_batch_start?).num_seconds();
+        if batch_seconds <= 0 {
+            return None;
+        }
+
+        let range_seconds = (self.range_end - self.range_start).num_seconds().max(0);
+        let rate = self.first_batch_transactions as f64 / batch_seconds as f64;
+
+        Some((rate * range_seconds as f64).round() as u64)
+    }
+}
+
+// Transactions a sync added to the db, per transaction_type.
+#[derive(Debug, Clone, Default)]
+pub struct SyncSummary {
+    pub new_by_type: BTreeMap<String, i64>,
+}
+
+impl SyncSummary {
+    // Stored counts per type before and after the sync, types missing before count from 0.
+    pub fn between(before: &BTreeMap<String, i64>, after: &BTreeMap<String, i64>) -> Self {
+        let new_by_type = after
+            .iter()
+            .map(|(transaction_type, count)| {
+                (
+                    transaction_type.clone(),
+                    count - before.get(transaction_type).copied().unwrap_or(0),
+                )
+            })
+            .filter(|(_, new)| *new != 0)
+            .collect();
+
+        Self { new_by_type }
+    }
+
+    pub fn count(&self, transaction_type: &str) -> i64 {
+        self.new_by_type.get(transaction_type).copied().unwrap_or(0)
+    }
+
+    pub fn total(&self) -> i64 {
+        self.new_by_type.values().sum()
+    }
+}
+
 #[async_trait]
 pub trait AMMService: Send + Sync {
-    fn repo(&self) -> &TransactionRepo;
+    fn repo(&self) -> &dyn TransactionSyncRepoTrait;
     fn api(&self) -> &TransactionApi;
 
     async fn fetch_transactions(&self, pool_address: &str, cursor: Cursor) -> Result<Vec<Value>>;
@@ -58,6 +192,11 @@ pub trait AMMService: Send + Sync {
         latest_db_transaction: Option<TransactionModel>,
     ) -> Result<()>;
 
+    // Cursor pointing at the most recent batch, which is what a dry run samples.
+    fn latest_cursor(&self) -> Cursor {
+        Cursor::OptionalSignature(None)
+    }
+
     async fn insert_transactions(&self, transactions: Vec<TransactionModel>) -> Result<()> {
         match self.repo().insert(&transactions).await {
             Ok(count) => {
@@ -71,20 +210,97 @@ pub trait AMMService: Send + Sync {
         }
     }
 
+    // Returns how many transactions of each type were new to the db (already stored ones aren't inserted twice).
     async fn sync_transactions(
         &self,
         pool_address: &str,
         start_time: DateTime<Utc>,
         sync_mode: SyncMode,
-    ) -> Result<()> {
+    ) -> Result<SyncSummary> {
+        let counts_before = self.repo().count_transactions_by_type(pool_address).await?;
+        let count_before: i64 = counts_before.values().sum();
+
         match sync_mode {
             // Update sync uses the most recent transaction from db and updates from the current time to that transaction.
-            SyncMode::Update => self.update_sync(pool_address).await,
+            SyncMode::Update => self.update_sync(pool_address).await?,
             // Historical sync uses the oldest transaction from db and updates from the "start time" passed in until it reaches that transaction.
-            SyncMode::Historical => self.historical_sync(pool_address, start_time).await,
+            SyncMode::Historical => self.historical_sync(pool_address, start_time).await?,
             // Ignores all transactions in db and just full syncs from start_time to end_time.
-            SyncMode::FullRange => self.full_range_sync(pool_address, start_time).await,
+            SyncMode::FullRange => self.full_range_sync(pool_address, start_time).await?,
+            // Only the transactions newer than the latest stored one, so rerunning it doesn't re-walk the whole window.
+            SyncMode::Incremental => {
+                if count_before > 0 {
+                    println!("Pool already synced, only fetching transactions newer than the latest stored one.");
+                    self.update_sync(pool_address).await?
+                } else {
+                    self.full_range_sync(pool_address, start_time).await?
+                }
+            }
         }
+
+        let counts_after = self.repo().count_transactions_by_type(pool_address).await?;
+
+        Ok(SyncSummary::between(&counts_before, &counts_after))
+    }
+
+    // Resolves the range sync_transactions would cover and samples the latest batch, without writing anything to the db.
+    async fn preview_sync(
+        &self,
+        pool_address: &str,
+        start_time: DateTime<Utc>,
+        sync_mode: SyncMode,
+    ) -> Result<SyncPreview> {
+        let now = Utc::now();
+
+        let sync_mode = match sync_mode {
+            SyncMode::Incremental => {
+                if self.repo().count_transactions(pool_address).await? > 0 {
+                    SyncMode::Update
+                } else {
+                    SyncMode::FullRange
+                }
+            }
+            sync_mode => sync_mode,
+        };
+
+        let (range_start, range_end) = match sync_mode {
+            SyncMode::Update => {
+                let highest_block_tx = self
+                    .repo()
+                    .fetch_highest_block_time_transaction(pool_address)
+                    .await?
+                    .ok_or_else(|| anyhow!("No existing transactions found for update sync"))?;
+
+                (highest_block_tx.block_time_utc, now)
+            }
+            SyncMode::Historical => {
+                let lowest_block_tx = self
+                    .repo()
+                    .fetch_lowest_block_time_transaction(pool_address)
+                    .await?
+                    .ok_or_else(|| anyhow!("No existing transactions found for historical sync"))?
+                    .transform_to_tx_model();
+
+                (
+                    lowest_block_tx.block_time_utc - (now - start_time),
+                    lowest_block_tx.block_time_utc,
+                )
+            }
+            SyncMode::FullRange | SyncMode::Incremental => (start_time, now),
+        };
+
+        let tx_data = self
+            .fetch_transactions(pool_address, self.latest_cursor())
+            .await?;
+        let transactions = self.convert_data_to_transactions_model(pool_address, tx_data)?;
+
+        Ok(SyncPreview {
+            range_start,
+            range_end,
+            first_batch_transactions: transactions.len(),
+            first_batch_start: transactions.iter().map(|tx| tx.block_time_utc).min(),
+            first_batch_end: transactions.iter().map(|tx| tx.block_time_utc).max(),
+        })
     }
 
     async fn update_sync(&self, pool_address: &str) -> Result<()> {
@@ -132,6 +348,18 @@ pub trait AMMService: Send + Sync {
     }
 }
 
+// RPC_CONCURRENCY, how many transaction batches are requested at once. Lower it when the rpc rate limits the sync.
+pub fn rpc_concurrency() -> Result<usize> {
+    match std::env::var("RPC_CONCURRENCY") {
+        Ok(value) => value
+            .parse::<usize>()
+            .ok()
+            .filter(|concurrency| *concurrency > 0)
+            .ok_or_else(|| anyhow!("RPC_CONCURRENCY must be a positive number")),
+        Err(_) => Ok(DEFAULT_RPC_CONCURRENCY),
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
 pub async fn create_amm_service(
     platform: AMMPlatforms,
@@ -150,6 +378,8 @@ pub async fn create_amm_service(
                 .unwrap_or_else(|_| "FALSE".to_string())
                 .to_uppercase();
 
+            let rpc_concurrency = rpc_concurrency()?;
+
             if feature_flag != "TRUE" {
                 return Ok(Arc::new(
                     OrcaStandardAMM::new(
@@ -159,6 +389,7 @@ pub async fn create_amm_service(
                         String::from(token_b_address),
                         token_a_decimals,
                         token_b_decimals,
+                        rpc_concurrency,
                     )
                     .await,
                 ));
@@ -179,6 +410,13 @@ pub async fn create_amm_service(
 
             if let Ok(resp) = response {
                 if resp.status().is_success() {
+                    let archive_concurrency = match std::env::var("ARCHIVE_CONCURRENCY") {
+                        Ok(value) => value
+                            .parse::<usize>()
+                            .context("ARCHIVE_CONCURRENCY must be a positive number")?,
+                        Err(_) => DEFAULT_ARCHIVE_CONCURRENCY,
+                    };
+
                     return Ok(Arc::new(
                         OrcaOptimizedAMM::new(
                             transaction_repo,
@@ -187,6 +425,7 @@ pub async fn create_amm_service(
                             String::from(token_b_address),
                             String::from(token_a_vault),
                             String::from(token_b_vault),
+                            archive_concurrency,
                         )
                         .await,
                     ));
@@ -202,6 +441,7 @@ pub async fn create_amm_service(
                     String::from(token_b_address),
                     token_a_decimals,
                     token_b_decimals,
+                    rpc_concurrency,
                 )
                 .await,
             ))
@@ -212,5 +452,52 @@ pub async fn create_amm_service(
             String::from(token_a_address),
             String::from(token_b_address),
         ))),
+        AMMPlatforms::Meteora => Err(anyhow!("Meteora pools can't be synced yet")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_platform_from_program_id() {
+        assert_eq!(
+            AMMPlatforms::from_program_id(constants::ORCA_WHIRLPOOL_PROGRAM_ID).unwrap(),
+            AMMPlatforms::Orca
+        );
+        assert_eq!(
+            AMMPlatforms::from_program_id(constants::RAYDIUM_CLMM_PROGRAM_ID).unwrap(),
+            AMMPlatforms::Raydium
+        );
+        assert_eq!(
+            AMMPlatforms::from_program_id(constants::METEORA_DLMM_PROGRAM_ID).unwrap(),
+            AMMPlatforms::Meteora
+        );
+        assert!(AMMPlatforms::from_program_id("11111111111111111111111111111111").is_err());
+    }
+
+    #[test]
+    fn test_sync_preview_projects_first_batch_rate() {
+        let range_end = Utc::now();
+        let preview = SyncPreview {
+            range_start: range_end - Duration::days(10),
+            range_end,
+            first_batch_transactions: 500,
+            first_batch_start: Some(range_end - Duration::hours(12)),
+            first_batch_end: Some(range_end),
+        };
+
+        assert_eq!(preview.range_days(), 10.0);
+        assert_eq!(preview.projected_transactions(), Some(10_000));
+
+        let empty_batch = SyncPreview {
+            first_batch_transactions: 0,
+            first_batch_start: None,
+            first_batch_end: None,
+            ..preview
+        };
+        assert_eq!(empty_batch.projected_transactions(), None);
     }
 }
@@ -1,18 +1,133 @@
-use uint::construct_uint;
+// The macro expansion trips a couple of clippy lints we can't fix from here.
+#[allow(clippy::manual_div_ceil, clippy::assign_op_pattern)]
+mod u256 {
+    use uint::construct_uint;
 
-construct_uint! {
-    pub struct U256(4);
+    construct_uint! {
+        pub struct U256(4);
+    }
+
+    // Only for intermediate products in mul_div.
+    construct_uint! {
+        pub struct U512(8);
+    }
+}
+
+pub use u256::U256;
+use u256::U512;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    hash::{BuildHasherDefault, Hasher},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+// As a decimal string, JSON numbers can't hold 256 bits.
+impl Serialize for U256 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for U256 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        U256::from_dec_str(&value).map_err(|e| de::Error::custom(format!("{:?}", e)))
+    }
 }
 
 pub const Q64: U256 = U256([0, 1, 0, 0]);
 pub const Q128: U256 = U256([0, 0, 1, 0]);
 
-// WORKS WITHIN A REASONABLE LIMIT. TESTED AGAINST LIVE STUFF.
+// Orca whirlpool tick bounds. No position or price can live outside of these.
+pub const MIN_TICK: i32 = -443_636;
+pub const MAX_TICK: i32 = 443_636;
+
+// Ticks whose sqrt price is kept (TICK_PRICE_CACHE_SIZE). A replay keeps hitting the same few (the cached initialized
+// ticks, position bounds), a pool's whole range fits well under the default. Past the limit new ticks are computed only.
+pub const DEFAULT_TICK_PRICE_CACHE_SIZE: usize = 100_000;
+
+static TICK_PRICE_CACHE_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_TICK_PRICE_CACHE_SIZE);
+
+// One cache per thread, a shared lock costs about as much as the powf it saves. The default SipHash does too, tick keys
+// only need a multiply to spread.
+#[derive(Default)]
+struct TickHasher(u64);
+
+impl Hasher for TickHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8 | *byte as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        }
+    }
+
+    fn write_i32(&mut self, tick: i32) {
+        self.0 = (tick as u32 as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    }
+}
+
+thread_local! {
+    static TICK_PRICE_CACHE: RefCell<HashMap<i32, U256, BuildHasherDefault<TickHasher>>> =
+        RefCell::new(HashMap::default());
+}
+
+// 0 turns the cache off. Caches over the new size start over on their next lookup.
+pub fn set_tick_price_cache_size(size: usize) {
+    TICK_PRICE_CACHE_SIZE.store(size, Ordering::Relaxed);
+}
+
+// Memoized, the result is the same as tick_to_sqrt_price_u256_uncached.
 pub fn tick_to_sqrt_price_u256(tick: i32) -> U256 {
+    let max_size = TICK_PRICE_CACHE_SIZE.load(Ordering::Relaxed);
+    if max_size == 0 {
+        return tick_to_sqrt_price_u256_uncached(tick);
+    }
+
+    TICK_PRICE_CACHE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if let Some(sqrt_price) = cache.get(&tick) {
+            return *sqrt_price;
+        }
+
+        if cache.len() > max_size {
+            cache.clear();
+        }
+
+        let sqrt_price = tick_to_sqrt_price_u256_uncached(tick);
+        if cache.len() < max_size {
+            cache.insert(tick, sqrt_price);
+        }
+
+        sqrt_price
+    })
+}
+
+// WORKS WITHIN A REASONABLE LIMIT. TESTED AGAINST LIVE STUFF.
+pub fn tick_to_sqrt_price_u256_uncached(tick: i32) -> U256 {
     let sqrt_price = (1.0001_f64.powf(tick as f64 / 2.0)) * (Q64.as_u128() as f64);
     U256::from(sqrt_price as u128)
 }
 
+// Goes through f64 limb by limb, as_u128 would panic on values past u128 and f64 can't keep more precision anyway.
+pub fn u256_to_f64(value: U256) -> f64 {
+    value
+        .0
+        .iter()
+        .rev()
+        .fold(0.0, |acc, limb| acc * 2f64.powi(64) + *limb as f64)
+}
+
+// Token B per token A in raw units (no decimal adjustment). Through f64 since sqrt prices can be past u128.
+pub fn sqrt_price_to_price(sqrt_price: U256) -> f64 {
+    (u256_to_f64(sqrt_price) / 2f64.powi(64)).powi(2)
+}
+
 // WORKS GREAT. DO NOT TOUCH. ACCURATE. TESTED AGAINST LIVE SWAPS.
 pub fn price_to_tick(price: f64) -> i32 {
     let numerator = price.sqrt().ln();
@@ -134,28 +249,141 @@ pub fn calculate_amounts(
     }
 }
 
+// a * b / c rounded down once, with the product in 512 bits so it can't overflow.
+pub fn mul_div(a: U256, b: U256, c: U256) -> U256 {
+    mul_div_rounding(a, b, c, false)
+}
+
+// Same as mul_div but rounded up, for amounts that have to be enough rather than at most.
+pub fn mul_div_round_up(a: U256, b: U256, c: U256) -> U256 {
+    mul_div_rounding(a, b, c, true)
+}
+
+fn mul_div_rounding(a: U256, b: U256, c: U256, round_up: bool) -> U256 {
+    let to_u512 = |value: U256| {
+        let mut bytes = [0u8; 32];
+        value.to_little_endian(&mut bytes);
+        U512::from_little_endian(&bytes)
+    };
+
+    let product = to_u512(a) * to_u512(b);
+    let mut result = product / to_u512(c);
+    if round_up && !(product % to_u512(c)).is_zero() {
+        result += U512::one();
+    }
+
+    let mut bytes = [0u8; 64];
+    result.to_little_endian(&mut bytes);
+    assert!(
+        bytes[32..].iter().all(|byte| *byte == 0),
+        "mul_div result overflows U256"
+    );
+
+    U256::from_little_endian(&bytes[..32])
+}
+
+// Whether a and b are within bps basis points of each other, relative to the larger one. For comparing reconstructions
+// of the same value that are expected to drift a little (backwards vs forwards sync, replayed vs on-chain).
+pub fn within_tolerance(a: U256, b: U256, bps: u32) -> bool {
+    let larger = a.max(b);
+    a.abs_diff(b) <= mul_div(larger, U256::from(bps), U256::from(10_000))
+}
+
+// Inverse of calculate_liquidity: the token amounts a position needs at the current price to hold target_liquidity.
+// Rounded up, so calculate_liquidity on the result gives back at least the target.
+pub fn amounts_for_target_liquidity(
+    target_liquidity: U256,
+    current_sqrt_price: U256,
+    lower_sqrt_price: U256,
+    upper_sqrt_price: U256,
+) -> (U256, U256) {
+    // Outside the range only one token is needed, same as calculate_amounts.
+    let sqrt_price = current_sqrt_price.clamp(lower_sqrt_price, upper_sqrt_price);
+
+    let amount_a = mul_div_round_up(
+        target_liquidity << 64,
+        upper_sqrt_price - sqrt_price,
+        sqrt_price * upper_sqrt_price,
+    );
+    let amount_b = mul_div_round_up(target_liquidity, sqrt_price - lower_sqrt_price, Q64);
+
+    (amount_a, amount_b)
+}
+
+// Split of total_value_in_b (raw token B, token A valued at the current price) that deploys fully into the range, so
+// opening it needs no rebalance swap. Inverts calculate_liquidity/calculate_amounts: per unit of liquidity the range holds
+// a = (sqrtP_upper - sqrtP) * Q64 / (sqrtP * sqrtP_upper) and b = (sqrtP - sqrtP_lower) / Q64, worth
+// (sqrtP * (sqrtP_upper - sqrtP) / sqrtP_upper + sqrtP - sqrtP_lower) / Q64 in B. Rounded down, so it never needs more
+// than total_value_in_b.
+pub fn optimal_entry_amounts(
+    total_value_in_b: U256,
+    current_sqrt_price: U256,
+    lower_sqrt_price: U256,
+    upper_sqrt_price: U256,
+) -> (U256, U256) {
+    // Outside the range it's all one token.
+    if current_sqrt_price <= lower_sqrt_price {
+        let amount_a = mul_div(
+            mul_div(total_value_in_b, Q64, current_sqrt_price),
+            Q64,
+            current_sqrt_price,
+        );
+        return (amount_a, U256::zero());
+    }
+    if current_sqrt_price >= upper_sqrt_price {
+        return (U256::zero(), total_value_in_b);
+    }
+
+    let value_per_liquidity_q64 = mul_div(
+        current_sqrt_price,
+        upper_sqrt_price - current_sqrt_price,
+        upper_sqrt_price,
+    ) + (current_sqrt_price - lower_sqrt_price);
+    let liquidity = mul_div(total_value_in_b, Q64, value_per_liquidity_q64);
+
+    calculate_amounts(
+        liquidity,
+        current_sqrt_price,
+        lower_sqrt_price,
+        upper_sqrt_price,
+    )
+}
+
+// Token amounts that move when the price goes between two sqrt prices (any order), in a single formula rounded down once.
+// Used for swap outputs instead of diffing two separately rounded calculate_amounts results, which drifts over many steps.
+// Δa = L * (sqrtP_upper - sqrtP_lower) * Q64 / (sqrtP_lower * sqrtP_upper)
+pub fn calculate_amount_a_delta(liquidity: U256, sqrt_price_1: U256, sqrt_price_2: U256) -> U256 {
+    let (lower, upper) = if sqrt_price_1 < sqrt_price_2 {
+        (sqrt_price_1, sqrt_price_2)
+    } else {
+        (sqrt_price_2, sqrt_price_1)
+    };
+
+    mul_div(liquidity << 64, upper - lower, lower * upper)
+}
+
+// Δb = L * (sqrtP_upper - sqrtP_lower) / Q64
+pub fn calculate_amount_b_delta(liquidity: U256, sqrt_price_1: U256, sqrt_price_2: U256) -> U256 {
+    mul_div(liquidity, sqrt_price_1.abs_diff(sqrt_price_2), Q64)
+}
+
 // General formulas:
 // amount_a changing: sqrt_P_new = (sqrt_P * L) / (L + Δx * sqrt_P)
 // amount_b changing: sqrt_P_new = sqrt_P + (Δy / L)
 pub fn calculate_new_sqrt_price(
     current_sqrt_price: U256,
     liquidity: U256,
-    amount_in: U256, 
+    amount_in: U256,
     is_sell: bool,
 ) -> U256 {
     if is_sell {
-        // sqrtP_new = (L * sqrtP_current) / (L + Δx * sqrtP_current)
-        let numerator = liquidity.checked_mul(current_sqrt_price).unwrap();
-        let denominator = liquidity
-            .checked_add(
-                amount_in
-                    .checked_mul(current_sqrt_price)
-                    .unwrap()
-                    .checked_div(Q64)
-                    .unwrap(),
-            )
+        // sqrtP_new = (L * sqrtP_current) / (L + Δx * sqrtP_current), both sides scaled by Q64 so Δx * sqrtP isn't rounded on its own.
+        // Rounded up like on-chain, a lower price would pay out token B the seller didn't pay for.
+        let liquidity_q64 = liquidity << 64;
+        let denominator = liquidity_q64
+            .checked_add(amount_in.checked_mul(current_sqrt_price).unwrap())
             .unwrap();
-        numerator.checked_div(denominator).unwrap()
+        mul_div_round_up(liquidity_q64, current_sqrt_price, denominator)
     } else {
         // sqrtP_new = sqrtP_current + (Δy * Q64) / L
         let delta = amount_in
@@ -167,11 +395,104 @@ pub fn calculate_new_sqrt_price(
     }
 }
 
+// FEE GROWTH SCALING CONVENTION:
+// The backtester keeps fee growth (global, outside, inside) per unit of liquidity scaled by Q128 to keep precision on tiny swaps.
+// Orca on-chain accounts store the same value as Q64.64 in a u128 that wraps on overflow (fee_growth_global_a/b on the whirlpool).
+// So reconstructed fee growth has 64 more fractional bits than the pool account, these helpers convert between the two for validation.
+pub fn fee_growth_to_onchain_q64(fee_growth_q128: U256) -> u128 {
+    // low_u128 mirrors the on-chain wrapping u128.
+    (fee_growth_q128 >> 64).low_u128()
+}
+
+pub fn fee_growth_from_onchain_q64(fee_growth_q64: u128) -> U256 {
+    U256::from(fee_growth_q64) << 64
+}
+
 // THE LIQUIDITY AND AMOUNTS CALCULATIONS ARE CHECKED ON SAME POSITIONS AGAINST EACH OTHER.
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_within_tolerance() {
+        let price = tick_to_sqrt_price_u256(-19_000);
+
+        assert!(within_tolerance(price, price, 0));
+        // 0.1% off either way is within 10 bps, 0.2% isn't.
+        assert!(within_tolerance(price, price + price / 1_000, 10));
+        assert!(within_tolerance(price - price / 1_000, price, 10));
+        assert!(!within_tolerance(price, price + price / 500, 10));
+        assert!(within_tolerance(U256::zero(), U256::zero(), 0));
+    }
+
+    #[test]
+    fn test_amounts_for_target_liquidity_round_trip() {
+        let current = tick_to_sqrt_price_u256(-19_000);
+        let lower = tick_to_sqrt_price_u256(-20_000);
+        let upper = tick_to_sqrt_price_u256(-18_000);
+        let target = U256::from(123_456_789_000_u128);
+
+        let (amount_a, amount_b) = amounts_for_target_liquidity(target, current, lower, upper);
+        let liquidity = calculate_liquidity(amount_a, amount_b, current, lower, upper);
+
+        // At least the target, and no more than a rounding unit's worth above it.
+        assert!(liquidity >= target);
+        assert!(liquidity - target < target / 1_000_000);
+
+        // Below the range it's all token A.
+        let (amount_a, amount_b) =
+            amounts_for_target_liquidity(target, tick_to_sqrt_price_u256(-21_000), lower, upper);
+        assert!(amount_b.is_zero());
+        assert_eq!(
+            amount_a,
+            mul_div_round_up(target << 64, upper - lower, lower * upper)
+        );
+    }
+
+    #[test]
+    fn test_optimal_entry_amounts_deploy_fully() {
+        let current = tick_to_sqrt_price_u256(-19_000);
+        let lower = tick_to_sqrt_price_u256(-20_000);
+        let upper = tick_to_sqrt_price_u256(-18_000);
+        let total_value_in_b = U256::from(5_000_000_000_u128);
+
+        let (amount_a, amount_b) = optimal_entry_amounts(total_value_in_b, current, lower, upper);
+
+        // Worth the whole value at the current price, give or take rounding.
+        let value =
+            amount_a.as_u128() as f64 * sqrt_price_to_price(current) + amount_b.as_u128() as f64;
+        assert!(value <= total_value_in_b.as_u128() as f64);
+        assert!((total_value_in_b.as_u128() as f64 - value) / value < 1e-6);
+
+        // Both tokens back the same liquidity, nothing is left over to rebalance.
+        let liquidity_a = calculate_liquidity_a(amount_a, current, upper);
+        let liquidity_b = calculate_liquidity_b(amount_b, lower, current);
+        assert!(liquidity_a.abs_diff(liquidity_b) < liquidity_b / 1_000_000);
+
+        // Outside the range it's all one token.
+        let below = tick_to_sqrt_price_u256(-21_000);
+        let (amount_a, amount_b) = optimal_entry_amounts(total_value_in_b, below, lower, upper);
+        assert!(amount_b.is_zero());
+        let value = amount_a.as_u128() as f64 * sqrt_price_to_price(below);
+        assert!((total_value_in_b.as_u128() as f64 - value) / value < 1e-6);
+
+        let above = tick_to_sqrt_price_u256(-17_000);
+        assert_eq!(
+            optimal_entry_amounts(total_value_in_b, above, lower, upper),
+            (U256::zero(), total_value_in_b)
+        );
+    }
+
+    #[test]
+    fn test_sqrt_price_to_price_past_u128() {
+        assert_eq!(sqrt_price_to_price(Q64), 1.0);
+        assert_eq!(sqrt_price_to_price(Q64 * 3), 9.0);
+
+        // 2^130 as a sqrt price, as_u128 would panic here.
+        let sqrt_price = U256::from(1) << 130;
+        assert_eq!(sqrt_price_to_price(sqrt_price), 2f64.powi(132));
+    }
+
     fn sqrt_price_to_u256(sqrt_price: f64) -> U256 {
         let scaled_sqrt_price = (sqrt_price * Q64.as_u128() as f64) as u128;
         U256::from(scaled_sqrt_price)
@@ -225,6 +546,21 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_cached_tick_to_sqrt_price_matches_uncached() {
+        // Twice over, the second pass is served from the cache.
+        for _ in 0..2 {
+            for tick in (-30_000..30_000).step_by(97).chain([MIN_TICK, 0, MAX_TICK]) {
+                assert_eq!(
+                    tick_to_sqrt_price_u256(tick),
+                    tick_to_sqrt_price_u256_uncached(tick),
+                    "tick {}",
+                    tick
+                );
+            }
+        }
+    }
+
     #[test]
     fn test_sqrt_price_to_tick() {
         // SOL_USDC
@@ -416,7 +752,7 @@ mod tests {
 
         assert_eq!(
             U256::from(4999),
-            U256::from(amount_a / 10_i32.pow(9)),
+            amount_a / 10_i32.pow(9),
             "amount_a match when below lower range"
         );
 
@@ -429,7 +765,7 @@ mod tests {
 
         assert_eq!(
             U256::from(815893),
-            U256::from(amount_b / 10_i32.pow(6)),
+            amount_b / 10_i32.pow(6),
             "amount_a match when below lower range"
         );
 
@@ -502,21 +838,38 @@ mod tests {
             upper_sqrt_price,
         );
 
-        let TOLERANCE = U256::from(2);
+        let tolerance = U256::from(2);
 
         // THE AMOUNTS COME OUT AS 999 FOR BOTH. KEEP IN MIND THESE CALCS WILL NEVER BE 100% precise, its same in the real world systems. GOOD ENOUGH THO.
         assert!(
-            (starting_amount_a - amount_a) <= TOLERANCE,
+            (starting_amount_a - amount_a) <= tolerance,
             "amount_a should be within tolerance. Expected: {}, Got: {}",
             starting_amount_a,
             amount_a
         );
 
         assert!(
-            (starting_amount_b - amount_b) <= TOLERANCE,
+            (starting_amount_b - amount_b) <= tolerance,
             "amount_b should be within tolerance. Expected: {}, Got: {}",
             starting_amount_b,
             amount_b
         );
     }
+
+    #[test]
+    fn test_fee_growth_onchain_round_trip() {
+        // 1.5 fee units per liquidity in Q64.64
+        let onchain_fee_growth: u128 = (3 << 64) / 2;
+
+        let fee_growth_q128 = fee_growth_from_onchain_q64(onchain_fee_growth);
+        assert_eq!(fee_growth_q128, (Q128 * U256::from(3)) / U256::from(2));
+        assert_eq!(
+            fee_growth_to_onchain_q64(fee_growth_q128),
+            onchain_fee_growth
+        );
+
+        // Extra Q128 precision below the Q64 resolution is dropped when going on-chain.
+        let with_dust = fee_growth_q128 + U256::from(12345);
+        assert_eq!(fee_growth_to_onchain_q64(with_dust), onchain_fee_growth);
+    }
 }
@@ -0,0 +1,251 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, NaiveDate, Utc};
+use std::collections::BTreeMap;
+use std::io::Write;
+
+use crate::{
+    backtester::liquidity_array::LiquidityArray,
+    repositories::transactions_repo::{OrderDirection, TransactionRepoTrait},
+};
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+// Pool activity of one UTC day, independent of any strategy. Volumes and fees are raw units of the token paid in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DailyStats {
+    pub day: NaiveDate,
+    pub swap_count: u64,
+    pub volume_a: u128,
+    pub volume_b: u128,
+    pub fees_a: u128,
+    pub fees_b: u128,
+    // Active liquidity weighted by how long it held during the day.
+    pub avg_active_liquidity: f64,
+}
+
+#[derive(Default)]
+struct DayBucket {
+    stats: DailyStats,
+    liquidity_seconds: f64,
+    seconds: i64,
+}
+
+// Replays every transaction in [start_time, end_time) on liquidity_arr (the pool state at start_time) and buckets them
+// by UTC day. Fees use the same fee_rate_at / per million math as simulate_swap.
+pub async fn aggregate_daily_stats<T: TransactionRepoTrait>(
+    transaction_repo: &T,
+    pool_address: &str,
+    token_a_address: &str,
+    liquidity_arr: &mut LiquidityArray,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<DailyStats>> {
+    let transactions = transaction_repo
+        .fetch_transactions_in_time_range(
+            pool_address,
+            start_time,
+            end_time,
+            OrderDirection::Ascending,
+        )
+        .await?;
+
+    let mut buckets: BTreeMap<NaiveDate, DayBucket> = BTreeMap::new();
+    let mut last_time = start_time.timestamp();
+
+    for transaction in &transactions {
+        let block_time = transaction.block_time_utc.timestamp();
+        add_liquidity_time(&mut buckets, liquidity_arr, last_time, block_time);
+        last_time = block_time;
+
+        match transaction.transaction_type.as_str() {
+            "IncreaseLiquidity" | "DecreaseLiquidity" => {
+                let liquidity_data = transaction.data.to_liquidity_data()?;
+
+                if let (Some(lower), Some(upper), Ok(amount)) = (
+                    liquidity_data.tick_lower,
+                    liquidity_data.tick_upper,
+                    liquidity_data.liquidity_amount.parse::<u128>(),
+                ) {
+                    liquidity_arr.update_liquidity(
+                        lower,
+                        upper,
+                        amount,
+                        transaction.transaction_type == "IncreaseLiquidity",
+                    );
+                }
+            }
+            "Swap" => {
+                let swap_data = transaction.data.to_swap_data()?;
+                let is_sell = swap_data.token_in == token_a_address;
+
+                liquidity_arr.apply_fee_rate_at(transaction.block_time);
+                let fee = swap_data.amount_in as u128 * liquidity_arr.fee_rate as u128 / 1_000_000;
+
+                let stats = &mut day_bucket(&mut buckets, block_time).stats;
+                stats.swap_count += 1;
+                if is_sell {
+                    stats.volume_a += swap_data.amount_in as u128;
+                    stats.fees_a += fee;
+                } else {
+                    stats.volume_b += swap_data.amount_in as u128;
+                    stats.fees_b += fee;
+                }
+
+                liquidity_arr
+                    .simulate_swap(swap_data.amount_in.into(), is_sell)
+                    .context(format!("Failed to replay swap {}", transaction.signature))?;
+            }
+            _ => {}
+        }
+    }
+
+    add_liquidity_time(&mut buckets, liquidity_arr, last_time, end_time.timestamp());
+
+    Ok(buckets
+        .into_values()
+        .map(|bucket| {
+            let mut stats = bucket.stats;
+            if bucket.seconds > 0 {
+                stats.avg_active_liquidity = bucket.liquidity_seconds / bucket.seconds as f64;
+            }
+            stats
+        })
+        .collect())
+}
+
+fn day_bucket(buckets: &mut BTreeMap<NaiveDate, DayBucket>, timestamp: i64) -> &mut DayBucket {
+    let day = DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .date_naive();
+
+    buckets.entry(day).or_insert_with(|| DayBucket {
+        stats: DailyStats {
+            day,
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+// The active liquidity holds from one transaction to the next, split over the days the span covers.
+fn add_liquidity_time(
+    buckets: &mut BTreeMap<NaiveDate, DayBucket>,
+    liquidity_arr: &LiquidityArray,
+    from: i64,
+    to: i64,
+) {
+    let liquidity = liquidity_arr.active_liquidity.as_u128() as f64;
+    let mut time = from;
+
+    while time < to {
+        let day_end = (time.div_euclid(SECONDS_PER_DAY) + 1) * SECONDS_PER_DAY;
+        let span_end = day_end.min(to);
+
+        let bucket = day_bucket(buckets, time);
+        bucket.liquidity_seconds += liquidity * (span_end - time) as f64;
+        bucket.seconds += span_end - time;
+
+        time = span_end;
+    }
+}
+
+// One row per day, volumes and fees in whole tokens.
+pub fn write_daily_stats_csv<W: Write>(
+    daily_stats: &[DailyStats],
+    token_a_decimals: i16,
+    token_b_decimals: i16,
+    writer: &mut W,
+) -> Result<()> {
+    let scale_a = 10_f64.powi(token_a_decimals as i32);
+    let scale_b = 10_f64.powi(token_b_decimals as i32);
+
+    writeln!(
+        writer,
+        "day,swap_count,volume_a,volume_b,fees_a,fees_b,avg_active_liquidity"
+    )?;
+
+    for stats in daily_stats {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{:.0}",
+            stats.day,
+            stats.swap_count,
+            stats.volume_a as f64 / scale_a,
+            stats.volume_b as f64 / scale_b,
+            stats.fees_a as f64 / scale_a,
+            stats.fees_b as f64 / scale_b,
+            stats.avg_active_liquidity
+        )?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtester::test_helpers::{
+        create_test_liquidity_array, create_test_swap_transactions, InMemoryTransactionRepo,
+    };
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_daily_stats_split_at_utc_midnight() {
+        let midnight = Utc
+            .with_ymd_and_hms(2024, 8, 2, 0, 0, 0)
+            .unwrap()
+            .timestamp();
+
+        // Four swaps, two either side of midnight. Odd tx_ids sell token B, even ones sell token A.
+        let mut transactions = create_test_swap_transactions(4, 1_000_000, "TokenA", "TokenB");
+        for (transaction, offset) in transactions.iter_mut().zip([-20, -10, 10, 20]) {
+            transaction.block_time = midnight + offset;
+            transaction.block_time_utc = Utc.timestamp_opt(midnight + offset, 0).unwrap();
+        }
+        let repo = InMemoryTransactionRepo { transactions };
+
+        let mut liquidity_arr = create_test_liquidity_array(0);
+        let liquidity = liquidity_arr.active_liquidity.as_u128() as f64;
+
+        let daily_stats = aggregate_daily_stats(
+            &repo,
+            "test_pool",
+            "TokenA",
+            &mut liquidity_arr,
+            Utc.timestamp_opt(midnight - 30, 0).unwrap(),
+            Utc.timestamp_opt(midnight + 30, 0).unwrap(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(daily_stats.len(), 2);
+        assert_eq!(
+            daily_stats[0].day,
+            NaiveDate::from_ymd_opt(2024, 8, 1).unwrap()
+        );
+        assert_eq!(
+            daily_stats[1].day,
+            NaiveDate::from_ymd_opt(2024, 8, 2).unwrap()
+        );
+
+        for stats in &daily_stats {
+            assert_eq!(stats.swap_count, 2);
+            assert_eq!(stats.volume_a, 1_000_000);
+            assert_eq!(stats.volume_b, 1_000_000);
+            // fee rate 500 per million.
+            assert_eq!(stats.fees_a, 500);
+            assert_eq!(stats.fees_b, 500);
+            // The small swaps stay inside the initialized ticks, so the active liquidity never changes.
+            assert!((stats.avg_active_liquidity - liquidity).abs() < 1e-6);
+        }
+
+        let mut output = Vec::new();
+        write_daily_stats_csv(&daily_stats, 6, 6, &mut output).unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert!(lines[1].starts_with("2024-08-01,2,1,1,0.0005,0.0005,"));
+    }
+}
@@ -1,7 +1,7 @@
 use serde::ser::SerializeMap;
 use serde::{Deserialize, Serialize, Serializer};
 use std::fs::File;
-use std::io::Write;
+use std::io::{self, BufWriter, Write};
 
 #[derive(Clone)]
 pub enum FieldValue {
@@ -11,6 +11,7 @@ pub enum FieldValue {
     Float(f64),
 }
 
+#[derive(Default)]
 pub struct LogEntry {
     fields: Vec<(String, FieldValue)>,
 }
@@ -43,23 +44,193 @@ impl Serialize for LogEntry {
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelinePosition {
+    pub position_id: String,
+    pub lower_tick: i32,
+    pub upper_tick: i32,
+}
+
+// Where the price was relative to every open range at one point in time. Rendered as a heatmap of tick vs ranges.
+#[derive(Debug, Clone, Serialize)]
+pub struct TimelineSample {
+    pub current_block_time: i64,
+    pub current_swap_nmr: u128,
+    pub current_tick: i32,
+    // Share of the active liquidity held by the in-range positions (0.0 - 1.0).
+    pub liquidity_share: f64,
+    pub positions: Vec<TimelinePosition>,
+}
+
+// What a position was opened with and, once closed, what it returned. Kept in both modes (one per position, so small),
+// the profit calcs split the PnL into realized and unrealized from these.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionRecord {
+    pub position_id: String,
+    pub token_a_lped: u128,
+    pub token_b_lped: u128,
+    pub entry_price: f64,
+    // None while the position is still open.
+    pub exit: Option<PositionExit>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PositionExit {
+    pub token_a_returned: u128,
+    pub token_b_returned: u128,
+    pub fees_a: u128,
+    pub fees_b: u128,
+    pub exit_price: f64,
+    // Closed by on_window_end, the position was still open when the window ran out.
+    pub is_final_close: bool,
+}
+
+// Every record (entries and timeline samples) carries current_block_time and current_swap_nmr, so either can be the x-axis
+// of a plot. Solana blocks are irregular, so the two don't map linearly onto each other.
+// In memory (default) keeps every record until export_to_json. Streaming writes each record to the file as it is
+// logged, so long runs don't hold everything in memory.
+#[derive(Default)]
 pub struct DataLogger {
     entries: Vec<LogEntry>,
+    position_timeline: Vec<TimelineSample>,
+    position_records: Vec<PositionRecord>,
+    stream: Option<BufWriter<File>>,
+    // Records logged so far in either mode.
+    entry_count: usize,
+    timeline_sample_count: usize,
+    // First failed write while streaming. log can't return it, finish does.
+    stream_error: Option<io::Error>,
 }
 
 impl DataLogger {
     pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Newline delimited JSON, one {"entry": {...}} or {"position_timeline": {...}} per line in the order they were logged.
+    pub fn new_streaming(path: &str) -> io::Result<Self> {
+        Ok(Self {
+            stream: Some(BufWriter::new(File::create(path)?)),
+            ..Self::default()
+        })
+    }
+
+    // Picks up the position records of a saved backtest, the entries and timeline samples stay in the earlier run's export.
+    pub fn resumed(position_records: Vec<PositionRecord>) -> Self {
         Self {
-            entries: Vec::new(),
+            position_records,
+            ..Self::default()
         }
     }
 
+    // Same, from another logger. For swapping in a fresh (e.g. streaming) logger on a resumed backtest.
+    pub fn carry_over(&mut self, previous: &DataLogger) {
+        self.position_records = previous.position_records.clone();
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn entry_count(&self) -> usize {
+        self.entry_count
+    }
+
+    pub fn timeline_sample_count(&self) -> usize {
+        self.timeline_sample_count
+    }
+
     pub fn log(&mut self, entry: LogEntry) {
-        self.entries.push(entry);
+        self.entry_count += 1;
+
+        if self.is_streaming() {
+            self.write_record("entry", &entry);
+        } else {
+            self.entries.push(entry);
+        }
+    }
+
+    // Empty while streaming, the samples are only in the file.
+    pub fn entries(&self) -> &[LogEntry] {
+        &self.entries
+    }
+
+    pub fn position_timeline(&self) -> &[TimelineSample] {
+        &self.position_timeline
+    }
+
+    pub fn position_records(&self) -> &[PositionRecord] {
+        &self.position_records
+    }
+
+    pub fn log_timeline_sample(
+        &mut self,
+        current_block_time: i64,
+        current_swap_nmr: u128,
+        current_tick: i32,
+        liquidity_share: f64,
+        positions: Vec<TimelinePosition>,
+    ) {
+        self.timeline_sample_count += 1;
+
+        let sample = TimelineSample {
+            current_block_time,
+            current_swap_nmr,
+            current_tick,
+            liquidity_share,
+            positions,
+        };
+
+        if self.is_streaming() {
+            self.write_record("position_timeline", &sample);
+        } else {
+            self.position_timeline.push(sample);
+        }
+    }
+
+    fn write_record<T: Serialize>(&mut self, kind: &str, record: &T) {
+        let Some(stream) = self.stream.as_mut() else {
+            return;
+        };
+
+        if self.stream_error.is_some() {
+            return;
+        }
+
+        let result = serde_json::to_writer(&mut *stream, &serde_json::json!({ kind: record }))
+            .map_err(io::Error::from)
+            .and_then(|_| stream.write_all(b"\n"));
+
+        if let Err(e) = result {
+            self.stream_error = Some(e);
+        }
+    }
+
+    // Flushes the streaming file and reports the first write that failed. No-op in memory.
+    pub fn finish(&mut self) -> io::Result<()> {
+        if let Some(e) = self.stream_error.take() {
+            return Err(e);
+        }
+
+        match self.stream.as_mut() {
+            Some(stream) => stream.flush(),
+            None => Ok(()),
+        }
     }
 
+    // {"entries": [actions...], "position_timeline": [samples...]}. In memory mode only.
     pub fn export_to_json(&self, filename: &str) -> std::io::Result<()> {
-        let json_string = serde_json::to_string_pretty(&self.entries)?;
+        if self.is_streaming() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Streaming DataLogger already wrote its records, use finish()",
+            ));
+        }
+
+        let json_string = serde_json::to_string_pretty(&serde_json::json!({
+            "entries": self.entries,
+            "position_timeline": self.position_timeline,
+        }))?;
         let mut file = File::create(filename)?;
         file.write_all(json_string.as_bytes())?;
         Ok(())
@@ -82,7 +253,18 @@ impl DataLogger {
         current_token_a_volume: u128,
         current_token_b_volume: u128,
         current_active_liquidity: u128,
+        pct_token_a_deployed: f64,
+        pct_token_b_deployed: f64,
+        entry_price: f64,
     ) {
+        self.position_records.push(PositionRecord {
+            position_id: position_id.clone(),
+            token_a_lped,
+            token_b_lped,
+            entry_price,
+            exit: None,
+        });
+
         let mut entry = LogEntry::new();
         entry.add_field("action", "CreatePosition".to_string());
         entry.add_field("position_id", position_id);
@@ -99,6 +281,33 @@ impl DataLogger {
         entry.add_field("current_token_a_volume", current_token_a_volume);
         entry.add_field("current_token_b_volume", current_token_b_volume);
         entry.add_field("current_active_liquidity", current_active_liquidity);
+        entry.add_field("pct_token_a_deployed", pct_token_a_deployed);
+        entry.add_field("pct_token_b_deployed", pct_token_b_deployed);
+        entry.add_field("entry_price", entry_price);
+        self.log(entry);
+    }
+
+    // Logged after the CreatePosition of a position opened during the run. The entry carries the running totals (kept in
+    // SwappingData), so the churn cost of a strategy can be read off its last Rebalance entry.
+    pub fn log_rebalance(
+        &mut self,
+        position_id: String,
+        current_block_time: u128,
+        current_swap_nmr: u128,
+        (swap_cost_a, swap_cost_b): (u128, u128),
+        rebalance_count: u64,
+        (total_cost_a, total_cost_b): (u128, u128),
+    ) {
+        let mut entry = LogEntry::new();
+        entry.add_field("action", "Rebalance".to_string());
+        entry.add_field("position_id", position_id);
+        entry.add_field("current_block_time", current_block_time);
+        entry.add_field("current_swap_nmr", current_swap_nmr);
+        entry.add_field("swap_cost_a", swap_cost_a);
+        entry.add_field("swap_cost_b", swap_cost_b);
+        entry.add_field("rebalance_count", rebalance_count as u128);
+        entry.add_field("total_rebalance_cost_a", total_cost_a);
+        entry.add_field("total_rebalance_cost_b", total_cost_b);
         self.log(entry);
     }
 
@@ -122,8 +331,28 @@ impl DataLogger {
         swap_nmr_in_position: u128,
         token_a_volume_in_position: u128,
         token_b_volume_in_position: u128,
+        entry_price: f64,
+        exit_price: f64,
+        is_final_close: bool,
     ) {
         let mut entry = LogEntry::new();
+        // Position ids get reused after a close, the open record is the latest one with the id.
+        if let Some(record) = self
+            .position_records
+            .iter_mut()
+            .rev()
+            .find(|record| record.position_id == position_id && record.exit.is_none())
+        {
+            record.exit = Some(PositionExit {
+                token_a_returned,
+                token_b_returned,
+                fees_a,
+                fees_b,
+                exit_price,
+                is_final_close,
+            });
+        }
+
         entry.add_field("action", "ClosePosition".to_string());
         entry.add_field("position_id", position_id);
         entry.add_field("lower_tick", lower_tick as i64);
@@ -142,6 +371,22 @@ impl DataLogger {
         entry.add_field("swap_nmr_in_position", swap_nmr_in_position);
         entry.add_field("token_a_volume_in_position", token_a_volume_in_position);
         entry.add_field("token_b_volume_in_position", token_b_volume_in_position);
+        entry.add_field("entry_price", entry_price);
+        entry.add_field("exit_price", exit_price);
+        entry.add_field(
+            "fee_collection",
+            if is_final_close {
+                "at_end"
+            } else {
+                "during_run"
+            }
+            .to_string(),
+        );
+        // Closes from on_window_end are forced by the window running out, not decided by the strategy.
+        entry.add_field(
+            "close_reason",
+            if is_final_close { "forced" } else { "strategy" }.to_string(),
+        );
         self.log(entry);
     }
 }
@@ -170,3 +415,63 @@ impl From<f64> for FieldValue {
         FieldValue::Float(value)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufRead;
+
+    #[test]
+    fn test_streaming_logger_writes_ndjson() {
+        let path = std::env::temp_dir().join(format!(
+            "data_logger_stream_test_{}.jsonl",
+            std::process::id()
+        ));
+        let path_str = path.to_str().unwrap();
+
+        let mut logger = DataLogger::new_streaming(path_str).unwrap();
+
+        let mut entry = LogEntry::new();
+        entry.add_field("action", "CreatePosition".to_string());
+        entry.add_field("lower_tick", -100_i64);
+        logger.log(entry);
+        logger.log_timeline_sample(
+            1_700_000_000,
+            42,
+            5,
+            0.5,
+            vec![TimelinePosition {
+                position_id: "pos".to_string(),
+                lower_tick: -100,
+                upper_tick: 100,
+            }],
+        );
+        logger.finish().unwrap();
+
+        // Only the counters stay in memory.
+        assert_eq!(logger.entry_count(), 1);
+        assert_eq!(logger.timeline_sample_count(), 1);
+        assert!(logger.position_timeline().is_empty());
+        assert!(logger.export_to_json(path_str).is_err());
+
+        let lines: Vec<serde_json::Value> = io::BufReader::new(File::open(&path).unwrap())
+            .lines()
+            .map(|line| serde_json::from_str(&line.unwrap()).unwrap())
+            .collect();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["entry"]["action"], "CreatePosition");
+        assert_eq!(lines[0]["entry"]["lower_tick"], -100);
+        assert_eq!(lines[1]["position_timeline"]["current_tick"], 5);
+        assert_eq!(
+            lines[1]["position_timeline"]["current_block_time"],
+            1_700_000_000
+        );
+        assert_eq!(lines[1]["position_timeline"]["current_swap_nmr"], 42);
+        assert_eq!(
+            lines[1]["position_timeline"]["positions"][0]["position_id"],
+            "pos"
+        );
+    }
+}
@@ -6,8 +6,9 @@ use sha2::{Digest, Sha256};
 use std::fmt;
 use std::io::{Cursor, Read};
 
-use crate::models::pool_model::Whirlpool;
+use crate::models::pool_model::{PoolPrice, Whirlpool};
 use crate::models::positions_model::{Position, PositionRewardInfo};
+use crate::services::transactions_sync_amm_service::AMMPlatforms;
 
 // These are the first 8 bytes of each instruction's data, encoded in Base58
 pub const INCREASE_LIQUIDITY_DISCRIMINANT: &str = "3KLKPPgnNhb";
@@ -98,6 +99,35 @@ pub fn decode_whirlpool(data: &[u8]) -> Result<Whirlpool> {
     })
 }
 
+// Current sqrt price and tick of a pool account, laid out per platform.
+pub fn decode_pool_price(platform: AMMPlatforms, data: &[u8]) -> Result<PoolPrice> {
+    match platform {
+        AMMPlatforms::Orca => {
+            let whirlpool = decode_whirlpool(data)?;
+
+            Ok(PoolPrice {
+                sqrt_price: whirlpool.sqrt_price,
+                tick: whirlpool.tick_current_index,
+            })
+        }
+        AMMPlatforms::Raydium => {
+            let mut rdr = Cursor::new(data);
+
+            // Discriminator, bump, amm config, owner, both mints and vaults, observation key, both decimals, tick
+            // spacing and liquidity come before the price.
+            rdr.set_position(8 + 1 + 7 * 32 + 1 + 1 + 2 + 16);
+
+            Ok(PoolPrice {
+                sqrt_price: rdr.read_u128::<LittleEndian>()?,
+                tick: rdr.read_i32::<LittleEndian>()?,
+            })
+        }
+        AMMPlatforms::Meteora => Err(anyhow!(
+            "Meteora pools are bin based, they have no sqrt price"
+        )),
+    }
+}
+
 pub fn decode_position(data: &[u8]) -> Result<Position> {
     let mut rdr = Cursor::new(data);
 
@@ -297,6 +327,34 @@ pub fn decode_open_position_data(encoded_data: &str) -> Result<(i32, i32)> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_decode_pool_price() {
+        let sqrt_price = 6_786_462_339_120_549_069_u128;
+        let tick = -19_969_i32;
+
+        // Whirlpool: discriminator, config, bump, tick spacing and seed, fee rates, liquidity, then the price.
+        let mut whirlpool = vec![0_u8; 8 + 32 + 1 + 2 + 2 + 2 + 2 + 16];
+        whirlpool.extend_from_slice(&sqrt_price.to_le_bytes());
+        whirlpool.extend_from_slice(&tick.to_le_bytes());
+        whirlpool.resize(whirlpool.len() + 16 + 3 * 32 + 16 + 32 + 16, 0);
+
+        let mut raydium_pool = vec![0_u8; 8 + 1 + 7 * 32 + 1 + 1 + 2 + 16];
+        raydium_pool.extend_from_slice(&sqrt_price.to_le_bytes());
+        raydium_pool.extend_from_slice(&tick.to_le_bytes());
+
+        let expected = PoolPrice { sqrt_price, tick };
+        assert_eq!(
+            decode_pool_price(AMMPlatforms::Orca, &whirlpool).unwrap(),
+            expected
+        );
+        assert_eq!(
+            decode_pool_price(AMMPlatforms::Raydium, &raydium_pool).unwrap(),
+            expected
+        );
+        assert!(decode_pool_price(AMMPlatforms::Raydium, &raydium_pool[..100]).is_err());
+        assert!(decode_pool_price(AMMPlatforms::Meteora, &raydium_pool).is_err());
+    }
+
     #[test]
     fn test_decode_increase_liquidity_data() {
         let encoded_data = "3KLKPPgnNhbLEPrG4SnAHuz32CMyh9PqNtR4MvzWpxA9qgnNNVYKU6K";
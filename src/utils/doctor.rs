@@ -0,0 +1,256 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::models::transactions_model::TransactionTypeCounts;
+
+// Liquidity events per swap above which the swap parsing is more likely broken than the pool unusually busy.
+const MAX_LIQUIDITY_PER_SWAP: f64 = 1.0;
+
+#[derive(Debug, Default, PartialEq)]
+pub struct ReadinessTotals {
+    pub total: i64,
+    pub swaps: i64,
+    pub two_hop_swaps: i64,
+    pub liquidity: i64,
+    pub liquidity_not_ready: i64,
+    pub liquidity_missing_ticks: i64,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct DoctorFinding {
+    pub problem: String,
+    pub fix: String,
+}
+
+impl ReadinessTotals {
+    pub fn from_counts(counts: &[TransactionTypeCounts]) -> Self {
+        let mut totals = Self::default();
+
+        for count in counts {
+            totals.total += count.total;
+
+            match count.transaction_type.as_str() {
+                "Swap" => totals.swaps += count.total,
+                "TwoHopSwap" => totals.two_hop_swaps += count.total,
+                "IncreaseLiquidity" | "DecreaseLiquidity" => {
+                    totals.liquidity += count.total;
+                    totals.liquidity_not_ready += count.total - count.ready;
+                    totals.liquidity_missing_ticks += count.missing_ticks;
+                }
+                _ => {}
+            }
+        }
+
+        totals
+    }
+
+    // Every liquidity transaction has its ticks and is ready, so the replay can place all of them.
+    pub fn fully_backfilled(&self) -> bool {
+        self.liquidity_not_ready == 0 && self.liquidity_missing_ticks == 0
+    }
+}
+
+// What would make a backtest over this pool quietly wrong, each with the sync step that fixes it. Empty when the data looks ready.
+// ClosePosition rows are never ready_for_backtesting (they only feed the closed position lookup), so they aren't flagged.
+pub fn diagnose(totals: &ReadinessTotals) -> Vec<DoctorFinding> {
+    let mut findings = Vec::new();
+
+    if totals.total == 0 {
+        findings.push(DoctorFinding {
+            problem: "No transactions stored for this pool.".to_string(),
+            fix: "Set POOL_ADDRESS to the pool and run `cargo run sync` with SYNC_MODE=FULL_RANGE."
+                .to_string(),
+        });
+
+        return findings;
+    }
+
+    if totals.swaps == 0 {
+        findings.push(DoctorFinding {
+            problem: "No swaps stored, there is nothing to replay.".to_string(),
+            fix: "Check POOL_PLATFORM matches the pool and rerun `cargo run sync` with SYNC_MODE=FULL_RANGE."
+                .to_string(),
+        });
+    }
+
+    if totals.liquidity_not_ready > 0 {
+        findings.push(DoctorFinding {
+            problem: format!(
+                "{} of {} liquidity transactions are not ready_for_backtesting and will be skipped.",
+                totals.liquidity_not_ready, totals.liquidity
+            ),
+            fix: "Run `cargo run sync` (SYNC_MODE=UPDATE is enough), its last steps create the closed positions and fill the liquidity transactions."
+                .to_string(),
+        });
+    }
+
+    if totals.liquidity_missing_ticks > 0 {
+        findings.push(DoctorFinding {
+            problem: format!(
+                "{} liquidity transactions have no tick range and can't be placed in the liquidity array.",
+                totals.liquidity_missing_ticks
+            ),
+            fix: "Run `cargo run sync` so the position fetch and liquidity fill steps can resolve their positions. Positions that are still missing afterwards are closed positions that couldn't be found."
+                .to_string(),
+        });
+    }
+
+    if totals.swaps > 0 && totals.liquidity == 0 {
+        findings.push(DoctorFinding {
+            problem:
+                "Swaps but no liquidity transactions, the liquidity can't move during the replay."
+                    .to_string(),
+            fix: "Rerun `cargo run sync` with SYNC_MODE=FULL_RANGE over the same SYNC_DAYS."
+                .to_string(),
+        });
+    } else if totals.liquidity as f64 > totals.swaps as f64 * MAX_LIQUIDITY_PER_SWAP {
+        findings.push(DoctorFinding {
+            problem: format!(
+                "More liquidity transactions ({}) than swaps ({}), swaps were probably dropped while parsing.",
+                totals.liquidity, totals.swaps
+            ),
+            fix: "Rerun `cargo run sync` with SYNC_MODE=FULL_RANGE and check the sync log for parsing errors."
+                .to_string(),
+        });
+    }
+
+    findings
+}
+
+// The present state a backtest is reconstructed from is only as recent as the pool row and the latest synced swap.
+// Either one older than max_age before the backtest end anchors the backwards sync in the past.
+pub fn diagnose_staleness(
+    pool_last_updated_at: DateTime<Utc>,
+    latest_swap_time: Option<DateTime<Utc>>,
+    backtest_end: DateTime<Utc>,
+    max_age: Duration,
+) -> Vec<DoctorFinding> {
+    let mut findings = Vec::new();
+    let fix = "Run `cargo run sync` (SYNC_MODE=UPDATE is enough) before backtesting.".to_string();
+
+    if backtest_end - pool_last_updated_at > max_age {
+        findings.push(DoctorFinding {
+            problem: format!(
+                "Pool data was last updated at {}, more than {} hours before the backtest end.",
+                pool_last_updated_at,
+                max_age.num_hours()
+            ),
+            fix: fix.clone(),
+        });
+    }
+
+    match latest_swap_time {
+        Some(latest_swap_time) if backtest_end - latest_swap_time > max_age => {
+            findings.push(DoctorFinding {
+                problem: format!(
+                    "The latest synced swap is from {}, more than {} hours before the backtest end.",
+                    latest_swap_time,
+                    max_age.num_hours()
+                ),
+                fix,
+            });
+        }
+        Some(_) => {}
+        None => findings.push(DoctorFinding {
+            problem: "No swaps synced for this pool.".to_string(),
+            fix,
+        }),
+    }
+
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn counts(
+        transaction_type: &str,
+        total: i64,
+        ready: i64,
+        missing_ticks: i64,
+    ) -> TransactionTypeCounts {
+        TransactionTypeCounts {
+            transaction_type: transaction_type.to_string(),
+            total,
+            ready,
+            missing_ticks,
+        }
+    }
+
+    #[test]
+    fn test_diagnose_ready_dataset() {
+        let totals = ReadinessTotals::from_counts(&[
+            counts("Swap", 1_000, 1_000, 0),
+            counts("IncreaseLiquidity", 40, 40, 0),
+            counts("DecreaseLiquidity", 30, 30, 0),
+            counts("ClosePosition", 10, 0, 0),
+        ]);
+
+        assert_eq!(totals.total, 1_080);
+        assert_eq!(totals.liquidity, 70);
+        assert!(diagnose(&totals).is_empty());
+        assert!(totals.fully_backfilled());
+    }
+
+    #[test]
+    fn test_diagnose_partially_processed_dataset() {
+        let totals = ReadinessTotals::from_counts(&[
+            counts("Swap", 1_000, 1_000, 0),
+            counts("IncreaseLiquidity", 40, 30, 8),
+            counts("DecreaseLiquidity", 30, 25, 5),
+        ]);
+
+        assert_eq!(totals.liquidity_not_ready, 15);
+        assert_eq!(totals.liquidity_missing_ticks, 13);
+        assert!(!totals.fully_backfilled());
+
+        let problems: Vec<String> = diagnose(&totals).into_iter().map(|f| f.problem).collect();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("15 of 70 liquidity transactions"));
+        assert!(problems[1].starts_with("13 liquidity transactions have no tick range"));
+    }
+
+    #[test]
+    fn test_diagnose_unbalanced_dataset() {
+        let totals = ReadinessTotals::from_counts(&[
+            counts("Swap", 10, 10, 0),
+            counts("IncreaseLiquidity", 40, 40, 0),
+        ]);
+
+        let findings = diagnose(&totals);
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].problem.contains("swaps were probably dropped"));
+
+        assert_eq!(diagnose(&ReadinessTotals::default()).len(), 1);
+    }
+
+    #[test]
+    fn test_diagnose_staleness() {
+        let end = Utc.with_ymd_and_hms(2024, 8, 20, 12, 0, 0).unwrap();
+        let max_age = Duration::hours(24);
+
+        assert!(diagnose_staleness(
+            end - Duration::hours(2),
+            Some(end - Duration::hours(1)),
+            end,
+            max_age
+        )
+        .is_empty());
+
+        let findings = diagnose_staleness(
+            end - Duration::days(14),
+            Some(end - Duration::hours(1)),
+            end,
+            max_age,
+        );
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0]
+            .problem
+            .starts_with("Pool data was last updated"));
+
+        let findings = diagnose_staleness(end - Duration::days(14), None, end, max_age);
+        assert_eq!(findings.len(), 2);
+        assert_eq!(findings[1].problem, "No swaps synced for this pool.");
+    }
+}
@@ -14,47 +14,74 @@ impl Error for PriceCalcError {}
 
 #[derive(Debug)]
 pub enum SyncError {
-    DatabaseError(String),
+    DatabaseError(anyhow::Error),
     CalculationError(String),
-    ParseError(String),
+    ParseError(anyhow::Error),
+    PriceCalculation(PriceCalcError),
+    LiquidityArray(LiquidityArrayError),
+    Backtest(BacktestError),
+    // The replay didn't match RECONSTRUCTION_CHECKPOINT with FAIL_ON_CHECKPOINT_MISMATCH set.
+    CheckpointMismatch(String),
+    // Writing --save-state failed.
+    SaveState(anyhow::Error),
     Other(String),
 }
 
 impl fmt::Display for SyncError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            SyncError::DatabaseError(msg) => write!(f, "Database error: {}", msg),
+            // Wrapped errors are exposed through source() so they aren't printed twice in a chain.
+            SyncError::DatabaseError(_) => write!(f, "Database error"),
             SyncError::CalculationError(msg) => write!(f, "Calculation error: {}", msg),
-            SyncError::ParseError(msg) => write!(f, "Parse error: {}", msg),
+            SyncError::ParseError(_) => write!(f, "Parse error"),
+            SyncError::PriceCalculation(_) => write!(f, "Calculation error"),
+            SyncError::LiquidityArray(_) => write!(f, "Liquidity array error"),
+            SyncError::Backtest(_) => write!(f, "Backtest error"),
+            SyncError::CheckpointMismatch(msg) => write!(f, "Checkpoint mismatch: {}", msg),
+            SyncError::SaveState(_) => write!(f, "Failed to save the backtest state"),
             SyncError::Other(msg) => write!(f, "Other error: {}", msg),
         }
     }
 }
 
-impl Error for SyncError {}
+impl Error for SyncError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SyncError::DatabaseError(err)
+            | SyncError::ParseError(err)
+            | SyncError::SaveState(err) => Some(err.as_ref()),
+            SyncError::PriceCalculation(err) => Some(err),
+            SyncError::LiquidityArray(err) => Some(err),
+            SyncError::Backtest(err) => Some(err),
+            SyncError::CalculationError(_)
+            | SyncError::CheckpointMismatch(_)
+            | SyncError::Other(_) => None,
+        }
+    }
+}
 
 impl From<LiquidityArrayError> for SyncError {
     fn from(error: LiquidityArrayError) -> Self {
-        match error {
-            LiquidityArrayError::PriceCalculation(err) => {
-                SyncError::CalculationError(err.to_string())
-            }
-            _ => SyncError::Other(error.to_string()),
-        }
+        SyncError::LiquidityArray(error)
     }
 }
 
 impl From<PriceCalcError> for SyncError {
     fn from(error: PriceCalcError) -> Self {
-        SyncError::CalculationError(error.to_string())
+        SyncError::PriceCalculation(error)
+    }
+}
+
+impl From<BacktestError> for SyncError {
+    fn from(error: BacktestError) -> Self {
+        SyncError::Backtest(error)
     }
 }
 
 #[derive(Debug)]
 pub enum BacktestError {
-    InitializedTickNotFound,
     PriceCalculationError(String),
-    PositionNotFound(String),
+    LiquidityArray(LiquidityArrayError),
     Other(String),
 }
 
@@ -64,21 +91,31 @@ impl fmt::Display for BacktestError {
             BacktestError::PriceCalculationError(msg) => {
                 write!(f, "Price calculation error: {}", msg)
             }
-            BacktestError::PositionNotFound(id) => write!(f, "Position not found: {}", id),
-            BacktestError::InitializedTickNotFound => write!(f, "Initialized tick not found."),
+            BacktestError::LiquidityArray(_) => write!(f, "Liquidity array error"),
             BacktestError::Other(msg) => write!(f, "Unknown error: {}", msg),
         }
     }
 }
 
-impl Error for BacktestError {}
+impl Error for BacktestError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BacktestError::LiquidityArray(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum LiquidityArrayError {
     PositionNotFound(String),
     InitializedTickNotFound,
     FeeCalculationError,
+    TickOutOfRange(i32),
     PriceCalculation(PriceCalcError),
+    PriceNotInitialized,
+    IncompatiblePositions(String),
+    TooManyTickCrossings(usize),
 }
 
 impl fmt::Display for LiquidityArrayError {
@@ -88,7 +125,19 @@ impl fmt::Display for LiquidityArrayError {
             LiquidityArrayError::FeeCalculationError => {
                 write!(f, "Overflow/underflow fee calculation error")
             }
+            LiquidityArrayError::TickOutOfRange(tick) => {
+                write!(f, "Tick {} is outside of the liquidity array range", tick)
+            }
             LiquidityArrayError::PriceCalculation(err) => write!(f, "{}", err),
+            LiquidityArrayError::PriceNotInitialized => {
+                write!(f, "Current sqrt price is not initialized (zero)")
+            }
+            LiquidityArrayError::IncompatiblePositions(msg) => {
+                write!(f, "Incompatible positions: {}", msg)
+            }
+            LiquidityArrayError::TooManyTickCrossings(max) => {
+                write!(f, "Swap crossed more than {} initialized ticks", max)
+            }
             LiquidityArrayError::InitializedTickNotFound => {
                 write!(f, "Initialized tick not found")
             }
@@ -96,20 +145,19 @@ impl fmt::Display for LiquidityArrayError {
     }
 }
 
-impl Error for LiquidityArrayError {}
+impl Error for LiquidityArrayError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            LiquidityArrayError::PriceCalculation(err) => Some(err),
+            _ => None,
+        }
+    }
+}
 
+// Keep the original liquidity array error as the source so callers can still walk the chain.
 impl From<LiquidityArrayError> for BacktestError {
     fn from(error: LiquidityArrayError) -> Self {
-        match error {
-            LiquidityArrayError::PositionNotFound(id) => BacktestError::PositionNotFound(id),
-            LiquidityArrayError::FeeCalculationError => {
-                BacktestError::Other("Fee Calculation Error".to_string())
-            }
-            LiquidityArrayError::PriceCalculation(err) => {
-                BacktestError::PriceCalculationError(err.to_string())
-            }
-            LiquidityArrayError::InitializedTickNotFound => BacktestError::InitializedTickNotFound,
-        }
+        BacktestError::LiquidityArray(error)
     }
 }
 
@@ -118,3 +166,41 @@ impl From<PriceCalcError> for LiquidityArrayError {
         LiquidityArrayError::PriceCalculation(error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_chain_is_preserved() {
+        let price_err = PriceCalcError("sqrt price out of range".to_string());
+        let backtest_err: BacktestError = LiquidityArrayError::from(price_err).into();
+        let sync_err: SyncError = backtest_err.into();
+
+        let backtest_err = sync_err.source().expect("missing backtest source");
+        let liquidity_err = backtest_err
+            .source()
+            .expect("missing liquidity array source");
+        let price_err = liquidity_err.source().expect("missing price calc source");
+
+        assert!(price_err.downcast_ref::<PriceCalcError>().is_some());
+        assert!(price_err.source().is_none());
+
+        // Each level only describes itself, the wrapped error is left to source().
+        let sync_err: SyncError =
+            BacktestError::from(LiquidityArrayError::PriceNotInitialized).into();
+        assert_eq!(sync_err.to_string(), "Backtest error");
+
+        let io_err = std::io::Error::new(std::io::ErrorKind::PermissionDenied, "read-only");
+        let sync_err = SyncError::SaveState(
+            anyhow::Error::new(io_err).context("Failed to create state file state.json"),
+        );
+        let context_err = sync_err.source().expect("missing save state source");
+        assert_eq!(
+            context_err.to_string(),
+            "Failed to create state file state.json"
+        );
+        let io_err = context_err.source().expect("missing io source");
+        assert!(io_err.downcast_ref::<std::io::Error>().is_some());
+    }
+}
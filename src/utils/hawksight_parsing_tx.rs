@@ -1,14 +1,21 @@
 use crate::{
     models::transactions_model::{LiquidityData, SwapData, TransactionData, TransactionModel},
-    services::orca_amm_standard::CommonTransactionData,
+    services::{
+        orca_amm_standard::CommonTransactionData, transactions_sync_amm_service::constants,
+    },
 };
 use anyhow::{anyhow, Result};
 use serde_json::Value;
 
-use super::decode::{
-    decode_hawksight_swap_data, find_encoded_inner_instruction, HAWKSIGHT_SWAP_DISCRIMINANT,
+use super::{
+    decode::{
+        decode_hawksight_swap_data, find_encoded_inner_instruction, HAWKSIGHT_SWAP_DISCRIMINANT,
+    },
+    transaction_utils::possible_positions,
 };
 
+pub const HAWKSIGHT_PROGRAM_ID: &str = "HAWK3BVnwptKRFYfVoVGhBc2TYxpyG9jmAbkHeW9tyKE";
+
 pub struct HawksightParser;
 
 #[allow(dead_code)]
@@ -26,9 +33,9 @@ impl HawksightParser {
     pub fn is_hawksight_transaction(transaction: &Value) -> bool {
         transaction["transaction"]["message"]["accountKeys"]
             .as_array()
-            .map_or(false, |keys| {
+            .is_some_and(|keys| {
                 keys.iter()
-                    .any(|key| key.as_str() == Some("HAWK3BVnwptKRFYfVoVGhBc2TYxpyG9jmAbkHeW9tyKE"))
+                    .any(|key| key.as_str() == Some(HAWKSIGHT_PROGRAM_ID))
             })
     }
 
@@ -57,6 +64,7 @@ impl HawksightParser {
                 )?);
             } else if message.contains("Instruction: IncreaseLiquidity") {
                 liquidity_data = Some(Self::extract_liquidity_from_logs(
+                    transaction,
                     log_messages,
                     pool_info,
                     common_data.account_keys.clone(),
@@ -143,10 +151,13 @@ impl HawksightParser {
             token_out,
             amount_in,
             amount_out,
+            // The parser only ever sees hawksight transactions.
+            source_program: Some(HAWKSIGHT_PROGRAM_ID.to_string()),
         })
     }
 
     fn extract_liquidity_from_logs(
+        transaction: &Value,
         log_messages: &[Value],
         pool_info: &PoolInfo,
         account_keys: Vec<String>,
@@ -161,15 +172,15 @@ impl HawksightParser {
         for message in log_messages {
             let message = message.as_str().unwrap_or("");
             if message.starts_with("Program log: Will deposit: ") {
+                // "Program log: Will deposit: 2536570 amount in A". The side comes from the last word, since a single sided
+                // deposit logs a zero for the other token and the order alone can't tell them apart.
                 let parts: Vec<&str> = message.split_whitespace().collect();
                 if parts.len() >= 6 {
                     let amount: u64 = parts[4].parse().unwrap_or(0);
 
-                    // since amount_a is always parsed first, we update that one.
-                    if amount_a == 0 {
-                        amount_a = amount;
-                    } else {
-                        amount_b = amount;
+                    match parts.last() {
+                        Some(&"B") => amount_b = amount,
+                        _ => amount_a = amount,
                     }
                 }
             } else if message.starts_with("Program log: Tick lower index: ") {
@@ -196,7 +207,8 @@ impl HawksightParser {
             }
         }
 
-        if amount_a == 0 || amount_b == 0 {
+        // One side can legitimately be zero when the price sits at a range edge, the liquidity amount is what matters.
+        if liquidity_amount == 0 && (amount_a == 0 || amount_b == 0) {
             return Err(anyhow!("Failed to extract liquidity data from logs"));
         }
 
@@ -211,6 +223,11 @@ impl HawksightParser {
             tick_upper,
             // on hawksight parser, the position in acc keys is always on the 6th position.
             position_address: account_keys[5].clone(),
+            possible_positions: possible_positions(
+                transaction,
+                constants::ORCA_WHIRLPOOL_PROGRAM_ID,
+                &account_keys[5],
+            ),
         })
     }
 }
@@ -351,4 +368,74 @@ mod tests {
             panic!("Expected IncreaseLiquidity data");
         }
     }
+
+    #[test]
+    fn test_hawksight_single_sided_increase_liquidity() {
+        // Price below the range, so only token A goes in.
+        let transaction_json = json!({
+            "blockTime": 1725258498,
+            "meta": {
+                "logMessages": [
+                    "Program log: Tick lower index: -18000",
+                    "Program log: Tick upper index: -15560",
+                    "Program log: liquidity_amount: 3100000",
+                    "Program log: Will deposit: 2536570 amount in A",
+                    "Program log: Will deposit: 0 amount in B",
+                    "Program log: Instruction: IncreaseLiquidity"
+                ]
+            },
+            "transaction": {
+                "message": {
+                    "accountKeys": [
+                        "HAWK3BVnwptKRFYfVoVGhBc2TYxpyG9jmAbkHeW9tyKE",
+                        "HAWK3BVnwptKRFYfVoVGhBc2TYxpyG9jmAbkHeW9tyKE",
+                        "HAWK3BVnwptKRFYfVoVGhBc2TYxpyG9jmAbkHeW9tyKE",
+                        "dche7M2764e8AxNihBdn7uffVzZvTBNeL8x4LZg5E2c",
+                        "HN5jKXfzyg6KXaq6X8GxYyPH1WQtWHYx4zN2DwFvoPAi",
+                        "FpCMFDFGYotvufJ7HrFHsWEiiQCGbkLCtwHiDnh7o28Q"
+                    ]
+                }
+            }
+        });
+
+        let pool_info = PoolInfo {
+            address: "FpCMFDFGYotvufJ7HrFHsWEiiQCGbkLCtwHiDnh7o28Q".to_string(),
+            token_a: "So11111111111111111111111111111111111111112".to_string(),
+            token_b: "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v".to_string(),
+            decimals_a: 9,
+            decimals_b: 6,
+        };
+
+        let common_data = CommonTransactionData {
+            signature: "test_signature".to_string(),
+            block_time: 1725258498,
+            block_time_utc: chrono::DateTime::from_timestamp(1725258498, 0).unwrap(),
+            account_keys: transaction_json["transaction"]["message"]["accountKeys"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|key| key.as_str().unwrap().to_string())
+                .collect(),
+        };
+
+        let parsed_transactions = HawksightParser::parse_hawksight_auto_compounder(
+            &transaction_json,
+            &pool_info,
+            &common_data,
+        )
+        .unwrap();
+
+        assert_eq!(parsed_transactions.len(), 1);
+        assert_eq!(parsed_transactions[0].transaction_type, "IncreaseLiquidity");
+
+        if let TransactionData::IncreaseLiquidity(liquidity_data) = &parsed_transactions[0].data {
+            assert_eq!(liquidity_data.amount_a, 2536570);
+            assert_eq!(liquidity_data.amount_b, 0);
+            assert_eq!(liquidity_data.liquidity_amount, "3100000");
+            assert_eq!(liquidity_data.tick_lower, Some(-18000));
+            assert_eq!(liquidity_data.tick_upper, Some(-15560));
+        } else {
+            panic!("Expected IncreaseLiquidity data");
+        }
+    }
 }
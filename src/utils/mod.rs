@@ -1,7 +1,12 @@
-pub mod decode;
-pub mod transaction_utils;
-pub mod hawksight_parsing_tx;
 pub mod core_math;
+pub mod daily_stats;
+pub mod data_logger;
+pub mod decode;
+pub mod doctor;
 pub mod error;
+pub mod hawksight_parsing_tx;
+pub mod number_format;
 pub mod profit_calcs;
-pub mod data_logger;
+pub mod stats;
+pub mod transaction_utils;
+pub mod tx_export;
@@ -0,0 +1,92 @@
+// How the report prints its numbers (REPORT_DECIMALS, REPORT_THOUSANDS_SEPARATOR, REPORT_CURRENCY_SYMBOL).
+#[derive(Debug, Clone, PartialEq)]
+pub struct NumberFormat {
+    // Decimal places of the USD values and percentages.
+    pub decimals: usize,
+    // Groups the integer part by thousands, None leaves it as is.
+    pub thousands_separator: Option<char>,
+    pub currency_symbol: String,
+}
+
+impl Default for NumberFormat {
+    fn default() -> Self {
+        Self {
+            decimals: 3,
+            thousands_separator: None,
+            currency_symbol: "$".to_string(),
+        }
+    }
+}
+
+impl NumberFormat {
+    pub fn number(&self, value: f64, decimals: usize) -> String {
+        let formatted = format!("{:.*}", decimals, value.abs());
+        let (integer, fraction) = match formatted.split_once('.') {
+            Some((integer, fraction)) => (integer, Some(fraction)),
+            None => (formatted.as_str(), None),
+        };
+
+        let mut result = String::new();
+        if value.is_sign_negative() && formatted.chars().any(|c| c.is_ascii_digit() && c != '0') {
+            result.push('-');
+        }
+
+        match self.thousands_separator {
+            Some(separator) => {
+                for (i, digit) in integer.chars().enumerate() {
+                    if i > 0 && (integer.len() - i) % 3 == 0 {
+                        result.push(separator);
+                    }
+                    result.push(digit);
+                }
+            }
+            None => result.push_str(integer),
+        }
+
+        if let Some(fraction) = fraction {
+            result.push('.');
+            result.push_str(fraction);
+        }
+
+        result
+    }
+
+    // The sign goes before the symbol, -$1,234.500.
+    pub fn usd(&self, value: f64) -> String {
+        let number = self.number(value, self.decimals);
+
+        match number.strip_prefix('-') {
+            Some(number) => format!("-{}{}", self.currency_symbol, number),
+            None => format!("{}{}", self.currency_symbol, number),
+        }
+    }
+
+    pub fn pct(&self, value: f64) -> String {
+        format!("{}%", self.number(value, self.decimals))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_format() {
+        let default = NumberFormat::default();
+        assert_eq!(default.usd(1234567.891), "$1234567.891");
+        assert_eq!(default.pct(-12.3456), "-12.346%");
+        // Rounds to zero, no "-0.000".
+        assert_eq!(default.usd(-0.0001), "$0.000");
+
+        let grouped = NumberFormat {
+            decimals: 6,
+            thousands_separator: Some(','),
+            currency_symbol: "USD ".to_string(),
+        };
+        assert_eq!(grouped.usd(-1234567.5), "-USD 1,234,567.500000");
+        assert_eq!(grouped.usd(0.0000042), "USD 0.000004");
+        assert_eq!(grouped.number(999.0, 0), "999");
+        assert_eq!(grouped.number(1000.0, 0), "1,000");
+        assert_eq!(grouped.pct(123456.0), "123,456.000000%");
+    }
+}
@@ -1,40 +1,204 @@
+use anyhow::anyhow;
 use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::Value;
 use std::error::Error;
+use std::str::FromStr;
 
 use crate::{
-    api::{price_api::PriceApi, token_metadata_api::TokenMetadataApi},
-    backtester::backtester_core::Backtest,
+    api::{
+        price_api::{PriceApi, PricePoint, PriceSource, PriceToken, StableToken},
+        token_metadata_api::TokenMetadataApiTrait,
+    },
+    backtester::{backtest_utils::calculate_full_range_liquidity, backtester_core::Backtest},
     models::transactions_model::TransactionModelFromDB,
-    utils::core_math::Q64,
+    utils::{
+        core_math::{sqrt_price_to_price, Q128, Q64, U256},
+        data_logger::PositionRecord,
+    },
 };
 
+// USD value of the stable side of the pool when STABLE_TOKEN is set.
+const STABLE_PEG_USD: f64 = 1.0;
+
+// Staking yield of a liquid staking token side (mSOL, jitoSOL...) in bps per day, 0 for regular tokens.
+// Simplification: a constant rate compounded daily, applied to the ending USD price only.
+// Rule: the yield is only added to prices that don't already carry it. Prices derived from the pool (STABLE_TOKEN) trade
+// the LST at its appreciated value, so they never get it. An external price source is assumed to follow the underlying
+// (SOL's price for mSOL), set the rate only when that holds.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TokenYields {
+    pub token_a_bps_per_day: f64,
+    pub token_b_bps_per_day: f64,
+}
+
+impl TokenYields {
+    // The yields still to be added on top of prices from the given source, none when the pool price already includes them.
+    pub fn for_price_source(self, stable_token: Option<StableToken>) -> Self {
+        match stable_token {
+            Some(_) => Self::default(),
+            None => self,
+        }
+    }
+
+    // (token A, token B) appreciation over a window of days.
+    pub fn accrual_factors(&self, days: f64) -> (f64, f64) {
+        let factor = |bps_per_day: f64| (1.0 + bps_per_day / 10_000.0).powf(days);
+
+        (
+            factor(self.token_a_bps_per_day),
+            factor(self.token_b_bps_per_day),
+        )
+    }
+}
+
+// How far the time of a provider's price may be from the time it was asked for (PRICE_TIME_TOLERANCE_SECONDS). Past it
+// the report warns, or fails when strict (FAIL_ON_PRICE_TIME_MISMATCH): a stale start or end price can make a flat window
+// look like a big price change.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PriceTimeCheck {
+    pub tolerance_seconds: i64,
+    pub strict: bool,
+}
+
+impl Default for PriceTimeCheck {
+    fn default() -> Self {
+        Self {
+            tolerance_seconds: 3600,
+            strict: false,
+        }
+    }
+}
+
+impl PriceTimeCheck {
+    pub fn check(
+        &self,
+        symbol: &str,
+        requested: DateTime<Utc>,
+        found: &PricePoint,
+    ) -> anyhow::Result<()> {
+        let deviation_seconds = (found.timestamp - requested).num_seconds().abs();
+        if deviation_seconds <= self.tolerance_seconds {
+            return Ok(());
+        }
+
+        let message = format!(
+            "The {} price asked for {} is from {}, {} seconds off (tolerance {} seconds).",
+            symbol, requested, found.timestamp, deviation_seconds, self.tolerance_seconds
+        );
+        if self.strict {
+            return Err(anyhow!(
+                "{} Aborting (FAIL_ON_PRICE_TIME_MISMATCH is set).",
+                message
+            ));
+        }
+
+        println!("WARNING: {}", message);
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize)]
 pub struct PriceCalculationResult {
+    // Which provider the USD prices below came from, so the numbers can be audited and cross-checked.
+    pub price_source: PriceSource,
+    // Set when the stable side was pegged and the other side priced off the pool, instead of both coming from price_source.
+    pub stable_token: Option<StableToken>,
     pub start_time: DateTime<Utc>,
     pub end_time: DateTime<Utc>,
+    // When the prices price_source returned are actually for, can be off start_time/end_time. None when pegged.
+    pub token_a_start_price_time: Option<DateTime<Utc>>,
+    pub token_b_start_price_time: Option<DateTime<Utc>>,
+    pub token_a_end_price_time: Option<DateTime<Utc>>,
+    pub token_b_end_price_time: Option<DateTime<Utc>>,
+    // Set when the strategy hit MAX_ACTIONS and the replay stopped early, the figures only cover the window up to there.
+    pub max_actions_hit: bool,
+    pub strategy_actions_executed: usize,
+    // Swaps the source program filter left out of the replay.
+    pub swaps_filtered_by_source: u64,
+    // Swaps below MIN_SWAP_AMOUNT_IN left out of the replay.
+    pub dust_swaps_skipped: u64,
+    // Replayed swaps that hit an edge of the liquidity array with input left over, only partially filled.
+    pub swaps_stopped_at_edge: u64,
+    // Ticks emptied with leftover net liquidity that was reset to zero, in the reconstruction or the replay.
+    pub ticks_net_liquidity_reset: u64,
+    pub token_a_symbol: String,
+    pub token_b_symbol: String,
+    // Pool price (token B per token A, decimals applied) when the backtest started and ended.
+    pub start_pool_price: f64,
+    pub end_pool_price: f64,
     pub token_a_price_change_pct: f64,
     pub token_b_price_change_pct: f64,
+    // Staking appreciation applied on top of the ending prices (TOKEN_A/B_YIELD_BPS_PER_DAY), 1.0 without a yield.
+    pub token_a_yield_factor: f64,
+    pub token_b_yield_factor: f64,
     pub pnl_no_lping: f64,
     pub pnl_no_lping_pct: f64,
     pub starting_total_value_in_usd: f64,
     pub ending_total_value_in_usd: f64,
     pub final_value_total: f64,
     pub total_pnl_pct: f64,
+    pub entry_cost_usd: f64,
+    pub pnl_before_entry_cost: f64,
+    // Value lost on the swaps of positions opened during the run, priced at the end like the fees.
+    pub rebalance_cost_usd: f64,
+    // Part of rebalance_cost_usd lost to the sandwich penalty (SANDWICH_PENALTY_BPS), 0 with the penalty off.
+    pub sandwich_loss_usd: f64,
+    // Positions opened during the run, each one paid a rebalance swap (or had nothing to swap).
+    pub rebalance_count: u64,
+    // Collected fees plus what the positions the strategy closed gained or lost, net of the rebalance swaps.
+    pub realized_pnl_usd: f64,
+    // The positions still open at the window end marked to market against what they were opened with, net of the
+    // entry swaps.
+    pub unrealized_pnl_usd: f64,
+    // Largest drop from a previous peak of the wallet plus open positions, valued in token B.
+    pub max_drawdown_pct: f64,
+    // Time weighted share of the active liquidity while in range. Low fees with a small share = a deep pool, not a bad range.
+    pub avg_liquidity_share_pct: f64,
+    // Time weighted value left in the wallet (remainders, undeployed capital, collected fees) over the timeline samples,
+    // None (unavailable) when TIMELINE_SAMPLE_SECONDS is off.
+    pub avg_idle_capital_usd: Option<f64>,
+    pub avg_idle_capital_pct: Option<f64>,
+    // Fees the idle capital would have earned at the rate of the deployed capital, the return left on the table. None
+    // like the idle capital.
+    pub cash_drag_usd: Option<f64>,
     pub token_a_collected_fees: f64,
     pub token_b_collected_fees: f64,
     pub capital_earned_in_token_a: f64,
     pub capital_earned_in_token_a_in_pct: f64,
     pub total_fees_collected_in_usd: f64,
+    pub fees_collected_during_run_in_usd: f64,
+    pub fees_collected_at_end_in_usd: f64,
+    // Fees still pending in the positions at the end when COUNT_FEES_AT_END is off. Not part of any PnL figure above.
+    pub uncollected_fees_at_end_usd: f64,
+    // Which of the two below fees_collected_during_run_in_usd (and every PnL figure) counts, see FeeHandling.
+    pub fee_handling: FeeHandling,
+    pub fees_during_run_held_usd: f64,
+    pub fees_during_run_as_basket_usd: f64,
+    // Basket minus held, what converting the fees right away would have gained (or lost) by the end.
+    pub fee_handling_difference_usd: f64,
     pub total_fees_in_pct: f64,
+    pub full_range_fees_in_usd: f64,
+    pub capital_efficiency_multiple: f64,
     pub range_efficiency: f64,
     pub fee_apr_percentage: f64,
+    // Days of fees at the window's rate to pay back the entry and rebalance swaps. None when no fees were earned, the
+    // costs are never covered.
+    pub fee_breakeven_days: Option<f64>,
     pub impermanent_loss: f64,
 }
 
 // Price calculations from start to show growth in strategy in USD.
-// We are using Binance public market data for pricing so niche tokens will not be supported
+// The price source is configurable (PRICE_SOURCE), niche tokens may not be supported by every provider.
+// With a stable_token the price api isn't needed at all, both prices follow from the pool price and the peg.
+#[allow(clippy::too_many_arguments)]
 pub async fn calculate_prices_and_pnl(
-    token_metadata_api: &TokenMetadataApi,
-    price_api: &PriceApi,
+    token_metadata_api: &dyn TokenMetadataApiTrait,
+    price_api: &dyn PriceApi,
+    stable_token: Option<StableToken>,
+    token_yields: TokenYields,
+    fee_handling: FeeHandling,
+    price_time_check: PriceTimeCheck,
     backtest: &Backtest,
     highest_tx: &TransactionModelFromDB,
     tx_to_sync_from: &TransactionModelFromDB,
@@ -54,33 +218,80 @@ pub async fn calculate_prices_and_pnl(
     let symbols = token_metadata_api
         .get_token_symbols_for_addresses(&token_addr_arr)
         .await?;
-    let token_a_symbol = format!("{}USDT", symbols[0]);
-    let token_b_symbol = format!("{}USDT", symbols[1]);
+    let token_a = PriceToken {
+        symbol: symbols[0].clone(),
+        address: token_a_addr.clone(),
+    };
+    let token_b = PriceToken {
+        symbol: symbols[1].clone(),
+        address: token_b_addr.clone(),
+    };
 
-    let token_a_starting_price_usd = price_api
-        .get_historical_price(&token_a_symbol, highest_tx.block_time_utc)
-        .await?;
-    let token_a_ending_price_usd = price_api
-        .get_historical_price(&token_a_symbol, tx_to_sync_from.block_time_utc)
-        .await?;
-    let token_b_starting_price_usd = price_api
-        .get_historical_price(&token_b_symbol, highest_tx.block_time_utc)
-        .await?;
-    let token_b_ending_price_usd = price_api
-        .get_historical_price(&token_b_symbol, tx_to_sync_from.block_time_utc)
-        .await?;
+    let start_pool_price = pool_price(
+        backtest.start_info.sqrt_price,
+        backtest.wallet.token_a_decimals,
+        backtest.wallet.token_b_decimals,
+    );
+    let a_b_end_price = pool_price(
+        backtest.liquidity_arr.current_sqrt_price,
+        backtest.wallet.token_a_decimals,
+        backtest.wallet.token_b_decimals,
+    );
+
+    let (
+        token_a_starting_price_usd,
+        token_b_starting_price_usd,
+        token_a_ending_price_usd,
+        token_b_ending_price_usd,
+        price_times,
+    ) = match stable_token {
+        Some(stable_token) => {
+            let (a_start, b_start) = pegged_prices_usd(stable_token, start_pool_price);
+            let (a_end, b_end) = pegged_prices_usd(stable_token, a_b_end_price);
+            (a_start, b_start, a_end, b_end, None)
+        }
+        None => {
+            let mut points = Vec::new();
+            for (token, time) in [
+                (&token_a, highest_tx.block_time_utc),
+                (&token_b, highest_tx.block_time_utc),
+                (&token_a, tx_to_sync_from.block_time_utc),
+                (&token_b, tx_to_sync_from.block_time_utc),
+            ] {
+                let point = price_api.get_price(token, time).await?;
+                price_time_check.check(&token.symbol, time, &point)?;
+                points.push(point);
+            }
+
+            (
+                points[0].price,
+                points[1].price,
+                points[2].price,
+                points[3].price,
+                Some([
+                    points[0].timestamp,
+                    points[1].timestamp,
+                    points[2].timestamp,
+                    points[3].timestamp,
+                ]),
+            )
+        }
+    };
+
+    // An LST is worth more of its underlying at the end, independent of the pool trading. See TokenYields for when it applies.
+    let window_days = (tx_to_sync_from.block_time_utc - highest_tx.block_time_utc).num_seconds()
+        as f64
+        / 86_400.0;
+    let (token_a_yield_factor, token_b_yield_factor) = token_yields
+        .for_price_source(stable_token)
+        .accrual_factors(window_days);
+    let token_a_ending_price_usd = token_a_ending_price_usd * token_a_yield_factor;
+    let token_b_ending_price_usd = token_b_ending_price_usd * token_b_yield_factor;
 
     let starting_amount_token_a = (backtest.start_info.token_a_amount.as_u128() as f64)
         / 10.0f64.powi(backtest.wallet.token_a_decimals as i32);
     let starting_amount_token_b = (backtest.start_info.token_b_amount.as_u128() as f64)
         / 10.0f64.powi(backtest.wallet.token_b_decimals as i32);
-
-    let a_b_end_price = (backtest.liquidity_arr.current_sqrt_price.as_u128() as f64
-        / Q64.as_u128() as f64)
-        .powf(2.0)
-        * 10.0_f64.powf(
-            backtest.wallet.token_a_decimals as f64 - backtest.wallet.token_b_decimals as f64,
-        );
     let a_b_start_price = starting_amount_token_b / starting_amount_token_a;
 
     // Real starting value in USD
@@ -95,6 +306,25 @@ pub async fn calculate_prices_and_pnl(
     let pnl_no_lping = start_amount_end_value_in_usd - starting_total_value_in_usd;
     let pnl_no_lping_pct = (pnl_no_lping / starting_total_value_in_usd) * 100.0;
 
+    // The fees collected during the run as they sit in the wallet vs converted to a 50/50 basket when collected. With
+    // FeeHandling::Basket the difference is counted on top of the wallet.
+    let fees_during_run_held_usd = (backtest.data.fees_a_collected_during_run as f64
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_ending_price_usd
+        + (backtest.data.fees_b_collected_during_run as f64
+            / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd;
+    let fees_during_run_as_basket_usd = (backtest.data.fee_basket_a
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_ending_price_usd
+        + (backtest.data.fee_basket_b / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd;
+    let fee_handling_difference_usd = fees_during_run_as_basket_usd - fees_during_run_held_usd;
+    let (fees_collected_during_run_in_usd, fee_handling_adjustment_usd) = match fee_handling {
+        FeeHandling::Hold => (fees_during_run_held_usd, 0.0),
+        FeeHandling::Basket => (fees_during_run_as_basket_usd, fee_handling_difference_usd),
+    };
+
     // Fees already included when closing position in backtester.
     let token_a_end_amount = backtest.wallet.amount_token_a.as_u128() as f64
         / 10.0f64.powi(backtest.wallet.token_a_decimals as i32);
@@ -103,11 +333,45 @@ pub async fn calculate_prices_and_pnl(
 
     // Final real ending value using wallet stuff
     let ending_total_value_in_usd = token_a_end_amount * token_a_ending_price_usd
-        + token_b_end_amount * token_b_ending_price_usd;
+        + token_b_end_amount * token_b_ending_price_usd
+        + fee_handling_adjustment_usd;
 
     let final_value_total = ending_total_value_in_usd - starting_total_value_in_usd;
     let total_pnl_pct = (final_value_total / starting_total_value_in_usd) * 100.0;
 
+    // Value lost swapping into the first positions, priced at the start. The ending wallet already holds less because of it,
+    // so final_value_total is net of it and pnl_before_entry_cost shows what the strategy made once in position.
+    let entry_cost_usd = (backtest.start_info.entry_cost_a as f64
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_starting_price_usd
+        + (backtest.start_info.entry_cost_b as f64
+            / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_starting_price_usd;
+    let pnl_before_entry_cost = final_value_total + entry_cost_usd;
+
+    let rebalance_cost_usd = (backtest.data.rebalance_cost_a as f64
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_ending_price_usd
+        + (backtest.data.rebalance_cost_b as f64
+            / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd;
+
+    let sandwich_loss_usd = (backtest.data.sandwich_loss_a as f64
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_ending_price_usd
+        + (backtest.data.sandwich_loss_b as f64
+            / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd;
+
+    let token_amounts_usd = |amount_a: u128, amount_b: u128| {
+        (amount_a as f64 / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+            * token_a_ending_price_usd
+            + (amount_b as f64 / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+                * token_b_ending_price_usd
+    };
+    let (closed_positions_pnl_usd, open_positions_pnl_usd) =
+        position_pnl_usd(backtest.data_logger.position_records(), token_amounts_usd);
+
     let token_a_collected_fees = (backtest.wallet.amount_a_fees_collected.as_u128() as f64)
         / 10.0f64.powi(backtest.wallet.token_a_decimals as i32);
 
@@ -115,10 +379,81 @@ pub async fn calculate_prices_and_pnl(
         / 10.0f64.powi(backtest.wallet.token_b_decimals as i32);
 
     let total_fees_collected_in_usd = (token_a_collected_fees * token_a_ending_price_usd)
-        + (token_b_collected_fees * token_b_ending_price_usd);
+        + (token_b_collected_fees * token_b_ending_price_usd)
+        + fee_handling_adjustment_usd;
 
     let total_fees_in_pct = (total_fees_collected_in_usd / starting_total_value_in_usd) * 100.0;
 
+    // Valued in token B at the pool price of each sample, converted at the ending price like the fees.
+    let idle_and_total_value_b = backtest.data.average_idle_and_total_value_b();
+    let avg_idle_capital_usd = idle_and_total_value_b.map(|(avg_idle_value_b, _)| {
+        (avg_idle_value_b / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd
+    });
+    let avg_idle_capital_pct =
+        idle_and_total_value_b.map(|(avg_idle_value_b, avg_total_value_b)| {
+            if avg_total_value_b > 0.0 {
+                avg_idle_value_b / avg_total_value_b * 100.0
+            } else {
+                0.0
+            }
+        });
+    let cash_drag_usd = idle_and_total_value_b.map(|(avg_idle_value_b, avg_total_value_b)| {
+        let avg_deployed_value_b = avg_total_value_b - avg_idle_value_b;
+
+        if avg_deployed_value_b > 0.0 {
+            total_fees_collected_in_usd * avg_idle_value_b / avg_deployed_value_b
+        } else {
+            0.0
+        }
+    });
+
+    let realized_pnl_usd =
+        total_fees_collected_in_usd + closed_positions_pnl_usd - rebalance_cost_usd;
+    let unrealized_pnl_usd = open_positions_pnl_usd - entry_cost_usd;
+
+    // Split of the fees above by when they were collected (rebalance closes vs the final close).
+    let fees_collected_at_end_in_usd = (backtest.data.fees_a_collected_at_end as f64
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_ending_price_usd
+        + (backtest.data.fees_b_collected_at_end as f64
+            / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd;
+
+    let uncollected_fees_at_end_usd = (backtest.data.fees_a_uncollected_at_end as f64
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_ending_price_usd
+        + (backtest.data.fees_b_uncollected_at_end as f64
+            / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd;
+
+    // Fees the same starting capital would have earned in a full range position. It is always in range so its fee growth inside
+    // is just the global fee growth over the run. Ignores that our own liquidity would dilute the pool slightly.
+    let full_range_liquidity = calculate_full_range_liquidity(
+        backtest.start_info.token_a_amount,
+        backtest.start_info.token_b_amount,
+        backtest.start_info.sqrt_price,
+    );
+    let full_range_fees_a = (full_range_liquidity
+        * (backtest.liquidity_arr.fee_growth_global_a - backtest.start_info.fee_growth_global_a))
+        / Q128;
+    let full_range_fees_b = (full_range_liquidity
+        * (backtest.liquidity_arr.fee_growth_global_b - backtest.start_info.fee_growth_global_b))
+        / Q128;
+    let full_range_fees_in_usd = (full_range_fees_a.as_u128() as f64
+        / 10.0f64.powi(backtest.wallet.token_a_decimals as i32))
+        * token_a_ending_price_usd
+        + (full_range_fees_b.as_u128() as f64
+            / 10.0f64.powi(backtest.wallet.token_b_decimals as i32))
+            * token_b_ending_price_usd;
+
+    // How many times more fees per dollar the strategy earned vs the full range position.
+    let capital_efficiency_multiple = if full_range_fees_in_usd > 0.0 {
+        total_fees_collected_in_usd / full_range_fees_in_usd
+    } else {
+        0.0
+    };
+
     let capital_earned_in_token_a = token_a_collected_fees + token_b_collected_fees / a_b_end_price;
 
     let capital_earned_in_token_a_in_pct = (capital_earned_in_token_a
@@ -141,6 +476,13 @@ pub async fn calculate_prices_and_pnl(
     let fee_apr = (total_fees_in_pct / 100.0 + 1.0).powf(365.0 / days) - 1.0;
     let fee_apr_percentage = fee_apr * 100.0;
 
+    let fee_breakeven_days = if total_fees_collected_in_usd > 0.0 && window_days > 0.0 {
+        let fees_per_day = total_fees_collected_in_usd / window_days;
+        Some((entry_cost_usd + rebalance_cost_usd).max(0.0) / fees_per_day)
+    } else {
+        None
+    };
+
     // impermanent loss
     let price_ratio = a_b_end_price / a_b_start_price;
     let impermanent_loss = (2.0 * (price_ratio.sqrt() / (1.0 + price_ratio)) - 1.0) * 100.0;
@@ -151,25 +493,558 @@ pub async fn calculate_prices_and_pnl(
         .sqrt();
     let rebalanced_value = starting_total_value_in_usd * growth_factor;
 
-    Ok(PriceCalculationResult {
+    let mut result = PriceCalculationResult {
+        price_source: price_api.source(),
+        stable_token,
         start_time: highest_tx.block_time_utc,
         end_time: tx_to_sync_from.block_time_utc,
+        token_a_start_price_time: price_times.map(|times| times[0]),
+        token_b_start_price_time: price_times.map(|times| times[1]),
+        token_a_end_price_time: price_times.map(|times| times[2]),
+        token_b_end_price_time: price_times.map(|times| times[3]),
+        max_actions_hit: backtest.max_actions_hit,
+        strategy_actions_executed: backtest.strategy_actions_executed,
+        swaps_filtered_by_source: backtest.data.swaps_filtered_by_source as u64,
+        dust_swaps_skipped: backtest.data.dust_swaps_skipped as u64,
+        swaps_stopped_at_edge: backtest.liquidity_arr.swaps_stopped_at_edge,
+        ticks_net_liquidity_reset: backtest.liquidity_arr.ticks_net_liquidity_reset,
+        token_a_symbol: token_a.symbol.clone(),
+        token_b_symbol: token_b.symbol.clone(),
+        start_pool_price,
+        end_pool_price: a_b_end_price,
         token_a_price_change_pct,
         token_b_price_change_pct,
+        token_a_yield_factor,
+        token_b_yield_factor,
         pnl_no_lping,
         pnl_no_lping_pct,
         starting_total_value_in_usd,
         ending_total_value_in_usd,
         final_value_total,
         total_pnl_pct,
+        entry_cost_usd,
+        pnl_before_entry_cost,
+        rebalance_cost_usd,
+        sandwich_loss_usd,
+        rebalance_count: backtest.data.rebalance_count,
+        realized_pnl_usd,
+        unrealized_pnl_usd,
+        max_drawdown_pct: backtest.data.max_drawdown_pct,
+        avg_liquidity_share_pct: backtest.data.average_liquidity_share() * 100.0,
+        avg_idle_capital_usd,
+        avg_idle_capital_pct,
+        cash_drag_usd,
         token_a_collected_fees,
         token_b_collected_fees,
         capital_earned_in_token_a,
         capital_earned_in_token_a_in_pct,
         total_fees_collected_in_usd,
+        fees_collected_during_run_in_usd,
+        fees_collected_at_end_in_usd,
+        uncollected_fees_at_end_usd,
+        fee_handling,
+        fees_during_run_held_usd,
+        fees_during_run_as_basket_usd,
+        fee_handling_difference_usd,
         total_fees_in_pct,
+        full_range_fees_in_usd,
+        capital_efficiency_multiple,
         range_efficiency,
         fee_apr_percentage,
+        fee_breakeven_days,
         impermanent_loss,
-    })
+    };
+
+    let replaced = result.sanitize_non_finite();
+    if !replaced.is_empty() {
+        println!(
+            "WARNING: {} divided by zero (zero starting value, no swaps or a zero price), reported as 0: {}",
+            if replaced.len() == 1 { "A result" } else { "Some results" },
+            replaced.join(", ")
+        );
+    }
+
+    Ok(result)
+}
+
+// What happens to the fees collected during the run (rebalance closes). Hold keeps them in the tokens they came in, Basket
+// assumes each collection was converted to a 50/50 basket at the pool price right away and held. Only the valuation
+// changes, the replay itself is the same.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum FeeHandling {
+    #[default]
+    Hold,
+    Basket,
+}
+
+impl FromStr for FeeHandling {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_uppercase().as_str() {
+            "HOLD" => Ok(FeeHandling::Hold),
+            "BASKET" => Ok(FeeHandling::Basket),
+            _ => Err(anyhow!(
+                "Unknown fee handling: {} (expected HOLD or BASKET)",
+                s
+            )),
+        }
+    }
+}
+
+// How the backtest result is printed. Tsv and Json are single records meant for other tools, Pretty is the report.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Pretty,
+    Tsv,
+    Json,
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s.to_lowercase().as_str() {
+            "pretty" => Ok(OutputFormat::Pretty),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "json" => Ok(OutputFormat::Json),
+            _ => Err(anyhow!(
+                "Unknown output format: {} (expected pretty, tsv or json)",
+                s
+            )),
+        }
+    }
+}
+
+impl PriceCalculationResult {
+    // A zero denominator (no starting value, no swaps, a zero price) turns ratios into NaN or inf, which would print as
+    // "NaN%". Those are replaced with 0.0, the names of the replaced fields are returned so the caller can say why.
+    pub fn sanitize_non_finite(&mut self) -> Vec<&'static str> {
+        let mut replaced = Vec::new();
+
+        for (name, value) in [
+            ("start_pool_price", &mut self.start_pool_price),
+            ("end_pool_price", &mut self.end_pool_price),
+            (
+                "token_a_price_change_pct",
+                &mut self.token_a_price_change_pct,
+            ),
+            (
+                "token_b_price_change_pct",
+                &mut self.token_b_price_change_pct,
+            ),
+            ("token_a_yield_factor", &mut self.token_a_yield_factor),
+            ("token_b_yield_factor", &mut self.token_b_yield_factor),
+            ("pnl_no_lping", &mut self.pnl_no_lping),
+            ("pnl_no_lping_pct", &mut self.pnl_no_lping_pct),
+            (
+                "starting_total_value_in_usd",
+                &mut self.starting_total_value_in_usd,
+            ),
+            (
+                "ending_total_value_in_usd",
+                &mut self.ending_total_value_in_usd,
+            ),
+            ("final_value_total", &mut self.final_value_total),
+            ("total_pnl_pct", &mut self.total_pnl_pct),
+            ("entry_cost_usd", &mut self.entry_cost_usd),
+            ("pnl_before_entry_cost", &mut self.pnl_before_entry_cost),
+            ("rebalance_cost_usd", &mut self.rebalance_cost_usd),
+            ("sandwich_loss_usd", &mut self.sandwich_loss_usd),
+            ("realized_pnl_usd", &mut self.realized_pnl_usd),
+            ("unrealized_pnl_usd", &mut self.unrealized_pnl_usd),
+            ("max_drawdown_pct", &mut self.max_drawdown_pct),
+            ("avg_liquidity_share_pct", &mut self.avg_liquidity_share_pct),
+            ("token_a_collected_fees", &mut self.token_a_collected_fees),
+            ("token_b_collected_fees", &mut self.token_b_collected_fees),
+            (
+                "capital_earned_in_token_a",
+                &mut self.capital_earned_in_token_a,
+            ),
+            (
+                "capital_earned_in_token_a_in_pct",
+                &mut self.capital_earned_in_token_a_in_pct,
+            ),
+            (
+                "total_fees_collected_in_usd",
+                &mut self.total_fees_collected_in_usd,
+            ),
+            (
+                "fees_collected_during_run_in_usd",
+                &mut self.fees_collected_during_run_in_usd,
+            ),
+            (
+                "fees_collected_at_end_in_usd",
+                &mut self.fees_collected_at_end_in_usd,
+            ),
+            (
+                "uncollected_fees_at_end_usd",
+                &mut self.uncollected_fees_at_end_usd,
+            ),
+            (
+                "fees_during_run_held_usd",
+                &mut self.fees_during_run_held_usd,
+            ),
+            (
+                "fees_during_run_as_basket_usd",
+                &mut self.fees_during_run_as_basket_usd,
+            ),
+            (
+                "fee_handling_difference_usd",
+                &mut self.fee_handling_difference_usd,
+            ),
+            ("total_fees_in_pct", &mut self.total_fees_in_pct),
+            ("full_range_fees_in_usd", &mut self.full_range_fees_in_usd),
+            (
+                "capital_efficiency_multiple",
+                &mut self.capital_efficiency_multiple,
+            ),
+            ("range_efficiency", &mut self.range_efficiency),
+            ("fee_apr_percentage", &mut self.fee_apr_percentage),
+            ("impermanent_loss", &mut self.impermanent_loss),
+        ] {
+            if !value.is_finite() {
+                *value = 0.0;
+                replaced.push(name);
+            }
+        }
+
+        for (name, value) in [
+            ("avg_idle_capital_usd", &mut self.avg_idle_capital_usd),
+            ("avg_idle_capital_pct", &mut self.avg_idle_capital_pct),
+            ("cash_drag_usd", &mut self.cash_drag_usd),
+        ] {
+            if value.is_some_and(|value| !value.is_finite()) {
+                *value = Some(0.0);
+                replaced.push(name);
+            }
+        }
+
+        replaced
+    }
+
+    // Header line and value line of every field, tab separated and sorted by name. Nested values are written as JSON.
+    pub fn to_tsv(&self) -> serde_json::Result<String> {
+        let fields = match serde_json::to_value(self)? {
+            Value::Object(fields) => fields,
+            _ => unreachable!("A struct always serializes to an object"),
+        };
+
+        let header: Vec<&str> = fields.keys().map(String::as_str).collect();
+        let row: Vec<String> = fields
+            .values()
+            .map(|value| match value {
+                Value::String(text) => text.clone(),
+                Value::Null => String::new(),
+                other => other.to_string(),
+            })
+            .collect();
+
+        Ok(format!("{}\n{}", header.join("\t"), row.join("\t")))
+    }
+
+    // (start, end, label) of the pool price. Inverted shows token A per token B, for pools where B per A is a tiny number (USDC/SOL).
+    pub fn pool_prices_for_display(&self, inverted: bool) -> (f64, f64, String) {
+        if inverted {
+            (
+                1.0 / self.start_pool_price,
+                1.0 / self.end_pool_price,
+                format!("{} per {}", self.token_a_symbol, self.token_b_symbol),
+            )
+        } else {
+            (
+                self.start_pool_price,
+                self.end_pool_price,
+                format!("{} per {}", self.token_b_symbol, self.token_a_symbol),
+            )
+        }
+    }
+}
+
+// What the positions' tokens gained or lost (returned minus LPed, fees aside) valued by token_amounts_usd, as (closed by
+// the strategy, still open at the window end). The closes forced by on_window_end only mark the open ones to market.
+pub fn position_pnl_usd(
+    position_records: &[PositionRecord],
+    token_amounts_usd: impl Fn(u128, u128) -> f64,
+) -> (f64, f64) {
+    let mut closed_pnl_usd = 0.0;
+    let mut open_pnl_usd = 0.0;
+
+    for record in position_records {
+        // Never closed, the tokens aren't back in the wallet so the ending value doesn't include them either.
+        let Some(exit) = &record.exit else {
+            continue;
+        };
+
+        let pnl_usd = token_amounts_usd(exit.token_a_returned, exit.token_b_returned)
+            - token_amounts_usd(record.token_a_lped, record.token_b_lped);
+
+        if exit.is_final_close {
+            open_pnl_usd += pnl_usd;
+        } else {
+            closed_pnl_usd += pnl_usd;
+        }
+    }
+
+    (closed_pnl_usd, open_pnl_usd)
+}
+
+// Token B per token A in human units (decimals applied).
+pub fn pool_price(sqrt_price: U256, token_a_decimals: i16, token_b_decimals: i16) -> f64 {
+    sqrt_price_to_price(sqrt_price)
+        * 10.0_f64.powf(token_a_decimals as f64 - token_b_decimals as f64)
+}
+
+// (token A, token B) USD prices from the pool price when one side is pegged to USD.
+pub fn pegged_prices_usd(stable_token: StableToken, a_b_price: f64) -> (f64, f64) {
+    match stable_token {
+        StableToken::A => (STABLE_PEG_USD, STABLE_PEG_USD / a_b_price),
+        StableToken::B => (a_b_price * STABLE_PEG_USD, STABLE_PEG_USD),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{core_math::tick_to_sqrt_price_u256, data_logger::PositionExit};
+
+    #[test]
+    fn test_pegged_prices_usd() {
+        // SOL/USDC around tick -19000: 9 and 6 decimals.
+        let sqrt_price = tick_to_sqrt_price_u256(-19_000);
+        let sol_usdc = pool_price(sqrt_price, 9, 6);
+        assert!((sol_usdc - 149.6).abs() < 0.1);
+
+        assert_eq!(pegged_prices_usd(StableToken::B, sol_usdc), (sol_usdc, 1.0));
+
+        let (usdc_usd, sol_usd) = pegged_prices_usd(StableToken::A, 1.0 / sol_usdc);
+        assert_eq!(usdc_usd, 1.0);
+        assert!((sol_usd - sol_usdc).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_position_pnl_split() {
+        let record = |token_a_lped, token_b_lped, exit| PositionRecord {
+            position_id: "position".to_string(),
+            token_a_lped,
+            token_b_lped,
+            entry_price: 2.0,
+            exit,
+        };
+        let exit = |token_a_returned, token_b_returned, is_final_close| {
+            Some(PositionExit {
+                token_a_returned,
+                token_b_returned,
+                fees_a: 7,
+                fees_b: 7,
+                exit_price: 2.0,
+                is_final_close,
+            })
+        };
+        let records = [
+            // Rebalanced out after the price fell: more A, less B.
+            record(100, 200, exit(150, 80, false)),
+            record(150, 80, exit(100, 190, true)),
+            record(10, 10, None),
+        ];
+
+        // Token A at 2, token B at 1, fees don't count.
+        let (closed, open) = position_pnl_usd(&records, |a, b| a as f64 * 2.0 + b as f64);
+        assert_eq!(closed, 380.0 - 400.0);
+        assert_eq!(open, 390.0 - 380.0);
+    }
+
+    #[test]
+    fn test_price_time_check() {
+        let requested = Utc::now();
+        let point = |minutes_off: i64| PricePoint {
+            price: 1.0,
+            timestamp: requested - chrono::Duration::minutes(minutes_off),
+        };
+        let check = PriceTimeCheck::default();
+
+        assert!(check.check("SOL", requested, &point(0)).is_ok());
+        assert!(check.check("SOL", requested, &point(60)).is_ok());
+        // Only warns past the tolerance unless strict.
+        assert!(check.check("SOL", requested, &point(61)).is_ok());
+
+        let strict = PriceTimeCheck {
+            strict: true,
+            ..check
+        };
+        assert!(strict.check("SOL", requested, &point(60)).is_ok());
+        assert!(strict.check("SOL", requested, &point(-61)).is_err());
+        let error = strict.check("SOL", requested, &point(180)).unwrap_err();
+        assert!(error.to_string().contains("10800 seconds off"));
+    }
+
+    #[test]
+    fn test_token_yield_accrual() {
+        assert_eq!(TokenYields::default().accrual_factors(30.0), (1.0, 1.0));
+
+        // ~7% APY for mSOL is ~1.85 bps a day.
+        let yields = TokenYields {
+            token_a_bps_per_day: 1.85,
+            token_b_bps_per_day: 0.0,
+        };
+        let (a_factor, b_factor) = yields.accrual_factors(365.0);
+        assert!((a_factor - 1.07).abs() < 0.001, "{}", a_factor);
+        assert_eq!(b_factor, 1.0);
+
+        let (half_day_factor, _) = yields.accrual_factors(0.5);
+        assert!(half_day_factor > 1.0 && half_day_factor < 1.000_1);
+
+        // Pool derived prices already trade the LST at its appreciated value.
+        assert_eq!(yields.for_price_source(None), yields);
+        assert_eq!(
+            yields.for_price_source(Some(StableToken::B)),
+            TokenYields::default()
+        );
+    }
+
+    fn usdc_sol_result() -> PriceCalculationResult {
+        PriceCalculationResult {
+            price_source: PriceSource::Binance,
+            stable_token: None,
+            start_time: Utc::now(),
+            end_time: Utc::now(),
+            token_a_start_price_time: None,
+            token_b_start_price_time: None,
+            token_a_end_price_time: None,
+            token_b_end_price_time: None,
+            max_actions_hit: false,
+            strategy_actions_executed: 0,
+            swaps_filtered_by_source: 0,
+            dust_swaps_skipped: 0,
+            swaps_stopped_at_edge: 0,
+            ticks_net_liquidity_reset: 0,
+            token_a_symbol: "USDC".to_string(),
+            token_b_symbol: "SOL".to_string(),
+            start_pool_price: 0.008,
+            end_pool_price: 0.005,
+            token_a_price_change_pct: 0.0,
+            token_b_price_change_pct: 0.0,
+            token_a_yield_factor: 1.0,
+            token_b_yield_factor: 1.0,
+            pnl_no_lping: 0.0,
+            pnl_no_lping_pct: 0.0,
+            starting_total_value_in_usd: 0.0,
+            ending_total_value_in_usd: 0.0,
+            final_value_total: 0.0,
+            total_pnl_pct: 0.0,
+            entry_cost_usd: 0.0,
+            pnl_before_entry_cost: 0.0,
+            rebalance_cost_usd: 0.0,
+            sandwich_loss_usd: 0.0,
+            rebalance_count: 0,
+            realized_pnl_usd: 0.0,
+            unrealized_pnl_usd: 0.0,
+            max_drawdown_pct: 0.0,
+            avg_liquidity_share_pct: 0.0,
+            avg_idle_capital_usd: None,
+            avg_idle_capital_pct: None,
+            cash_drag_usd: None,
+            token_a_collected_fees: 0.0,
+            token_b_collected_fees: 0.0,
+            capital_earned_in_token_a: 0.0,
+            capital_earned_in_token_a_in_pct: 0.0,
+            total_fees_collected_in_usd: 0.0,
+            fees_collected_during_run_in_usd: 0.0,
+            fees_collected_at_end_in_usd: 0.0,
+            uncollected_fees_at_end_usd: 0.0,
+            fee_handling: FeeHandling::Hold,
+            fees_during_run_held_usd: 0.0,
+            fees_during_run_as_basket_usd: 0.0,
+            fee_handling_difference_usd: 0.0,
+            total_fees_in_pct: 0.0,
+            full_range_fees_in_usd: 0.0,
+            capital_efficiency_multiple: 0.0,
+            range_efficiency: 0.0,
+            fee_apr_percentage: 0.0,
+            fee_breakeven_days: None,
+            impermanent_loss: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_pool_prices_for_display() {
+        let result = usdc_sol_result();
+
+        assert_eq!(
+            result.pool_prices_for_display(false),
+            (0.008, 0.005, "SOL per USDC".to_string())
+        );
+        assert_eq!(
+            result.pool_prices_for_display(true),
+            (125.0, 200.0, "USDC per SOL".to_string())
+        );
+    }
+
+    #[test]
+    fn test_result_serializes_for_callers() {
+        let json = serde_json::to_value(usdc_sol_result()).unwrap();
+
+        assert_eq!(json["price_source"], "BINANCE");
+        assert_eq!(json["start_pool_price"], 0.008);
+        assert_eq!(json["max_actions_hit"], false);
+    }
+
+    #[test]
+    fn test_non_finite_results_are_zeroed() {
+        // Zero starting value: every ratio over it is NaN (0 / 0) or inf.
+        let mut result = usdc_sol_result();
+        result.starting_total_value_in_usd = 0.0;
+        result.total_pnl_pct = f64::NAN;
+        result.pnl_no_lping_pct = f64::INFINITY;
+        result.total_fees_in_pct = f64::NAN;
+        result.fee_apr_percentage = f64::NAN;
+
+        assert_eq!(
+            result.sanitize_non_finite(),
+            vec![
+                "pnl_no_lping_pct",
+                "total_pnl_pct",
+                "total_fees_in_pct",
+                "fee_apr_percentage"
+            ]
+        );
+        assert_eq!(result.total_pnl_pct, 0.0);
+        assert_eq!(result.pnl_no_lping_pct, 0.0);
+        assert_eq!(result.start_pool_price, 0.008);
+
+        // No swaps: range_efficiency is 0 / 0.
+        let mut result = usdc_sol_result();
+        result.range_efficiency = f64::NAN;
+        result.token_a_price_change_pct = f64::NEG_INFINITY;
+
+        assert_eq!(
+            result.sanitize_non_finite(),
+            vec!["token_a_price_change_pct", "range_efficiency"]
+        );
+        assert_eq!(result.range_efficiency, 0.0);
+        assert!(result.sanitize_non_finite().is_empty());
+    }
+
+    #[test]
+    fn test_result_as_tsv() {
+        let tsv = usdc_sol_result().to_tsv().unwrap();
+        let lines: Vec<&str> = tsv.lines().collect();
+        assert_eq!(lines.len(), 2);
+
+        let header: Vec<&str> = lines[0].split('\t').collect();
+        let row: Vec<&str> = lines[1].split('\t').collect();
+        assert_eq!(header.len(), row.len());
+
+        let field = |name: &str| row[header.iter().position(|column| *column == name).unwrap()];
+        assert_eq!(field("price_source"), "BINANCE");
+        assert_eq!(field("start_pool_price"), "0.008");
+        assert_eq!(field("token_a_symbol"), "USDC");
+        assert_eq!(field("stable_token"), "");
+
+        assert_eq!("json".parse::<OutputFormat>().unwrap(), OutputFormat::Json);
+        assert_eq!("TSV".parse::<OutputFormat>().unwrap(), OutputFormat::Tsv);
+        assert!("csv".parse::<OutputFormat>().is_err());
+    }
 }
@@ -0,0 +1,141 @@
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+
+use crate::repositories::transactions_repo::{OrderDirection, TransactionRepoTrait};
+
+const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
+const PRICE_SERIES_BATCH_SIZE: i64 = 10_000;
+
+// (block time, price of token A in token B) for every swap in [start_time, end_time], oldest first.
+// tx_ids aren't strictly time ordered (update syncs append newer txs), so the whole pool is paged and sorted by time.
+pub async fn fetch_swap_price_series<T: TransactionRepoTrait>(
+    transaction_repo: &T,
+    pool_address: &str,
+    token_a_address: &str,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Result<Vec<(DateTime<Utc>, f64)>> {
+    let mut prices = Vec::new();
+    let mut cursor = None;
+
+    loop {
+        let transactions = transaction_repo
+            .fetch_transactions(
+                pool_address,
+                cursor,
+                PRICE_SERIES_BATCH_SIZE,
+                OrderDirection::Descending,
+            )
+            .await?;
+
+        for transaction in &transactions {
+            if transaction.block_time_utc < start_time || transaction.block_time_utc > end_time {
+                continue;
+            }
+
+            if let Ok(swap_data) = transaction.data.to_swap_data() {
+                if swap_data.amount_in == 0 || swap_data.amount_out == 0 {
+                    continue;
+                }
+
+                let is_sell = swap_data.token_in == token_a_address;
+                prices.push((transaction.block_time_utc, swap_data.implied_price(is_sell)));
+            }
+        }
+
+        if transactions.len() < PRICE_SERIES_BATCH_SIZE as usize {
+            break;
+        }
+
+        cursor = transactions.last().map(|tx| tx.tx_id);
+    }
+
+    prices.sort_by_key(|(time, _)| *time);
+
+    Ok(prices)
+}
+
+// Annualized realized volatility from log returns between the last price of each bucket_seconds bucket.
+// Bucketing evens out the irregular swap spacing and the bid/ask bounce between consecutive swaps.
+// None if there are fewer than two returns to work with.
+pub fn realized_volatility(prices: &[(DateTime<Utc>, f64)], bucket_seconds: i64) -> Option<f64> {
+    let mut bucket_closes: Vec<(i64, f64)> = Vec::new();
+
+    for (time, price) in prices {
+        let bucket = time.timestamp().div_euclid(bucket_seconds);
+
+        match bucket_closes.last_mut() {
+            Some((last_bucket, last_price)) if *last_bucket == bucket => *last_price = *price,
+            _ => bucket_closes.push((bucket, *price)),
+        }
+    }
+
+    let log_returns: Vec<f64> = bucket_closes
+        .windows(2)
+        .map(|pair| (pair[1].1 / pair[0].1).ln())
+        .collect();
+
+    if log_returns.len() < 2 {
+        return None;
+    }
+
+    let n = log_returns.len() as f64;
+    let mean = log_returns.iter().sum::<f64>() / n;
+    let variance = log_returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (n - 1.0);
+
+    let periods_per_year = SECONDS_PER_YEAR / bucket_seconds as f64;
+
+    Some(variance.sqrt() * periods_per_year.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtester::test_helpers::{create_test_swap_transactions, InMemoryTransactionRepo};
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn test_realized_volatility() {
+        let start = Utc.timestamp_opt(1_700_000_000, 0).unwrap();
+
+        let flat: Vec<(DateTime<Utc>, f64)> = (0..24)
+            .map(|hour| (start + Duration::hours(hour), 100.0))
+            .collect();
+        assert_eq!(realized_volatility(&flat, 3600), Some(0.0));
+
+        // Hourly +1% / -1% moves, several swaps per hour where only the last one counts.
+        let choppy: Vec<(DateTime<Utc>, f64)> = (0..100)
+            .flat_map(|hour| {
+                let close = if hour % 2 == 0 { 100.0 } else { 101.0 };
+                vec![
+                    (start + Duration::hours(hour), 150.0),
+                    (start + Duration::hours(hour) + Duration::minutes(30), close),
+                ]
+            })
+            .collect();
+
+        let hourly_std = 1.01_f64.ln() * (99.0_f64 / 98.0).sqrt();
+        let expected = hourly_std * (365.0_f64 * 24.0).sqrt();
+        let vol = realized_volatility(&choppy, 3600).unwrap();
+        assert!((vol - expected).abs() / expected < 0.01);
+
+        assert_eq!(realized_volatility(&flat[..2], 3600), None);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_swap_price_series_filters_window() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(25_000, 1_000, "TokenA", "TokenB"),
+        };
+        let start = Utc.timestamp_opt(1_700_000_000 + 100, 0).unwrap();
+        let end = Utc.timestamp_opt(1_700_000_000 + 20_100, 0).unwrap();
+
+        let prices = fetch_swap_price_series(&repo, "test_pool", "TokenA", start, end)
+            .await
+            .unwrap();
+
+        assert_eq!(prices.len(), 20_001);
+        assert!(prices.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert!(prices.iter().all(|(_, price)| *price == 1.0));
+    }
+}
@@ -9,7 +9,9 @@ use tokio_retry::{
     Retry,
 };
 
-use crate::services::{orca_amm_standard::CommonTransactionData, transactions_sync_amm_service::AMMPlatforms};
+use crate::services::{
+    orca_amm_standard::CommonTransactionData, transactions_sync_amm_service::AMMPlatforms,
+};
 
 pub async fn retry_with_backoff<F, Fut, T, E>(
     f: F,
@@ -39,6 +41,7 @@ impl FromStr for AMMPlatforms {
         match s.to_uppercase().as_str() {
             "ORCA" => Ok(AMMPlatforms::Orca),
             "RAYDIUM" => Ok(AMMPlatforms::Raydium),
+            "METEORA" => Ok(AMMPlatforms::Meteora),
             // Add other platforms as needed
             _ => Err(anyhow!("Unknown platform: {}", s)),
         }
@@ -50,6 +53,7 @@ impl fmt::Display for AMMPlatforms {
         match self {
             AMMPlatforms::Orca => write!(f, "ORCA"),
             AMMPlatforms::Raydium => write!(f, "RAYDIUM"),
+            AMMPlatforms::Meteora => write!(f, "METEORA"),
         }
     }
 }
@@ -82,3 +86,177 @@ pub fn extract_common_data(tx_data: &Value) -> Result<CommonTransactionData> {
         account_keys,
     })
 }
+
+// Where the position sits in the accounts of the whirlpool liquidity instructions (increase/decrease liquidity, ...).
+const POSITION_ACCOUNT_INDEX: usize = 3;
+
+// Instructions index into the static keys followed by the ones loaded from lookup tables (v0 transactions).
+fn instruction_account_keys(tx_data: &Value) -> Option<Vec<&Value>> {
+    Some(
+        tx_data["transaction"]["message"]["accountKeys"]
+            .as_array()?
+            .iter()
+            .chain(
+                ["writable", "readonly"]
+                    .iter()
+                    .flat_map(|kind| tx_data["meta"]["loadedAddresses"][*kind].as_array())
+                    .flatten(),
+            )
+            .collect(),
+    )
+}
+
+fn key_str(key: &Value) -> Option<&str> {
+    key.as_str().or_else(|| key["pubkey"].as_str())
+}
+
+// Fallbacks for position resolution: the position account of every program_id instruction, top level or through CPI,
+// except the one already picked as the position. Kept JSON quoted like the account keys from extract_common_data.
+pub fn possible_positions(
+    tx_data: &Value,
+    program_id: &str,
+    position_address: &str,
+) -> Vec<String> {
+    let Some(account_keys) = instruction_account_keys(tx_data) else {
+        return vec![];
+    };
+
+    let inner_instructions = tx_data["meta"]["innerInstructions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|inner| inner["instructions"].as_array().into_iter().flatten());
+
+    let mut positions: Vec<String> = Vec::new();
+    for instruction in tx_data["transaction"]["message"]["instructions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .chain(inner_instructions)
+    {
+        let account = |index: Option<u64>| account_keys.get(index? as usize).copied();
+
+        if account(instruction["programIdIndex"].as_u64()).and_then(key_str) != Some(program_id) {
+            continue;
+        }
+
+        if let Some(position) = account(instruction["accounts"][POSITION_ACCOUNT_INDEX].as_u64()) {
+            let position = position.to_string();
+            if position.trim_matches('"') != position_address.trim_matches('"')
+                && !positions.contains(&position)
+            {
+                positions.push(position);
+            }
+        }
+    }
+
+    positions
+}
+
+// Program of the top level instruction that reaches target_program, directly or through its inner instructions (CPI).
+// Lets a swap be told apart by who sent it, e.g. the whirlpool program for direct swaps vs an aggregator.
+pub fn outer_program_id(tx_data: &Value, target_program: &str) -> Option<String> {
+    let account_keys: Vec<&str> = instruction_account_keys(tx_data)?
+        .into_iter()
+        .filter_map(key_str)
+        .collect();
+
+    let program_of = |instruction: &Value| -> Option<String> {
+        instruction["programId"]
+            .as_str()
+            .or_else(|| {
+                let index = instruction["programIdIndex"].as_u64()? as usize;
+                account_keys.get(index).copied()
+            })
+            .map(str::to_string)
+    };
+
+    let inner_instructions = tx_data["meta"]["innerInstructions"].as_array();
+
+    tx_data["transaction"]["message"]["instructions"]
+        .as_array()?
+        .iter()
+        .enumerate()
+        .find_map(|(index, instruction)| {
+            let program = program_of(instruction)?;
+
+            let calls_target = inner_instructions
+                .into_iter()
+                .flatten()
+                .filter(|inner| inner["index"].as_u64() == Some(index as u64))
+                .flat_map(|inner| inner["instructions"].as_array().into_iter().flatten())
+                .any(|inner| program_of(inner).as_deref() == Some(target_program));
+
+            (program == target_program || calls_target).then_some(program)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const WHIRLPOOL: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
+
+    #[test]
+    fn test_outer_program_id() {
+        let direct = json!({
+            "transaction": { "message": {
+                "accountKeys": ["Signer", "ComputeBudget111111111111111111111111111111", WHIRLPOOL],
+                "instructions": [{ "programIdIndex": 1 }, { "programIdIndex": 2 }]
+            }},
+            "meta": { "innerInstructions": [] }
+        });
+        assert_eq!(
+            outer_program_id(&direct, WHIRLPOOL).as_deref(),
+            Some(WHIRLPOOL)
+        );
+
+        // The aggregator calls the whirlpool program from its own instruction, the whirlpool key comes from a lookup table.
+        let routed = json!({
+            "transaction": { "message": {
+                "accountKeys": ["Signer", "Aggregator"],
+                "instructions": [{ "programIdIndex": 1 }]
+            }},
+            "meta": {
+                "loadedAddresses": { "writable": [], "readonly": [WHIRLPOOL] },
+                "innerInstructions": [{ "index": 0, "instructions": [{ "programIdIndex": 2 }] }]
+            }
+        });
+        assert_eq!(
+            outer_program_id(&routed, WHIRLPOOL).as_deref(),
+            Some("Aggregator")
+        );
+
+        assert_eq!(outer_program_id(&routed, "SomeOtherProgram"), None);
+    }
+
+    #[test]
+    fn test_possible_positions_only_keeps_whirlpool_position_accounts() {
+        // A wrapper program deposits into two positions through CPI, the whirlpool key comes from a lookup table.
+        let tx = json!({
+            "transaction": { "message": {
+                "accountKeys": ["Signer", "Wrapper", "Pool", "PositionA", "PositionB", "TokenAccount"],
+                "instructions": [{ "programIdIndex": 1, "accounts": [0, 2, 5, 3, 4] }]
+            }},
+            "meta": {
+                "loadedAddresses": { "writable": [], "readonly": [WHIRLPOOL] },
+                "innerInstructions": [{ "index": 0, "instructions": [
+                    { "programIdIndex": 6, "accounts": [2, 5, 0, 3, 5] },
+                    { "programIdIndex": 6, "accounts": [2, 5, 0, 4, 5] },
+                    { "programIdIndex": 6, "accounts": [2, 5, 0, 4, 5] }
+                ]}]
+            }
+        });
+
+        assert_eq!(
+            possible_positions(&tx, WHIRLPOOL, "\"PositionA\""),
+            vec!["\"PositionB\"".to_string()]
+        );
+        assert_eq!(
+            possible_positions(&tx, WHIRLPOOL, "\"Other\""),
+            vec!["\"PositionA\"".to_string(), "\"PositionB\"".to_string()]
+        );
+        assert!(possible_positions(&tx, "SomeOtherProgram", "\"PositionA\"").is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use std::io::{BufRead, Write};
+
+use crate::{
+    models::transactions_model::TransactionModelFromDB,
+    repositories::transactions_repo::{OrderDirection, TransactionRepoTrait},
+};
+
+const EXPORT_BATCH_SIZE: i64 = 10_000;
+
+// Writes every transaction of the pool with a block time in [start_time, end_time] as one JSON line, in the order
+// sync_forward replays them (highest tx_id first = oldest first). Returns how many were written.
+// tx_ids aren't strictly time ordered (update syncs append newer txs), so the whole pool is paged instead of stopping early.
+pub async fn export_transactions_jsonl<T: TransactionRepoTrait, W: Write>(
+    transaction_repo: &T,
+    pool_address: &str,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    writer: &mut W,
+) -> Result<usize> {
+    let mut written = 0;
+    let mut cursor = None;
+
+    loop {
+        let transactions = transaction_repo
+            .fetch_transactions(
+                pool_address,
+                cursor,
+                EXPORT_BATCH_SIZE,
+                OrderDirection::Descending,
+            )
+            .await?;
+
+        for transaction in &transactions {
+            if transaction.block_time_utc < start_time || transaction.block_time_utc > end_time {
+                continue;
+            }
+
+            serde_json::to_writer(&mut *writer, transaction).context(format!(
+                "Failed to serialize transaction {}",
+                transaction.tx_id
+            ))?;
+            writer.write_all(b"\n")?;
+            written += 1;
+        }
+
+        if transactions.len() < EXPORT_BATCH_SIZE as usize {
+            break;
+        }
+
+        cursor = transactions.last().map(|tx| tx.tx_id);
+    }
+
+    writer.flush()?;
+
+    Ok(written)
+}
+
+// Reads back a file written by export_transactions_jsonl, keeping the replay order. Blank lines are skipped.
+pub fn read_transactions_jsonl<R: BufRead>(reader: R) -> Result<Vec<TransactionModelFromDB>> {
+    let mut transactions = Vec::new();
+
+    for (line_nmr, line) in reader.lines().enumerate() {
+        let line = line?;
+
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let transaction = serde_json::from_str(&line)
+            .context(format!("Invalid transaction on line {}", line_nmr + 1))?;
+        transactions.push(transaction);
+    }
+
+    Ok(transactions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backtester::test_helpers::{create_test_swap_transactions, InMemoryTransactionRepo};
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn test_export_transactions_jsonl_window() {
+        let repo = InMemoryTransactionRepo {
+            transactions: create_test_swap_transactions(20, 1_000, "TokenA", "TokenB"),
+        };
+
+        // tx_ids 6..=16 fall inside the window (block_time = 1_700_000_021 - tx_id).
+        let start_time = Utc.timestamp_opt(1_700_000_005, 0).unwrap();
+        let end_time = Utc.timestamp_opt(1_700_000_015, 0).unwrap();
+
+        let mut output = Vec::new();
+        let written =
+            export_transactions_jsonl(&repo, "test_pool", start_time, end_time, &mut output)
+                .await
+                .unwrap();
+
+        assert_eq!(written, 11);
+
+        let exported = read_transactions_jsonl(output.as_slice()).unwrap();
+
+        let tx_ids: Vec<i64> = exported.iter().map(|tx| tx.tx_id).collect();
+        assert_eq!(tx_ids, (6..=16).rev().collect::<Vec<i64>>());
+        assert_eq!(exported[0].signature, "sig16");
+        assert_eq!(exported[0].transaction_type, "Swap");
+        assert!(exported[0].data.to_swap_data().is_ok());
+    }
+}
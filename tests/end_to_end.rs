@@ -0,0 +1,280 @@
+// The backtest pipeline of run_backtest without a database, RPC or price provider: build the present range from
+// fixture positions, sync it backwards over an in-memory repo, replay a strategy forward and compute the PnL.
+
+use std::collections::HashMap;
+
+use chrono::{TimeZone, Utc};
+use src::{
+    backtester::{
+        backtest_utils::{
+            create_full_liquidity_range, sync_backwards, ReconstructionCheckpoint, StartPrice,
+            TickBounds,
+        },
+        backtester_core::{Backtest, Wallet},
+        no_rebalance_strategy::NoRebalanceStrategy,
+        test_helpers::{InMemoryTransactionRepo, MockPriceApi, MockTokenMetadataApi},
+    },
+    models::{
+        pool_model::PoolModel,
+        positions_model::LivePositionModel,
+        transactions_model::{SwapData, TransactionData, TransactionModelFromDB},
+    },
+    utils::{
+        core_math::{within_tolerance, U256},
+        profit_calcs::{
+            calculate_prices_and_pnl, FeeHandling, PriceCalculationResult, PriceTimeCheck,
+            TokenYields,
+        },
+    },
+};
+
+const SOL: &str = "So11111111111111111111111111111111111111112";
+const USDC: &str = "EPjFWdd5AufqSSqeM5qZzEVv9j7pvWcsPxTdFvvU6ZaK";
+const BASE_TIME: i64 = 1_722_470_400; // 2024-08-01
+
+fn pool_model() -> PoolModel {
+    PoolModel {
+        address: "pool".to_string(),
+        name: "SOL/USDC".to_string(),
+        token_a_name: "SOL".to_string(),
+        token_b_name: "USDC".to_string(),
+        token_a_address: SOL.to_string(),
+        token_b_address: USDC.to_string(),
+        token_a_vault: "vault_a".to_string(),
+        token_b_vault: "vault_b".to_string(),
+        token_a_decimals: 9,
+        token_b_decimals: 6,
+        tick_spacing: 1,
+        fee_rate: 300,
+        last_updated_at: Utc.timestamp_opt(BASE_TIME + 3_600, 0).unwrap(),
+    }
+}
+
+fn live_positions() -> Vec<LivePositionModel> {
+    [
+        (-21_000, -19_000, 2_000_000_000_000),
+        (-20_200, -19_800, 5_000_000_000_000),
+        (-19_990, -19_900, 1_000_000_000_000),
+    ]
+    .into_iter()
+    .enumerate()
+    .map(
+        |(i, (tick_lower, tick_upper, liquidity))| LivePositionModel {
+            address: format!("position{}", i),
+            liquidity,
+            tick_lower,
+            tick_upper,
+            created_at: Utc.timestamp_opt(BASE_TIME, 0).unwrap(),
+        },
+    )
+    .collect()
+}
+
+// Like the synced table: the newest swap is tx_id 1, higher ids go back in time. A SOL sell and a USDC buy of about the
+// same value alternate, one a minute, at ~135 USDC per SOL (tick ~-20_000 with the 9/6 decimals).
+fn swaps(count: i64) -> Vec<TransactionModelFromDB> {
+    (1..=count)
+        .map(|tx_id| {
+            let block_time = BASE_TIME + (count - tx_id) * 60;
+            let data = if tx_id % 2 == 0 {
+                SwapData {
+                    token_in: SOL.to_string(),
+                    token_out: USDC.to_string(),
+                    amount_in: 2_000_000_000,
+                    amount_out: 270_000_000,
+                    source_program: None,
+                }
+            } else {
+                SwapData {
+                    token_in: USDC.to_string(),
+                    token_out: SOL.to_string(),
+                    amount_in: 300_000_000,
+                    amount_out: 2_220_000_000,
+                    source_program: None,
+                }
+            };
+
+            TransactionModelFromDB {
+                tx_id,
+                signature: format!("sig{}", tx_id),
+                pool_address: "pool".to_string(),
+                block_time,
+                block_time_utc: Utc.timestamp_opt(block_time, 0).unwrap(),
+                transaction_type: "Swap".to_string(),
+                ready_for_backtesting: true,
+                data: TransactionData::Swap(data),
+            }
+        })
+        .collect()
+}
+
+async fn run_pipeline(fee_rate: i16) -> (Backtest, PriceCalculationResult) {
+    let pool_model = PoolModel {
+        fee_rate,
+        ..pool_model()
+    };
+    let repo = InMemoryTransactionRepo {
+        transactions: swaps(40),
+    };
+    let latest_tx = repo.transactions[0].clone();
+
+    let present_range = create_full_liquidity_range(
+        pool_model.tick_spacing,
+        live_positions(),
+        pool_model.clone(),
+        latest_tx.clone(),
+        StartPrice::LatestSwap,
+        None,
+        pool_model.fee_rate,
+        TickBounds::Derived { padding: 5_000 },
+    )
+    .unwrap();
+    let present_sqrt_price = present_range.current_sqrt_price;
+
+    // A snapshot taken at the latest swap is the present range itself, the backwards sync checks it before rewinding.
+    let checkpoint = ReconstructionCheckpoint {
+        timestamp: latest_tx.block_time_utc,
+        expected_tick: present_range.current_tick,
+        expected_liquidity: present_range.active_liquidity.as_u128(),
+        tick_tolerance: 0,
+        liquidity_tolerance_bps: 0,
+        strict: true,
+    };
+
+    let (mut starting_range, highest_tx) = sync_backwards(
+        &repo,
+        present_range,
+        pool_model.clone(),
+        latest_tx.clone(),
+        None,
+        0,
+        Some(&checkpoint),
+        16,
+    )
+    .await
+    .unwrap();
+    assert_eq!(highest_tx.tx_id, 40);
+
+    starting_range.reset_fee_growth();
+    starting_range.current_block_time = highest_tx.block_time;
+
+    let amount_a = U256::from(10 * 10_u128.pow(9));
+    let amount_b = U256::from(1_350 * 10_u128.pow(6));
+    let wallet = Wallet {
+        token_a_addr: SOL.to_string(),
+        token_b_addr: USDC.to_string(),
+        amount_token_a: amount_a,
+        amount_token_b: amount_b,
+        token_a_decimals: 9,
+        token_b_decimals: 6,
+        amount_a_fees_collected: U256::zero(),
+        amount_b_fees_collected: U256::zero(),
+    };
+    let strategy = NoRebalanceStrategy::new(
+        starting_range.current_tick - 200,
+        starting_range.current_tick + 200,
+    );
+    let mut backtest = Backtest::new(
+        amount_a,
+        amount_b,
+        starting_range,
+        wallet,
+        Box::new(strategy),
+    );
+
+    backtest
+        .sync_forward(&repo, highest_tx.tx_id, latest_tx.tx_id, "pool", 16)
+        .await
+        .unwrap();
+
+    // The forward replay walks the same swaps back to the present price, our position only damps the moves slightly.
+    assert!(within_tolerance(
+        backtest.liquidity_arr.current_sqrt_price,
+        present_sqrt_price,
+        100
+    ));
+
+    let price_api = MockPriceApi {
+        prices_usd: HashMap::from([("SOL".to_string(), 135.0), ("USDC".to_string(), 1.0)]),
+    };
+    let token_metadata_api = MockTokenMetadataApi {
+        symbols: HashMap::from([
+            (SOL.to_string(), "SOL".to_string()),
+            (USDC.to_string(), "USDC".to_string()),
+        ]),
+    };
+
+    let result = calculate_prices_and_pnl(
+        &token_metadata_api,
+        &price_api,
+        None,
+        TokenYields::default(),
+        FeeHandling::Hold,
+        PriceTimeCheck::default(),
+        &backtest,
+        &highest_tx,
+        &latest_tx,
+    )
+    .await
+    .unwrap();
+
+    (backtest, result)
+}
+
+#[tokio::test]
+async fn test_backtest_pipeline_end_to_end() {
+    let (backtest, result) = run_pipeline(300).await;
+
+    assert_eq!(result.token_a_symbol, "SOL");
+    assert_eq!(result.token_b_symbol, "USDC");
+    assert_eq!(result.start_time, Utc.timestamp_opt(BASE_TIME, 0).unwrap());
+    assert_eq!(
+        result.end_time,
+        Utc.timestamp_opt(BASE_TIME + 39 * 60, 0).unwrap()
+    );
+
+    // The rewound tx 40 is the starting state, the other 39 swaps are replayed and all stay inside the position.
+    assert_eq!(backtest.data.current_swap_nmr, 39);
+    assert_eq!(backtest.data.swap_nmr_in_position, 39);
+
+    // 10 SOL and 1350 USDC at the mock prices. Same prices at both ends, so holding makes nothing.
+    assert_eq!(result.starting_total_value_in_usd, 2_700.0);
+    assert_eq!(result.pnl_no_lping, 0.0);
+
+    // Regression values, a change here means the pipeline computes something different than before.
+    assert_eq!(backtest.wallet.amount_a_fees_collected, U256::from(565_257));
+    assert_eq!(backtest.wallet.amount_b_fees_collected, U256::from(89_251));
+    assert!((result.start_pool_price - 135.101026).abs() < 1e-6);
+    assert!((result.end_pool_price - 135.187302).abs() < 1e-6);
+    assert!((result.total_fees_collected_in_usd - 0.165561).abs() < 1e-6);
+    assert!((result.ending_total_value_in_usd - 2_700.211272).abs() < 1e-6);
+    assert!((result.final_value_total - 0.211272).abs() < 1e-6);
+
+    // Nothing was closed by the strategy, so only the fees are realized and the position forced closed at the end is
+    // what's left of the PnL.
+    assert_eq!(result.realized_pnl_usd, result.total_fees_collected_in_usd);
+    assert!(
+        (result.realized_pnl_usd + result.unrealized_pnl_usd - result.final_value_total).abs()
+            < 1e-3
+    );
+    assert!(result.fee_breakeven_days.is_some_and(|days| days >= 0.0));
+}
+
+#[tokio::test]
+async fn test_zero_fee_pool_reports_zero_fees() {
+    let (backtest, result) = run_pipeline(0).await;
+
+    assert_eq!(backtest.data.current_swap_nmr, 39);
+    assert_eq!(backtest.wallet.amount_a_fees_collected, U256::zero());
+    assert_eq!(backtest.wallet.amount_b_fees_collected, U256::zero());
+
+    // Every fee figure is a plain zero rather than NaN, and the swap costs are never paid back.
+    assert_eq!(result.total_fees_collected_in_usd, 0.0);
+    assert_eq!(result.total_fees_in_pct, 0.0);
+    assert_eq!(result.full_range_fees_in_usd, 0.0);
+    assert_eq!(result.capital_efficiency_multiple, 0.0);
+    assert_eq!(result.fee_apr_percentage, 0.0);
+    assert_eq!(result.fee_breakeven_days, None);
+    assert!(result.final_value_total.is_finite());
+    assert!(result.realized_pnl_usd.abs() < 1e-9);
+}